@@ -208,3 +208,33 @@ fn map_add_island_xhz(b: &mut Bencher) {
     let gen = MapAddIsland::new(base_seed, world_seed);
     b.iter(|| gen.get_map_from_pmap(&m));
 }
+
+// This is a real world benchmark: the river seed finder generates one of these maps for each
+// candidate seed, and most of the time is spent in the 6 MapZoom layers
+#[bench]
+fn candidate_river_map_1234(b: &mut Bencher) {
+    let world_seed = 1234;
+    let (w, h) = DIM;
+    let area = Area {
+        x: 0,
+        z: 0,
+        w: w as u64,
+        h: h as u64,
+    };
+    b.iter(|| candidate_river_map(area, world_seed));
+}
+
+// Full stack of layers, including the layers that use next_int_n_const
+#[bench]
+fn generate_1_16_1234(b: &mut Bencher) {
+    use slime_seed_finder::seed_info::MinecraftVersion;
+    let world_seed = 1234;
+    let (w, h) = DIM;
+    let area = Area {
+        x: 0,
+        z: 0,
+        w: w as u64,
+        h: h as u64,
+    };
+    b.iter(|| generate(MinecraftVersion::Java1_16, area, world_seed, 0));
+}
@@ -1,5 +1,4 @@
 use log::*;
-use palette::{Gradient, LinSrgb};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_file_reader::WebSysFile;
@@ -12,16 +11,24 @@ use slime_seed_finder::biome_layers::GetMap;
 use slime_seed_finder::biome_layers::Map;
 use slime_seed_finder::biome_layers::MapTreasure;
 use slime_seed_finder::biome_layers::PanicMap;
+use slime_seed_finder::biome_layers::SinMode;
+use slime_seed_finder::biome_layers::TreasureMapColors;
+use slime_seed_finder::cancel::CancelToken;
+use slime_seed_finder::cancel::Partial;
 use slime_seed_finder::chunk::Point;
+use slime_seed_finder::java_rng::parse_seed;
 use slime_seed_finder::java_rng::JavaRng;
+use slime_seed_finder::lru_cache::LruCache;
 use slime_seed_finder::mc_rng::McRng;
 use slime_seed_finder::seed_info;
 use slime_seed_finder::seed_info::BiomeId;
 use slime_seed_finder::seed_info::MinecraftVersion;
 use slime_seed_finder::seed_info::SeedInfo;
 use slime_seed_finder::slime::SlimeChunks;
+use slime_seed_finder::wasm_error::*;
 use slime_seed_finder::*;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::rc::Rc;
@@ -32,6 +39,35 @@ use std::rc::Rc;
 pub struct Options {
     seed_info: SeedInfo,
     range: Option<(u32, u32)>,
+    /// Stop the search after this many milliseconds, returning the seeds found so far
+    timeout_ms: Option<f64>,
+}
+
+#[wasm_bindgen]
+extern "C" {
+    // std::time::Instant is not available in wasm
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
+// Cancel token that stops the search after `timeout_ms` milliseconds, if set
+fn timeout_cancel_token(timeout_ms: Option<f64>) -> CancelToken {
+    match timeout_ms {
+        Some(timeout_ms) => {
+            let deadline = date_now() + timeout_ms;
+            CancelToken::from_fn(move || date_now() >= deadline)
+        }
+        None => CancelToken::new(),
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialSeeds {
+    seeds: Vec<String>,
+    /// True if the search was stopped by the timeout, so some seeds may be missing
+    truncated: bool,
 }
 
 #[wasm_bindgen]
@@ -63,8 +99,18 @@ pub fn slime_seed_finder(o: JsValue) -> String {
     };
     debug!("Hello from Rust");
     let r = find_seed(o);
+    let timeout = if r.truncated {
+        "Search stopped by the timeout, some seeds may be missing.\n"
+    } else {
+        ""
+    };
 
-    format!("Found {} seeds!\n{:#?}", r.len(), r)
+    format!(
+        "{}Found {} seeds!\n{:#?}",
+        timeout,
+        r.results.len(),
+        r.results
+    )
 }
 
 #[wasm_bindgen]
@@ -73,12 +119,65 @@ pub fn river_seed_finder(o: String) -> Vec<JsValue> {
     let o = o.unwrap();
     debug!("Hello from Rust");
     let r = find_seed_rivers(o);
+    if r.truncated {
+        warn!("Search stopped by the timeout, some seeds may be missing");
+    }
 
-    r.into_iter()
+    r.results
+        .into_iter()
         .map(|seed| JsValue::from_str(&format!("{}", seed)))
         .collect()
 }
 
+/// Same as `river_seed_finder`, but also returns if the search was stopped by the `timeoutMs`
+/// option. Returns `{ seeds, truncated }`, with the seeds as strings.
+#[wasm_bindgen]
+pub fn river_seed_finder_with_timeout(o: String) -> JsValue {
+    let o: Result<Options, _> = serde_json::from_str(&o);
+    let o = o.unwrap();
+    let r = find_seed_rivers(o);
+    let ret = PartialSeeds {
+        seeds: r.results.iter().map(|seed| seed.to_string()).collect(),
+        truncated: r.truncated,
+    };
+    JsValue::from_serde(&ret)
+        .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))
+}
+
+/// Start a river seed finder search that is run in small steps, to avoid freezing the tab. Uses
+/// the range of the options, or the full range. Returns the serialized `RangeSearchState`, which
+/// must be passed to `river_seed_finder_step`.
+#[wasm_bindgen]
+pub fn river_seed_finder_start(o: String) -> Result<String, JsValue> {
+    let o: Options = parse_options(&o).map_err(js_error)?;
+    let (range_lo, range_hi) = o.range.unwrap_or((0, 1 << 24));
+
+    Ok(serde_json::to_string(&biome_layers::RangeSearchState::new(range_lo, range_hi)).unwrap())
+}
+
+/// Check 1/256th of the range of a search started by `river_seed_finder_start`, using the same
+/// options. Returns the new state: the search is done when `next >= rangeHi`, and the seeds found
+/// so far are in `found`. The state can be stored to resume the search later.
+#[wasm_bindgen]
+pub fn river_seed_finder_step(o: String, state: String) -> Result<String, JsValue> {
+    let mut o: Options = parse_options(&o).map_err(js_error)?;
+    let mut state: biome_layers::RangeSearchState = serde_json::from_str(&state).map_err(|e| {
+        js_error(WasmError::new(
+            WasmErrorKind::InvalidState,
+            format!("Failed to parse search state: {}", e),
+        ))
+    })?;
+    let step = (state.range_hi.saturating_sub(state.range_lo) / 256).max(1);
+    // A step stopped by the timeout would be marked as done, so steps cannot use a timeout
+    o.timeout_ms = None;
+    state.step(step, |range_lo, range_hi| {
+        o.range = Some((range_lo, range_hi));
+        find_seed_rivers(o).results
+    });
+
+    Ok(serde_json::to_string(&state).unwrap())
+}
+
 #[wasm_bindgen]
 pub fn draw_rivers(o: String) -> JsValue {
     // TODO: detect when there are two separate river areas and return a vec of maps?
@@ -95,22 +194,71 @@ pub fn draw_rivers(o: String) -> JsValue {
     let target_map = biome_layers::map_with_river_at(
         &o.seed_info.biomes[&BiomeId(biome_id::river)],
         area_rivers,
-    );
+    )
+    .to_map();
     let m = biome_layers::reverse_map_voronoi_zoom(&target_map).unwrap_or_default();
 
     let area_hd = Area::from_coords(hd_coords.iter().copied());
-    let target_map_hd = biome_layers::map_with_river_at(&hd_coords, area_hd);
+    let target_map_hd = biome_layers::map_with_river_at(&hd_coords, area_hd).to_map();
 
     let ret = DrawRivers {
         l43_area: target_map_hd.area(),
-        l43: biome_layers::draw_map_image(&target_map_hd),
+        l43: biome_layers::draw_map_image(&target_map_hd).into_raw(),
         l42_area: m.area(),
-        l42: biome_layers::draw_map_image(&m),
+        l42: biome_layers::draw_map_image(&m).into_raw(),
     };
     JsValue::from_serde(&ret)
         .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))
 }
 
+#[wasm_bindgen]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrawCandidateDiff {
+    seed_info: SeedInfo,
+    seed: String,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateDiff {
+    area: Area,
+    image: Vec<u8>,
+}
+
+/// Compare the 1:1 biomes of the seed info with the biomes generated by a candidate seed, to show
+/// where they do not match. Returns a `CandidateDiff` object, or throws a `WasmError`.
+#[wasm_bindgen]
+pub fn draw_candidate_diff(o: String) -> Result<JsValue, JsValue> {
+    let o: DrawCandidateDiff = parse_options(&o).map_err(js_error)?;
+    let seed = parse_seed_or_error(&o.seed).map_err(js_error)?;
+    let version: MinecraftVersion = o.seed_info.version.parse().map_err(|e| {
+        js_error(WasmError::new(
+            WasmErrorKind::InvalidVersion,
+            format!("{:?} is not a valid version: {}", o.seed_info.version, e),
+        ))
+    })?;
+    let points = o
+        .seed_info
+        .biomes
+        .iter()
+        .flat_map(|(id, v)| v.iter().map(move |p| (*id, *p)));
+    let area = Area::from_coords(points.clone().map(|(_, p)| p));
+    let mut observed = Map::from_area_fn(area, |_| biome_info::UNKNOWN_BIOME_ID);
+    for (id, p) in points {
+        observed.a[((p.x - area.x) as usize, (p.z - area.z) as usize)] = id.0;
+    }
+    let generated = biome_layers::generate(version, area, seed, 0);
+
+    let ret = CandidateDiff {
+        area,
+        image: biome_layers::draw_diff_image(&observed, &generated).into_raw(),
+    };
+    Ok(JsValue::from_serde(&ret)
+        .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e))))
+}
+
 #[wasm_bindgen]
 /// Returns `DrawRivers` object
 pub fn generate_rivers_candidate(o: String) -> JsValue {
@@ -132,7 +280,7 @@ pub fn generate_rivers_candidate(o: String) -> JsValue {
         l42: biome_layers::generate_image_up_to_layer(
             o.version.parse().unwrap(),
             o.area,
-            o.seed.parse().unwrap(),
+            parse_seed(&o.seed).unwrap(),
             magic_layer_river_candidate,
             0,
         ),
@@ -170,10 +318,14 @@ pub fn draw_reverse_voronoi(o: JsValue) -> Vec<u8> {
             return vec![];
         }
     };
-    let target_map = seed_info::biomes_to_map(o.seed_info.biomes);
+    // Only draw the biggest cluster of observations
+    let target_map = seed_info::biomes_to_maps(o.seed_info.biomes)
+        .into_iter()
+        .max_by_key(|m| m.area().w * m.area().h)
+        .unwrap_or_default();
 
     let m = biome_layers::reverse_map_voronoi_zoom(&target_map).unwrap_or_default();
-    biome_layers::draw_map_image(&m)
+    biome_layers::draw_map_image(&m).into_raw()
 }
 
 #[wasm_bindgen]
@@ -219,23 +371,31 @@ pub fn count_rivers(o: String) -> String {
     }
 }
 
-pub fn find_seed(o: Options) -> Vec<u64> {
+pub fn find_seed(o: Options) -> Partial<u64> {
+    let cancel = timeout_cancel_token(o.timeout_ms);
     let c: Vec<_> = o.seed_info.positive.slime_chunks;
     let nc: Vec<_> = o.seed_info.negative.slime_chunks;
 
     if (c.len() == 0) && (nc.len() == 0) {
         error!("Can't find seed without chunks");
-        return vec![];
+        return Partial {
+            results: vec![],
+            truncated: false,
+        };
     }
     let sc = SlimeChunks::new(&c, 0, &nc, 0);
     let num_cand = sc.num_low_18_candidates() as u32;
     info!("Found {} * 2^30 candidates", num_cand);
     info!("ETA: about {} seconds", num_cand * 7);
-    let seeds = sc.find_seed();
+    let seeds = sc.find_seed_cancellable(&cancel);
 
     {
         // Display only seeds that could be generated by java (empty box)
-        let java_seeds: Vec<_> = seeds.iter().map(|&s| JavaRng::extend_long_48(s)).collect();
+        let java_seeds: Vec<_> = seeds
+            .results
+            .iter()
+            .map(|&s| JavaRng::extend_long_48(s))
+            .collect();
 
         info!("Java seeds: \n{:#?}", java_seeds);
     }
@@ -243,7 +403,8 @@ pub fn find_seed(o: Options) -> Vec<u64> {
     seeds
 }
 
-pub fn find_seed_rivers(o: Options) -> Vec<i64> {
+pub fn find_seed_rivers(o: Options) -> Partial<i64> {
+    let cancel = timeout_cancel_token(o.timeout_ms);
     let extra_biomes: Vec<_> = o
         .seed_info
         .biomes
@@ -258,35 +419,88 @@ pub fn find_seed_rivers(o: Options) -> Vec<i64> {
         .collect();
     let version = o.seed_info.version.parse().unwrap();
     if let Some(rivers) = o.seed_info.biomes.get(&BiomeId(biome_id::river)) {
-        if let Some((range_lo, range_hi)) = o.range {
-            biome_layers::river_seed_finder_range(
-                rivers,
-                &extra_biomes,
-                version,
-                range_lo,
-                range_hi,
-            )
-        } else {
-            biome_layers::river_seed_finder(rivers, &extra_biomes, version)
-        }
+        let (range_lo, range_hi) = o.range.unwrap_or((0, 1 << 24));
+        biome_layers::river_seed_finder_range_cancellable(
+            rivers,
+            &extra_biomes,
+            version,
+            range_lo,
+            range_hi,
+            &cancel,
+        )
     } else if let Some(rivers) = o
         .seed_info
         .biomes_quarter_scale
         .get(&BiomeId(biome_id::river))
     {
         // Only quarter scale biomes: find 26-bit candidates and exit
-        if let Some((range_lo, range_hi)) = o.range {
-            biome_layers::river_seed_finder_26_range(rivers, range_lo, range_hi)
-        } else {
-            biome_layers::river_seed_finder_26_range(rivers, 0, 1 << 24)
-        }
+        let (range_lo, range_hi) = o.range.unwrap_or((0, 1 << 24));
+        biome_layers::river_seed_finder_26_seeds_cancellable(
+            rivers,
+            biome_layers::seeds_25_range(mc_rng::SeedOrder::Sequential, range_lo, range_hi),
+            &biome_layers::SearchConfig::default(),
+            &cancel,
+        )
     } else {
         error!("Can't find seed without rivers");
-        vec![]
+        Partial {
+            results: vec![],
+            truncated: false,
+        }
     }
 }
 
+// WasmError is defined in the main crate, so it cannot implement Into<JsValue>
+fn js_error(e: WasmError) -> JsValue {
+    JsValue::from_serde(&e).unwrap_or_else(|_| JsValue::from_str(&e.message))
+}
+
+fn fragment_area(fx: i32, fy: i32, frag_size: usize) -> Area {
+    let frag_size = frag_size as u64;
+    Area {
+        x: fx as i64 * frag_size as i64,
+        z: fy as i64 * frag_size as i64,
+        w: frag_size,
+        h: frag_size,
+    }
+}
+
+// Versions that can be parsed but have no biome generator
+fn has_biome_generator(version: MinecraftVersion) -> bool {
+    !matches!(
+        version,
+        MinecraftVersion::JavaAlpha1_2_5 | MinecraftVersion::JavaBeta | MinecraftVersion::Java1_2
+    )
+}
+
+fn generate_treasure_map_fragment(
+    version: &str,
+    fx: i32,
+    fy: i32,
+    seed: &str,
+    frag_size: usize,
+) -> Result<Vec<u8>, WasmError> {
+    let seed = parse_seed_or_error(seed)?;
+    let area = fragment_area(fx, fy, frag_size);
+    let mc_version = match version {
+        "TreasureMap13" => MinecraftVersion::Java1_13,
+        "TreasureMap14" => MinecraftVersion::Java1_14,
+        "TreasureMap15" => MinecraftVersion::Java1_15,
+        _ => {
+            return Err(WasmError::new(
+                WasmErrorKind::InvalidVersion,
+                format!("{} is not a valid treasure map version", version),
+            ))
+        }
+    };
+
+    Ok(biome_layers::generate_image_treasure_map(
+        mc_version, area, seed,
+    ))
+}
+
 #[wasm_bindgen]
+/// Returns the RGBA image of a fragment, or throws a `WasmError`
 pub fn generate_fragment(
     version: String,
     fx: i32,
@@ -294,47 +508,40 @@ pub fn generate_fragment(
     seed: String,
     frag_size: usize,
     y_offset: u32,
-) -> Vec<u8> {
-    let empty_map_as_error = || vec![0; frag_size * frag_size * 4];
-    let version1: MinecraftVersion = match version.parse() {
-        Ok(s) => s,
-        Err(_) => {
-            if version.starts_with("TreasureMap") {
-                let seed = if let Ok(s) = seed.parse() {
-                    s
-                } else {
-                    error!("{} is not a valid seed", seed);
-                    return empty_map_as_error();
-                };
-                let frag_size = frag_size as u64;
-                let area = Area {
-                    x: fx as i64 * frag_size as i64,
-                    z: fy as i64 * frag_size as i64,
-                    w: frag_size,
-                    h: frag_size,
-                };
-                let mc_version = if version == "TreasureMap13" {
-                    MinecraftVersion::Java1_13
-                } else if version == "TreasureMap14" {
-                    MinecraftVersion::Java1_14
-                } else if version == "TreasureMap15" {
-                    MinecraftVersion::Java1_15
-                } else {
-                    error!("{} is not a valid treasure map version", version);
-                    return empty_map_as_error();
-                };
-                return biome_layers::generate_image_treasure_map(mc_version, area, seed);
-            } else {
-                error!("{} is not a valid version", version);
-                return empty_map_as_error();
-            }
-        }
+) -> Result<Vec<u8>, JsValue> {
+    // Treasure maps do not use the layer
+    let num_layers = match version.parse::<MinecraftVersion>() {
+        Ok(v) if has_biome_generator(v) => v.num_layers(),
+        _ => 0,
     };
-    let num_layers = version1.num_layers();
     generate_fragment_up_to_layer(version, fx, fy, seed, frag_size, num_layers, y_offset)
 }
 
+type FragmentKey = (MinecraftVersion, i64, u32, u32, Area);
+
+/// Maximum size of the cached fragment images, in bytes: 64 fragments of 256x256 tiles. A
+/// fragment of `MAX_FRAGMENT_SIZE` is 4 MiB, so only 4 of them fit.
+const FRAGMENT_CACHE_BYTES: usize = 16 << 20;
+
+thread_local! {
+    // Images of the last generated fragments
+    static FRAGMENT_CACHE: RefCell<LruCache<FragmentKey, Vec<u8>>> =
+        RefCell::new(LruCache::with_max_weight(64, FRAGMENT_CACHE_BYTES, |v: &Vec<u8>| v.len()));
+    // Generators of the last seeds and layers
+    static GENERATOR_CACHE: RefCell<biome_layers::GeneratorCache> =
+        RefCell::new(biome_layers::GeneratorCache::new(4));
+}
+
+/// Debug mode: panic if a fragment generated at once is different from the same fragment
+/// generated in smaller pieces. The cached images are removed so they are generated again.
+#[wasm_bindgen]
+pub fn set_check_fragment_stitching(check: bool) {
+    GENERATOR_CACHE.with(|cache| cache.borrow_mut().set_check_stitching(check));
+    FRAGMENT_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
 #[wasm_bindgen]
+/// Returns the RGBA image of a fragment, or throws a `WasmError`
 pub fn generate_fragment_up_to_layer(
     version: String,
     fx: i32,
@@ -344,149 +551,122 @@ pub fn generate_fragment_up_to_layer(
     layer: u32,
     // y_offset used to render slice of 3D biome
     y_offset: u32,
-) -> Vec<u8> {
-    let empty_map_as_error = || vec![0; frag_size * frag_size * 4];
-    let frag_size = frag_size as usize;
-    let version = match version.parse() {
+) -> Result<Vec<u8>, JsValue> {
+    check_fragment_size(frag_size).map_err(js_error)?;
+    let version: MinecraftVersion = match version.parse() {
         Ok(s) => s,
         Err(_) => {
             if version.starts_with("TreasureMap") {
-                let seed = if let Ok(s) = seed.parse() {
-                    s
-                } else {
-                    error!("{} is not a valid seed", seed);
-                    return empty_map_as_error();
-                };
-                let frag_size = frag_size as u64;
-                let area = Area {
-                    x: fx as i64 * frag_size as i64,
-                    z: fy as i64 * frag_size as i64,
-                    w: frag_size,
-                    h: frag_size,
-                };
-                let mc_version = if version == "TreasureMap13" {
-                    MinecraftVersion::Java1_13
-                } else if version == "TreasureMap14" {
-                    MinecraftVersion::Java1_14
-                } else if version == "TreasureMap15" {
-                    MinecraftVersion::Java1_15
-                } else {
-                    error!("{} is not a valid treasure map version", version);
-                    return empty_map_as_error();
-                };
-                return biome_layers::generate_image_treasure_map(mc_version, area, seed);
+                return generate_treasure_map_fragment(&version, fx, fy, &seed, frag_size)
+                    .map_err(js_error);
             } else {
-                error!("{} is not a valid version", version);
-                return empty_map_as_error();
+                return Err(js_error(WasmError::new(
+                    WasmErrorKind::InvalidVersion,
+                    format!("{} is not a valid version", version),
+                )));
             }
         }
     };
-    let seed = if let Ok(s) = seed.parse() {
-        s
-    } else {
-        error!("{} is not a valid seed", seed);
-        return empty_map_as_error();
-    };
-
-    let frag_size = frag_size as u64;
-    let area = Area {
-        x: fx as i64 * frag_size as i64,
-        z: fy as i64 * frag_size as i64,
-        w: frag_size,
-        h: frag_size,
-    };
-    //let last_layer = 43;
-    //let map = cubiomes_test::call_layer(last_layer, seed, area);
-    let v = biome_layers::generate_image_up_to_layer(version, area, seed, layer, y_offset);
-
-    v
-}
-
-pub fn slime_to_color(id: u32, total: u32, grad1: &Gradient<LinSrgb>) -> [u8; 4] {
-    assert!(id <= total);
-
-    if id == 0 {
-        // black
-        [0x00, 0x00, 0x00, 0xFF]
-    } else if id == total {
-        // white
-        [0xFF, 0xFF, 0xFF, 0xFF]
-    } else {
-        let color = grad1.get(id as f32 / total as f32);
-        [
-            (color.red * 255.0) as u8,
-            (color.green * 255.0) as u8,
-            (color.blue * 255.0) as u8,
-            0xFF,
-        ]
+    if !has_biome_generator(version) {
+        return Err(js_error(WasmError::new(
+            WasmErrorKind::UnsupportedVersion,
+            format!(
+                "Biome generator for version {:?} is not implemented",
+                version
+            ),
+        )));
     }
+    let seed = parse_seed_or_error(&seed).map_err(js_error)?;
+
+    let area = fragment_area(fx, fy, frag_size);
+    // Panning the map requests the same fragments again, so reuse the images and generators
+    Ok(FRAGMENT_CACHE.with(|fragments| {
+        fragments
+            .borrow_mut()
+            .get_or_insert_with((version, seed, layer, y_offset, area), || {
+                let map = GENERATOR_CACHE.with(|generators| {
+                    generators
+                        .borrow_mut()
+                        .generate_up_to_layer(version, area, seed, layer, y_offset)
+                });
+                biome_layers::draw_layer_image(&map, version, layer)
+            })
+            .clone()
+    }))
 }
 
 #[wasm_bindgen]
+/// Returns the RGBA image of the slime chunks of these seeds, or throws a `WasmError`
 pub fn generate_fragment_slime_map(
     fx: i32,
     fy: i32,
     seeds: Vec<JsValue>,
     frag_size: usize,
-) -> Vec<u8> {
-    let seeds = seeds.into_iter().map(|s| {
-        s.as_string()
-            .unwrap_or_else(|| String::new())
-            .parse()
-            .unwrap_or_else(|s| {
-                error!("{} is not a valid seed", s);
-                panic!("{} is not a valid seed", s);
-            })
-    });
+) -> Result<Vec<u8>, JsValue> {
+    generate_fragment_slime_map_with_mode(
+        fx,
+        fy,
+        seeds,
+        frag_size,
+        slime::SlimeMapMode::Probability,
+    )
+}
 
-    let frag_size = frag_size as u64;
-    let area = Area {
-        x: fx as i64 * frag_size as i64,
-        z: fy as i64 * frag_size as i64,
-        w: frag_size,
-        h: frag_size,
-    };
-    //let last_layer = 43;
+/// Same as `generate_fragment_slime_map`, but highlighting the chunks where the candidates
+/// disagree, which are the best chunks to check next.
+#[wasm_bindgen]
+pub fn generate_fragment_slime_disagreement_map(
+    fx: i32,
+    fy: i32,
+    seeds: Vec<JsValue>,
+    frag_size: usize,
+) -> Result<Vec<u8>, JsValue> {
+    generate_fragment_slime_map_with_mode(
+        fx,
+        fy,
+        seeds,
+        frag_size,
+        slime::SlimeMapMode::Disagreement,
+    )
+}
+
+fn generate_fragment_slime_map_with_mode(
+    fx: i32,
+    fy: i32,
+    seeds: Vec<JsValue>,
+    frag_size: usize,
+    mode: slime::SlimeMapMode,
+) -> Result<Vec<u8>, JsValue> {
+    check_fragment_size(frag_size).map_err(js_error)?;
     let num_seeds = seeds.len();
+    if num_seeds > MAX_SLIME_MAP_SEEDS {
+        return Err(js_error(WasmError::new(
+            WasmErrorKind::TooManySeeds,
+            format!(
+                "Too many seeds: {}, the maximum is {}",
+                num_seeds, MAX_SLIME_MAP_SEEDS
+            ),
+        )));
+    }
+    let seeds = seeds
+        .into_iter()
+        .map(|s| parse_seed_or_error(&s.as_string().unwrap_or_default()).map(|s| s as u64))
+        .collect::<Result<Vec<u64>, WasmError>>()
+        .map_err(js_error)?;
+
+    let area = fragment_area(fx, fy, frag_size);
     if num_seeds > (0x10000) {
         // 65k seeds
         info!("This may take a while");
     }
-    let (w, h) = (area.w as usize, area.h as usize);
-    let mut map_sum = vec![0; w * h];
-    for seed in seeds {
-        let map = slime::gen_map_from_seed(area, seed);
-        for x in 0..w {
-            for z in 0..h {
-                let is_slime_chunk = map.a[(x, z)] != 0;
-                if is_slime_chunk {
-                    let i = z * h + x;
-                    map_sum[i] += 1;
-                }
-            }
-        }
-    }
+    let counts = slime::slime_probability_map(&seeds, area);
 
-    let grad1 = Gradient::new(vec![
-        LinSrgb::new(0.0, 0.2, 0.0),
-        //LinSrgb::new(1.0, 1.0, 0.0),
-        LinSrgb::new(0.0, 1.0, 0.0),
-    ]);
-    let mut v = vec![0; w * h * 4];
-    for i in 0..w * h {
-        let color = slime_to_color(map_sum[i], num_seeds as u32, &grad1);
-        v[i * 4 + 0] = color[0];
-        v[i * 4 + 1] = color[1];
-        v[i * 4 + 2] = color[2];
-        v[i * 4 + 3] = color[3];
-    }
-
-    v
+    Ok(slime::draw_slime_map(&counts, num_seeds as u32, mode))
 }
 
 #[wasm_bindgen]
 pub fn is_i64(seed: String) -> String {
-    match seed.parse::<i64>() {
+    match parse_seed(&seed) {
         Ok(_) => format!("OK"),
         Err(e) => format!("ERROR: {}", e.to_string()),
     }
@@ -498,7 +678,7 @@ pub fn add_2_n(seed: String, n: u8) -> String {
         return seed;
     }
 
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", s.wrapping_add(1 << n))
     } else {
         seed
@@ -511,7 +691,7 @@ pub fn sub_2_n(seed: String, n: u8) -> String {
         return seed;
     }
 
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", s.wrapping_sub(1 << n))
     } else {
         seed
@@ -520,7 +700,7 @@ pub fn sub_2_n(seed: String, n: u8) -> String {
 
 #[wasm_bindgen]
 pub fn gen_test_seed_base_n_bits(base: String, n: String, bits: String) -> String {
-    let base: i64 = base.parse().unwrap();
+    let base: i64 = parse_seed(&base).unwrap();
     let n: i64 = n.parse().unwrap();
     let bits: usize = bits.parse().unwrap();
 
@@ -536,9 +716,18 @@ pub fn gen_test_seed_base_n_bits(base: String, n: String, bits: String) -> Strin
     s
 }
 
+#[wasm_bindgen]
+/// Returns a synthetic SeedInfo generated from this seed, to show a demo of the seed finders, or
+/// throws a `WasmError`
+pub fn demo_world(seed: String) -> Result<String, JsValue> {
+    let seed = parse_seed_or_error(&seed).map_err(js_error)?;
+
+    Ok(serde_json::to_string(&demo_world::demo_world(seed)).unwrap())
+}
+
 #[wasm_bindgen]
 pub fn similar_biome_seed(seed: String) -> String {
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", McRng::similar_biome_seed(s))
     } else {
         seed
@@ -571,14 +760,15 @@ pub fn draw_treasure_map(o: String) -> Vec<u8> {
     }
     let mt = MapTreasure {
         parent: Rc::from(PanicMap),
+        sin_mode: SinMode::default(),
     };
 
     let tmap_no_margin = mt.get_map_from_pmap(&pmap);
 
     // tmap_no_margin has 126x126 size but the output of this function should have 128x128 size
-    let tmap = biome_layers::add_margin_to_map(&tmap_no_margin, 0);
+    let tmap = TreasureMapColors(biome_layers::add_margin_to_map(&tmap_no_margin, 0));
 
-    biome_layers::draw_treasure_map_image(&tmap)
+    biome_layers::draw_treasure_map_image(&tmap).into_raw()
 }
 
 #[wasm_bindgen]
@@ -656,6 +846,31 @@ pub fn anvil_region_to_river_seed_finder(
     serde_json::to_string(&s).unwrap()
 }
 
+#[wasm_bindgen]
+/// Returns a SeedInfo with the biomes copied from the debug screen, or throws a `WasmError`
+pub fn debug_screen_to_seed_info(text: String, version_str: String) -> Result<String, JsValue> {
+    let version: MinecraftVersion = version_str.parse().map_err(|e| {
+        js_error(WasmError::new(
+            WasmErrorKind::InvalidVersion,
+            format!("{:?} is not a valid version: {}", version_str, e),
+        ))
+    })?;
+    let biomes = debug_screen::parse_debug_screen(&text, version).map_err(|e| {
+        js_error(WasmError::new(
+            WasmErrorKind::InvalidInput,
+            format!("Failed to parse debug screen: {:?}", e),
+        ))
+    })?;
+
+    let mut s = SeedInfo::default();
+    s.version = version_str;
+    for (b_id, b_coords) in biomes {
+        s.biomes.entry(b_id).or_default().push(b_coords);
+    }
+
+    Ok(serde_json::to_string(&s).unwrap())
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtractMapResult {
@@ -678,9 +893,10 @@ pub fn extract_map_from_screenshot(width: u32, height: u32, screenshot: &[u8]) -
                 &detected_map.cropped_scaled_img,
             );
         // Convert image::GrayScale into Map
-        let palette_treasure_map = image_grayscale_into_map(palette_image);
+        let palette_treasure_map = TreasureMapColors(image_grayscale_into_map(palette_image));
 
-        let treasure_map_img = biome_layers::draw_treasure_map_image(&palette_treasure_map);
+        let treasure_map_img =
+            biome_layers::draw_treasure_map_image(&palette_treasure_map).into_raw();
         let land_water_map = biome_layers::reverse_map_treasure(&palette_treasure_map);
         // Convert land-water (only contains 0 or 1) into Vec<u8>
         let mut land_water = Vec::with_capacity(128 * 128);
@@ -944,6 +1160,46 @@ pub fn get_biome_id_to_biome_name_map() -> JsValue {
         .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BiomeTraits {
+    category: Option<i32>,
+    temperature_category: Option<i32>,
+    oceanic: bool,
+    snowy: bool,
+    jfto: bool,
+}
+
+#[wasm_bindgen]
+/// Returns HashMap<String, BiomeTraits>, with the biomes that exist in this version
+pub fn get_biome_traits(version_str: String) -> JsValue {
+    use slime_seed_finder::biome_traits;
+    let version: MinecraftVersion = match version_str.parse() {
+        Ok(s) => s,
+        Err(e) => {
+            return JsValue::from_str(&format!("{:?} is not a valid version: {}", version_str, e));
+        }
+    };
+    let mut h = HashMap::new();
+
+    for biome_id in 0..256 {
+        if !biome_traits::biome_exists_in_version(version, biome_id) {
+            continue;
+        }
+        let traits = BiomeTraits {
+            category: biome_traits::get_category(version, biome_id),
+            temperature_category: biome_traits::temperature_category(version, biome_id),
+            oceanic: biome_traits::is_oceanic(biome_id),
+            snowy: biome_traits::is_biome_snowy(biome_id),
+            jfto: biome_traits::is_biome_JFTO(biome_id),
+        };
+        h.insert(biome_id.to_string(), traits);
+    }
+
+    JsValue::from_serde(&h)
+        .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize result: {}", e)))
+}
+
 #[wasm_bindgen]
 pub fn read_fragment_biome_map(
     zip_file: web_sys::File,
@@ -1004,5 +1260,5 @@ pub fn read_fragment_biome_map(
         }
     }
 
-    biome_layers::draw_map_image(&map)
+    biome_layers::draw_map_image(&map).into_raw()
 }
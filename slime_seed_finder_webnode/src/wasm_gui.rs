@@ -1,7 +1,6 @@
 use node_bindgen::core::buffer::{ArrayBuffer, JSArrayBuffer};
 use node_bindgen::core::NjError;
 use node_bindgen::derive::node_bindgen;
-use palette::{Gradient, LinSrgb};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -13,7 +12,10 @@ use slime_seed_finder::biome_layers::GetMap;
 use slime_seed_finder::biome_layers::Map;
 use slime_seed_finder::biome_layers::MapTreasure;
 use slime_seed_finder::biome_layers::PanicMap;
+use slime_seed_finder::biome_layers::SinMode;
+use slime_seed_finder::biome_layers::TreasureMapColors;
 use slime_seed_finder::chunk::Point;
+use slime_seed_finder::java_rng::parse_seed;
 use slime_seed_finder::java_rng::JavaRng;
 use slime_seed_finder::mc_rng::McRng;
 use slime_seed_finder::seed_info;
@@ -21,6 +23,7 @@ use slime_seed_finder::seed_info::BiomeId;
 use slime_seed_finder::seed_info::MinecraftVersion;
 use slime_seed_finder::seed_info::SeedInfo;
 use slime_seed_finder::slime::SlimeChunks;
+use slime_seed_finder::wasm_error::*;
 use slime_seed_finder::*;
 
 use std::collections::HashMap;
@@ -104,17 +107,18 @@ pub fn draw_rivers(o: String) -> Serde<DrawRivers> {
     let target_map = biome_layers::map_with_river_at(
         &o.seed_info.biomes[&BiomeId(biome_id::river)],
         area_rivers,
-    );
+    )
+    .to_map();
     let m = biome_layers::reverse_map_voronoi_zoom(&target_map).unwrap_or_default();
 
     let area_hd = Area::from_coords(hd_coords.iter().copied());
-    let target_map_hd = biome_layers::map_with_river_at(&hd_coords, area_hd);
+    let target_map_hd = biome_layers::map_with_river_at(&hd_coords, area_hd).to_map();
 
     Serde(DrawRivers {
         l43_area: target_map_hd.area(),
-        l43: biome_layers::draw_map_image(&target_map_hd),
+        l43: biome_layers::draw_map_image(&target_map_hd).into_raw(),
         l42_area: m.area(),
-        l42: biome_layers::draw_map_image(&m),
+        l42: biome_layers::draw_map_image(&m).into_raw(),
     })
 }
 
@@ -139,7 +143,7 @@ pub fn generate_rivers_candidate(o: String) -> Serde<DrawRivers> {
         l42: biome_layers::generate_image_up_to_layer(
             o.version.parse().unwrap(),
             o.area,
-            o.seed.parse().unwrap(),
+            parse_seed(&o.seed).unwrap(),
             magic_layer_river_candidate,
             y_offset,
         ),
@@ -163,10 +167,14 @@ pub fn count_candidates(o: Serde<Options>) -> String {
 //#[node_bindgen]
 pub fn draw_reverse_voronoi(o: Serde<Options>) -> Vec<u8> {
     let o = o.0;
-    let target_map = seed_info::biomes_to_map(o.seed_info.biomes);
+    // Only draw the biggest cluster of observations
+    let target_map = seed_info::biomes_to_maps(o.seed_info.biomes)
+        .into_iter()
+        .max_by_key(|m| m.area().w * m.area().h)
+        .unwrap_or_default();
 
     let m = biome_layers::reverse_map_voronoi_zoom(&target_map).unwrap_or_default();
-    biome_layers::draw_map_image(&m)
+    biome_layers::draw_map_image(&m).into_raw()
 }
 
 #[node_bindgen]
@@ -279,7 +287,57 @@ pub fn find_seed_rivers(o: Options) -> Vec<i64> {
     }
 }
 
+// WasmError is defined in the main crate, so it cannot implement Into<NjError>
+pub fn nj_error(e: WasmError) -> NjError {
+    NjError::Other(serde_json::to_string(&e).unwrap_or(e.message))
+}
+
+fn fragment_area(fx: i32, fy: i32, frag_size: usize) -> Area {
+    let frag_size = frag_size as u64;
+    Area {
+        x: fx as i64 * frag_size as i64,
+        z: fy as i64 * frag_size as i64,
+        w: frag_size,
+        h: frag_size,
+    }
+}
+
+// Versions that can be parsed but have no biome generator
+fn has_biome_generator(version: MinecraftVersion) -> bool {
+    !matches!(
+        version,
+        MinecraftVersion::JavaAlpha1_2_5 | MinecraftVersion::JavaBeta | MinecraftVersion::Java1_2
+    )
+}
+
+fn generate_treasure_map_fragment(
+    version: &str,
+    fx: i32,
+    fy: i32,
+    seed: &str,
+    frag_size: usize,
+) -> Result<Vec<u8>, WasmError> {
+    let seed = parse_seed_or_error(seed)?;
+    let area = fragment_area(fx, fy, frag_size);
+    let mc_version = match version {
+        "TreasureMap13" => MinecraftVersion::Java1_13,
+        "TreasureMap14" => MinecraftVersion::Java1_14,
+        "TreasureMap15" => MinecraftVersion::Java1_15,
+        _ => {
+            return Err(WasmError::new(
+                WasmErrorKind::InvalidVersion,
+                format!("{} is not a valid treasure map version", version),
+            ))
+        }
+    };
+
+    Ok(biome_layers::generate_image_treasure_map(
+        mc_version, area, seed,
+    ))
+}
+
 //#[node_bindgen]
+/// Returns the RGBA image of a fragment, or an error if the arguments are not valid
 pub fn generate_fragment(
     version: String,
     fx: i32,
@@ -287,50 +345,17 @@ pub fn generate_fragment(
     seed: String,
     frag_size: usize,
     y_offset: u32,
-) -> Vec<u8> {
-    let empty_map_as_error = || vec![0; frag_size * frag_size * 4];
-    let version1: MinecraftVersion = match version.parse() {
-        Ok(s) => s,
-        Err(_) => {
-            if version.starts_with("TreasureMap") {
-                let seed = if let Ok(s) = seed.parse() {
-                    s
-                } else {
-                    //console!(error, format!("{} is not a valid seed", seed));
-                    return empty_map_as_error();
-                };
-                let frag_size = frag_size as u64;
-                let area = Area {
-                    x: fx as i64 * frag_size as i64,
-                    z: fy as i64 * frag_size as i64,
-                    w: frag_size,
-                    h: frag_size,
-                };
-                let mc_version = if version == "TreasureMap13" {
-                    MinecraftVersion::Java1_13
-                } else if version == "TreasureMap14" {
-                    MinecraftVersion::Java1_14
-                } else if version == "TreasureMap15" {
-                    MinecraftVersion::Java1_15
-                } else {
-                    //console!(
-                    //    error,
-                    //    format!("{} is not a valid treasure map version", version)
-                    //);
-                    return empty_map_as_error();
-                };
-                return biome_layers::generate_image_treasure_map(mc_version, area, seed);
-            } else {
-                //console!(error, format!("{} is not a valid version", version));
-                return empty_map_as_error();
-            }
-        }
+) -> Result<Vec<u8>, WasmError> {
+    // Treasure maps do not use the layer
+    let num_layers = match version.parse::<MinecraftVersion>() {
+        Ok(v) if has_biome_generator(v) => v.num_layers(),
+        _ => 0,
     };
-    let num_layers = version1.num_layers();
     generate_fragment_up_to_layer(version, fx, fy, seed, frag_size, num_layers, y_offset)
 }
 
 //#[node_bindgen]
+/// Returns the RGBA image of a fragment, or an error if the arguments are not valid
 pub fn generate_fragment_up_to_layer(
     version: String,
     fx: i32,
@@ -339,149 +364,83 @@ pub fn generate_fragment_up_to_layer(
     frag_size: usize,
     layer: u32,
     y_offset: u32,
-) -> Vec<u8> {
-    let empty_map_as_error = || vec![0; frag_size * frag_size * 4];
-    let frag_size = frag_size as usize;
-    let version = match version.parse() {
+) -> Result<Vec<u8>, WasmError> {
+    check_fragment_size(frag_size)?;
+    let version: MinecraftVersion = match version.parse() {
         Ok(s) => s,
         Err(_) => {
             if version.starts_with("TreasureMap") {
-                let seed = if let Ok(s) = seed.parse() {
-                    s
-                } else {
-                    //console!(error, format!("{} is not a valid seed", seed));
-                    return empty_map_as_error();
-                };
-                let frag_size = frag_size as u64;
-                let area = Area {
-                    x: fx as i64 * frag_size as i64,
-                    z: fy as i64 * frag_size as i64,
-                    w: frag_size,
-                    h: frag_size,
-                };
-                let mc_version = if version == "TreasureMap13" {
-                    MinecraftVersion::Java1_13
-                } else if version == "TreasureMap14" {
-                    MinecraftVersion::Java1_14
-                } else if version == "TreasureMap15" {
-                    MinecraftVersion::Java1_15
-                } else {
-                    //console!(
-                    //    error,
-                    //    format!("{} is not a valid treasure map version", version)
-                    //);
-                    return empty_map_as_error();
-                };
-                return biome_layers::generate_image_treasure_map(mc_version, area, seed);
+                return generate_treasure_map_fragment(&version, fx, fy, &seed, frag_size);
             } else {
-                //console!(error, format!("{} is not a valid version", version));
-                return empty_map_as_error();
+                return Err(WasmError::new(
+                    WasmErrorKind::InvalidVersion,
+                    format!("{} is not a valid version", version),
+                ));
             }
         }
     };
-    let seed = if let Ok(s) = seed.parse() {
-        s
-    } else {
-        //console!(error, format!("{} is not a valid seed", seed));
-        return empty_map_as_error();
-    };
+    if !has_biome_generator(version) {
+        return Err(WasmError::new(
+            WasmErrorKind::UnsupportedVersion,
+            format!(
+                "Biome generator for version {:?} is not implemented",
+                version
+            ),
+        ));
+    }
+    let seed = parse_seed_or_error(&seed)?;
 
-    let frag_size = frag_size as u64;
-    let area = Area {
-        x: fx as i64 * frag_size as i64,
-        z: fy as i64 * frag_size as i64,
-        w: frag_size,
-        h: frag_size,
-    };
+    let area = fragment_area(fx, fy, frag_size);
     //let last_layer = 43;
     //let map = cubiomes_test::call_layer(last_layer, seed, area);
     let v = biome_layers::generate_image_up_to_layer(version, area, seed, layer, y_offset);
 
-    v
-}
-
-pub fn slime_to_color(id: u32, total: u32, grad1: &Gradient<LinSrgb>) -> [u8; 4] {
-    assert!(id <= total);
-
-    if id == 0 {
-        // black
-        [0x00, 0x00, 0x00, 0xFF]
-    } else if id == total {
-        // white
-        [0xFF, 0xFF, 0xFF, 0xFF]
-    } else {
-        let color = grad1.get(id as f32 / total as f32);
-        [
-            (color.red * 255.0) as u8,
-            (color.green * 255.0) as u8,
-            (color.blue * 255.0) as u8,
-            0xFF,
-        ]
-    }
+    Ok(v)
 }
 
 //#[node_bindgen]
+/// Returns the RGBA image of the slime chunks of these seeds, or an error if the arguments are
+/// not valid
 pub fn generate_fragment_slime_map(
     fx: i32,
     fy: i32,
     seeds: Vec<String>,
     frag_size: usize,
-) -> Vec<u8> {
-    let seeds = seeds.into_iter().map(|s| {
-        s.parse().unwrap_or_else(|s| {
-            //console!(error, format!("{} is not a valid seed", s));
-            panic!("{} is not a valid seed", s);
-        })
-    });
+) -> Result<Vec<u8>, WasmError> {
+    check_fragment_size(frag_size)?;
+    let num_seeds = seeds.len();
+    if num_seeds > MAX_SLIME_MAP_SEEDS {
+        return Err(WasmError::new(
+            WasmErrorKind::TooManySeeds,
+            format!(
+                "Too many seeds: {}, the maximum is {}",
+                num_seeds, MAX_SLIME_MAP_SEEDS
+            ),
+        ));
+    }
+    let seeds = seeds
+        .iter()
+        .map(|s| parse_seed_or_error(s).map(|s| s as u64))
+        .collect::<Result<Vec<u64>, WasmError>>()?;
 
-    let frag_size = frag_size as u64;
-    let area = Area {
-        x: fx as i64 * frag_size as i64,
-        z: fy as i64 * frag_size as i64,
-        w: frag_size,
-        h: frag_size,
-    };
+    let area = fragment_area(fx, fy, frag_size);
     //let last_layer = 43;
-    let num_seeds = seeds.len();
     if num_seeds > (0x10000) {
         // 65k seeds
         //console!(log, "This may take a while");
     }
-    let (w, h) = (area.w as usize, area.h as usize);
-    let mut map_sum = vec![0; w * h];
-    for seed in seeds {
-        let map = slime::gen_map_from_seed(area, seed);
-        for x in 0..w {
-            for z in 0..h {
-                let is_slime_chunk = map.a[(x, z)] != 0;
-                if is_slime_chunk {
-                    let i = z * h + x;
-                    map_sum[i] += 1;
-                }
-            }
-        }
-    }
-
-    let grad1 = Gradient::new(vec![
-        LinSrgb::new(0.0, 0.2, 0.0),
-        //LinSrgb::new(1.0, 1.0, 0.0),
-        LinSrgb::new(0.0, 1.0, 0.0),
-    ]);
-    let mut v = vec![0; w * h * 4];
-    for i in 0..w * h {
-        let color = slime_to_color(map_sum[i], num_seeds as u32, &grad1);
-        v[i * 4 + 0] = color[0];
-        v[i * 4 + 1] = color[1];
-        v[i * 4 + 2] = color[2];
-        v[i * 4 + 3] = color[3];
-    }
+    let counts = slime::slime_probability_map(&seeds, area);
 
-    v
+    Ok(slime::draw_slime_map(
+        &counts,
+        num_seeds as u32,
+        slime::SlimeMapMode::Probability,
+    ))
 }
 
 #[node_bindgen]
 pub fn is_i64(seed: String) -> String {
-    match seed.parse::<i64>() {
+    match parse_seed(&seed) {
         Ok(_) => format!("OK"),
         Err(e) => format!("ERROR: {}", e.to_string()),
     }
@@ -493,7 +452,7 @@ pub fn add_2_n(seed: String, n: u8) -> String {
         return seed;
     }
 
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", s.wrapping_add(1 << n))
     } else {
         seed
@@ -506,7 +465,7 @@ pub fn sub_2_n(seed: String, n: u8) -> String {
         return seed;
     }
 
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", s.wrapping_sub(1 << n))
     } else {
         seed
@@ -515,7 +474,7 @@ pub fn sub_2_n(seed: String, n: u8) -> String {
 
 #[node_bindgen(name = "gen_test_seed_base_n_bits")]
 pub fn gen_test_seed_base_n_bits(base: String, n: String, bits: String) -> String {
-    let base: i64 = base.parse().unwrap();
+    let base: i64 = parse_seed(&base).unwrap();
     let n: i64 = n.parse().unwrap();
     let bits: usize = bits.parse().unwrap();
 
@@ -533,7 +492,7 @@ pub fn gen_test_seed_base_n_bits(base: String, n: String, bits: String) -> Strin
 
 #[node_bindgen]
 pub fn similar_biome_seed(seed: String) -> String {
-    if let Ok(s) = seed.parse::<i64>() {
+    if let Ok(s) = parse_seed(&seed) {
         format!("{}", McRng::similar_biome_seed(s))
     } else {
         seed
@@ -566,14 +525,15 @@ pub fn draw_treasure_map(o: String) -> Vec<u8> {
     }
     let mt = MapTreasure {
         parent: Rc::from(PanicMap),
+        sin_mode: SinMode::default(),
     };
 
     let tmap_no_margin = mt.get_map_from_pmap(&pmap);
 
     // tmap_no_margin has 126x126 size but the output of this function should have 128x128 size
-    let tmap = biome_layers::add_margin_to_map(&tmap_no_margin, 0);
+    let tmap = TreasureMapColors(biome_layers::add_margin_to_map(&tmap_no_margin, 0));
 
-    biome_layers::draw_treasure_map_image(&tmap)
+    biome_layers::draw_treasure_map_image(&tmap).into_raw()
 }
 
 #[node_bindgen]
@@ -678,9 +638,10 @@ pub fn extract_map_from_screenshot(
                 &detected_map.cropped_scaled_img,
             );
         // Convert image::GrayScale into Map
-        let palette_treasure_map = image_grayscale_into_map(palette_image);
+        let palette_treasure_map = TreasureMapColors(image_grayscale_into_map(palette_image));
 
-        let treasure_map_img = biome_layers::draw_treasure_map_image(&palette_treasure_map);
+        let treasure_map_img =
+            biome_layers::draw_treasure_map_image(&palette_treasure_map).into_raw();
         let land_water_map = biome_layers::reverse_map_treasure(&palette_treasure_map);
         // Convert land-water (only contains 0 or 1) into Vec<u8>
         let mut land_water = Vec::with_capacity(128 * 128);
@@ -951,7 +912,7 @@ pub async fn read_fragment_biome_map(
         }
     }
 
-    let v = biome_layers::draw_map_image(&map);
+    let v = biome_layers::draw_map_image(&map).into_raw();
 
     log::debug!("Creating arraybuffer:");
 
@@ -15,7 +15,7 @@ use std::path::Path;
 use std::fs::OpenOptions;
 use log::*;
 use crate::biome_layers::Area;
-use crate::biome_layers::is_oceanic;
+use crate::biome_traits::is_oceanic;
 use crate::biome_info::biome_id;
 use crate::biome_info::UNKNOWN_BIOME_ID;
 use crate::chunk::Point;
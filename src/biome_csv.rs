@@ -0,0 +1,129 @@
+//! Import quarter-scale biome evidence from CSV files
+//!
+//! Each line is one biome, `x,z,biome_id`, where `x` and `z` are block coordinates and
+//! `biome_id` is the numeric biome id. The biome is the quarter-scale biome of the 4x4 block area that contains the block,
+//! as generated by the layer before the voronoi zoom, so it can be used as evidence directly,
+//! without going through `reverse_map_voronoi_zoom`. Empty lines, lines starting with `#`, and a
+//! header line that does not start with a number are ignored.
+
+use crate::biome_layers::Area;
+use crate::biome_layers::SparseMap;
+use crate::chunk::Point4;
+use crate::seed_info::BiomeId;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+#[derive(Debug)]
+pub enum BiomeCsvError {
+    Io(std::io::Error),
+    /// Line number (starting at 1) and contents of a line that could not be parsed
+    Parse(usize, String),
+    /// The same quarter-scale position appears twice with different biomes
+    Conflict(Point4),
+}
+
+impl From<std::io::Error> for BiomeCsvError {
+    fn from(x: std::io::Error) -> Self {
+        BiomeCsvError::Io(x)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(i64, i64, i32)> {
+    let mut parts = line.split(',').map(|x| x.trim());
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    let biome_id = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((x, z, biome_id))
+}
+
+/// Read a quarter-scale biome CSV into a list of quarter-scale biomes
+pub fn read_biomes_csv<R: BufRead>(r: R) -> Result<HashMap<Point4, BiomeId>, BiomeCsvError> {
+    let mut biomes = HashMap::new();
+
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (x, z, biome_id) = match parse_line(line) {
+            Some(x) => x,
+            // Allow a header in the first line
+            None if i == 0 => continue,
+            None => return Err(BiomeCsvError::Parse(i + 1, line.to_string())),
+        };
+        // All the blocks of a 4x4 area have the same quarter-scale biome
+        let p = Point4 {
+            x: x >> 2,
+            z: z >> 2,
+        };
+        if let Some(old) = biomes.insert(p, BiomeId(biome_id)) {
+            if old != BiomeId(biome_id) {
+                return Err(BiomeCsvError::Conflict(p));
+            }
+        }
+    }
+
+    Ok(biomes)
+}
+
+/// Convert a list of quarter-scale biomes into a `SparseMap`. Returns `None` if `biomes` is
+/// empty.
+pub fn biomes_to_sparse_map(biomes: &HashMap<Point4, BiomeId>) -> Option<SparseMap> {
+    if biomes.is_empty() {
+        return None;
+    }
+    let area = Area::from_coords4(biomes.keys().copied());
+    let mut m = SparseMap::new(area);
+    for (p, biome_id) in biomes {
+        m.a[((p.x - area.x) as usize, (p.z - area.z) as usize)] = Some(biome_id.0);
+    }
+
+    Some(m)
+}
+
+/// Read a quarter-scale biome CSV into a `SparseMap`
+pub fn read_biomes_csv_sparse_map<R: BufRead>(r: R) -> Result<Option<SparseMap>, BiomeCsvError> {
+    read_biomes_csv(r).map(|biomes| biomes_to_sparse_map(&biomes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_csv() {
+        let csv = "x,z,biome\n0,0,1\n4,0,7\n\n# comment\n-4,-8,24\n";
+        let m = read_biomes_csv_sparse_map(csv.as_bytes()).unwrap().unwrap();
+        assert_eq!(
+            m.area(),
+            Area {
+                x: -1,
+                z: -2,
+                w: 3,
+                h: 3
+            }
+        );
+        assert_eq!(m.a[(1, 2)], Some(1));
+        assert_eq!(m.a[(2, 2)], Some(7));
+        assert_eq!(m.a[(0, 0)], Some(24));
+        assert_eq!(m.a[(0, 1)], None);
+    }
+
+    #[test]
+    fn read_csv_errors() {
+        assert!(matches!(
+            read_biomes_csv("0,0,1\n1,0,2\n".as_bytes()),
+            Err(BiomeCsvError::Conflict(Point4 { x: 0, z: 0 }))
+        ));
+        assert!(matches!(
+            read_biomes_csv("0,0,1\n0,1\n".as_bytes()),
+            Err(BiomeCsvError::Parse(2, _))
+        ));
+        assert!(read_biomes_csv_sparse_map("".as_bytes()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+//! Export generated biome maps as CSV or JSON lines
+//!
+//! The CSV format has a header line followed by one `x,z,biome` line per tile, the same columns
+//! read by `biome_csv::read_biomes_csv` when the biomes are numeric, but using the coordinates of
+//! the tiles of the map instead of block coordinates. The JSON lines format has one
+//! `{"x":0,"z":0,"biome":1}` object per line. The biomes can be written as our numeric ids, or as
+//! the `minecraft:*` resource names used since 1.18, so the output can be used by other tools
+//! without translating the ids.
+
+use crate::biome_info::biome_resource_name;
+use crate::biome_layers::Map;
+use crate::candidate_export::ExportFormat;
+use serde::Serialize;
+use std::io::Write;
+
+const CSV_HEADER: &str = "x,z,biome";
+
+/// How to write the biome of each tile
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BiomeIdFormat {
+    /// Numeric id, as used by the biome layers
+    Numeric,
+    /// Resource name used since 1.18, see `biome_resource_name`. Unknown biomes are written
+    /// using their numeric id.
+    ResourceName,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ExportedBiome {
+    Id(i32),
+    Name(&'static str),
+}
+
+impl ExportedBiome {
+    fn new(biome_id: i32, ids: BiomeIdFormat) -> Self {
+        match (ids, biome_resource_name(biome_id)) {
+            (BiomeIdFormat::ResourceName, Some(name)) => ExportedBiome::Name(name),
+            _ => ExportedBiome::Id(biome_id),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedTile {
+    x: i64,
+    z: i64,
+    biome: ExportedBiome,
+}
+
+/// Write all the tiles of the map, sorted by z and then by x
+pub fn write_biome_map<W: Write>(
+    mut w: W,
+    map: &Map,
+    format: ExportFormat,
+    ids: BiomeIdFormat,
+) -> Result<(), std::io::Error> {
+    let area = map.area();
+    if format == ExportFormat::Csv {
+        writeln!(w, "{}", CSV_HEADER)?;
+    }
+    for z in area.z..area.z + area.h as i64 {
+        for x in area.x..area.x + area.w as i64 {
+            let biome = ExportedBiome::new(map.get(x, z), ids);
+            match format {
+                ExportFormat::Csv => match biome {
+                    ExportedBiome::Id(id) => writeln!(w, "{},{},{}", x, z, id)?,
+                    ExportedBiome::Name(name) => writeln!(w, "{},{},{}", x, z, name)?,
+                },
+                ExportFormat::JsonLines => {
+                    serde_json::to_writer(&mut w, &ExportedTile { x, z, biome })?;
+                    writeln!(w)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_info::biome_id;
+    use crate::biome_layers::Area;
+
+    fn example_map() -> Map {
+        let area = Area {
+            x: -1,
+            z: 10,
+            w: 2,
+            h: 2,
+        };
+        let mut map = Map::new(area);
+        map.a[(0, 0)] = biome_id::extremeHills;
+        map.a[(1, 0)] = biome_id::plains;
+        map.a[(0, 1)] = biome_id::desertHills;
+        map.a[(1, 1)] = 255;
+
+        map
+    }
+
+    fn write_to_string(format: ExportFormat, ids: BiomeIdFormat) -> String {
+        let mut buf = vec![];
+        write_biome_map(&mut buf, &example_map(), format, ids).unwrap();
+
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn resource_names() {
+        assert_eq!(
+            biome_resource_name(biome_id::plains),
+            Some("minecraft:plains")
+        );
+        assert_eq!(
+            biome_resource_name(biome_id::extremeHills),
+            biome_resource_name(191)
+        );
+        assert_eq!(
+            biome_resource_name(biome_id::giantSpruceTaigaHills),
+            Some("minecraft:old_growth_spruce_taiga")
+        );
+        assert_eq!(biome_resource_name(-1), None);
+        // All the known biomes have a resource name
+        for id in 0..256 {
+            assert_eq!(
+                biome_resource_name(id).is_some(),
+                crate::biome_info::biome_name(id).is_some(),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            write_to_string(ExportFormat::Csv, BiomeIdFormat::Numeric),
+            "x,z,biome\n-1,10,3\n0,10,1\n-1,11,17\n0,11,255\n"
+        );
+        assert_eq!(
+            write_to_string(ExportFormat::Csv, BiomeIdFormat::ResourceName),
+            "x,z,biome\n-1,10,minecraft:windswept_hills\n0,10,minecraft:plains\n-1,11,minecraft:desert\n0,11,255\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let s = write_to_string(ExportFormat::JsonLines, BiomeIdFormat::ResourceName);
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            r#"{"x":-1,"z":10,"biome":"minecraft:windswept_hills"}"#
+        );
+        assert_eq!(lines[3], r#"{"x":0,"z":11,"biome":255}"#);
+        let s = write_to_string(ExportFormat::JsonLines, BiomeIdFormat::Numeric);
+        assert!(s.starts_with(r#"{"x":-1,"z":10,"biome":3}"#));
+    }
+}
@@ -0,0 +1,165 @@
+//! Import biome maps from images
+//!
+//! The images rendered by this crate use the same biome colors as AMIDST, so the biome id of
+//! each pixel can be found by looking up its color in the palette. The imported map can then be
+//! used as evidence, at full resolution for the images rendered by this crate, or at quarter
+//! scale for AMIDST images where each pixel is a 4x4 block area.
+
+use crate::biome_layers::biome_to_color;
+use crate::biome_layers::Area;
+use crate::biome_layers::ImageBuffer;
+use crate::biome_layers::ImageOrientation;
+use crate::biome_layers::SparseMap;
+use crate::chunk::Point;
+use crate::chunk::Point4;
+use crate::seed_info::BiomeId;
+use std::collections::HashMap;
+
+/// Color of each biome, as drawn by `biome_to_color`. Colors shared by more than one biome id,
+/// such as the black used for biomes that do not exist, are not included because the biome
+/// cannot be known.
+pub fn biome_palette() -> HashMap<[u8; 3], BiomeId> {
+    let mut palette = HashMap::new();
+    let mut ambiguous = vec![];
+
+    for biome_id in 0..256 {
+        let [r, g, b, _a] = biome_to_color(biome_id);
+        if palette.insert([r, g, b], BiomeId(biome_id)).is_some() {
+            ambiguous.push([r, g, b]);
+        }
+    }
+    for color in ambiguous {
+        palette.remove(&color);
+    }
+
+    palette
+}
+
+/// Convert an image into a map of biome ids. The top-left pixel is at `(x, z)`, in the units of
+/// the map. Transparent pixels and pixels with a color not present in the palette are unknown.
+pub fn image_to_sparse_map(
+    img: &ImageBuffer,
+    x: i64,
+    z: i64,
+    orientation: ImageOrientation,
+    palette: &HashMap<[u8; 3], BiomeId>,
+) -> SparseMap {
+    let (w, h) = match orientation {
+        ImageOrientation::XRight => (img.width, img.height),
+        ImageOrientation::ZRight => (img.height, img.width),
+    };
+    let mut m = SparseMap::new(Area {
+        x,
+        z,
+        w: w as u64,
+        h: h as u64,
+    });
+    for i in 0..w {
+        for j in 0..h {
+            let [r, g, b, a] = match orientation {
+                ImageOrientation::XRight => img.get_pixel(i, j),
+                ImageOrientation::ZRight => img.get_pixel(j, i),
+            };
+            if a == 0 {
+                continue;
+            }
+            m.a[(i, j)] = palette.get(&[r, g, b]).map(|biome_id| biome_id.0);
+        }
+    }
+
+    m
+}
+
+/// Number of tiles of the map with a known biome
+pub fn known_tiles(m: &SparseMap) -> usize {
+    m.a.iter().filter(|x| x.is_some()).count()
+}
+
+/// Known biomes of a full resolution map, in the format used by `SeedInfo::biomes`
+pub fn sparse_map_to_biomes(m: &SparseMap) -> HashMap<BiomeId, Vec<Point>> {
+    let mut biomes: HashMap<BiomeId, Vec<Point>> = HashMap::new();
+    for ((i, j), biome_id) in m.a.indexed_iter() {
+        if let Some(biome_id) = biome_id {
+            biomes.entry(BiomeId(*biome_id)).or_default().push(Point {
+                x: m.x + i as i64,
+                z: m.z + j as i64,
+            });
+        }
+    }
+
+    biomes
+}
+
+/// Known biomes of a quarter-scale map, in the format used by `SeedInfo::biomes_quarter_scale`
+pub fn sparse_map_to_biomes_quarter_scale(m: &SparseMap) -> HashMap<BiomeId, Vec<Point4>> {
+    sparse_map_to_biomes(m)
+        .into_iter()
+        .map(|(biome_id, points)| {
+            let points = points
+                .into_iter()
+                .map(|p| Point4 { x: p.x, z: p.z })
+                .collect();
+            (biome_id, points)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_layers::draw_map_image;
+    use crate::biome_layers::draw_map_image_with;
+    use crate::biome_layers::generate;
+    use crate::seed_info::MinecraftVersion;
+
+    #[test]
+    fn palette_round_trip() {
+        let palette = biome_palette();
+        for biome_id in 0..256 {
+            let [r, g, b, _a] = biome_to_color(biome_id);
+            if let Some(found) = palette.get(&[r, g, b]) {
+                assert_eq!(*found, BiomeId(biome_id));
+            }
+        }
+        // Biomes that exist in 1.16 and 1.18 must have a unique color
+        assert_eq!(palette[&[0, 0, 112]], BiomeId(0));
+        assert_eq!(palette[&[181, 219, 136]], BiomeId(129));
+        assert_eq!(palette[&[96, 164, 69]], BiomeId(179));
+        assert!(!palette.contains_key(&[0, 0, 0]));
+    }
+
+    #[test]
+    fn rendered_map_round_trip() {
+        let area = Area {
+            x: -100,
+            z: 37,
+            w: 60,
+            h: 45,
+        };
+        let palette = biome_palette();
+        for &version in &[MinecraftVersion::Java1_7, MinecraftVersion::Java1_16] {
+            let map = generate(version, area, 1234, 0);
+            let img = draw_map_image(&map);
+            let m = image_to_sparse_map(&img, area.x, area.z, ImageOrientation::XRight, &palette);
+            assert_eq!(known_tiles(&m), (area.w * area.h) as usize);
+            assert_eq!(m.unwrap_or(-1), map);
+
+            let img = draw_map_image_with(&map, ImageOrientation::ZRight, biome_to_color);
+            let m = image_to_sparse_map(&img, area.x, area.z, ImageOrientation::ZRight, &palette);
+            assert_eq!(m.unwrap_or(-1), map);
+        }
+    }
+
+    #[test]
+    fn unknown_pixels() {
+        let mut img = ImageBuffer::new(2, 1);
+        img.set_pixel(0, 0, biome_to_color(1));
+        img.set_pixel(1, 0, [12, 34, 56, 255]);
+        let m = image_to_sparse_map(&img, 4, -3, ImageOrientation::XRight, &biome_palette());
+        assert_eq!(m.a[(0, 0)], Some(1));
+        assert_eq!(m.a[(1, 0)], None);
+        let biomes = sparse_map_to_biomes_quarter_scale(&m);
+        assert_eq!(biomes[&BiomeId(1)], vec![Point4 { x: 4, z: -3 }]);
+        assert_eq!(biomes.len(), 1);
+    }
+}
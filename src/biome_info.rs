@@ -1,8 +1,11 @@
 use crate::biome_layers::Biome;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
 
-pub fn biome_name(biome_id: i32) -> Option<&'static str> {
+pub fn biome_name<B: Into<BiomeId>>(biome_id: B) -> Option<&'static str> {
     use biome_id::*;
-    let name = match biome_id {
+    let biome_id: BiomeId = biome_id.into();
+    let name = match biome_id.0 {
         ocean => "Ocean",
         plains => "Plains",
         desert => "Desert",
@@ -104,6 +107,158 @@ pub fn biome_name(biome_id: i32) -> Option<&'static str> {
     Some(name)
 }
 
+/// Inverse of `biome_name`. Accepts the names shown in the F3 screen, with or without the
+/// `minecraft:` prefix, ignoring case, spaces and underscores: "minecraft:windswept_hills",
+/// "Windswept Hills" and "windsweptHills" are all valid.
+pub fn biome_id_from_name(name: &str) -> Option<i32> {
+    fn normalize(name: &str) -> String {
+        name.chars().filter(|c| *c != '_' && *c != ' ').flat_map(|c| c.to_lowercase()).collect()
+    }
+
+    let name = name.trim();
+    let name = normalize(name.strip_prefix("minecraft:").unwrap_or(name));
+    (0..256).find(|id| biome_name(*id).is_some_and(|x| normalize(x) == name))
+}
+
+/// Resource name of the biome used since 1.18, for example "minecraft:windswept_hills" for
+/// `extremeHills`. Biomes removed in 1.18 use the name of the biome that replaces them when a
+/// world is upgraded to 1.18, so `desertHills` is "minecraft:desert". Returns `None` if this id is
+/// not a biome.
+pub fn biome_resource_name(biome_id: i32) -> Option<&'static str> {
+    use biome_id::*;
+    let name = match biome_id {
+        ocean => "minecraft:ocean",
+        plains => "minecraft:plains",
+        desert => "minecraft:desert",
+        extremeHills => "minecraft:windswept_hills",
+        forest => "minecraft:forest",
+        taiga => "minecraft:taiga",
+        swampland => "minecraft:swamp",
+        river => "minecraft:river",
+        hell => "minecraft:nether_wastes",
+        sky => "minecraft:the_end",
+        frozenOcean => "minecraft:frozen_ocean",
+        frozenRiver => "minecraft:frozen_river",
+        icePlains => "minecraft:snowy_plains",
+        iceMountains => "minecraft:snowy_plains",
+        mushroomIsland => "minecraft:mushroom_fields",
+        mushroomIslandShore => "minecraft:mushroom_fields",
+        beach => "minecraft:beach",
+        desertHills => "minecraft:desert",
+        forestHills => "minecraft:forest",
+        taigaHills => "minecraft:taiga",
+        extremeHillsEdge => "minecraft:windswept_hills",
+        jungle => "minecraft:jungle",
+        jungleHills => "minecraft:jungle",
+        jungleEdge => "minecraft:sparse_jungle",
+        deepOcean => "minecraft:deep_ocean",
+        stoneBeach => "minecraft:stony_shore",
+        coldBeach => "minecraft:snowy_beach",
+        birchForest => "minecraft:birch_forest",
+        birchForestHills => "minecraft:birch_forest",
+        roofedForest => "minecraft:dark_forest",
+        coldTaiga => "minecraft:snowy_taiga",
+        coldTaigaHills => "minecraft:snowy_taiga",
+        megaTaiga => "minecraft:old_growth_pine_taiga",
+        megaTaigaHills => "minecraft:old_growth_pine_taiga",
+        extremeHillsPlus => "minecraft:windswept_forest",
+        savanna => "minecraft:savanna",
+        savannaPlateau => "minecraft:savanna_plateau",
+        mesa => "minecraft:badlands",
+        mesaPlateau_F => "minecraft:wooded_badlands",
+        mesaPlateau => "minecraft:badlands",
+        skyIslandLow => "minecraft:small_end_islands",
+        skyIslandMedium => "minecraft:end_midlands",
+        skyIslandHigh => "minecraft:end_highlands",
+        skyIslandBarren => "minecraft:end_barrens",
+        warmOcean => "minecraft:warm_ocean",
+        lukewarmOcean => "minecraft:lukewarm_ocean",
+        coldOcean => "minecraft:cold_ocean",
+        warmDeepOcean => "minecraft:warm_ocean",
+        lukewarmDeepOcean => "minecraft:deep_lukewarm_ocean",
+        coldDeepOcean => "minecraft:deep_cold_ocean",
+        frozenDeepOcean => "minecraft:deep_frozen_ocean",
+        127 => "minecraft:the_void",
+        sunflowerPlains => "minecraft:sunflower_plains",
+        desertLakes => "minecraft:desert",
+        gravellyMountains => "minecraft:windswept_gravelly_hills",
+        flowerForest => "minecraft:flower_forest",
+        taigaMountains => "minecraft:taiga",
+        swampHills => "minecraft:swamp",
+        iceSpikes => "minecraft:ice_spikes",
+        modifiedJungle => "minecraft:jungle",
+        modifiedJungleEdge => "minecraft:sparse_jungle",
+        tallBirchForest => "minecraft:old_growth_birch_forest",
+        tallBirchHills => "minecraft:old_growth_birch_forest",
+        darkForestHills => "minecraft:dark_forest",
+        snowyTaigaMountains => "minecraft:snowy_taiga",
+        giantSpruceTaiga => "minecraft:old_growth_spruce_taiga",
+        giantSpruceTaigaHills => "minecraft:old_growth_spruce_taiga",
+        modifiedGravellyMountains => "minecraft:windswept_gravelly_hills",
+        shatteredSavanna => "minecraft:windswept_savanna",
+        shatteredSavannaPlateau => "minecraft:windswept_savanna",
+        erodedBadlands => "minecraft:eroded_badlands",
+        modifiedWoodedBadlandsPlateau => "minecraft:wooded_badlands",
+        modifiedBadlandsPlateau => "minecraft:badlands",
+        bambooJungle => "minecraft:bamboo_jungle",
+        bambooJungleHills => "minecraft:bamboo_jungle",
+        174 => "minecraft:dripstone_caves",
+        175 => "minecraft:frozen_peaks",
+        176 => "minecraft:grove",
+        177 => "minecraft:jagged_peaks",
+        178 => "minecraft:lush_caves",
+        179 => "minecraft:meadow",
+        180 => "minecraft:nether_wastes",
+        181 => "minecraft:old_growth_birch_forest",
+        182 => "minecraft:old_growth_pine_taiga",
+        183 => "minecraft:old_growth_spruce_taiga",
+        184 => "minecraft:snowy_plains",
+        185 => "minecraft:snowy_slopes",
+        186 => "minecraft:sparse_jungle",
+        187 => "minecraft:stony_peaks",
+        188 => "minecraft:stony_shore",
+        189 => "minecraft:windswept_forest",
+        190 => "minecraft:windswept_gravelly_hills",
+        191 => "minecraft:windswept_hills",
+        192 => "minecraft:windswept_savanna",
+        193 => "minecraft:wooded_badlands",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Biomes renamed in 1.18, which have a different id since that version:
+/// (id before 1.18, id since 1.18)
+pub const RENAMED_BIOMES_1_18: [(i32, i32); 11] = [
+    (biome_id::extremeHills, 191), // Windswept Hills
+    (biome_id::extremeHillsPlus, 189), // Windswept Forest
+    (biome_id::gravellyMountains, 190), // Windswept Gravelly Hills
+    (biome_id::shatteredSavanna, 192), // Windswept Savanna
+    (biome_id::mesaPlateau_F, 193), // Wooded Badlands
+    (biome_id::icePlains, 184), // Snowy Plains
+    (biome_id::jungleEdge, 186), // Sparse Jungle
+    (biome_id::stoneBeach, 188), // Stony Shore
+    (biome_id::megaTaiga, 182), // Old Growth Pine Taiga
+    (biome_id::giantSpruceTaiga, 183), // Old Growth Spruce Taiga
+    (biome_id::tallBirchForest, 181), // Old Growth Birch Forest
+];
+
+/// Translate the id of a renamed biome to the id used by this version. Biomes that were not
+/// renamed keep the same id.
+pub fn biome_id_for_version(biome_id: i32, version: MinecraftVersion) -> i32 {
+    for (old, new) in RENAMED_BIOMES_1_18 {
+        if version >= MinecraftVersion::Java1_18 && biome_id == old {
+            return new;
+        }
+        if version < MinecraftVersion::Java1_18 && biome_id == new {
+            return old;
+        }
+    }
+
+    biome_id
+}
+
 // Autogenerated code
 #[allow(non_upper_case_globals)]
 pub mod biome_id {
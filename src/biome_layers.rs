@@ -1,9 +1,16 @@
 use crate::mc_rng::McRng;
+use crate::mc_rng::SeedOrder;
 use crate::xoroshiro128plusplus::Xoroshiro128PlusPlus;
 use crate::noise_generator::NoiseGeneratorPerlin;
 use crate::noise_generator::NoiseGeneratorDoublePerlin128;
 use crate::seed_info::BiomeId;
+use crate::seed_info::FragmentVersion;
 use crate::seed_info::MinecraftVersion;
+use crate::seed_info::RiverLayers;
+use crate::cancel::CancelToken;
+use crate::cancel::Partial;
+use crate::evidence::impossible_oceans_at_spawn;
+use crate::evidence::spawn_island_seeds;
 use log::debug;
 use ndarray::Array2;
 use ndarray::Array3;
@@ -14,9 +21,13 @@ use std::sync::Arc;
 use std::sync::RwLock;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::convert::TryInto;
+use std::ops::Range;
+use crate::java_math::floor_mod;
 use crate::java_rng::JavaRng;
+use crate::chunk::Chunk;
 use crate::chunk::Point;
 use crate::chunk::Point2;
 use crate::chunk::Point4;
@@ -26,8 +37,33 @@ use crate::biome_info::biome_id;
 use crate::biome_info::BIOME_COLORS;
 use crate::biome_info::BIOME_INFO;
 use crate::biome_info::UNKNOWN_BIOME_ID;
+use crate::biome_traits::biome_exists;
+use crate::biome_traits::equal_or_plateau;
+use crate::biome_traits::get_category;
+use crate::biome_traits::is_biome_JFTO;
+use crate::biome_traits::is_biome_snowy;
+use crate::biome_traits::is_deep_ocean;
+use crate::biome_traits::is_mesa;
+use crate::biome_traits::is_oceanic;
+use crate::biome_traits::is_shallow_ocean;
 use crate::spline::Spline;
 use crate::climate::Climate;
+use crate::slime::is_slime_chunk;
+use crate::structures::is_region_structure_chunk;
+use crate::structures::is_treasure_chunk;
+use crate::structures::structure_biome_is_valid;
+use crate::structures::BiomeStructure;
+use crate::structures::RegionStructure;
+use crate::map_metrics::count_rivers;
+use crate::map_metrics::count_rivers_exact;
+use crate::map_metrics::RiverMask;
+use crate::lru_cache::LruCache;
+use crate::seed_filter::SearchCtx;
+use crate::seed_filter::SearchPipeline;
+use crate::trace;
+use crate::trace::Stage;
+use crate::trace::Trace;
+use crate::trace::TraceEvent;
 
 // The different Map* layers are copied from
 // https://github.com/Cubitect/cubiomes
@@ -283,6 +319,27 @@ impl Map {
         let (w, h) = self.a.dim();
         Area { x: self.x, z: self.z, w: w as u64, h: h as u64 }
     }
+    /// Change the area of this map and set all the values to 0, reusing the allocation when the
+    /// size does not change
+    pub fn reset(&mut self, area: Area) {
+        self.x = area.x;
+        self.z = area.z;
+        if self.a.dim() == (area.w as usize, area.h as usize) {
+            self.a.fill(0);
+        } else {
+            self.a = Array2::zeros((area.w as usize, area.h as usize));
+        }
+    }
+    /// Remove the tiles outside of `area`. The area must be inside the area of this map
+    pub fn crop(&mut self, area: Area) {
+        let (nx, nz) = ((area.x - self.x) as usize, (area.z - self.z) as usize);
+        self.a.slice_collapse(s![
+                nx..nx + area.w as usize,
+                nz..nz + area.h as usize
+        ]);
+        self.x = area.x;
+        self.z = area.z;
+    }
     /// Get value at real coordinate (x, z)
     pub fn get(&self, real_x: i64, real_z: i64) -> i32 {
         self.a[((real_x - self.x) as usize, (real_z - self.z) as usize)]
@@ -291,6 +348,11 @@ impl Map {
     pub fn set(&mut self, real_x: i64, real_z: i64, value: i32) {
         self.a[((real_x - self.x) as usize, (real_z - self.z) as usize)] = value;
     }
+    /// Get biome at real coordinate (x, z). Only meaningful for maps of biomes, not for treasure
+    /// maps or the layers that use other values
+    pub fn get_biome(&self, real_x: i64, real_z: i64) -> BiomeId {
+        BiomeId(self.get(real_x, real_z))
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -381,6 +443,9 @@ impl GetMap for CachedMap {
 
         self.get_map(area)
     }
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![&*self.parent]
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
@@ -462,44 +527,10 @@ pub struct Biome {
 fn get_biome_type(id: i32) -> i32 {
     BIOME_INFO[id as usize].type_0
 }
-fn get_category(v: MinecraftVersion, id: i32) -> Option<i32> {
-    use biome_id::*;
-
-    let category = match id {
-        ocean | frozenOcean | deepOcean | warmOcean | lukewarmOcean | coldOcean | warmDeepOcean | lukewarmDeepOcean | coldDeepOcean | frozenDeepOcean => Ocean,
-        plains | sunflowerPlains => Plains,
-        desert | desertHills | desertLakes => Desert,
-        extremeHills | extremeHillsEdge | extremeHillsPlus | gravellyMountains | modifiedGravellyMountains => Hills,
-        forest | forestHills | birchForest | birchForestHills | roofedForest | flowerForest | tallBirchForest | tallBirchHills | darkForestHills => Forest,
-        taiga | taigaHills | coldTaiga | coldTaigaHills | megaTaiga | megaTaigaHills | taigaMountains | snowyTaigaMountains | giantSpruceTaiga | giantSpruceTaigaHills => Taiga,
-        swampland | swampHills => Swamp,
-        river | frozenRiver => River,
-        hell => Hell,
-        sky | skyIslandLow | skyIslandMedium | skyIslandHigh | skyIslandBarren => Sky,
-        icePlains | iceMountains | iceSpikes => Snow,
-        mushroomIsland | mushroomIslandShore => MushroomIsland,
-        beach | coldBeach => Beach,
-        jungle | jungleHills | jungleEdge | modifiedJungle | modifiedJungleEdge | bambooJungle | bambooJungleHills => Jungle,
-        stoneBeach => StoneBeach,
-        savanna | savannaPlateau | shatteredSavanna | shatteredSavannaPlateau => Savanna,
-        mesa | erodedBadlands | modifiedWoodedBadlandsPlateau | modifiedBadlandsPlateau => Mesa,
-        mesaPlateau_F | mesaPlateau => if v >= MinecraftVersion::Java1_16 { mesaPlateau } else { Mesa },
-        _ => return None,
-    };
-
-    Some(category)
-}
-fn biome_exists(id: i32) -> bool {
-    if id <= 0xFF {
-        BIOME_INFO[id as usize].id & (!0xFF) == 0
-    } else {
-        false
-    }
-}
 fn get_mutated(v: MinecraftVersion, id: i32) -> Option<i32> {
     use biome_id::*;
 
-    if v == MinecraftVersion::Java1_9 {
+    if v.quirks().mutated_birch_forest_mc_98995 {
         // Simulate https://bugs.mojang.com/browse/MC-98995
         if id == birchForest {
             return Some(id + 129);
@@ -515,38 +546,6 @@ fn get_mutated(v: MinecraftVersion, id: i32) -> Option<i32> {
         None
     }
 }
-pub fn is_oceanic(id: i32) -> bool {
-    use biome_id::*;
-    match id {
-        ocean
-        | deepOcean
-        | warmOcean
-        | warmDeepOcean
-        | lukewarmOcean
-        | lukewarmDeepOcean
-        | coldOcean
-        | coldDeepOcean
-        | frozenOcean
-        | frozenDeepOcean
-        => true,
-        _ => false
-    }
-}
-fn is_mesa(id: i32) -> bool {
-    use biome_id::*;
-    match id {
-        mesa | mesaPlateau_F | mesaPlateau | erodedBadlands | modifiedWoodedBadlandsPlateau | modifiedBadlandsPlateau => true,
-        _ => false,
-    }
-}
-fn is_biome_JFTO(id: i32) -> bool {
-    use biome_id::*;
-    biome_exists(id) && (get_category(MinecraftVersion::Java1_16, id) == Some(Jungle) || id == forest || id == taiga || is_oceanic(id))
-}
-
-fn is_biome_snowy(id: i32) -> bool {
-    biome_exists(id) && BIOME_INFO[(id&0xff) as usize].temp < 0.1
-}
 pub fn biome_to_color(id: i32) -> [u8; 4] {
     let mut id = id as usize;
 
@@ -657,11 +656,144 @@ struct Layer {
 pub trait GetMap {
     fn get_map(&self, area: Area) -> Map;
     fn get_map_from_pmap(&self, pmap: &Map) -> Map;
+    /// Name, base seed and parents of this layer. Layers without base seed and without zoom,
+    /// like wrappers around other layers, can use the default and only implement
+    /// `parent_layers`.
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 1, self.parent_layers().into_iter().map(|p| p.layer_info()).collect())
+    }
+    /// Parents of this layer, used by the default `layer_info`
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![]
+    }
+}
+
+/// Like `GetMap::get_map_from_pmap`, but writing the output into an existing map to avoid
+/// allocating a new one
+pub trait GetMapInto {
+    fn get_map_from_pmap_into(&self, pmap: &Map, map: &mut Map);
 }
 
 pub trait GetMap3D {
     fn get_map_3d(&self, area: Area3D) -> Map3D;
     fn get_map_from_pmap_3d(&self, pmap: &Map3D) -> Map3D;
+    /// Same as `GetMap::layer_info`
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 1, vec![])
+    }
+}
+
+/// Description of a layer and all of its parents, used to draw the layer graph.
+#[derive(Clone, Debug)]
+pub struct LayerInfo {
+    /// Address of the layer, used to find the parents shared by more than one layer
+    pub id: usize,
+    pub name: &'static str,
+    pub base_seed: Option<i64>,
+    /// Each tile of the parent is zoomed into `zoom` x `zoom` tiles of this layer
+    pub zoom: u64,
+    pub parents: Vec<LayerInfo>,
+}
+
+impl LayerInfo {
+    pub fn new<T: ?Sized>(layer: &T, base_seed: Option<i64>, zoom: u64, parents: Vec<LayerInfo>) -> Self {
+        let type_name = std::any::type_name::<T>();
+        // Remove the module path, but not from the generic parameters
+        let name = type_name[..type_name.find('<').unwrap_or(type_name.len())].rsplit("::").next().unwrap();
+
+        Self { id: layer as *const T as *const () as usize, name, base_seed, zoom, parents }
+    }
+}
+
+/// Uniform way to connect a layer to its parent, to assemble custom layer stacks:
+///
+/// ```ignore
+/// let zoom = MapZoom::new(2001, world_seed).with_parent(Rc::new(MapIsland::new(1, world_seed)));
+/// ```
+///
+/// The base seed of each layer is the first argument of its `new` method. The base seeds used by
+/// each version can be listed with `layer_base_seeds`.
+pub trait WithParent: Sized {
+    fn set_parent(&mut self, parent: Rc<dyn GetMap>);
+    fn with_parent(mut self, parent: Rc<dyn GetMap>) -> Self {
+        self.set_parent(parent);
+        self
+    }
+}
+
+/// Same as `WithParent`, for the layers that merge two parent maps
+pub trait WithParents: Sized {
+    fn set_parents(&mut self, parent1: Rc<dyn GetMap>, parent2: Rc<dyn GetMap>);
+    fn with_parents(mut self, parent1: Rc<dyn GetMap>, parent2: Rc<dyn GetMap>) -> Self {
+        self.set_parents(parent1, parent2);
+        self
+    }
+}
+
+macro_rules! impl_with_parent {
+    ($($layer:ty),* $(,)?) => {
+        $(impl WithParent for $layer {
+            fn set_parent(&mut self, parent: Rc<dyn GetMap>) {
+                self.parent = Some(parent);
+            }
+        })*
+    };
+}
+
+macro_rules! impl_with_parents {
+    ($($layer:ty),* $(,)?) => {
+        $(impl WithParents for $layer {
+            fn set_parents(&mut self, parent1: Rc<dyn GetMap>, parent2: Rc<dyn GetMap>) {
+                self.parent1 = Some(parent1);
+                self.parent2 = Some(parent2);
+            }
+        })*
+    };
+}
+
+impl_with_parent!(
+    MapHalfVoronoiZoom, MapHalfVoronoiZoom115, MapVoronoiZoom, MapVoronoiZoom115, MapZoom, MapZoomFuzzy,
+    HelperMapZoomAllEdges, MapAddIsland, MapRemoveTooMuchOcean, MapAddSnow, MapCoolWarm, MapHeatIce, MapSpecial,
+    MapAddMushroomIsland, MapDeepOcean, MapBiome, MapBiomeEdge, MapRiverInit, MapRareBiome, MapShore, MapSmooth,
+    MapRiver, HelperMapRiverAll, MapSkip, MapAddBamboo, MapAddIsland13, MapIcePlains, MapBiome13, MapRegionHills,
+    MapMushroomShore, MapSwampRivers, MapRiverInit13, MapRiver13,
+);
+
+impl_with_parents!(MapHills, MapRiverMix, MapOceanMix, MapRiverMix13);
+
+/// Recipe to create a layer stack for any world seed.
+///
+/// The layers use `Rc`, so they cannot be sent to other threads, but the recipe can: each thread
+/// builds its own copy of the layers.
+#[derive(Clone)]
+pub struct LayerStackBuilder {
+    build_fn: Arc<dyn Fn(i64) -> Rc<dyn GetMap> + Send + Sync>,
+}
+
+impl LayerStackBuilder {
+    pub fn new<F>(build_fn: F) -> Self
+    where
+        F: Fn(i64) -> Rc<dyn GetMap> + Send + Sync + 'static,
+    {
+        Self { build_fn: Arc::new(build_fn) }
+    }
+
+    /// The layers used by `generate_up_to_layer`, if that layer is implemented as a 2D generator
+    pub fn from_version(version: MinecraftVersion, layer: u32) -> Option<Self> {
+        match GeneratorCache::new_generator(version, 0, layer) {
+            Some(CachedGenerator::Map(_)) => {}
+            _ => return None,
+        }
+
+        Some(Self::new(move |world_seed| match GeneratorCache::new_generator(version, world_seed, layer) {
+            Some(CachedGenerator::Map(g)) => g,
+            _ => unreachable!(),
+        }))
+    }
+
+    pub fn build(&self, world_seed: i64) -> Rc<dyn GetMap> {
+        (self.build_fn)(world_seed)
+    }
 }
 
 // Test layer which always generates a map consisting of only zeros.
@@ -762,6 +894,9 @@ impl<F: Fn(i64, i64, i32) -> i32, P: GetMap> GetMap for MapParentFn<P, F> {
 
         m
     }
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![&self.0]
+    }
 }
 
 // A map which applies a function to its parent map
@@ -781,6 +916,9 @@ impl GetMap for MapMap {
         m.a.mapv_inplace(self.f);
         m
     }
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![&*self.parent]
+    }
 }
 
 // A map which applies a function to its two parent maps
@@ -802,8 +940,35 @@ impl GetMap for MapMap2 {
     fn get_map_from_pmap(&self, _pmap: &Map) -> Map {
         panic!("MapMap2 requires 2 pmaps!");
     }
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![&*self.parent1, &*self.parent2]
+    }
+}
+
+/// Parent area needed by a zoom layer to generate `area`.
+///
+/// The zoom layer generates `1 << shift` tiles for each parent tile, except for the last parent
+/// tile in each direction, which is only used as a margin. The output starts at
+/// `(parea.x << shift) + start_offset`, so the parent area is chosen to make the output start at
+/// most `(1 << shift) - 1` tiles before `area.x`, and be as small as possible while still
+/// containing the whole area. Use `Map::crop` to remove the extra tiles.
+fn zoom_parent_area(area: Area, shift: u8, start_offset: i64) -> Area {
+    let mask = (1 << shift) - 1;
+    let (nx, nz) = ((area.x - start_offset) & mask, (area.z - start_offset) & mask);
+
+    Area {
+        x: (area.x - start_offset) >> shift,
+        z: (area.z - start_offset) >> shift,
+        w: ((nx as u64 + area.w + mask as u64) >> shift) + 1,
+        h: ((nz as u64 + area.h + mask as u64) >> shift) + 1,
+    }
 }
 
+/// Voronoi zoom used by treasure maps, which have one pixel every 2 blocks.
+///
+/// The tile (x, z) of the output map is the biome of the block (2 * x, 2 * z) as generated by
+/// `MapVoronoiZoom`. Treasure maps always sample even block coordinates, so the odd blocks are
+/// never needed.
 pub struct MapHalfVoronoiZoom {
     base_seed: i64,
     world_seed: i64,
@@ -816,28 +981,15 @@ impl MapHalfVoronoiZoom {
     }
 }
 
-// TODO: tests
 impl GetMap for MapHalfVoronoiZoom {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            let parea = Area {
-                x: area.x >> 1,
-                z: area.z >> 1,
-                w: (area.w >> 1) + 2,
-                h: (area.h >> 1) + 2
-            };
+            // The output of get_map_from_pmap starts at 2 * parea.x + 1
+            let parea = zoom_parent_area(area, 1, 1);
             let pmap = parent.get_map(parea);
 
             let mut map = self.get_map_from_pmap(&pmap);
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x + 1) & 1, (area.z + 1) & 1);
-            map.x -= nx;
-            map.z -= nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -858,15 +1010,20 @@ impl GetMap for MapHalfVoronoiZoom {
         let mut m = Map::new(marea);
         for x in 0..marea.w as usize {
             for z in 0..marea.h as usize {
-                // TODO: check if we need offset here
+                // varea.x is even, so no offset is needed
                 m.a[(x, z)] = vmap.a[(x * 2, z * 2)];
             }
         }
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 2, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
+/// Same as `MapHalfVoronoiZoom` but using `MapVoronoiZoom115`: the tile (x, z) of the output
+/// map is the biome of the block (2 * x, 2 * z).
 pub struct MapHalfVoronoiZoom115 {
     world_seed: i64,
     pub parent: Option<Rc<dyn GetMap>>,
@@ -878,28 +1035,15 @@ impl MapHalfVoronoiZoom115 {
     }
 }
 
-// TODO: tests
 impl GetMap for MapHalfVoronoiZoom115 {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            let parea = Area {
-                x: area.x >> 1,
-                z: area.z >> 1,
-                w: (area.w >> 1) + 2,
-                h: (area.h >> 1) + 2
-            };
+            // The output of get_map_from_pmap starts at 2 * parea.x + 1
+            let parea = zoom_parent_area(area, 1, 1);
             let pmap = parent.get_map(parea);
 
             let mut map = self.get_map_from_pmap(&pmap);
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x + 1) & 1, (area.z + 1) & 1);
-            map.x -= nx;
-            map.z -= nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -920,13 +1064,16 @@ impl GetMap for MapHalfVoronoiZoom115 {
         let mut m = Map::new(marea);
         for x in 0..marea.w as usize {
             for z in 0..marea.h as usize {
-                // TODO: check if we need offset here
+                // varea.x is even, so no offset is needed
                 m.a[(x, z)] = vmap.a[(x * 2, z * 2)];
             }
         }
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 2, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapVoronoiZoom {
@@ -941,52 +1088,15 @@ impl MapVoronoiZoom {
     }
 }
 
-// TODO: tests
 impl GetMap for MapVoronoiZoom {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            // TODO: Area::double(), Area::quadruple(), etc
-            // Example 1:
-            // area  1x1: we want to generate 1x1
-            // parea 2x2: we need 2x2 from the previous layer
-            // narea 4x4: instead of 8x8, we only zoom the top left corner
-            // now we need to crop that 4x4 into 1x1
-            //
-            // Example 2:
-            // area  10x10: we want to generate 10x10
-            // parea 4x4: we need 4x4 from the previous layer
-            // narea 12x12: instead of 16x16, we skip the bottom and right pixels
-            // now we need to crop that 12x12 into 10x10
-            // But wait. We actually need parea 5x5, for the worst case:
-            // |...*|****|****|*...|....|
-            // So it makes sense to rewrite this algorithm and account for that
-            // cases, allowing some optimizations
-            let parea = Area {
-                x: (area.x - 2) >> 2,
-                z: (area.z - 2) >> 2,
-                w: (area.w >> 2) + 2 + 1, // TODO: without the +1 the slicing fails
-                h: (area.h >> 2) + 2 + 1,
-            };
-
-            let narea = Area {
-                w: (parea.w - 1) << 2,
-                h: (parea.h - 1) << 2,
-                ..area
-            };
-
+            // The output of get_map_from_pmap starts at 4 * parea.x + 2
+            let parea = zoom_parent_area(area, 2, 2);
             let pmap = parent.get_map(parea);
+
             let mut map = self.get_map_from_pmap(&pmap);
-            let (nw, nh) = map.a.dim();
-            assert_eq!((nw, nh), (narea.w as usize, narea.h as usize));
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x - 2) & 3, (area.z - 2) & 3);
-            map.x += nx;
-            map.z += nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -1100,65 +1210,32 @@ impl GetMap for MapVoronoiZoom {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 4, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapVoronoiZoom115 {
     hashed_world_seed: i64,
     pub parent: Option<Rc<dyn GetMap>>,
+    pub float_order: VoronoiFloatOrder,
 }
 
 impl MapVoronoiZoom115 {
     pub fn new(world_seed: i64) -> Self {
-        Self { hashed_world_seed: sha256_long_to_long(world_seed), parent: None }
+        Self { hashed_world_seed: sha256_long_to_long(world_seed), parent: None, float_order: VoronoiFloatOrder::default() }
     }
 }
 
-// TODO: tests
 impl GetMap for MapVoronoiZoom115 {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            // TODO: Area::double(), Area::quadruple(), etc
-            // Example 1:
-            // area  1x1: we want to generate 1x1
-            // parea 2x2: we need 2x2 from the previous layer
-            // narea 4x4: instead of 8x8, we only zoom the top left corner
-            // now we need to crop that 4x4 into 1x1
-            //
-            // Example 2:
-            // area  10x10: we want to generate 10x10
-            // parea 4x4: we need 4x4 from the previous layer
-            // narea 12x12: instead of 16x16, we skip the bottom and right pixels
-            // now we need to crop that 12x12 into 10x10
-            // But wait. We actually need parea 5x5, for the worst case:
-            // |...*|****|****|*...|....|
-            // So it makes sense to rewrite this algorithm and account for that
-            // cases, allowing some optimizations
-            let parea = Area {
-                x: (area.x - 2) >> 2,
-                z: (area.z - 2) >> 2,
-                w: (area.w >> 2) + 2 + 1, // TODO: without the +1 the slicing fails
-                h: (area.h >> 2) + 2 + 1,
-            };
-
-            let narea = Area {
-                w: (parea.w - 1) << 2,
-                h: (parea.h - 1) << 2,
-                ..area
-            };
-
+            // The output of get_map_from_pmap starts at 4 * parea.x + 2
+            let parea = zoom_parent_area(area, 2, 2);
             let pmap = parent.get_map(parea);
+
             let mut map = self.get_map_from_pmap(&pmap);
-            let (nw, nh) = map.a.dim();
-            assert_eq!((nw, nh), (narea.w as usize, narea.h as usize));
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x - 2) & 3, (area.z - 2) & 3);
-            map.x += nx;
-            map.z += nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -1223,7 +1300,7 @@ impl GetMap for MapVoronoiZoom115 {
                         let idx = ((x << 2) + i, (z << 2) + j);
                         // y = 0; y2 = (y - 2)
                         let y2 = -2;
-                        m.a[idx] = map_voronoi_1_15(i as i32, y2, j as i32, &pos_offset, &biome_at);
+                        m.a[idx] = map_voronoi_1_15(i as i32, y2, j as i32, &pos_offset, &biome_at, self.float_order);
                     }
                 }
 
@@ -1234,16 +1311,20 @@ impl GetMap for MapVoronoiZoom115 {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 4, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapVoronoiZoom118 {
     hashed_world_seed: i64,
     pub parent: Option<Rc<dyn GetMap3D>>,
+    pub float_order: VoronoiFloatOrder,
 }
 
 impl MapVoronoiZoom118 {
     pub fn new(world_seed: i64) -> Self {
-        Self { hashed_world_seed: sha256_long_to_long(world_seed), parent: None }
+        Self { hashed_world_seed: sha256_long_to_long(world_seed), parent: None, float_order: VoronoiFloatOrder::default() }
     }
 }
 
@@ -1287,12 +1368,14 @@ impl GetMap3D for MapVoronoiZoom118 {
             let mut map = self.get_map_from_pmap_3d(&pmap);
             let (nsx, nsy, nsz) = map.a.dim();
             assert_eq!((nsx, nsy, nsz), (narea.sx as usize, narea.sy as usize, narea.sz as usize));
-            // TODO: is this correct?
-            let (nx, ny, nz) = ((area.x - 2) & 3, (area.y - 2) & 3, (area.z - 2) & 3);
+            // The output of get_map_from_pmap_3d starts at 4 * parea.x + 2. Only the first y
+            // level of the output is generated, the other levels are always 0, so that level is
+            // used as area.y
+            let (nx, nz) = ((area.x - 2) & 3, (area.z - 2) & 3);
             map.x += nx;
-            map.y += ny;
+            map.y = area.y;
             map.z += nz;
-            let (nx, ny, nz) = (nx as usize, ny as usize, nz as usize);
+            let (nx, ny, nz) = (nx as usize, 0, nz as usize);
             map.a.slice_collapse(s![
                     nx..nx + area.sx as usize,
                     ny..ny + area.sy as usize,
@@ -1366,7 +1449,7 @@ impl GetMap3D for MapVoronoiZoom118 {
                         let idx = ((x << 2) + i, 0, (z << 2) + j);
                         // y = 0; y2 = (y - 2)
                         let y2 = -2;
-                        m.a[idx] = map_voronoi_1_15(i as i32, y2, j as i32, &pos_offset, &biome_at);
+                        m.a[idx] = map_voronoi_1_15(i as i32, y2, j as i32, &pos_offset, &biome_at, self.float_order);
                     }
                 }
 
@@ -1377,6 +1460,9 @@ impl GetMap3D for MapVoronoiZoom118 {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 4, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 /// Overworld and Nether biome generator for 1.18
@@ -1683,25 +1769,45 @@ fn index_of_min_element(x: &[f64]) -> Option<usize> {
     x.iter().enumerate().min_by(|(_, a), (_, b)| a.partial_cmp(b).expect("NaN float")).map(|(i, _)| i)
 }
 
+/// Order of the floating point operations of the 1.15 voronoi zoom.
+///
+/// Each block is assigned to the nearest of 8 points. The position of each point relative to
+/// the block is `fraction - corner + offset`, where `fraction` is the position of the block
+/// inside the 4x4x4 cell, `corner` is 0 or 1, and `offset` is the random offset of the point.
+/// Floating point addition is not associative, so the order of these operations changes the
+/// last bits of the distance, and the biome of a block can change when it is at almost the same
+/// distance from two points.
+///
+/// Neither order is verified in game: the two orders only round the distance differently, no
+/// block with a different biome was found in 100 random seeds, and there is no in-game test vector
+/// at a block where they disagree. So the default is still `OffsetFirst`, the order used by older
+/// versions of this library. Use `voronoi_float_order_disagreements` to find the blocks that
+/// depend on this setting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VoronoiFloatOrder {
+    /// `(fraction - corner) + offset`, which looks like the order of the game code. Unverified.
+    FractionFirst,
+    /// `(offset - corner) + fraction`, the order used by older versions of this library.
+    /// Unverified.
+    OffsetFirst,
+}
+
+impl Default for VoronoiFloatOrder {
+    fn default() -> Self {
+        VoronoiFloatOrder::OffsetFirst
+    }
+}
+
+// Random offset of each of the 8 points around the cell (px, py, pz)
 fn voronoi_1_15_pos_offset(seed: i64, px: i32, py: i32, pz: i32) -> [(f64, f64, f64); 8] {
-    // Negative position of the voronoi point
     let mut pos_offset = [(0.0, 0.0, 0.0); 8];
 
     for i in 0..8 {
-        let flagx = (i & 4) == 0;
-        let flagy = (i & 2) == 0;
-        let flagz = (i & 1) == 0;
-
-        let x1 = if flagx { px } else { px + 1 };
-        let y1 = if flagy { py } else { py + 1 };
-        let z1 = if flagz { pz } else { pz + 1 };
+        let x1 = if (i & 4) == 0 { px } else { px + 1 };
+        let y1 = if (i & 2) == 0 { py } else { py + 1 };
+        let z1 = if (i & 1) == 0 { pz } else { pz + 1 };
 
         pos_offset[i] = rand_offset_3d(seed, x1, y1, z1);
-        // FIXME(voronoi_float_precision): these operations used to be performed
-        // right before mod_squared_3d
-        pos_offset[i].0 -= if flagx { 0.0 } else { 1.0 };
-        pos_offset[i].1 -= if flagy { 0.0 } else { 1.0 };
-        pos_offset[i].2 -= if flagz { 0.0 } else { 1.0 };
     }
 
     pos_offset
@@ -1711,7 +1817,7 @@ fn voronoi_1_15_pos_offset(seed: i64, px: i32, py: i32, pz: i32) -> [(f64, f64,
 // and returns the biome of the nearest point.
 // (x, y, z) are the coordinates inside the 4x4x4 cube that will be generated
 // by MapVoronoiZoom115, should be one of (0, 1, 2, 3).
-fn map_voronoi_1_15(x: i32, y: i32, z: i32, pos_offset: &[(f64, f64, f64); 8], biome_at: &[i32; 8]) -> i32 {
+fn map_voronoi_1_15(x: i32, y: i32, z: i32, pos_offset: &[(f64, f64, f64); 8], biome_at: &[i32; 8], order: VoronoiFloatOrder) -> i32 {
     // dx is one of 0.00, 0.25, 0.50, 0.75
     let dx = f64::from(x & 3) / 4.0;
     let dy = f64::from(y & 3) / 4.0;
@@ -1719,7 +1825,13 @@ fn map_voronoi_1_15(x: i32, y: i32, z: i32, pos_offset: &[(f64, f64, f64); 8], b
     let mut dists = [0.0; 8];
 
     for i in 0..8 {
-        dists[i] = mod_squared_3d(pos_offset[i].0 + dx, pos_offset[i].1 + dy, pos_offset[i].2 + dz);
+        let corner = |bit: usize| if (i & bit) == 0 { 0.0 } else { 1.0 };
+        let (ox, oy, oz) = pos_offset[i];
+        let (cx, cy, cz) = (corner(4), corner(2), corner(1));
+        dists[i] = match order {
+            VoronoiFloatOrder::FractionFirst => mod_squared_3d((dx - cx) + ox, (dy - cy) + oy, (dz - cz) + oz),
+            VoronoiFloatOrder::OffsetFirst => mod_squared_3d((ox - cx) + dx, (oy - cy) + dy, (oz - cz) + dz),
+        };
     }
 
     let min_index = index_of_min_element(&dists).unwrap();
@@ -1728,22 +1840,33 @@ fn map_voronoi_1_15(x: i32, y: i32, z: i32, pos_offset: &[(f64, f64, f64); 8], b
 }
 
 fn mod_squared_3d(x: f64, y: f64, z: f64) -> f64 {
-    // FIXME(voronoi_float_precision): the order of the arguments is important,
-    // but I don't have any test cases with the correct order. This may be a
-    // problem when two points are at about the same distance from a third
-    // point. In that case, the biome at the third point may be wrong because of
-    // the floating point precision. We cannot use AMIDST to generate test cases
-    // because we need the full resolution biome map.
+    // Same order as the game: z first
     z * z + y * y + x * x
 }
 
+/// Blocks of the output of `MapVoronoiZoom115` that have a different biome depending on the
+/// `VoronoiFloatOrder`. The biome of these blocks cannot be trusted, so they should be ignored
+/// when comparing against the game.
+pub fn voronoi_float_order_disagreements(world_seed: i64, pmap: &Map) -> Vec<Point> {
+    let mut a = MapVoronoiZoom115::new(world_seed);
+    a.float_order = VoronoiFloatOrder::FractionFirst;
+    let mut b = MapVoronoiZoom115::new(world_seed);
+    b.float_order = VoronoiFloatOrder::OffsetFirst;
+    let ma = a.get_map_from_pmap(pmap);
+    let mb = b.get_map_from_pmap(pmap);
+
+    ma.a.indexed_iter().zip(mb.a.iter()).filter(|((_, va), vb)| va != vb).map(|(((x, z), _), _)| {
+        Point { x: ma.x + x as i64, z: ma.z + z as i64 }
+    }).collect()
+}
+
 fn rand_offset_3d(seed: i64, x: i32, y: i32, z: i32) -> (f64, f64, f64) {
     // Returns number in range [-0.45, 0.45)
     fn rand_offset(seed: i64) -> f64 {
         // nextInt(1024) / 1024.0
         // Return a f64 between 0.0 and 1.0 with 10 bits of accuracy:
         // two different points cannot be closer than 2^-10
-        let d = McRng::math_floor_div(seed >> 24, 1024) as f64 / 1024.0;
+        let d = floor_mod(seed >> 24, 1024) as f64 / 1024.0;
 
         (d - 0.5) * 0.9
     }
@@ -1765,6 +1888,64 @@ fn rand_offset_3d(seed: i64, x: i32, y: i32, z: i32) -> (f64, f64, f64) {
     (dx, dy, dz)
 }
 
+/// Returns the 1:4 cell whose biome ends up at the 1:1 block (x, z) after the voronoi zoom.
+///
+/// Each cell (px, pz) has a voronoi point near the block (4 * px + 2, 4 * pz + 2), and each
+/// block takes the biome of the nearest point. In 1.15 and later the voronoi zoom is 3D, and this
+/// function assumes y = 0, like `MapVoronoiZoom115`.
+pub fn voronoi_cell_source(world_seed: i64, version: MinecraftVersion, x: i64, z: i64) -> Point4 {
+    let (x2, z2) = (x - 2, z - 2);
+    let (px, pz) = (x2 >> 2, z2 >> 2);
+    let (i, j) = (x2 & 3, z2 & 3);
+
+    // Corner of the nearest point: bit 1 is set for x + 1, bit 0 is set for z + 1
+    let corner = if version >= MinecraftVersion::Java1_15 {
+        // y = 0; py = (y - 2) >> 2
+        let pos_offset = voronoi_1_15_pos_offset(sha256_long_to_long(world_seed), px as i32, -1, pz as i32);
+        let corners = [0, 1, 0, 1, 2, 3, 2, 3];
+        // y = 0; y2 = (y - 2)
+        map_voronoi_1_15(i as i32, -2, j as i32, &pos_offset, &corners, VoronoiFloatOrder::default())
+    } else {
+        let mut r = McRng::new(10, world_seed);
+        let mut point_at = |x: i64, z: i64, ox: f64, oz: f64| {
+            r.set_chunk_seed(x << 2, z << 2);
+            let d1 = ((r.next_int_n(1024) as f64) / 1024.0 - 0.5) * 3.6 + ox;
+            let d2 = ((r.next_int_n(1024) as f64) / 1024.0 - 0.5) * 3.6 + oz;
+            (d1, d2)
+        };
+        let (da1, da2) = point_at(px, pz, 0.0, 0.0);
+        let (db1, db2) = point_at(px + 1, pz, 4.0, 0.0);
+        let (dc1, dc2) = point_at(px, pz + 1, 0.0, 4.0);
+        let (dd1, dd2) = point_at(px + 1, pz + 1, 4.0, 4.0);
+
+        let i = i as f64;
+        let j = j as f64;
+        let da = (j-da2)*(j-da2) + (i-da1)*(i-da1);
+        let db = (j-db2)*(j-db2) + (i-db1)*(i-db1);
+        let dc = (j-dc2)*(j-dc2) + (i-dc1)*(i-dc1);
+        let dd = (j-dd2)*(j-dd2) + (i-dd1)*(i-dd1);
+
+        // Same tie-breaking as MapVoronoiZoom
+        if da < db && da < dc && da < dd {
+            0
+        } else if db < da && db < dc && db < dd {
+            2
+        } else if dc < da && dc < db && dc < dd {
+            1
+        } else {
+            3
+        }
+    };
+
+    Point4 { x: px + i64::from(corner >> 1), z: pz + i64::from(corner & 1) }
+}
+
+/// Returns the 1:4 cell whose biome ends up at the 1:2 pixel (x, z) after the half voronoi zoom
+/// used by treasure maps.
+pub fn half_voronoi_cell_source(world_seed: i64, version: MinecraftVersion, x: i64, z: i64) -> Point4 {
+    voronoi_cell_source(world_seed, version, x * 2, z * 2)
+}
+
 pub struct MapIsland {
     base_seed: i64,
     world_seed: i64,
@@ -1783,7 +1964,7 @@ impl GetMap for MapIsland {
             let mut r = r;
             r.set_chunk_seed(x, z);
 
-            if r.next_int_n(10) == 0 { 1 } else { 0 }
+            if r.next_int_n_const::<10>() == 0 { 1 } else { 0 }
         }).get_map(area);
 
         // Force (0, 0) to island
@@ -1800,6 +1981,9 @@ impl GetMap for MapIsland {
 
         self.get_map(area)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, vec![])
+    }
 }
 
 // Random:
@@ -1827,24 +2011,12 @@ impl MapZoom {
 impl GetMap for MapZoom {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            let parea = Area {
-                x: area.x >> 1,
-                z: area.z >> 1,
-                w: (area.w >> 1) + 2,
-                h: (area.h >> 1) + 2
-            };
+            // The output of get_map_from_pmap starts at 2 * parea.x
+            let parea = zoom_parent_area(area, 1, 0);
             let pmap = parent.get_map(parea);
 
             let mut map = self.get_map_from_pmap(&pmap);
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x) & 1, (area.z) & 1);
-            map.x += nx;
-            map.z += nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -1852,6 +2024,18 @@ impl GetMap for MapZoom {
         }
     }
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
+        let mut map = Map::default();
+        self.get_map_from_pmap_into(pmap, &mut map);
+
+        map
+    }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 2, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
+}
+
+impl GetMapInto for MapZoom {
+    fn get_map_from_pmap_into(&self, pmap: &Map, map: &mut Map) {
         let mut r = McRng::default();
         r.set_base_seed(self.base_seed);
         if !self.bug_world_seed_not_set {
@@ -1865,11 +2049,14 @@ impl GetMap for MapZoom {
             h: ((p_h - 1) << 1) as u64
         };
 
-        let mut map = Map::new(area);
+        map.reset(area);
 
         for x in 0..p_w - 1 {
             let mut a = pmap.a[(x+0, 0)];
             let mut a1 = pmap.a[(x+1, 0)];
+            let chunk_x = (x as i64 + pmap.x) << 1;
+            // Half of the chunk seed calculation only depends on x
+            let row_seed = r.chunk_seed_row(chunk_x);
             for z in 0..p_h - 1 {
                 let b = pmap.a[(x+0, z+1)];
                 let b1 = pmap.a[(x+1, z+1)];
@@ -1897,10 +2084,9 @@ impl GetMap for MapZoom {
                     continue;
                 }
 
-                let chunk_x = (x as i64 + pmap.x) << 1;
                 let chunk_z = (z as i64 + pmap.z) << 1;
 
-                r.set_chunk_seed(chunk_x, chunk_z);
+                r.set_chunk_seed_from_row(row_seed, chunk_x, chunk_z);
                 let a_or_b = r.choose2(a, b);
                 map.a[((x << 1) + 0, (z << 1) + 0)] = a;
                 map.a[((x << 1) + 0, (z << 1) + 1)] = a_or_b;
@@ -1914,9 +2100,187 @@ impl GetMap for MapZoom {
                 a1 = b1;
             }
         }
+    }
+}
 
-        map
+/// Check if the tiles chosen at random by `MapZoom` in the observed map are consistent with this
+/// world seed. `pmap` is the input of the layer, and `observed` is a part of the output.
+///
+/// `next_int(2)` only depends on the lower 25 bits of the world seed, and `next_int(4)` on the
+/// lower 26 bits, so with `bits == 25` the tiles decided by `select_mode_or_random` are ignored.
+pub fn map_zoom_matches_evidence(base_seed: i64, world_seed: i64, pmap: &Map, observed: &SparseMap, bits: u8) -> bool {
+    assert!(bits >= 25, "MapZoom choices depend on at least 25 bits of the world seed");
+    let mut r = McRng::new(base_seed, world_seed);
+    let area = observed.area();
+    let get = |x: i64, z: i64| {
+        if area.contains(x, z) {
+            observed.a[((x - area.x) as usize, (z - area.z) as usize)]
+        } else {
+            None
+        }
+    };
+    let (p_w, p_h) = pmap.a.dim();
+
+    for x in 0..p_w - 1 {
+        for z in 0..p_h - 1 {
+            let a = pmap.a[(x+0, z+0)];
+            let a1 = pmap.a[(x+1, z+0)];
+            let b = pmap.a[(x+0, z+1)];
+            let b1 = pmap.a[(x+1, z+1)];
+            // Same shortcut as MapZoom: the rng is not used
+            if a == a1 && a == b {
+                continue;
+            }
+
+            let chunk_x = (x as i64 + pmap.x) << 1;
+            let chunk_z = (z as i64 + pmap.z) << 1;
+            let o01 = get(chunk_x + 0, chunk_z + 1);
+            let o10 = get(chunk_x + 1, chunk_z + 0);
+            let o11 = get(chunk_x + 1, chunk_z + 1);
+            if o01.is_none() && o10.is_none() && o11.is_none() {
+                continue;
+            }
+
+            r.set_chunk_seed(chunk_x, chunk_z);
+            let a_or_b = r.choose2(a, b);
+            if matches!(o01, Some(o) if o != a_or_b) {
+                return false;
+            }
+            let a_or_a1 = r.choose2(a, a1);
+            if matches!(o10, Some(o) if o != a_or_a1) {
+                return false;
+            }
+            if bits >= 26 {
+                let v = r.select_mode_or_random(a, a1, b, b1);
+                if matches!(o11, Some(o) if o != v) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    true
+}
+
+/// Probable result of one of the `choose2` calls of `MapZoom`, derived from the observed output of
+/// the layer by `zoom_choices_from_straight_borders`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ZoomChoice {
+    pub chunk_x: i64,
+    pub chunk_z: i64,
+    /// false for the first call (tile below `a`), true for the second call (tile right of `a`)
+    pub second_call: bool,
+    /// Result of `next_int(2)`: false if the first parent was chosen
+    pub value: bool,
+    /// Confidence in this choice, from 0.0 to 1.0
+    pub weight: f32,
+}
+
+/// Experimental: find the `choose2` results of `MapZoom` that can be read from the observed
+/// output of the layer. `pmap` is the input of the layer, and `observed` is a part of the output.
+///
+/// Each random choice decides whether a tile at a border between two parent tiles belongs to one
+/// side or the other, so a border that stays straight for many parent tiles is a sequence of
+/// choices with the same result. Isolated choices are usually changed by later layers, but long
+/// straight borders survive, so the weight of each choice is proportional to the length of the
+/// straight segment that contains it. Segments shorter than `min_run` parent tiles are ignored.
+pub fn zoom_choices_from_straight_borders(pmap: &Map, observed: &SparseMap, min_run: usize) -> Vec<ZoomChoice> {
+    // Weight of a segment of this length or longer
+    const MAX_RUN: usize = 8;
+    let area = observed.area();
+    let get = |x: i64, z: i64| {
+        if area.contains(x, z) {
+            observed.a[((x - area.x) as usize, (z - area.z) as usize)]
+        } else {
+            None
+        }
+    };
+    let (p_w, p_h) = pmap.a.dim();
+    let mut choices = vec![];
+    let mut run: Vec<ZoomChoice> = vec![];
+    let flush = |run: &mut Vec<ZoomChoice>, choices: &mut Vec<ZoomChoice>| {
+        if run.len() >= min_run.max(1) {
+            let weight = run.len().min(MAX_RUN) as f32 / MAX_RUN as f32;
+            choices.extend(run.iter().map(|c| ZoomChoice { weight, ..*c }));
+        }
+        run.clear();
+    };
+
+    // The first call decides between a and b, the tile below: horizontal borders. The second
+    // call decides between a and a1, the tile to the right: vertical borders.
+    for second_call in [false, true] {
+        let (outer, inner) = if second_call { (p_w - 1, p_h - 1) } else { (p_h - 1, p_w - 1) };
+        for i in 0..outer {
+            for j in 0..inner {
+                let (x, z) = if second_call { (i, j) } else { (j, i) };
+                let a = pmap.a[(x, z)];
+                let other = if second_call { pmap.a[(x + 1, z)] } else { pmap.a[(x, z + 1)] };
+                let chunk_x = (x as i64 + pmap.x) << 1;
+                let chunk_z = (z as i64 + pmap.z) << 1;
+                let o = if second_call { get(chunk_x + 1, chunk_z) } else { get(chunk_x, chunk_z + 1) };
+                let value = match o {
+                    Some(o) if a != other && o == a => false,
+                    Some(o) if a != other && o == other => true,
+                    _ => {
+                        flush(&mut run, &mut choices);
+                        continue;
+                    }
+                };
+                if run.last().map_or(false, |c| c.value != value) {
+                    flush(&mut run, &mut choices);
+                }
+                run.push(ZoomChoice { chunk_x, chunk_z, second_call, value, weight: 0.0 });
+            }
+            flush(&mut run, &mut choices);
+        }
     }
+
+    choices
+}
+
+/// Experimental: how well this world seed explains the choices, as the sum of the weights of the
+/// matching choices minus the weights of the other choices. Only depends on the lower 25 bits of
+/// the world seed.
+pub fn zoom_prior_score(base_seed: i64, world_seed: i64, choices: &[ZoomChoice]) -> f32 {
+    let mut r = McRng::new(base_seed, world_seed);
+    choices.iter().map(|c| {
+        r.set_chunk_seed(c.chunk_x, c.chunk_z);
+        let first = r.next_int_n(2) == 1;
+        let value = if c.second_call { r.next_int_n(2) == 1 } else { first };
+        if value == c.value { c.weight } else { -c.weight }
+    }).sum()
+}
+
+/// Experimental: sort the seeds so the ones that best explain the `MapZoom` choices come first.
+/// The result can be passed to `river_seed_finder_26_seeds_cancellable` to check the most likely
+/// seeds first.
+pub fn order_seeds_by_zoom_prior(base_seed: i64, choices: &[ZoomChoice], seeds: Vec<i64>) -> Vec<i64> {
+    let mut scored: Vec<(i64, f32)> = seeds.into_iter().map(|s| (s, zoom_prior_score(base_seed, s, choices))).collect();
+    // Stable sort, so seeds with the same score keep the original order
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    scored.into_iter().map(|(s, _)| s).collect()
+}
+
+/// Filter the world seeds that are consistent with the observed output of a `MapZoom` layer.
+/// See `map_zoom_matches_evidence`.
+pub fn filter_map_zoom_seeds<I>(candidates: I, base_seed: i64, pmap: &Map, observed: &SparseMap, bits: u8) -> Vec<i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    candidates.into_iter().filter(|world_seed| {
+        map_zoom_matches_evidence(base_seed, *world_seed, pmap, observed, bits)
+    }).collect()
+}
+
+/// Find all the `bits`-bit world seeds consistent with the observed output of a `MapZoom` layer,
+/// given its input `pmap`. This works with any biome borders, not only rivers: each tile picked
+/// at random between two different values gives one bit of information.
+///
+/// bits: 25 or 26
+pub fn map_zoom_seeds_from_evidence(base_seed: i64, pmap: &Map, observed: &SparseMap, bits: u8) -> Vec<i64> {
+    assert!(bits == 25 || bits == 26);
+    filter_map_zoom_seeds(0..1 << bits, base_seed, pmap, observed, bits)
 }
 
 pub struct MapZoomFuzzy {
@@ -1934,24 +2298,12 @@ impl MapZoomFuzzy {
 impl GetMap for MapZoomFuzzy {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            let parea = Area {
-                x: area.x >> 1,
-                z: area.z >> 1,
-                w: (area.w >> 1) + 2,
-                h: (area.h >> 1) + 2
-            };
+            // The output of get_map_from_pmap starts at 2 * parea.x
+            let parea = zoom_parent_area(area, 1, 0);
             let pmap = parent.get_map(parea);
 
             let mut map = self.get_map_from_pmap(&pmap);
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x) & 1, (area.z) & 1);
-            map.x += nx;
-            map.z += nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -2020,6 +2372,9 @@ impl GetMap for MapZoomFuzzy {
 
         map
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 2, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 /// Unlike the regular MapZoom, this one makes sure that v11 is different
@@ -2044,24 +2399,12 @@ impl HelperMapZoomAllEdges {
 impl GetMap for HelperMapZoomAllEdges {
     fn get_map(&self, area: Area) -> Map {
         if let Some(ref parent) = self.parent {
-            let parea = Area {
-                x: area.x >> 1,
-                z: area.z >> 1,
-                w: (area.w >> 1) + 2,
-                h: (area.h >> 1) + 2
-            };
+            // The output of get_map_from_pmap starts at 2 * parea.x
+            let parea = zoom_parent_area(area, 1, 0);
             let pmap = parent.get_map(parea);
 
             let mut map = self.get_map_from_pmap(&pmap);
-            // TODO: is this correct?
-            let (nx, nz) = ((area.x) & 1, (area.z) & 1);
-            map.x += nx;
-            map.z += nz;
-            let (nx, nz) = (nx as usize, nz as usize);
-            map.a.slice_collapse(s![
-                    nx..nx + area.w as usize,
-                    nz..nz + area.h as usize
-            ]);
+            map.crop(area);
 
             map
         } else {
@@ -2140,6 +2483,9 @@ impl GetMap for HelperMapZoomAllEdges {
 
         map
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 2, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 /// This layer uses 64 bits but only affects shores (regions near ocean).
@@ -2253,6 +2599,9 @@ impl GetMap for MapAddIsland {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRemoveTooMuchOcean {
@@ -2321,6 +2670,9 @@ impl GetMap for MapRemoveTooMuchOcean {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapAddSnow {
@@ -2358,7 +2710,7 @@ impl GetMap for MapAddSnow {
             } else {
                 let mut r = r;
                 r.set_chunk_seed(x, z);
-                let r = r.next_int_n(6);
+                let r = r.next_int_n_const::<6>();
 
                 if r == 0 {
                     4
@@ -2370,6 +2722,9 @@ impl GetMap for MapAddSnow {
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapCoolWarm {
@@ -2440,6 +2795,9 @@ impl GetMap for MapCoolWarm {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapHeatIce {
@@ -2510,6 +2868,9 @@ impl GetMap for MapHeatIce {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapSpecial {
@@ -2544,7 +2905,7 @@ impl GetMap for MapSpecial {
 
             if v != 0 {
                 r.set_chunk_seed(x, z);
-                if r.next_int_n(13) == 0 {
+                if r.next_int_n_const::<13>() == 0 {
                     // What does this mean?
                     // if v == 1 and here we set it to 0x101..0xF01
                     // then it won't trigger any v == 1 checks in the future
@@ -2555,6 +2916,9 @@ impl GetMap for MapSpecial {
             v
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapAddMushroomIsland {
@@ -2619,7 +2983,7 @@ impl GetMap for MapAddMushroomIsland {
                     // spawn a mushroom island, the scale is 1:256 so we
                     // don't need precise coordinates.
                     // Rewrite this as (r % 4 == 0 && r % 25 == 0)
-                    if r.next_int_n(100) == 0 {
+                    if r.next_int_n_const::<100>() == 0 {
                         v11 = 14; // mushroomIsland
                     }
                 }
@@ -2630,6 +2994,9 @@ impl GetMap for MapAddMushroomIsland {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapDeepOcean {
@@ -2692,17 +3059,69 @@ impl GetMap for MapDeepOcean {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
+}
+
+/// Biomes used by `MapBiome13`, `MapBiome` and `MapBiomeEdge` in each version. The arrays are in
+/// the same order as in Java, because the index is chosen using `next_int_n`. Before 1.7 there
+/// are no climates, all the land uses the same array. From 1.7 to 1.17 the arrays are the same,
+/// only the edges of bambooJungle change.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BiomeTables {
+    /// Biomes of the land before 1.7, used by `MapBiome13`. Empty starting from 1.7
+    pub land: &'static [i32],
+    /// Biomes of each climate starting from 1.7, used by `MapBiome`. Empty before 1.7
+    pub warm: &'static [i32],
+    pub lush: &'static [i32],
+    pub cold: &'static [i32],
+    pub snow: &'static [i32],
+    /// Swampland next to bambooJungle becomes jungleEdge, like next to jungle. bambooJungle was
+    /// added in 1.14
+    pub bamboo_jungle_edge: bool,
+}
+
+impl BiomeTables {
+    pub fn new(version: MinecraftVersion) -> Self {
+        use biome_id::*;
+        use MinecraftVersion::*;
+        let tables_1_7 = Self {
+            land: &[],
+            warm: &[desert, desert, desert, savanna, savanna, plains],
+            lush: &[forest, roofedForest, extremeHills, plains, birchForest, swampland],
+            cold: &[forest, extremeHills, taiga, plains],
+            snow: &[icePlains, icePlains, icePlains, coldTaiga],
+            bamboo_jungle_edge: false,
+        };
+
+        match version {
+            Java1_3 => Self {
+                land: &[desert, forest, extremeHills, swampland, plains, taiga, jungle],
+                warm: &[],
+                lush: &[],
+                cold: &[],
+                snow: &[],
+                bamboo_jungle_edge: false,
+            },
+            Java1_7 | Java1_9 | Java1_11 | Java1_13 => tables_1_7,
+            Java1_14 | Java1_15 | Java1_16_1 | Java1_16 | Java1_17 => Self { bamboo_jungle_edge: true, ..tables_1_7 },
+            _ => panic!("Biome tables not implemented for version {:?}", version),
+        }
+    }
 }
 
 pub struct MapBiome {
     base_seed: i64,
     world_seed: i64,
+    mc_version: MinecraftVersion,
+    tables: BiomeTables,
     pub parent: Option<Rc<dyn GetMap>>,
 }
 
 impl MapBiome {
-    pub fn new(base_seed: i64, world_seed: i64) -> Self {
-        Self { base_seed, world_seed, parent: None }
+    pub fn new(base_seed: i64, world_seed: i64, mc_version: MinecraftVersion) -> Self {
+        Self { base_seed, world_seed, mc_version, tables: BiomeTables::new(mc_version), parent: None }
     }
 }
 
@@ -2720,10 +3139,7 @@ impl GetMap for MapBiome {
     // pmap has no margin: pmap.w == map.w
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
         use biome_id::*;
-        let warmBiomes = [desert, desert, desert, savanna, savanna, plains];
-        let lushBiomes = [forest, roofedForest, extremeHills, plains, birchForest, swampland];
-        let coldBiomes = [forest, extremeHills, taiga, plains];
-        let snowBiomes = [icePlains, icePlains, icePlains, coldTaiga];
+        let BiomeTables { warm: warmBiomes, lush: lushBiomes, cold: coldBiomes, snow: snowBiomes, .. } = self.tables;
         let r = McRng::new(self.base_seed, self.world_seed);
 
         MapParentFn(PanicMap, |x, z, v| {
@@ -2732,7 +3148,7 @@ impl GetMap for MapBiome {
 
             let has_high_bit = ((id & 0xf00) >> 8) != 0;
             id &= !0xf00;
-            if get_category(MinecraftVersion::Java1_16, id) == Some(Ocean) || id == mushroomIsland {
+            if get_category(self.mc_version, id) == Some(Ocean) || id == mushroomIsland {
                 return id;
             }
 
@@ -2747,25 +3163,25 @@ impl GetMap for MapBiome {
                             mesaPlateau_F
                         }
                     } else {
-                        warmBiomes[r.next_int_n(6) as usize]
+                        warmBiomes[r.next_int_n(warmBiomes.len() as i32) as usize]
                     }
                 }
                 Lush => {
                     if has_high_bit {
                         jungle
                     } else {
-                        lushBiomes[r.next_int_n(6) as usize]
+                        lushBiomes[r.next_int_n(lushBiomes.len() as i32) as usize]
                     }
                 }
                 Cold => {
                     if has_high_bit {
                         megaTaiga
                     } else {
-                        coldBiomes[r.next_int_n(4) as usize]
+                        coldBiomes[r.next_int_n(coldBiomes.len() as i32) as usize]
                     }
                 }
                 Freezing => {
-                    snowBiomes[r.next_int_n(4) as usize]
+                    snowBiomes[r.next_int_n(snowBiomes.len() as i32) as usize]
                 }
                 _ => {
                     mushroomIsland
@@ -2773,45 +3189,9 @@ impl GetMap for MapBiome {
             }
         }).get_map_from_pmap(pmap)
     }
-}
-
-fn is_shallow_ocean(id: i32) -> bool {
-    use biome_id::*;
-    match id {
-        ocean | warmOcean | lukewarmOcean | coldOcean | frozenOcean => true,
-        _ => false,
-    }
-}
-
-fn is_deep_ocean(id: i32) -> bool {
-    use biome_id::*;
-    match id {
-        deepOcean | warmDeepOcean | lukewarmDeepOcean | coldDeepOcean | frozenDeepOcean => true,
-        _ => false,
-    }
-}
-
-fn equal_or_plateau(version: MinecraftVersion, id1: i32, id2: i32) -> bool {
-    use biome_id::*;
-    if id1 == id2 {
-        return true;
-    }
-    if id1 == mesaPlateau_F || id1 == mesaPlateau {
-        return id2 == mesaPlateau_F || id2 == mesaPlateau;
-    }
-    if !biome_exists(id1) || !biome_exists(id2) {
-        return false;
-    }
-    // adjust for asymmetric equality (workaround to simulate a bug in the MC java code)
-    if id1 >= 128 || id2 >= 128 {
-        // skip biomes that did not overload the isEqualTo() method
-        if id2 == 130 || id2 == 133 || id2 == 134 || id2 == 149 || id2 == 151 || id2 == 155 ||
-           id2 == 156 || id2 == 157 || id2 == 158 || id2 == 163 || id2 == 164 {
-               return false;
-           }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
     }
-
-    get_category(version, id1) == get_category(version, id2)
 }
 
 fn replace_edge(version: MinecraftVersion, out: &mut i32, v10: i32, v21: i32, v01: i32, v12: i32, id: i32, base_id: i32, edge_id: i32) -> bool {
@@ -2831,12 +3211,14 @@ fn replace_edge(version: MinecraftVersion, out: &mut i32, v10: i32, v21: i32, v0
 pub struct MapBiomeEdge {
     base_seed: i64,
     world_seed: i64,
+    mc_version: MinecraftVersion,
+    tables: BiomeTables,
     pub parent: Option<Rc<dyn GetMap>>,
 }
 
 impl MapBiomeEdge {
-    pub fn new(base_seed: i64, world_seed: i64) -> Self {
-        Self { base_seed, world_seed, parent: None }
+    pub fn new(base_seed: i64, world_seed: i64, mc_version: MinecraftVersion) -> Self {
+        Self { base_seed, world_seed, mc_version, tables: BiomeTables::new(mc_version), parent: None }
     }
 }
 
@@ -2863,7 +3245,8 @@ impl GetMap for MapBiomeEdge {
     // pmap has 1 wide margin on each size: pmap.w == map.w + 2
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
         use biome_id::*;
-        let version = MinecraftVersion::Java1_16;
+        let version = self.mc_version;
+        let bamboo_jungle_edge = self.tables.bamboo_jungle_edge;
         let (p_w, p_h) = pmap.a.dim();
         let area = Area {
             x: pmap.x + 1,
@@ -2897,9 +3280,8 @@ impl GetMap for MapBiomeEdge {
                                v10 != coldTaiga && v21 != coldTaiga && v01 != coldTaiga && v12 != coldTaiga &&
                                v10 != icePlains && v21 != icePlains && v01 != icePlains && v12 != icePlains {
                                 if v10 != jungle && v12 != jungle && v21 != jungle && v01 != jungle
-                                    // TODO: bambooJungle is from 1.14
-                                    && v10 != bambooJungle && v12 != bambooJungle && v21 != bambooJungle
-                                    && v01 != bambooJungle {
+                                    && !(bamboo_jungle_edge && (v10 == bambooJungle || v12 == bambooJungle
+                                    || v21 == bambooJungle || v01 == bambooJungle)) {
                                     v11
                                 } else {
                                     jungleEdge
@@ -2918,6 +3300,9 @@ impl GetMap for MapBiomeEdge {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRiverInit {
@@ -2950,12 +3335,15 @@ impl GetMap for MapRiverInit {
             if v > 0 {
                 let mut r = r;
                 r.set_chunk_seed(x, z);
-                r.next_int_n(299999) + 2
+                r.next_int_n_const::<299999>() + 2
             } else {
                 0
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub fn pretty_biome_map_hills(id: i32) -> i32 {
@@ -3094,6 +3482,9 @@ impl GetMap for MapHills {
     fn get_map_from_pmap(&self, _pmap: &Map) -> Map {
         panic!("MapHills requires 2 pmaps!");
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent1.iter().chain(&self.parent2).map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRareBiome {
@@ -3132,7 +3523,7 @@ impl GetMap for MapRareBiome {
             let mut r = r;
             r.set_chunk_seed(x, z);
 
-            if r.next_int_n(57) == 0 && v == plains {
+            if r.next_int_n_const::<57>() == 0 && v == plains {
                 // Sunflower Plains
                 plains + 128
             } else {
@@ -3140,6 +3531,9 @@ impl GetMap for MapRareBiome {
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapShore {
@@ -3266,6 +3660,9 @@ impl GetMap for MapShore {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapSmooth {
@@ -3302,6 +3699,18 @@ impl GetMap for MapSmooth {
 
     // pmap has 1 wide margin on each size: pmap.w == map.w + 2
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
+        let mut m = Map::default();
+        self.get_map_from_pmap_into(pmap, &mut m);
+
+        m
+    }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
+}
+
+impl GetMapInto for MapSmooth {
+    fn get_map_from_pmap_into(&self, pmap: &Map, m: &mut Map) {
         let (p_w, p_h) = pmap.a.dim();
         let area = Area {
             x: pmap.x + 1,
@@ -3309,7 +3718,7 @@ impl GetMap for MapSmooth {
             w: p_w as u64 - 2,
             h: p_h as u64 - 2
         };
-        let mut m = Map::new(area);
+        m.reset(area);
         let mut r = McRng::new(self.base_seed, self.world_seed);
         for x in 0..area.w as usize {
             for z in 0..area.h as usize {
@@ -3352,8 +3761,6 @@ impl GetMap for MapSmooth {
                 m.a[(x, z)] = v11;
             }
         }
-
-        m
     }
 }
 
@@ -3428,6 +3835,9 @@ impl GetMap for MapRiver {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 /// Like MapRiver, but will generate all the possible rivers for this 26-bit seed
@@ -3465,6 +3875,18 @@ impl GetMap for HelperMapRiverAll {
 
     // pmap has 1 wide margin on each size: pmap.w == map.w + 2
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
+        let mut m = Map::default();
+        self.get_map_from_pmap_into(pmap, &mut m);
+
+        m
+    }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
+}
+
+impl GetMapInto for HelperMapRiverAll {
+    fn get_map_from_pmap_into(&self, pmap: &Map, m: &mut Map) {
         use biome_id::*;
         let (p_w, p_h) = pmap.a.dim();
         assert!(p_w > 2);
@@ -3475,7 +3897,7 @@ impl GetMap for HelperMapRiverAll {
             w: p_w as u64 - 2,
             h: p_h as u64 - 2
         };
-        let mut m = Map::new(area);
+        m.reset(area);
         for x in 0..area.w as usize {
             for z in 0..area.h as usize {
                 let v11 = pmap.a[(x+1, z+1)];
@@ -3491,8 +3913,6 @@ impl GetMap for HelperMapRiverAll {
                 };
             }
         }
-
-        m
     }
 }
 
@@ -3569,6 +3989,9 @@ impl GetMap for MapRiverMix {
     fn get_map_from_pmap(&self, _pmap: &Map) -> Map {
         panic!("MapRiverMix requires 2 pmaps!")
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent1.iter().chain(&self.parent2).map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapOceanTemp {
@@ -3610,6 +4033,9 @@ impl GetMap for MapOceanTemp {
 
         self.get_map(area)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, vec![])
+    }
 }
 
 
@@ -3626,25 +4052,50 @@ impl MapOceanMix {
     pub fn new(base_seed: i64, world_seed: i64) -> Self {
         Self { base_seed, world_seed, parent1: None, parent2: None }
     }
+    /// Area of the land map needed to generate the area of the ocean map `pmap2`. The land map is
+    /// only checked in a 17x17 square around warm and frozen oceans, so when there are none this
+    /// is the same area as `pmap2`, instead of the full 8 tile margin.
+    pub fn land_area(pmap2: &Map) -> Area {
+        use biome_id::*;
+        let area = pmap2.area();
+        let warm_or_frozen = pmap2.a.indexed_iter().filter(|(_, v)| **v == warmOcean || **v == frozenOcean).map(|((x, z), _)| {
+            (area.x + x as i64, area.z + z as i64)
+        });
+        let mut coords: Vec<Point> = warm_or_frozen.flat_map(|(x, z)| {
+            vec![Point { x: x - 8, z: z - 8 }, Point { x: x + 8, z: z + 8 }]
+        }).collect();
+        if coords.is_empty() {
+            return area;
+        }
+        // There is at least one tile, so the area is not empty
+        coords.push(Point { x: area.x, z: area.z });
+        coords.push(Point { x: area.x + area.w as i64 - 1, z: area.z + area.h as i64 - 1 });
+
+        Area::from_coords(coords)
+    }
+    /// `pmap1` must contain at least `MapOceanMix::land_area(pmap2)`
     pub fn get_map_from_pmap12(&self, pmap1: &Map, pmap2: &Map) -> Map {
         use biome_id::*;
         let (p_w, p_h) = pmap2.a.dim();
-        {
-            // Check that both maps have the expected size and offset
-            let area = pmap2.area();
-            let land_area = Area {
-                x: area.x - 8,
-                z: area.z - 8,
-                w: area.w + 17,
-                h: area.h + 17,
-            };
-            assert_eq!(pmap1.area(), land_area);
-        }
+        // Offset of pmap2 inside pmap1, the 5x5 scan starts 8 tiles before this
+        let (dx, dz) = {
+            // Check that the land map contains all the tiles needed
+            let land_area = Self::land_area(pmap2);
+            let area = pmap1.area();
+            assert!(
+                area.x <= land_area.x
+                    && area.z <= land_area.z
+                    && area.x + area.w as i64 >= land_area.x + land_area.w as i64
+                    && area.z + area.h as i64 >= land_area.z + land_area.h as i64,
+                "Land map {:?} does not contain {:?}", area, land_area
+            );
+            ((pmap2.x - pmap1.x) as usize, (pmap2.z - pmap1.z) as usize)
+        };
 
         let mut m = pmap2.clone();
         for x in 0..p_w as usize {
             'loop_z: for z in 0..p_h as usize {
-                let land_id = pmap1.a[(x+8, z+8)];
+                let land_id = pmap1.a[(x+dx, z+dz)];
                 let mut ocean_id = pmap2.a[(x, z)];
 
                 if !is_oceanic(land_id) {
@@ -3659,7 +4110,7 @@ impl MapOceanMix {
                         for j in 0..=4 {
                             let i = i * 4;
                             let j = j * 4;
-                            let nearby_id = pmap1.a[(x + i, z + j)];
+                            let nearby_id = pmap1.a[(x + dx + i - 8, z + dz + j - 8)];
 
                             if is_oceanic(nearby_id) {
                                 continue;
@@ -3699,20 +4150,10 @@ impl MapOceanMix {
 impl GetMap for MapOceanMix {
     fn get_map(&self, area: Area) -> Map {
         if let (Some(ref parent1), Some(ref parent2)) = (&self.parent1, &self.parent2) {
-            let land_area = Area {
-                x: area.x - 8,
-                z: area.z - 8,
-                w: area.w + 17,
-                h: area.h + 17,
-            };
-            let parea = Area {
-                x: area.x,
-                z: area.z,
-                w: area.w,
-                h: area.h
-            };
-            let pmap1 = parent1.get_map(land_area);
-            let pmap2 = parent2.get_map(parea);
+            // Generate the ocean temperature first, the land margin is only needed around warm
+            // and frozen oceans
+            let pmap2 = parent2.get_map(area);
+            let pmap1 = parent1.get_map(Self::land_area(&pmap2));
 
             let map = self.get_map_from_pmap12(&pmap1, &pmap2);
 
@@ -3727,6 +4168,9 @@ impl GetMap for MapOceanMix {
     fn get_map_from_pmap(&self, _pmap: &Map) -> Map {
         panic!("MapOceanMix requires 2 pmaps!")
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent1.iter().chain(&self.parent2).map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapSkip {
@@ -3761,24 +4205,11 @@ impl GetMap for MapSkip {
             match self.zoom_factor {
                 0 => parent.get_map(area),
                 1 => {
-                    let parea = Area {
-                        x: area.x >> 1,
-                        z: area.z >> 1,
-                        w: (area.w >> 1) + 2,
-                        h: (area.h >> 1) + 2
-                    };
+                    let parea = zoom_parent_area(area, 1, 0);
                     let pmap = parent.get_map(parea);
 
                     let mut map = self.get_map_from_pmap(&pmap);
-                    // TODO: is this correct?
-                    let (nx, nz) = ((area.x) & 1, (area.z) & 1);
-                    map.x += nx;
-                    map.z += nz;
-                    let (nx, nz) = (nx as usize, nz as usize);
-                    map.a.slice_collapse(s![
-                            nx..nx + area.w as usize,
-                            nz..nz + area.h as usize
-                    ]);
+                    map.crop(area);
 
                     map
                 }
@@ -3821,6 +4252,9 @@ impl GetMap for MapSkip {
             }
         }
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 1 << self.zoom_factor, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapAddBamboo {
@@ -3855,7 +4289,7 @@ impl GetMap for MapAddBamboo {
             if v == jungle {
                 let mut r = r;
                 r.set_chunk_seed(x, z);
-                if r.next_int_n(10) == 0 {
+                if r.next_int_n_const::<10>() == 0 {
                     return bambooJungle;
                 }
             }
@@ -3863,6 +4297,9 @@ impl GetMap for MapAddBamboo {
             v
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapAddIsland13 {
@@ -3974,6 +4411,9 @@ impl GetMap for MapAddIsland13 {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapIcePlains {
@@ -4018,6 +4458,9 @@ impl GetMap for MapIcePlains {
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapBiome13 {
@@ -4046,7 +4489,7 @@ impl GetMap for MapBiome13 {
     // pmap has no margin: pmap.w == map.w
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
         use biome_id::*;
-        let biomes = [desert, forest, extremeHills, swampland, plains, taiga, jungle];
+        let biomes = BiomeTables::new(MinecraftVersion::Java1_3).land;
 
         let r = McRng::new(self.base_seed, self.world_seed);
         MapParentFn(PanicMap, |x, z, v| {
@@ -4068,6 +4511,9 @@ impl GetMap for MapBiome13 {
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRegionHills {
@@ -4155,6 +4601,9 @@ impl GetMap for MapRegionHills {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapMushroomShore {
@@ -4236,6 +4685,9 @@ impl GetMap for MapMushroomShore {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapSwampRivers {
@@ -4269,13 +4721,16 @@ impl GetMap for MapSwampRivers {
             let mut r = r;
             r.set_chunk_seed(x, z);
 
-            if (v != swampland || r.next_int_n(6) != 0) && (v != jungle && v != jungleHills || r.next_int_n(8) != 0) {
+            if (v != swampland || r.next_int_n_const::<6>() != 0) && (v != jungle && v != jungleHills || r.next_int_n(8) != 0) {
                 v
             } else {
                 river
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRiverInit13 {
@@ -4314,6 +4769,9 @@ impl GetMap for MapRiverInit13 {
             }
         }).get_map_from_pmap(pmap)
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRiver13 {
@@ -4377,6 +4835,9 @@ impl GetMap for MapRiver13 {
 
         m
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent.iter().map(|p| p.layer_info()).collect())
+    }
 }
 
 pub struct MapRiverMix13 {
@@ -4450,11 +4911,132 @@ impl GetMap for MapRiverMix13 {
     fn get_map_from_pmap(&self, _pmap: &Map) -> Map {
         panic!("MapRiverMix13 requires 2 pmaps!")
     }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, Some(self.base_seed), 1, self.parent1.iter().chain(&self.parent2).map(|p| p.layer_info()).collect())
+    }
+}
+
+
+/// Like `SparseMap`, but each tile has a set of possible values. An empty set means that the
+/// tile is unknown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandidateSetMap<T> {
+    pub x: i64,
+    pub z: i64,
+    pub a: Array2<Vec<T>>,
+}
+
+impl<T> CandidateSetMap<T> {
+    pub fn new(a: Area) -> Self {
+        Self { x: a.x, z: a.z, a: Array2::default((a.w as usize, a.h as usize)) }
+    }
+    pub fn area(&self) -> Area {
+        let (w, h) = self.a.dim();
+        Area { x: self.x, z: self.z, w: w as u64, h: h as u64 }
+    }
+}
+
+/// Possible parents of each tile of the output of `MapRiverMix`, as (biome, is_river) pairs.
+///
+/// Unlike `decompose_map_river_mix`, this keeps the ambiguous tiles: a mushroomIslandShore can be
+/// a river in a mushroomIsland or in a mushroomIslandShore, or a mushroomIslandShore generated by
+/// MapShore without any river. The biome below a river is unknown, so it is set to
+/// `UNKNOWN_BIOME_ID`. Tiles with `UNKNOWN_BIOME_ID` have no candidates.
+pub fn decompose_map_river_mix_candidates(map: &Map) -> CandidateSetMap<(i32, bool)> {
+    use biome_id::*;
+    let mut candidates = CandidateSetMap::new(map.area());
+    for ((x, z), b) in map.a.indexed_iter() {
+        candidates.a[(x, z)] = match *b {
+            UNKNOWN_BIOME_ID => vec![],
+            frozenRiver => vec![(icePlains, true)],
+            mushroomIslandShore => vec![(mushroomIsland, true), (mushroomIslandShore, true), (mushroomIslandShore, false)],
+            river => vec![(UNKNOWN_BIOME_ID, true)],
+            // Rivers do not overwrite oceans
+            b if is_oceanic(b) => vec![(b, false), (b, true)],
+            b => vec![(b, false)],
+        };
+    }
+
+    candidates
+}
+
+/// Coordinates of the tiles that must have a river in the parent of `MapRiverMix`, given the
+/// candidates returned by `decompose_map_river_mix_candidates`.
+pub fn certain_rivers(candidates: &CandidateSetMap<(i32, bool)>) -> Vec<Point> {
+    candidates.a.indexed_iter().filter(|(_, c)| {
+        !c.is_empty() && c.iter().all(|(_biome, is_river)| *is_river)
+    }).map(|((x, z), _)| Point { x: candidates.x + x as i64, z: candidates.z + z as i64 }).collect()
+}
+
+/// Layer numbers of the two parents of `MapRiverMix`: the biomes and the rivers. `None` if the
+/// rivers of this version are not implemented.
+pub fn river_mix_parent_layers(version: MinecraftVersion) -> Option<(u32, u32)> {
+    match version.river_layers()? {
+        RiverLayers::Java1_3 => Some((22, 31)),
+        RiverLayers::Java1_7 => Some((33, 41)),
+    }
+}
+
+/// The river branch of the layers at 1:4 scale: the last layer before `MapRiverMix`, with its real
+/// parents, from `MapIsland` to `MapRiverInit`. This skips the biome layers and the ocean layers,
+/// so it is much faster than generating all the biomes. The tiles are `river` or -1.
+///
+/// The rivers are not yet removed from oceans and mushroom islands, and not converted into frozen
+/// rivers, so there can be more rivers than in the final map. This is fine to compare the rivers
+/// with observed rivers, which are always on land. Returns `None` if this version does not use
+/// river layers.
+pub fn rivers_only_generator(world_seed: i64, version: MinecraftVersion) -> Option<Box<dyn GetMap>> {
+    let (_, river_layer) = river_mix_parent_layers(version)?;
+
+    Some(match version {
+        MinecraftVersion::Java1_3 => generator_up_to_layer_1_3(world_seed, river_layer),
+        MinecraftVersion::Java1_7 | MinecraftVersion::Java1_9 | MinecraftVersion::Java1_11 => generator_up_to_layer_1_7(world_seed, river_layer, version),
+        MinecraftVersion::Java1_13 => generator_up_to_layer_1_13(world_seed, river_layer),
+        MinecraftVersion::Java1_14 => generator_up_to_layer_1_14(world_seed, river_layer),
+        _ => generator_up_to_layer_1_15(world_seed, river_layer, version),
+    })
+}
+
+/// Returns true if the observed quarter-scale `mushroomIslandShore` at `p` can be generated by this
+/// seed: either the parent of `MapRiverMix` was already a `mushroomIslandShore` without a river,
+/// or there is a river over a `mushroomIsland` or a `mushroomIslandShore`. These are the
+/// candidates of `decompose_map_river_mix_candidates`. Returns `None` if this version does not use
+/// river layers.
+pub fn mushroom_shore_matches(world_seed: i64, p: Point4, version: MinecraftVersion) -> Option<bool> {
+    use biome_id::*;
+    let (biome_layer, river_layer) = river_mix_parent_layers(version)?;
+    let area = Area { x: p.x, z: p.z, w: 1, h: 1 };
+    let biome = generate_up_to_layer(version, area, world_seed, biome_layer, 0).a[(0, 0)];
+    let is_river = generate_up_to_layer(version, area, world_seed, river_layer, 0).a[(0, 0)] == river;
+
+    Some(if is_river {
+        biome == mushroomIsland || biome == mushroomIslandShore
+    } else {
+        biome == mushroomIslandShore
+    })
 }
 
+/// Second pass for the candidates that passed all the other filters: the `mushroomIslandShore`
+/// tiles are ignored by the river seed finder, because they may or may not be rivers. Here each
+/// observed quarter-scale `mushroomIslandShore` is checked using both explanations, and the seeds
+/// that cannot generate all of them are rejected. Candidates are returned unchanged if there are
+/// no mushroom shores in `biomes4`. Returns `None` if this version does not use river layers.
+pub fn filter_seeds_using_mushroom_shores(candidates: &[i64], biomes4: &SparseMap, version: MinecraftVersion) -> Option<Vec<i64>> {
+    river_mix_parent_layers(version)?;
+    let shores: Vec<Point4> = sparse_map_to_biomes4(biomes4).into_iter().filter(|(b, _)| b.0 == biome_id::mushroomIslandShore).map(|(_, p)| p).collect();
+
+    Some(candidates.iter().copied().filter(|world_seed| {
+        let valid = shores.iter().all(|p| mushroom_shore_matches(*world_seed, *p, version) == Some(true));
+        if !valid {
+            debug!("{:016X}: mushroom shore mismatch", world_seed);
+        }
+        valid
+    }).collect())
+}
 
 /// We lose some information here :/
 /// Returns a tuple (BiomeMap, RiverMap)
+/// See `decompose_map_river_mix_candidates` for a version that keeps the ambiguous tiles.
 fn decompose_map_river_mix(map: &Map) -> (SparseMap, SparseMap) {
     use biome_id::*;
     let mut parent1 = SparseMap::new(map.area());
@@ -4481,11 +5063,34 @@ fn decompose_map_river_mix(map: &Map) -> (SparseMap, SparseMap) {
     (parent1, parent2)
 }
 
-// This is supposed to be MathHelper::sin in Minecraft, which uses a lookup
-// table, but here it is not implemented and calls the native sin function
-// instead
-fn fast_sin(x: f32) -> f32 {
-    x.sin()
+/// Implementation of the sine function used to draw the water pattern of treasure maps
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SinMode {
+    /// Native sin function. Slightly faster, and it is accurate enough for almost all the pixels
+    Native,
+    /// Same lookup table as MathHelper::sin in Minecraft, for bit-exact treasure maps. This is
+    /// the default
+    LookupTable,
+}
+
+impl Default for SinMode {
+    fn default() -> Self {
+        SinMode::LookupTable
+    }
+}
+
+lazy_static! {
+    // MathHelper::SIN_TABLE
+    static ref SIN_TABLE: Vec<f32> = (0..65536).map(|i| (i as f64 * std::f64::consts::PI * 2.0 / 65536.0).sin() as f32).collect();
+}
+
+// MathHelper::sin in Minecraft uses a lookup table
+fn fast_sin(x: f32, mode: SinMode) -> f32 {
+    match mode {
+        SinMode::Native => x.sin(),
+        // The float to int cast saturates like in Java
+        SinMode::LookupTable => SIN_TABLE[((x * 10430.378) as i32 & 65535) as usize],
+    }
 }
 
 // return biome height >= 0
@@ -4533,12 +5138,38 @@ pub fn add_margin_to_map(map: &Map, value: i32) -> Map {
     m
 }
 
+/// Color id of each pixel of a treasure map, as returned by `MapTreasure`. These are not biome
+/// ids, so this type prevents passing a treasure map to the functions that expect biomes, such as
+/// `draw_map_image`. Use `draw_treasure_map_image` to convert it to an image.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TreasureMapColors(pub Map);
+
+impl TreasureMapColors {
+    pub fn map(&self) -> &Map {
+        &self.0
+    }
+    pub fn into_map(self) -> Map {
+        self.0
+    }
+    pub fn area(&self) -> Area {
+        self.0.area()
+    }
+}
+
 /// Apply the unexplored treasure map filter
 // This is not a world generation layer
 // The output of this Map is not biome_id, but color_id.
-// Use the treasure_map_to_color function to convert it to RGB values
+// Use get_colors to get a TreasureMapColors, which can be converted to an image
 pub struct MapTreasure {
     pub parent: Rc<dyn GetMap>,
+    pub sin_mode: SinMode,
+}
+
+impl MapTreasure {
+    /// Same as `get_map`, but the output has the correct type
+    pub fn get_colors(&self, area: Area) -> TreasureMapColors {
+        TreasureMapColors(self.get_map(area))
+    }
 }
 
 impl GetMap for MapTreasure {
@@ -4558,7 +5189,6 @@ impl GetMap for MapTreasure {
 
     // pmap has 1 wide margin on each size: pmap.w == map.w + 2
     fn get_map_from_pmap(&self, pmap: &Map) -> Map {
-        // TODO: only 1:1 maps are implemented
         let (p_w, p_h) = pmap.a.dim();
         let area = Area {
             x: pmap.x + 1,
@@ -4566,96 +5196,159 @@ impl GetMap for MapTreasure {
             w: p_w as u64 - 2,
             h: p_h as u64 - 2
         };
-        let coords_in_fragment = |x: i64, z: i64| -> (u8, u8) {
-            // Input: from -32 + (128 * kx) to 95 + (128 * kz)
-            // Output: from 0 to 127
-            (((x + 32) & 0x7F) as u8, ((z + 32) & 0x7F) as u8)
+
+        treasure_map_colors(area, self.sin_mode, |x, z| pmap.a[(x, z)])
+    }
+    fn parent_layers(&self) -> Vec<&dyn GetMap> {
+        vec![&*self.parent]
+    }
+}
+
+/// Same as `MapTreasure`, but the parent is at 1:4 scale, for example the output of layer 50
+/// before any voronoi zoom. Each parent tile covers 2x2 pixels of the treasure map, so the parent
+/// area is 4 times smaller.
+pub struct MapTreasureQuarterScale {
+    pub parent: Rc<dyn GetMap>,
+    pub sin_mode: SinMode,
+}
+
+impl MapTreasureQuarterScale {
+    /// Same as `get_map`, but the output has the correct type
+    pub fn get_colors(&self, area: Area) -> TreasureMapColors {
+        TreasureMapColors(self.get_map(area))
+    }
+}
+
+impl GetMap for MapTreasureQuarterScale {
+    fn get_map(&self, area: Area) -> Map {
+        // The 1:2 margin is 1 pixel wide, which can be a whole parent tile
+        let (x0, z0) = ((area.x - 1) >> 1, (area.z - 1) >> 1);
+        let (x1, z1) = ((area.x + area.w as i64) >> 1, (area.z + area.h as i64) >> 1);
+        let parea = Area {
+            x: x0,
+            z: z0,
+            w: (x1 - x0 + 1) as u64,
+            h: (z1 - z0 + 1) as u64
         };
-        let mut m = Map::new(area);
+        let pmap = self.parent.get_map(parea);
 
-        for x in 0..area.w as usize {
-            for z in 0..area.h as usize {
-                let mut num_water_neighbors = 8;
+        treasure_map_colors(area, self.sin_mode, |x, z| {
+            let px = ((area.x - 1 + x as i64) >> 1) - pmap.x;
+            let pz = ((area.z - 1 + z as i64) >> 1) - pmap.z;
+            pmap.a[(px as usize, pz as usize)]
+        })
+    }
 
-                for i in 0..3 {
-                    for j in 0..3 {
-                        if i == 1 && j == 1 {
-                            continue;
-                        }
-                        if is_land_biome(pmap.a[(x+i, z+j)]) {
-                            num_water_neighbors -= 1;
-                        }
-                    }
-                }
+    // pmap covers the output area with a margin of 1 tile: the output has 2*pmap.w - 2 pixels
+    fn get_map_from_pmap(&self, pmap: &Map) -> Map {
+        let (p_w, p_h) = pmap.a.dim();
+        let area = Area {
+            x: pmap.x * 2 + 1,
+            z: pmap.z * 2 + 1,
+            w: p_w as u64 * 2 - 2,
+            h: p_h as u64 * 2 - 2
+        };
 
-                // Land color. Default: black (transparent).
-                let color_land = 0;
-                // Water color.
-                let color_water = 15;
-                // Land-water border color.
-                let color_shore = 26;
-                let color;
-                let color_variant;
+        treasure_map_colors(area, self.sin_mode, |x, z| pmap.a[(x >> 1, z >> 1)])
+    }
+    fn layer_info(&self) -> LayerInfo {
+        LayerInfo::new(self, None, 2, vec![self.parent.layer_info()])
+    }
+}
 
-                let v11 = pmap.a[(x+1, z+1)];
+// Color the treasure map pixels of area. biome_at(x, z) returns the biome at 1:2 scale, with
+// coordinates relative to (area.x - 1, area.z - 1) so that the margin starts at 0.
+fn treasure_map_colors<F: Fn(usize, usize) -> i32>(area: Area, sin_mode: SinMode, biome_at: F) -> Map {
+    let coords_in_fragment = |x: i64, z: i64| -> (u8, u8) {
+        // Input: from -32 + (128 * kx) to 95 + (128 * kz)
+        // Output: from 0 to 127
+        (((x + 32) & 0x7F) as u8, ((z + 32) & 0x7F) as u8)
+    };
+    let mut m = Map::new(area);
 
-                if !is_land_biome(v11) {
-                    // If v11 is water
-                    // xf and zf are the coordinates inside the map fragment
-                    // must be in range [0, 127]
-                    let (xf, zf) = coords_in_fragment(area.x + x as i64, area.z + z as i64);
-                    if num_water_neighbors > 7 && zf % 2 == 0 {
-                        color = color_water;
-                        let mut random_int_5 = (xf as i32 + (fast_sin((zf as f32) + 0.0) * 7.0) as i32) / 8 % 5;
-                        // Map color_variant from (0, 1, 2, 3, 4) to (0, 1, 2, 1, 0)
-                        if random_int_5 == 3 {
-                            random_int_5 = 1;
-                        } else if random_int_5 == 4 {
-                            random_int_5 = 0;
-                        }
-                        color_variant = random_int_5;
-                    } else if num_water_neighbors > 7 {
-                        color = color_land;
-                        color_variant = 3;
-                    } else if num_water_neighbors > 5 {
-                        color = color_water;
-                        color_variant = 1;
-                    } else if num_water_neighbors > 3 {
-                        color = color_water;
-                        color_variant = 0;
-                    } else if num_water_neighbors > 1 {
-                        color = color_water;
-                        color_variant = 0;
-                    } else {
-                        color = color_water;
-                        color_variant = 3;
+    for x in 0..area.w as usize {
+        for z in 0..area.h as usize {
+            let mut num_water_neighbors = 8;
+
+            for i in 0..3 {
+                for j in 0..3 {
+                    if i == 1 && j == 1 {
+                        continue;
                     }
-                } else if num_water_neighbors > 0 {
-                    // If v11 is land but at least one of the 8-connected neighbors is water
-                    color = color_shore;
-                    if num_water_neighbors > 3 {
-                        color_variant = 1;
-                    } else {
-                        color_variant = 3;
+                    if is_land_biome(biome_at(x+i, z+j)) {
+                        num_water_neighbors -= 1;
                     }
-                } else {
-                    // If v11 is land and all of the 8-connected neighbors are also land
+                }
+            }
+
+            // Land color. Default: black (transparent).
+            let color_land = 0;
+            // Water color.
+            let color_water = 15;
+            // Land-water border color.
+            let color_shore = 26;
+            let color;
+            let color_variant;
+
+            let v11 = biome_at(x+1, z+1);
+
+            if !is_land_biome(v11) {
+                // If v11 is water
+                // xf and zf are the coordinates inside the map fragment
+                // must be in range [0, 127]
+                let (xf, zf) = coords_in_fragment(area.x + x as i64, area.z + z as i64);
+                if num_water_neighbors > 7 && zf % 2 == 0 {
+                    color = color_water;
+                    let mut random_int_5 = (xf as i32 + (fast_sin((zf as f32) + 0.0, sin_mode) * 7.0) as i32) / 8 % 5;
+                    // Map color_variant from (0, 1, 2, 3, 4) to (0, 1, 2, 1, 0)
+                    if random_int_5 == 3 {
+                        random_int_5 = 1;
+                    } else if random_int_5 == 4 {
+                        random_int_5 = 0;
+                    }
+                    color_variant = random_int_5;
+                } else if num_water_neighbors > 7 {
                     color = color_land;
                     color_variant = 3;
+                } else if num_water_neighbors > 5 {
+                    color = color_water;
+                    color_variant = 1;
+                } else if num_water_neighbors > 3 {
+                    color = color_water;
+                    color_variant = 0;
+                } else if num_water_neighbors > 1 {
+                    color = color_water;
+                    color_variant = 0;
+                } else {
+                    color = color_water;
+                    color_variant = 3;
                 }
-
-                if color != color_land {
-                    // color_variant is always in [0, 3]
-                    m.a[(x, z)] = color * 4 + color_variant;
+            } else if num_water_neighbors > 0 {
+                // If v11 is land but at least one of the 8-connected neighbors is water
+                color = color_shore;
+                if num_water_neighbors > 3 {
+                    color_variant = 1;
+                } else {
+                    color_variant = 3;
                 }
+            } else {
+                // If v11 is land and all of the 8-connected neighbors are also land
+                color = color_land;
+                color_variant = 3;
             }
-        }
 
-        m
+            if color != color_land {
+                // color_variant is always in [0, 3]
+                m.a[(x, z)] = color * 4 + color_variant;
+            }
+        }
     }
+
+    m
 }
 
-pub fn reverse_map_treasure(m: &Map) -> Map {
+pub fn reverse_map_treasure(m: &TreasureMapColors) -> Map {
+    let m = m.map();
     // Input: color and variant
     // Output: ocean or plains
     //
@@ -4992,67 +5685,424 @@ pub fn river_seed_finder(river_coords_voronoi: &[Point], extra_biomes: &[(BiomeI
     river_seed_finder_range(river_coords_voronoi, extra_biomes, version, 0, 1 << 24)
 }
 
-pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_lo: u32, range_hi: u32) -> Vec<i64> {
-    // This iterator has 2**24 elements
-    let iter25 = McRng::similar_biome_seed_iterator_bits(25).skip(range_lo as usize).take((range_hi - range_lo) as usize);
-    let mut target_maps_derived = vec![];
-    let river_fragments = split_rivers_into_fragments4(river_coords_quarter_scale);
-    let initial_num_river_fragments = river_fragments.len();
-    for x in river_fragments {
-        let rivers = count_rivers(&x);
-        target_maps_derived.push((x, rivers));
+/// Thresholds and weights used by `river_seed_finder_with_config`.
+///
+/// The 26-bit and 34-bit stages only use rivers, so they have their own thresholds. The 64-bit
+/// stage combines all the evidence into one weighted score: each kind of evidence adds its weight
+/// multiplied by the fraction of matching items, so a kind of evidence with many items does not
+/// hide the others. The kinds of evidence with no items are ignored.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Minimum percentage of matching rivers in each quarter-scale map, in the 26-bit stage
+    pub river_percent_26: u32,
+    /// Minimum percentage of matching river borders, in the 34-bit stage
+    pub river_percent_34: u32,
+    pub river_weight: u32,
+    pub biome_weight: u32,
+    /// Weight of the ocean biomes, which are generated by the ocean temperature layer starting
+    /// from 1.13. In older versions oceans are scored as biomes
+    pub ocean_weight: u32,
+    pub structure_weight: u32,
+    /// Minimum weighted score in the 64-bit stage, as a percentage of the maximum score
+    pub min_score_percent: u32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            river_percent_26: 90,
+            river_percent_34: 90,
+            river_weight: 1,
+            biome_weight: 1,
+            ocean_weight: 1,
+            structure_weight: 1,
+            min_score_percent: 90,
+        }
+    }
+}
+
+/// Structures that can be checked in the 64-bit stage of the river seed finder
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StructureEvidence {
+    SlimeChunk(Chunk),
+    BuriedTreasure(Chunk),
+    /// Start chunk of an ocean ruin or a shipwreck
+    Region(RegionStructure, Chunk),
+}
+
+impl StructureEvidence {
+    /// Returns true if this structure can generate with this seed, checking both the random
+    /// number generator of the structure and the biome
+    pub fn matches(&self, world_seed: i64, version: MinecraftVersion) -> bool {
+        match self {
+            StructureEvidence::SlimeChunk(c) => is_slime_chunk(world_seed as u64, c),
+            StructureEvidence::BuriedTreasure(c) => {
+                is_treasure_chunk(world_seed, c) && structure_biome_is_valid(world_seed, version, BiomeStructure::BuriedTreasure, c)
+            }
+            StructureEvidence::Region(s, c) => {
+                s.config(version).map_or(false, |config| is_region_structure_chunk(world_seed, &config, c))
+                    && structure_biome_is_valid(world_seed, version, s.biome_structure(), c)
+            }
+        }
     }
+}
 
-    // Sort target maps by river count: most rivers first
-    target_maps_derived.sort_unstable_by_key(|(_map, rivers)| !rivers);
+/// All the evidence used by `river_seed_finder_with_config`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchEvidence {
+    pub rivers: Vec<Point>,
+    pub extra_biomes: Vec<(BiomeId, Point)>,
+    pub structures: Vec<StructureEvidence>,
+}
 
-    // Keep at most 10 maps
-    target_maps_derived.truncate(10);
+fn is_ocean_temperature_biome(id: i32) -> bool {
+    matches!(id, biome_id::ocean | biome_id::frozenOcean | biome_id::deepOcean | 44..=50)
+}
 
-    // Remove all the maps with less than 10 rivers
-    target_maps_derived.retain(|(_map, rivers)| *rivers >= 10);
+// Weighted score of one candidate. Each kind of evidence adds weight * 100 * hits / total, and
+// the candidate can be rejected as soon as the unchecked evidence cannot reach the minimum score.
+struct ScoreBudget {
+    score: u64,
+    // Maximum score of the kinds of evidence that have not been added yet
+    remaining: u64,
+    max_score: u64,
+    needed: u64,
+}
 
-    // 2 bad maps are needed to discard the seed
-    let bad_map_target = match target_maps_derived.len() {
-        1 => 1,
-        _ => 2,
-    };
+impl ScoreBudget {
+    // kinds: (weight, total) of each kind of evidence
+    fn new(kinds: &[(u32, u64)], min_score_percent: u32) -> Self {
+        let max_score = kinds.iter().filter(|(_, total)| *total > 0).map(|(weight, _)| u64::from(*weight) * 100).sum();
 
-    let mut max_possible_score = 0;
-    for (_map, rivers) in &target_maps_derived {
-        max_possible_score += rivers;
+        Self { score: 0, remaining: max_score, max_score, needed: max_score * u64::from(min_score_percent) / 100 }
     }
 
-    debug!("Max score: {}", max_possible_score);
-    debug!("Max bad maps: {}", bad_map_target);
-    debug!("Using {} out of {} river maps. Total river count: {}\n{:?}", target_maps_derived.len(), initial_num_river_fragments, max_possible_score, target_maps_derived);
+    fn partial_score(weight: u32, hits: u64, total: u64) -> u64 {
+        if total == 0 {
+            0
+        } else {
+            u64::from(weight) * 100 * hits / total
+        }
+    }
 
-    let mut candidates_26 = vec![];
+    // Returns true if the minimum score cannot be reached, even if all the unchecked items of
+    // this kind of evidence match
+    fn is_hopeless(&self, weight: u32, misses: u64, total: u64) -> bool {
+        let max_this_kind = Self::partial_score(weight, total - misses, total);
 
-    'nextseed: for world_seed in iter25 {
-        let mut good_maps0 = 0;
-        let mut bad_maps0 = 0;
-        let mut good_maps1 = 0;
+        self.score + self.remaining - u64::from(weight) * 100 + max_this_kind < self.needed
+    }
+
+    fn add(&mut self, weight: u32, hits: u64, total: u64) {
+        if total == 0 {
+            return;
+        }
+        self.remaining -= u64::from(weight) * 100;
+        self.score += Self::partial_score(weight, hits, total);
+    }
+
+    // Score as a percentage of the maximum score
+    fn percent(&self) -> u32 {
+        if self.max_score == 0 {
+            100
+        } else {
+            (self.score * 100 / self.max_score) as u32
+        }
+    }
+
+    fn accepted(&self) -> bool {
+        self.score >= self.needed
+    }
+}
+
+// Check items until the budget becomes hopeless. Returns false if the candidate is rejected.
+fn add_item_hits<T, F>(budget: &mut ScoreBudget, weight: u32, items: &[T], mut matches: F) -> bool
+where
+    F: FnMut(&T) -> bool,
+{
+    let total = items.len() as u64;
+    let mut hits = 0;
+    for item in items {
+        if matches(item) {
+            hits += 1;
+        } else if budget.is_hopeless(weight, total - hits, total) {
+            return false;
+        }
+    }
+    budget.add(weight, hits, total);
+
+    true
+}
+
+// River map used by the 34-bit and 64-bit stages
+struct RiverTargetHd {
+    // Rivers at 1:4 scale, obtained by reversing the voronoi zoom
+    map: Map,
+    mask: RiverMask,
+    // Borders of the 1:1 rivers, only in the area that can be generated from `map`
+    voronoi_borders: RiverMask,
+    voronoi_score: u32,
+}
+
+// Evidence used by the 34-bit and 64-bit stages, prepared once for all the candidates
+struct EvidenceTargets {
+    target_maps_hd: Vec<RiverTargetHd>,
+    river_total: u64,
+    biomes: Vec<(BiomeId, Point)>,
+    oceans: Vec<(BiomeId, Point)>,
+    structures: Vec<StructureEvidence>,
+}
+
+impl EvidenceTargets {
+    fn new(evidence: &SearchEvidence, version: MinecraftVersion) -> Self {
+        let target_maps_hd = river_target_maps_hd(&evidence.rivers);
+        let river_total = target_maps_hd.iter().map(|t| u64::from(t.mask.count())).sum();
+        let has_ocean_temperature = version >= MinecraftVersion::Java1_13;
+        // Oceans near the world origin that no seed can generate would reject all the seeds
+        let ocean_points: Vec<Point> = evidence.extra_biomes.iter().filter(|(biome, _)| is_oceanic(biome.0)).map(|(_, p)| *p).collect();
+        let ignored_oceans = impossible_oceans_at_spawn(&ocean_points, version, &spawn_island_seeds());
+        if !ignored_oceans.is_empty() {
+            debug!("Ignoring {} impossible oceans near the world origin: {:?}", ignored_oceans.len(), ignored_oceans);
+        }
+        let extra_biomes: Vec<(BiomeId, Point)> = evidence.extra_biomes.iter().copied().filter(|(biome, p)| {
+            !(is_oceanic(biome.0) && ignored_oceans.contains(p))
+        }).collect();
+        // The most informative biomes are checked first
+        let extra_biomes = sort_by_information_bits(&extra_biomes, version);
+        let (oceans, biomes) = extra_biomes.into_iter().map(|(biome, p, _)| (biome, p)).partition(|(biome, _)| {
+            has_ocean_temperature && is_ocean_temperature_biome(biome.0)
+        });
+
+        Self { target_maps_hd, river_total, biomes, oceans, structures: evidence.structures.clone() }
+    }
+
+    // Weighted score of one seed in the 64-bit stage, as a percentage of the maximum score, or
+    // None if the seed is rejected. The cheapest evidence is checked first.
+    fn score(&self, world_seed: i64, version: MinecraftVersion, config: &SearchConfig) -> Option<u32> {
+        let last_layer = version.num_layers();
+        let mut budget = ScoreBudget::new(&[
+            (config.structure_weight, self.structures.len() as u64),
+            (config.ocean_weight, self.oceans.len() as u64),
+            (config.biome_weight, self.biomes.len() as u64),
+            (config.river_weight, self.river_total),
+        ], config.min_score_percent);
+
+        if !add_item_hits(&mut budget, config.structure_weight, &self.structures, |s| s.matches(world_seed, version)) {
+            return None;
+        }
+
+        let biome_matches = |(biome, Point { x, z }): &(BiomeId, Point)| {
+            let area = Area { x: *x, z: *z, w: 1, h: 1 };
+            let g43 = generate_up_to_layer(version, area, world_seed, last_layer, 0);
+
+            g43.a[(0, 0)] == biome.0
+        };
+        if !add_item_hits(&mut budget, config.ocean_weight, &self.oceans, biome_matches) {
+            return None;
+        }
+        if !add_item_hits(&mut budget, config.biome_weight, &self.biomes, biome_matches) {
+            return None;
+        }
+
+        let mut river_hits = 0;
+        let mut river_misses = 0;
+        let mut candidate_mask = RiverMask::default();
+        // Only the river branch is needed, see rivers_only_generator
+        let rivers = rivers_only_generator(world_seed, version);
+        for target in &self.target_maps_hd {
+            let target_score = u64::from(target.mask.count());
+            let candidate_map = match &rivers {
+                Some(g) => g.get_map(target.map.area()),
+                None => generate_up_to_layer(version, target.map.area(), world_seed, last_layer - 1, 0),
+            };
+            candidate_mask.set_from_map(&candidate_map);
+            let candidate_score = u64::from(candidate_mask.count_and(&target.mask));
+            river_hits += candidate_score;
+            river_misses += target_score - candidate_score;
+            if budget.is_hopeless(config.river_weight, river_misses, self.river_total) {
+                return None;
+            }
+        }
+        budget.add(config.river_weight, river_hits, self.river_total);
+
+        if budget.accepted() {
+            debug!("{:016X}: score {}%", world_seed, budget.percent());
+            Some(budget.percent())
+        } else {
+            None
+        }
+    }
+}
+
+// Target maps used by the 26-bit phase, and the number of bad maps needed to discard a seed
+fn river_target_maps_26(river_coords_quarter_scale: &[Point4]) -> (Vec<(RiverMask, u32)>, u32) {
+    let mut target_maps_derived = vec![];
+    let river_fragments = split_rivers_into_fragments4(river_coords_quarter_scale);
+    let initial_num_river_fragments = river_fragments.len();
+    for x in river_fragments {
+        let rivers = x.count();
+        target_maps_derived.push((x, rivers));
+    }
+
+    // Sort target maps by river count: most rivers first
+    target_maps_derived.sort_unstable_by_key(|(_map, rivers)| !rivers);
+
+    // Keep at most 10 maps
+    target_maps_derived.truncate(10);
+
+    // Remove all the maps with less than 10 rivers
+    target_maps_derived.retain(|(_map, rivers)| *rivers >= 10);
+
+    // 2 bad maps are needed to discard the seed
+    let bad_map_target = match target_maps_derived.len() {
+        1 => 1,
+        _ => 2,
+    };
+
+    debug!("Using {} out of {} river maps", target_maps_derived.len(), initial_num_river_fragments);
+
+    (target_maps_derived, bad_map_target)
+}
+
+// Check one seed with the same criteria as river_seed_finder_26_range, returning the number of
+// matching rivers if the seed is a candidate
+fn candidate_26_score(world_seed: i64, target_masks: &[(RiverMask, u32)], bad_map_target: u32, river_percent: u32) -> Option<u32> {
+    let generator = CandidateRiverMapGenerator::new(world_seed);
+    let mut candidate_mask = RiverMask::default();
+    let mut bad_maps = 0;
+    let mut score = 0;
+    for (target_mask, target_score) in target_masks {
+        generator.get_mask_into(target_mask.area(), &mut candidate_mask);
+        let candidate_score = candidate_mask.count_and(target_mask);
+        score += candidate_score;
+        if candidate_score < target_score * river_percent / 100 {
+            bad_maps += 1;
+            if bad_maps >= bad_map_target {
+                return None;
+            }
+        }
+    }
+
+    Some(score)
+}
+
+// Number of seeds checked between two checks of the cancel token
+const CANCEL_CHECK_INTERVAL: usize = 256;
+
+pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_lo: u32, range_hi: u32) -> Vec<i64> {
+    let seeds = seeds_25_range(SeedOrder::Sequential, range_lo, range_hi);
+
+    river_seed_finder_26_seeds_cancellable(river_coords_quarter_scale, seeds, &SearchConfig::default(), &CancelToken::new()).results
+}
+
+/// The seeds of `McRng::similar_biome_seed_iterator_25_ordered(order)` between `range_lo` and
+/// `range_hi`, the input of `river_seed_finder_26_seeds_cancellable`. The full range is
+/// `0..1 << 24`.
+pub fn seeds_25_range(order: SeedOrder, range_lo: u32, range_hi: u32) -> impl Iterator<Item = i64> {
+    McRng::similar_biome_seed_iterator_25_ordered(order).skip(range_lo as usize).take((range_hi - range_lo) as usize)
+}
+
+// Same as `river_seed_finder_26_seeds_cancellable` with seeds from `seeds_25_range`, but returns
+// the candidates as soon as they are found. The range is checked in small parts, so the first
+// candidates are available long before the whole range is done, and dropping the iterator stops
+// the search. The iterator ends early if `cancel` is cancelled.
+pub(crate) fn river_seed_finder_26_iter(river_coords_quarter_scale: Vec<Point4>, range_lo: u32, range_hi: u32, config: SearchConfig, cancel: CancelToken) -> impl Iterator<Item = i64> {
+    let cancel_chunks = cancel.clone();
+    (range_lo..range_hi).step_by(RIVER_26_CHUNK_SIZE as usize).take_while(move |_| !cancel_chunks.is_cancelled()).flat_map(move |lo| {
+        let hi = range_hi.min(lo.saturating_add(RIVER_26_CHUNK_SIZE));
+        let seeds = seeds_25_range(SeedOrder::Sequential, lo, hi);
+        river_seed_finder_26_seeds_cancellable(&river_coords_quarter_scale, seeds, &config, &cancel).results
+    })
+}
+
+/// 26-bit phase of the river seed finder: find the lower 26 bits of the world seed using the
+/// rivers at quarter scale. All the other 26-bit searches use this function.
+///
+/// The seeds are checked in the order of the iterator, and must be taken from
+/// `McRng::similar_biome_seed_iterator_bits(25)`, for example using `seeds_25_range`: each seed is
+/// checked with bit 25 set to 0 and to 1, and the similar biome seeds are also returned.
+///
+/// Stops early if `cancel` is cancelled, returning the candidates found so far.
+pub fn river_seed_finder_26_seeds_cancellable<I>(river_coords_quarter_scale: &[Point4], seeds: I, config: &SearchConfig, cancel: &CancelToken) -> Partial<i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    let (target_maps_derived, bad_map_target) = river_target_maps_26(river_coords_quarter_scale);
+
+    let mut max_possible_score = 0;
+    for (_map, rivers) in &target_maps_derived {
+        max_possible_score += rivers;
+    }
+
+    debug!("Max score: {}", max_possible_score);
+    debug!("Max bad maps: {}", bad_map_target);
+    debug!("Total river count: {}\n{:?}", max_possible_score, target_maps_derived);
+
+    let mut candidates_26 = vec![];
+    // One generator and one output map for each seed of the pair and each target map, to reuse
+    // the allocations
+    let mut generators: Vec<_> = target_maps_derived.iter().map(|_| (CandidateRiverMapGenerator::new(0), RiverMask::default(), RiverMask::default())).collect();
+
+    let mut truncated = false;
+    'nextseed: for (i, world_seed) in seeds.into_iter().enumerate() {
+        if i % CANCEL_CHECK_INTERVAL == 0 && cancel.is_cancelled() {
+            truncated = true;
+            break;
+        }
+        let mut good_maps0 = 0;
+        let mut bad_maps0 = 0;
+        let mut good_maps1 = 0;
         let mut bad_maps1 = 0;
         let mut check0 = true;
         let mut check1 = true;
         let mut score0 = 0;
         let mut score1 = 0;
-        'nextmap: for (target_map, target_score) in &target_maps_derived {
-            let area = target_map.area();
+        for ((target_mask, target_score), (generator, candidate_mask0, candidate_mask1)) in target_maps_derived.iter().zip(generators.iter_mut()) {
+            let area = target_mask.area();
+            let min_score = target_score * config.river_percent_26 / 100;
+
+            if check0 {
+                // Bit 25 set to 0
+                generator.set_world_seed(world_seed);
+                generator.get_mask_into(area, candidate_mask0);
+            }
+            if check1 {
+                // Bit 25 set to 1
+                generator.set_world_seed(world_seed ^ (1 << 25));
+                generator.get_mask_into(area, candidate_mask1);
+            }
+
+            // The candidate map will probably have more rivers than the target map
+            // Basically, target_map is a subset of candidate_map
+            // Except in some rare cases where target_map can have rivers not present
+            // in candidate_map.
+            // The OR of the two candidate maps has all the rivers of both seeds, so if the
+            // target map is not covered by the OR map, it is not covered by any of the two maps
+            // and this is a bad map for both seeds.
+            if check0 && check1 && target_mask.count_and_or(candidate_mask0, candidate_mask1) < min_score {
+                bad_maps0 += 1;
+                bad_maps1 += 1;
+                check0 = bad_maps0 < bad_map_target;
+                check1 = bad_maps1 < bad_map_target;
+                if check0 == false && check1 == false {
+                    continue 'nextseed;
+                }
+                // The score is only needed if the seed can still be a candidate
+                if check0 {
+                    score0 += candidate_mask0.count_and(target_mask);
+                }
+                if check1 {
+                    score1 += candidate_mask1.count_and(target_mask);
+                }
+                continue;
+            }
 
             if check0 {
-                // Check with bit 25 set to 0
-                let candidate_map = candidate_river_map(area, world_seed);
-                //debug!("{}", draw_map(&candidate_map));
-
-                // The candidate map will probably have more rivers than the target map
-                // Basically, target_map is a subset of candidate_map
-                // Except in some rare cases where target_map can have rivers not present
-                // in candidate_map.
-                let candidate_score = count_rivers_and(&candidate_map, &target_map);
+                let candidate_score = candidate_mask0.count_and(target_mask);
                 score0 += candidate_score;
-                if candidate_score >= target_score * 90 / 100 {
+                if candidate_score >= min_score {
                     good_maps0 += 1;
                 } else {
                     bad_maps0 += 1;
@@ -5064,21 +6114,9 @@ pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_l
             }
 
             if check1 {
-                // Check with bit 25 set to 1
-                // If the area is large enough, we could skip this check if the map
-                // with bit 25 set to 0 had very few matches, as the two maps are
-                // usually pretty similar at large scales
-                let world_seed = world_seed ^ (1 << 25);
-                let candidate_map = candidate_river_map(area, world_seed);
-                //debug!("{}", draw_map(&candidate_map));
-
-                // The candidate map will probably have more rivers than the target map
-                // Basically, target_map is a subset of candidate_map
-                // Except in some rare cases where target_map can have rivers not present
-                // in candidate_map.
-                let candidate_score = count_rivers_and(&candidate_map, &target_map);
+                let candidate_score = candidate_mask1.count_and(target_mask);
                 score1 += candidate_score;
-                if candidate_score >= target_score * 90 / 100 {
+                if candidate_score >= min_score {
                     good_maps1 += 1;
                 } else {
                     bad_maps1 += 1;
@@ -5098,6 +6136,7 @@ pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_l
             let similar_biome_seed = McRng::similar_biome_seed(world_seed) & ((1 << 26) - 1);
             debug!("{:08X}: {}/{} maps, {}/{} rivers", world_seed, good_maps0, target_maps_derived.len(), score0, max_possible_score);
             debug!("{:08X}: {}/{} maps, {}/{} rivers", similar_biome_seed, good_maps0, target_maps_derived.len(), score0, max_possible_score);
+            trace::record(Stage::Bits26, world_seed, true, score0);
             candidates_26.push(world_seed);
             candidates_26.push(similar_biome_seed);
         }
@@ -5107,6 +6146,7 @@ pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_l
             let similar_biome_seed = McRng::similar_biome_seed(world_seed) & ((1 << 26) - 1);
             debug!("{:08X}: {}/{} maps, {}/{} rivers", world_seed, good_maps1, target_maps_derived.len(), score1, max_possible_score);
             debug!("{:08X}: {}/{} maps, {}/{} rivers", similar_biome_seed, good_maps1, target_maps_derived.len(), score1, max_possible_score);
+            trace::record(Stage::Bits26, world_seed, true, score1);
             candidates_26.push(world_seed);
             candidates_26.push(similar_biome_seed);
         }
@@ -5114,15 +6154,11 @@ pub fn river_seed_finder_26_range(river_coords_quarter_scale: &[Point4], range_l
     debug!("{:08X?}", candidates_26);
     debug!("26 bit candidates: {}", candidates_26.len());
 
-    candidates_26
+    Partial { results: candidates_26, truncated }
 }
 
-/// River Seed Finder
-///
-/// range_lo: 0
-/// range_hi: 1 << 24
-/// Even though this is a 26-bit bruteforce, we check 4 seeds at a time
-pub fn river_seed_finder_range(river_coords_voronoi: &[Point], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<i64> {
+// Returns the river maps used to check candidates
+fn river_target_maps_hd(river_coords_voronoi: &[Point]) -> Vec<RiverTargetHd> {
     // For the 34-bit voronoi phase we only want to compare hd_coords
     let mut target_maps_hd = vec![];
     let river_fragments = split_rivers_into_fragments(river_coords_voronoi);
@@ -5145,7 +6181,12 @@ pub fn river_seed_finder_range(river_coords_voronoi: &[Point], extra_biomes: &[(
                 let target_map_voronoi_sliced = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&target_map_voronoi_sliced);
                 let target_score_voronoi_sliced = count_rivers(&target_map_voronoi_sliced);
 
-                target_maps_hd.push((target_map_derived_hd, target_map_voronoi_sliced, target_score_voronoi_sliced));
+                target_maps_hd.push(RiverTargetHd {
+                    mask: RiverMask::from_map(&target_map_derived_hd),
+                    map: target_map_derived_hd,
+                    voronoi_borders: RiverMask::from_map(&target_map_voronoi_sliced),
+                    voronoi_score: target_score_voronoi_sliced,
+                });
             }
             Err(()) => {
                 debug!("Too few rivers, minimum map size is 8x8");
@@ -5154,100 +6195,185 @@ pub fn river_seed_finder_range(river_coords_voronoi: &[Point], extra_biomes: &[(
     }
 
     // Sort target maps by river count: most rivers first
-    target_maps_hd.sort_unstable_by_key(|(_map, _map_sliced, rivers)| !rivers);
+    target_maps_hd.sort_unstable_by_key(|t| !t.voronoi_score);
 
     // Keep at most 4 maps
     target_maps_hd.truncate(4);
 
     // Remove all the maps with less than 40 rivers
-    target_maps_hd.retain(|(_map, _map_sliced, rivers)| *rivers >= 40);
+    target_maps_hd.retain(|t| t.voronoi_score >= 40);
 
-    // Ok, begin bruteforce!
+    target_maps_hd
+}
 
-    let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(river_coords_voronoi);
-    let candidates_26 = river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_hi);
-
-    //let target_maps_hd = vec![(target_map_hd, target_map_voronoi_sliced, target_score_voronoi_sliced)];
-    // Now use voronoi zoom to bruteforce the remaining (34-26 = 8 bits)
-    let candidates_34 = candidates_26.into_iter().flat_map(|x| {
-        let mut v = vec![];
-        'nextseed: for seed in 0..(1 << (34 - 26)) {
-            let world_seed = x | (seed << 26);
-            let g43 = MapVoronoiZoom::new(10, world_seed);
-            for (target_map_hd, target_map_voronoi_sliced, target_score_voronoi_sliced) in &target_maps_hd {
-                let candidate_voronoi = g43.get_map_from_pmap(&target_map_hd);
-                let candidate_voronoi = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&candidate_voronoi);
-                //debug!("{}", draw_map(&target_map_voronoi_sliced));
-                //debug!("{}", draw_map(&candidate_voronoi));
-                let candidate_score = count_rivers_and(&candidate_voronoi, &target_map_voronoi_sliced);
-                // One match is enough to mark this as a candidate
-                if candidate_score >= target_score_voronoi_sliced * 90 / 100 {
-                    debug!("{:09X}: {}", world_seed, candidate_score);
-                    v.push(world_seed);
-                    continue 'nextseed;
-                }
-            }
+// Check one seed with the same criteria as the 34-bit phase of river_seed_finder_range,
+// returning the number of matching rivers of the first matching map
+fn candidate_34_score(world_seed: i64, target_maps_hd: &[RiverTargetHd], river_percent: u32) -> Option<u32> {
+    let g43 = MapVoronoiZoom::new(10, world_seed);
+    let mut candidate_mask = RiverMask::default();
+    for target in target_maps_hd {
+        let candidate_voronoi = g43.get_map_from_pmap(&target.map);
+        let candidate_voronoi = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&candidate_voronoi);
+        //debug!("{}", draw_map(&candidate_voronoi));
+        candidate_mask.set_from_map(&candidate_voronoi);
+        let candidate_score = candidate_mask.count_and(&target.voronoi_borders);
+        // One match is enough to mark this as a candidate
+        if candidate_score >= target.voronoi_score * river_percent / 100 {
+            return Some(candidate_score);
         }
+    }
 
-        v
-    }).collect::<Vec<_>>();
-    debug!("{:09X?}", candidates_34);
-    debug!("34 bit candidates: {}", candidates_34.len());
+    None
+}
 
-    // Can't use rivers to find 48 bits because rivers use 64 bits
-    // Can't use biomes because biomes also use 64 bits
-    // But we can use rivers + extend48 to end the search with a 2^14 bruteforce
-    // TODO: insert a filter by structures before the extend48
-    let mut candidates_64 = candidates_34.into_iter().flat_map(|x| {
-        let mut v = vec![];
-        for seed in 0..(1 << (48 - 34)) {
-            let world_seed = x | (seed << 34);
-            v.extend(JavaRng::extend_long_48(world_seed as u64));
-        }
+/// Check the events of a trace recorded by `river_seed_finder_range` against the current code,
+/// using the same input. Returns the recorded events whose result is different now, together
+/// with the new result.
+pub fn replay_river_seed_finder_trace(trace: &Trace, river_coords_voronoi: &[Point], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion) -> Vec<(TraceEvent, TraceEvent)> {
+    let config = SearchConfig::default();
+    let evidence = SearchEvidence {
+        rivers: river_coords_voronoi.to_vec(),
+        extra_biomes: extra_biomes.to_vec(),
+        structures: vec![],
+    };
+    let targets = EvidenceTargets::new(&evidence, version);
+    let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(river_coords_voronoi);
+    let (target_maps_26, bad_map_target) = river_target_maps_26(&river_coords_quarter_scale);
 
-        v
-    }).filter_map(|world_seed| {
-        let world_seed = world_seed as i64;
-        let last_layer = version.num_layers();
-        for (target_map, _target_map_voronoi, _voronoi_score) in &target_maps_hd {
-            let target_score = count_rivers(target_map);
-            let area = target_map.area();
-            // Compare only rivers
-            //let g41 = generate_up_to_layer(MinecraftVersion::Java1_7, area, world_seed, 41);
-            // Compare all biomes (slower)
-            let g42 = generate_up_to_layer(version, area, world_seed, last_layer - 1, 0);
-            let candidate_score = count_rivers_and(&g42, &target_map);
-            if candidate_score < target_score * 90 / 100 {
-                // Skip this seed
-                return None;
-            }
+    trace.events.iter().filter_map(|event| {
+        let score = match event.stage {
+            Stage::Bits26 => candidate_26_score(event.world_seed, &target_maps_26, bad_map_target, config.river_percent_26),
+            Stage::Bits34 => candidate_34_score(event.world_seed, &targets.target_maps_hd, config.river_percent_34),
+            Stage::Bits64 => targets.score(event.world_seed, version, &config),
+        };
+        let replayed = TraceEvent { accepted: score.is_some(), score: score.unwrap_or(0), ..*event };
+
+        if replayed != *event {
+            Some((*event, replayed))
+        } else {
+            None
         }
+    }).collect()
+}
 
-        // When most rivers match, try extra biomes
-        let mut hits = 0;
-        let mut misses = 0;
-        let target = extra_biomes.len() * 90 / 100;
-        let max_misses = extra_biomes.len() - target;
-        for (biome, Point {x, z}) in extra_biomes.iter().cloned() {
-            let area = Area { x, z, w: 1, h: 1 };
-            let g43 = generate_up_to_layer(version, area, world_seed, last_layer, 0);
-            if g43.a[(0, 0)] == biome.0 {
-                hits += 1;
-            } else {
-                misses += 1;
-                if misses > max_misses {
-                    break;
-                }
-            }
+/// River Seed Finder
+///
+/// range_lo: 0
+/// range_hi: 1 << 24
+/// Even though this is a 26-bit bruteforce, we check 4 seeds at a time
+pub fn river_seed_finder_range(river_coords_voronoi: &[Point], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<i64> {
+    let evidence = SearchEvidence {
+        rivers: river_coords_voronoi.to_vec(),
+        extra_biomes: extra_biomes.to_vec(),
+        structures: vec![],
+    };
+
+    river_seed_finder_with_config(&evidence, version, range_lo, range_hi, &SearchConfig::default())
+}
+
+/// Progress of a search split into many calls to a function with a `(range_lo, range_hi)`
+/// argument, such as `river_seed_finder_range`. It can be serialized, so a search can continue
+/// later, for example after a browser tab was in the background.
+///
+/// ```ignore
+/// let mut state = RangeSearchState::new(0, 1 << 24);
+/// while !state.is_done() {
+///     state.step(1 << 16, |lo, hi| river_seed_finder_range(&rivers, &extra_biomes, version, lo, hi));
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeSearchState {
+    pub range_lo: u32,
+    pub range_hi: u32,
+    /// Start of the next step. The range from `range_lo` to `next` is already checked
+    pub next: u32,
+    /// Seeds found so far, serialized as strings
+    #[serde(with = "crate::seed_info::vec_seed_string")]
+    pub found: Vec<i64>,
+}
+
+impl RangeSearchState {
+    pub fn new(range_lo: u32, range_hi: u32) -> Self {
+        Self { range_lo, range_hi, next: range_lo, found: vec![] }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.range_hi
+    }
+
+    /// Fraction of the range that is already checked, from 0.0 to 1.0
+    pub fn progress(&self) -> f64 {
+        if self.range_hi <= self.range_lo {
+            return 1.0;
         }
 
-        if hits >= target {
-            debug!("{:016X}: {}/{}", world_seed, hits, extra_biomes.len());
-            Some(world_seed)
-        } else {
-            None
+        f64::from(self.next.min(self.range_hi) - self.range_lo) / f64::from(self.range_hi - self.range_lo)
+    }
+
+    /// Check the next `max_len` values of the range using `f(lo, hi)`, and add the results to
+    /// `found`. Returns the seeds found in this step. Does nothing if the search is done.
+    pub fn step<F>(&mut self, max_len: u32, f: F) -> Vec<i64>
+    where
+        F: FnOnce(u32, u32) -> Vec<i64>,
+    {
+        assert!(max_len > 0);
+        if self.is_done() {
+            return vec![];
         }
-    }).collect::<Vec<_>>();
+        let lo = self.next;
+        let hi = lo.saturating_add(max_len).min(self.range_hi);
+        let seeds = f(lo, hi);
+        self.found.extend_from_slice(&seeds);
+        self.next = hi;
+
+        seeds
+    }
+}
+
+/// Same as `river_seed_finder_range`, but also using structures as evidence, and with
+/// configurable thresholds and weights.
+pub fn river_seed_finder_with_config(evidence: &SearchEvidence, version: MinecraftVersion, range_lo: u32, range_hi: u32, config: &SearchConfig) -> Vec<i64> {
+    let mut candidates_64: Vec<i64> = river_seed_finder_iter(evidence, version, range_lo, range_hi, config).map(|c| c.world_seed).collect();
+    candidates_64.sort_unstable();
+    debug!("{:016X?}", candidates_64);
+    debug!("64 bit candidates: {}", candidates_64.len());
+
+    candidates_64
+}
+
+/// 64-bit seed found by `river_seed_finder_iter`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedCandidate {
+    pub world_seed: i64,
+    /// Weighted score, as a percentage of the maximum score
+    pub score: u32,
+}
+
+// Number of 26-bit seeds checked before passing the candidates to the next stages
+const RIVER_26_CHUNK_SIZE: u32 = 1 << 16;
+
+/// Same as `river_seed_finder_with_config`, but returns the candidates as soon as they are found,
+/// so the caller can show them immediately or stop after the first candidates. The candidates
+/// are not sorted.
+pub fn river_seed_finder_iter(evidence: &SearchEvidence, version: MinecraftVersion, range_lo: u32, range_hi: u32, config: &SearchConfig) -> impl Iterator<Item = SeedCandidate> {
+    river_seed_finder_multi_version_iter(&[(version, evidence.clone())], range_lo, range_hi, config)
+}
+
+/// Same as `river_seed_finder_with_config`, but the evidence comes from different regions of the
+/// world, each one generated using a different minecraft version. This happens when a server is
+/// upgraded and new chunks are generated by the new version.
+///
+/// The rivers of all the fragments are used together in the 26-bit and 34-bit phases, because
+/// the river layers are the same in all the supported versions: see `RiverLayers::Java1_7`. The
+/// 64-bit candidates are checked against the evidence of each fragment using the layers of its
+/// version, and must be accepted by all of them. The score of a candidate is the lowest score of
+/// all the fragments.
+///
+/// Panics if a fragment uses a version with different river layers.
+pub fn river_seed_finder_multi_version(fragments: &[(MinecraftVersion, SearchEvidence)], range_lo: u32, range_hi: u32, config: &SearchConfig) -> Vec<i64> {
+    let mut candidates_64: Vec<i64> = river_seed_finder_multi_version_iter(fragments, range_lo, range_hi, config).map(|c| c.world_seed).collect();
     candidates_64.sort_unstable();
     debug!("{:016X?}", candidates_64);
     debug!("64 bit candidates: {}", candidates_64.len());
@@ -5255,31 +6381,311 @@ pub fn river_seed_finder_range(river_coords_voronoi: &[Point], extra_biomes: &[(
     candidates_64
 }
 
+/// Same as `river_seed_finder_multi_version`, but returns the candidates as soon as they are
+/// found, like `river_seed_finder_iter`.
+///
+/// Panics if a fragment uses a version with different river layers, use
+/// `river_seed_finder_check_versions` to check the versions first.
+pub fn river_seed_finder_multi_version_iter(fragments: &[(MinecraftVersion, SearchEvidence)], range_lo: u32, range_hi: u32, config: &SearchConfig) -> impl Iterator<Item = SeedCandidate> {
+    let fragments: Vec<(FragmentVersion, SearchEvidence)> = fragments.iter().map(|(version, evidence)| {
+        (FragmentVersion::Known(*version), evidence.clone())
+    }).collect();
+
+    river_seed_finder_mixed_version_iter(&fragments, range_lo, range_hi, config).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Returns an error if the river seed finder cannot be used with the versions of these fragments,
+/// because they use different river layers than 1.7
+pub fn river_seed_finder_check_versions(fragments: &[(FragmentVersion, SearchEvidence)]) -> Result<(), String> {
+    for version in fragments.iter().flat_map(|(version, _)| version.versions()) {
+        if version.river_layers() != Some(RiverLayers::Java1_7) {
+            return Err(format!("River seed finder not implemented for version {:?}", version));
+        }
+    }
+
+    Ok(())
+}
+
+/// Same as `river_seed_finder_multi_version_iter`, but the version of some fragments can be
+/// unknown, see `FragmentVersion::Any`. A candidate is accepted by one of those fragments if it
+/// is accepted using any of its possible versions, and the score of the fragment is the best
+/// score of all of them. This avoids rejecting the correct seed in worlds with regenerated
+/// chunks, where the version used to generate each region is not always known.
+///
+/// Returns an error if a fragment uses a version with different river layers, see
+/// `river_seed_finder_check_versions`.
+pub fn river_seed_finder_mixed_version_iter(fragments: &[(FragmentVersion, SearchEvidence)], range_lo: u32, range_hi: u32, config: &SearchConfig) -> Result<impl Iterator<Item = SeedCandidate>, String> {
+    river_seed_finder_mixed_version_iter_cancellable(fragments, range_lo, range_hi, config, CancelToken::new())
+}
+
+/// Same as `river_seed_finder_mixed_version_iter`, but stops early if `cancel` is cancelled,
+/// returning the sorted candidates found so far.
+pub fn river_seed_finder_mixed_version_cancellable(fragments: &[(FragmentVersion, SearchEvidence)], range_lo: u32, range_hi: u32, config: &SearchConfig, cancel: &CancelToken) -> Result<Partial<SeedCandidate>, String> {
+    let mut results: Vec<SeedCandidate> = river_seed_finder_mixed_version_iter_cancellable(fragments, range_lo, range_hi, config, cancel.clone())?.collect();
+    results.sort_unstable_by_key(|c| c.world_seed);
+
+    Ok(Partial { results, truncated: cancel.is_cancelled() })
+}
+
+/// Same as `river_seed_finder_range`, but stops early if `cancel` is cancelled, returning the
+/// seeds found so far.
+pub fn river_seed_finder_range_cancellable(river_coords_voronoi: &[Point], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion, range_lo: u32, range_hi: u32, cancel: &CancelToken) -> Partial<i64> {
+    let evidence = SearchEvidence {
+        rivers: river_coords_voronoi.to_vec(),
+        extra_biomes: extra_biomes.to_vec(),
+        structures: vec![],
+    };
+    let fragments = [(FragmentVersion::Known(version), evidence)];
+    let found = river_seed_finder_mixed_version_cancellable(&fragments, range_lo, range_hi, &SearchConfig::default(), cancel).unwrap_or_else(|e| panic!("{}", e));
+
+    Partial { results: found.results.into_iter().map(|c| c.world_seed).collect(), truncated: found.truncated }
+}
+
+// The iterator ends early if `cancel` is cancelled, the stages of the pipeline check it in their
+// outer loops
+fn river_seed_finder_mixed_version_iter_cancellable(fragments: &[(FragmentVersion, SearchEvidence)], range_lo: u32, range_hi: u32, config: &SearchConfig, cancel: CancelToken) -> Result<impl Iterator<Item = SeedCandidate>, String> {
+    river_seed_finder_check_versions(fragments)?;
+    let ctx = SearchCtx { fragments, config, cancel };
+
+    // Ok, begin bruteforce!
+    // The stages are lazy, so the candidates of the first 26-bit ranges are checked before the
+    // whole range is done
+    Ok(SearchPipeline::river_search(range_lo, range_hi).run_iter(Box::new(std::iter::empty()), &ctx))
+}
+
+/// Last stage of `river_seed_finder_mixed_version_iter`, see `seed_filter::EvidenceScore`. The
+/// candidates must be accepted by all the fragments, and their score is lowered to the lowest
+/// score of all the fragments.
+pub(crate) fn score_candidates_mixed_version_iter<I>(candidates: I, fragments: &[(FragmentVersion, SearchEvidence)], config: &SearchConfig, cancel: CancelToken) -> impl Iterator<Item = SeedCandidate>
+where
+    I: IntoIterator<Item = SeedCandidate>
+{
+    // For each fragment, the targets of each possible version
+    let targets: Vec<Vec<(MinecraftVersion, EvidenceTargets)>> = fragments.iter().map(|(version, evidence)| {
+        version.versions().into_iter().map(|version| (version, EvidenceTargets::new(evidence, version))).collect()
+    }).collect();
+    let config = config.clone();
+
+    candidates.into_iter().enumerate().take_while(move |(i, _)| {
+        i % CANCEL_CHECK_INTERVAL != 0 || !cancel.is_cancelled()
+    }).filter_map(move |(_, c)| {
+        let world_seed = c.world_seed;
+        let score = targets.iter().try_fold(c.score, |min_score, alternatives| {
+            let best = alternatives.iter().filter_map(|(version, targets)| targets.score(world_seed, *version, &config)).max();
+            best.map(|score| min_score.min(score))
+        });
+        trace::record(Stage::Bits64, world_seed, score.is_some(), score.unwrap_or(0));
+        score.map(|score| SeedCandidate { world_seed, score })
+    })
+}
+
+// Voronoi phase of the river seed finder, ends early if `cancel` is cancelled
+fn extend_26_to_34_iter<I>(candidates_26: I, target_maps_hd: Vec<RiverTargetHd>, river_percent: u32, cancel: CancelToken) -> impl Iterator<Item = i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    candidates_26.into_iter().take_while(move |_| !cancel.is_cancelled()).flat_map(|x| {
+        // Now use voronoi zoom to bruteforce the remaining (34-26 = 8 bits)
+        (0..(1 << (34 - 26))).map(move |seed| x | (seed << 26))
+    }).filter(move |world_seed| {
+        let score = candidate_34_score(*world_seed, &target_maps_hd, river_percent);
+        trace::record(Stage::Bits34, *world_seed, score.is_some(), score.unwrap_or(0));
+        if let Some(candidate_score) = score {
+            debug!("{:09X}: {}", world_seed, candidate_score);
+        }
+        score.is_some()
+    })
+}
+
+/// Extend 26-bit candidates to 34 bits using the voronoi zoom of the rivers, the same as the
+/// second phase of `river_seed_finder_range`. The candidates can come from any other filter, for
+/// example structures or slime chunks: only their lowest 26 bits are used. The rivers are in
+/// block coordinates, like `SearchEvidence::rivers`. Returns the sorted 34-bit candidates.
+///
+/// Panics if the version uses different river layers, see `RiverLayers::Java1_7`.
+pub fn extend_candidates_with_hd_rivers(candidates_26: &[i64], river_coords_voronoi: &[Point], version: MinecraftVersion, config: &SearchConfig) -> Vec<i64> {
+    assert_eq!(version.river_layers(), Some(RiverLayers::Java1_7), "River seed finder not implemented for version {:?}", version);
+    let mut candidates_34: Vec<i64> = extend_candidates_with_hd_rivers_iter(candidates_26.iter().copied(), river_coords_voronoi, config, CancelToken::new()).collect();
+    candidates_34.sort_unstable();
+
+    candidates_34
+}
+
+/// Same as `extend_candidates_with_hd_rivers`, but returns the candidates as soon as they are
+/// found, see `seed_filter::HdRivers34`. Candidates with the same lowest 26 bits are only
+/// extended once. The candidates are not sorted, and the iterator ends early if `cancel` is
+/// cancelled.
+pub(crate) fn extend_candidates_with_hd_rivers_iter<I>(candidates_26: I, river_coords_voronoi: &[Point], config: &SearchConfig, cancel: CancelToken) -> impl Iterator<Item = i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    let target_maps_hd = river_target_maps_hd(river_coords_voronoi);
+    let mut seen = HashSet::new();
+    let low_26 = candidates_26.into_iter().map(|x| x & ((1 << 26) - 1)).filter(move |x| seen.insert(*x));
+
+    extend_26_to_34_iter(low_26, target_maps_hd, config.river_percent_34, cancel)
+}
+
+/// Check an explicit list of candidates, using the same criteria as the last step of
+/// `river_seed_finder_range`.
+///
+/// Useful when there is some prior knowledge about the seed, for example when it was created
+/// from a text string: see `java_rng::world_seed_from_string`.
+pub fn filter_seed_list<I>(seeds: I, river_coords_voronoi: &[Point], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion) -> Vec<i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    let evidence = SearchEvidence {
+        rivers: river_coords_voronoi.to_vec(),
+        extra_biomes: extra_biomes.to_vec(),
+        structures: vec![],
+    };
+
+    filter_seed_list_with_config(seeds, &evidence, version, &SearchConfig::default())
+}
+
+/// Same as `filter_seed_list`, using the weighted score of `river_seed_finder_with_config`
+pub fn filter_seed_list_with_config<I>(seeds: I, evidence: &SearchEvidence, version: MinecraftVersion, config: &SearchConfig) -> Vec<i64>
+where
+    I: IntoIterator<Item = i64>
+{
+    let targets = EvidenceTargets::new(evidence, version);
+
+    seeds.into_iter().filter(|world_seed| {
+        targets.score(*world_seed, version, config).is_some()
+    }).collect()
+}
+
+/// Same as `filter_seed_list_with_config`, but also returns the score of each accepted seed
+pub fn score_seed_list_with_config<I>(seeds: I, evidence: &SearchEvidence, version: MinecraftVersion, config: &SearchConfig) -> Vec<SeedCandidate>
+where
+    I: IntoIterator<Item = i64>
+{
+    let targets = EvidenceTargets::new(evidence, version);
+
+    seeds.into_iter().filter_map(|world_seed| {
+        targets.score(world_seed, version, config).map(|score| SeedCandidate { world_seed, score })
+    }).collect()
+}
+
+// Approximate fraction of the world covered by this biome, following the layers that generate it
+fn biome_fraction(biome: i32, version: MinecraftVersion) -> f64 {
+    use biome_id::*;
+    // Land tiles after MapRemoveTooMuchOcean and MapDeepOcean, the rest is ocean and deepOcean
+    let land = 0.35;
+    // MapBiome splits the land in 4 temperatures: warm and lush choose one of 6 biomes, cold and
+    // freezing one of 4. Some biomes are repeated in the lists.
+    let t = land / 4.0;
+    // MapSpecial converts 1 in 13 tiles of a temperature into its special biome
+    let special = t / 13.0;
+    // MapHills converts 1 in 3 tiles into the hills variant, and 1 in 29 into the mutated variant
+    let hills = 1.0 / 3.0;
+    let mutated = 1.0 / 29.0;
+    let has_ocean_temperature = version >= MinecraftVersion::Java1_13;
+    // Ocean temperatures since 1.13
+    let ocean_share = |share: f64| if has_ocean_temperature { share } else { 0.0 };
+
+    match biome {
+        ocean | deepOcean => (1.0 - land) / 2.0 * if has_ocean_temperature { 0.4 } else { 1.0 },
+        lukewarmOcean | coldOcean | lukewarmDeepOcean | coldDeepOcean => (1.0 - land) / 2.0 * ocean_share(0.25),
+        // Before 1.13 frozen oceans are oceans next to icePlains
+        frozenOcean => if has_ocean_temperature { (1.0 - land) / 2.0 * 0.05 } else { t * 0.75 * 0.1 },
+        warmOcean | warmDeepOcean | frozenDeepOcean => (1.0 - land) / 2.0 * ocean_share(0.05),
+        // MapAddMushroomIsland only converts 1 in 100 tiles surrounded by ocean, at 1:256 scale
+        mushroomIsland => 0.001,
+        mushroomIslandShore => 0.0005,
+        river => 0.06,
+        frozenRiver => 0.06 * 0.1,
+        beach => 0.03,
+        coldBeach | stoneBeach => 0.005,
+        extremeHillsEdge => 0.005,
+        plains => t * (1.0 / 6.0 + 1.0 / 6.0 + 1.0 / 4.0),
+        desert => t * 3.0 / 6.0,
+        savanna => t * 2.0 / 6.0,
+        forest => t * (1.0 / 6.0 + 1.0 / 4.0),
+        extremeHills => t * (1.0 / 6.0 + 1.0 / 4.0),
+        roofedForest | birchForest | swampland => t / 6.0,
+        taiga | coldTaiga => t / 4.0,
+        icePlains => t * 3.0 / 4.0,
+        jungle | megaTaiga => special,
+        mesaPlateau_F => special * 2.0 / 3.0,
+        mesaPlateau => special / 3.0,
+        jungleEdge => special * 0.1,
+        desertHills => biome_fraction(desert, version) * hills,
+        forestHills => biome_fraction(forest, version) * hills,
+        taigaHills => biome_fraction(taiga, version) * hills,
+        iceMountains => biome_fraction(icePlains, version) * hills,
+        birchForestHills => biome_fraction(birchForest, version) * hills,
+        coldTaigaHills => biome_fraction(coldTaiga, version) * hills,
+        jungleHills => biome_fraction(jungle, version) * hills,
+        megaTaigaHills => biome_fraction(megaTaiga, version) * hills,
+        extremeHillsPlus => biome_fraction(extremeHills, version) * hills,
+        savannaPlateau => biome_fraction(savanna, version) * hills,
+        mesa => biome_fraction(mesaPlateau_F, version) * hills,
+        bambooJungle if version >= MinecraftVersion::Java1_14 => biome_fraction(jungle, version) * 0.1,
+        bambooJungleHills if version >= MinecraftVersion::Java1_14 => biome_fraction(jungleHills, version) * 0.1,
+        // The mutated variants have the id of their base biome + 128
+        129..=167 => biome_fraction(biome - 128, version) * mutated,
+        // Unknown biomes and biomes that are not generated by the layers, use a common biome
+        _ => t / 6.0,
+    }
+}
+
+/// Rough estimate of how much one observation of `biome` constrains the world seed, in bits:
+/// `-log2(p)`, where `p` is the fraction of the world covered by that biome. The fraction is
+/// estimated from the layers that generate the biome, so a mushroom island is worth about 10 bits
+/// while a plains tile is worth less than 5.
+///
+/// Neighbouring tiles usually have the same biome, so the information of many observations in the
+/// same area is much less than the sum of their bits. This is only meant to sort the evidence.
+pub fn evidence_information_bits(biome: BiomeId, version: MinecraftVersion) -> f64 {
+    let fraction = biome_fraction(biome.0, version);
+    if fraction <= 0.0 {
+        // This biome is not generated in this version, any seed will be rejected
+        return 64.0;
+    }
+
+    -fraction.log2()
+}
+
+/// Sort the biomes so the most informative are checked first, see `evidence_information_bits`.
+/// Returns the biomes with their information bits.
+fn sort_by_information_bits(biomes: &[(BiomeId, Point)], version: MinecraftVersion) -> Vec<(BiomeId, Point, f64)> {
+    let mut v: Vec<_> = biomes.iter().map(|&(biome, p)| (biome, p, evidence_information_bits(biome, version))).collect();
+    v.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+
+    v
+}
+
+/// Keep the candidates that generate at least 90% of the extra biomes. Each biome is weighted by
+/// `evidence_information_bits`, so a candidate is rejected as soon as it misses more than 10% of
+/// the total bits, and the rare biomes are checked first.
 pub fn filter_seeds_using_biomes(candidates: &[i64], extra_biomes: &[(BiomeId, Point)], version: MinecraftVersion) -> Vec<i64> {
     let mut valid_seeds = vec![];
     let last_layer = version.num_layers();
+    let extra_biomes = sort_by_information_bits(extra_biomes, version);
+    let total_bits: f64 = extra_biomes.iter().map(|(_, _, bits)| bits).sum();
+    let max_missed_bits = total_bits * 0.1;
 
     for world_seed in candidates {
         let world_seed = *world_seed;
         // When most rivers match, try extra biomes
         let mut hits = 0;
-        let mut misses = 0;
-        let target = extra_biomes.len() * 90 / 100;
-        let max_misses = extra_biomes.len() - target;
-        for (biome, Point {x, z}) in extra_biomes.iter().cloned() {
+        let mut missed_bits = 0.0;
+        for &(biome, Point {x, z}, bits) in &extra_biomes {
             let area = Area { x, z, w: 1, h: 1 };
             let g43 = generate_up_to_layer(version, area, world_seed, last_layer, 0);
             if g43.a[(0, 0)] == biome.0 {
                 hits += 1;
             } else {
-                misses += 1;
-                if misses > max_misses {
+                missed_bits += bits;
+                if missed_bits > max_missed_bits {
                     break;
                 }
             }
         }
 
-        if hits >= target {
+        if missed_bits <= max_missed_bits {
             debug!("{:016X}: {}/{}", world_seed, hits, extra_biomes.len());
             valid_seeds.push(world_seed);
         }
@@ -5288,18 +6694,170 @@ pub fn filter_seeds_using_biomes(candidates: &[i64], extra_biomes: &[(BiomeId, P
     valid_seeds
 }
 
+/// Keep the candidates that generate the 1:1 biomes observed near spawn.
+///
+/// Instead of generating the bounding box of `observed`, the known tiles are split into 16x16
+/// blocks and only the known part of each block is generated, starting from the block nearest to
+/// (0, 0). Each block is generated with all the layers, so the island that `MapIsland` forces
+/// at (0, 0) is also generated when the area of the block is small. A candidate is rejected as soon as more than 10% of the known tiles do not match, so most of the wrong
+/// candidates only need to generate the first block.
+pub fn spawn_area_seed_filter(observed: &SparseMap, candidates: &[i64], version: MinecraftVersion) -> Vec<i64> {
+    // (area, known tiles) of each block
+    let mut blocks: Vec<(Area, Vec<(Point, i32)>)> = vec![];
+    let mut block_index = HashMap::new();
+    for ((x, z), b) in observed.a.indexed_iter() {
+        if let Some(b) = b {
+            let p = Point { x: observed.x + x as i64, z: observed.z + z as i64 };
+            let i = *block_index.entry((p.x >> 4, p.z >> 4)).or_insert_with(|| {
+                blocks.push((Area::default(), vec![]));
+                blocks.len() - 1
+            });
+            blocks[i].1.push((p, *b));
+        }
+    }
+    for (area, tiles) in blocks.iter_mut() {
+        *area = Area::from_coords(tiles.iter().map(|(p, _)| *p));
+    }
+    let distance_to_spawn = |a: &Area| {
+        let dx = (a.x + a.w as i64 / 2).abs();
+        let dz = (a.z + a.h as i64 / 2).abs();
+        dx * dx + dz * dz
+    };
+    blocks.sort_by_key(|(area, _)| distance_to_spawn(area));
+
+    let total: usize = blocks.iter().map(|(_, tiles)| tiles.len()).sum();
+    let max_misses = total - total * 90 / 100;
+
+    candidates.iter().copied().filter(|world_seed| {
+        let mut misses = 0;
+        for (area, tiles) in &blocks {
+            let m = generate(version, *area, *world_seed, 0);
+            misses += tiles.iter().filter(|(p, b)| m.get(p.x, p.z) != *b).count();
+            if misses > max_misses {
+                return false;
+            }
+        }
+        debug!("{:016X}: {}/{}", world_seed, total - misses, total);
+
+        true
+    }).collect()
+}
+
+/// Biomes of a quarter-scale map as a list of (biome, position), ignoring unknown positions
+pub fn sparse_map_to_biomes4(m: &SparseMap) -> Vec<(BiomeId, Point4)> {
+    m.a.indexed_iter().filter_map(|((x, z), b)| {
+        b.map(|b| (BiomeId(b), Point4 { x: m.x + x as i64, z: m.z + z as i64 }))
+    }).collect()
+}
+
+/// Returns true if at least 90% of the quarter-scale biomes match the layer before the voronoi zoom
+fn seed_matches_biomes4(world_seed: i64, biomes4: &[(BiomeId, Point4)], version: MinecraftVersion) -> bool {
+    let last_layer = version.num_layers();
+    let mut hits = 0;
+    let mut misses = 0;
+    let target = biomes4.len() * 90 / 100;
+    let max_misses = biomes4.len() - target;
+    for (biome, Point4 {x, z}) in biomes4.iter().cloned() {
+        let area = Area { x, z, w: 1, h: 1 };
+        let g42 = generate_up_to_layer(version, area, world_seed, last_layer - 1, 0);
+        if g42.a[(0, 0)] == biome.0 {
+            hits += 1;
+        } else {
+            misses += 1;
+            if misses > max_misses {
+                return false;
+            }
+        }
+    }
+
+    hits >= target
+}
+
+/// Same as `filter_seeds_using_biomes`, but using quarter-scale biomes
+pub fn filter_seeds_using_biomes4(candidates: &[i64], biomes4: &[(BiomeId, Point4)], version: MinecraftVersion) -> Vec<i64> {
+    candidates.iter().copied().filter(|world_seed| {
+        let valid = seed_matches_biomes4(*world_seed, biomes4, version);
+        if valid {
+            debug!("{:016X}: valid", world_seed);
+        }
+        valid
+    }).collect()
+}
+
+/// River Seed Finder using quarter-scale evidence, for example read by `biome_csv`
+///
+/// Since the input is already at 1:4 scale there is no need to use `reverse_map_voronoi_zoom`.
+/// The rivers are used to find 26-bit candidates, but without the voronoi phase the remaining
+/// 22 bits must be bruteforced before `JavaRng::extend_long_48_batch`, and each 64-bit seed must
+/// be checked against the biomes, so this is much slower than `river_seed_finder_range`.
+///
+/// range_lo: 0
+/// range_hi: 1 << 24
+pub fn river_seed_finder_quarter_scale_range(biomes4: &SparseMap, version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<i64> {
+    river_seed_finder_quarter_scale(biomes4, version, range_lo, range_hi, 0..(1 << (48 - 26)))
+}
+
+// Number of 48-bit seeds passed to `JavaRng::extend_long_48_batch` at once
+const QUARTER_SCALE_BATCH_SIZE: u64 = 1 << 16;
+
+// Same as `river_seed_finder_quarter_scale_range`, but only bruteforcing the values of the bits
+// 26 to 47 in `range_48`
+fn river_seed_finder_quarter_scale(biomes4: &SparseMap, version: MinecraftVersion, range_lo: u32, range_hi: u32, range_48: Range<u64>) -> Vec<i64> {
+    // Frozen rivers are also rivers, but the mushroom island shores may not be rivers
+    let river_mix_candidates = decompose_map_river_mix_candidates(&biomes4.clone().unwrap_or(UNKNOWN_BIOME_ID));
+    let rivers4: Vec<Point4> = certain_rivers(&river_mix_candidates).into_iter().map(|p| Point4 { x: p.x, z: p.z }).collect();
+    let mut biomes4 = sparse_map_to_biomes4(biomes4);
+    if rivers4.is_empty() {
+        debug!("Can't find seed without rivers");
+        return vec![];
+    }
+    let candidates_26 = river_seed_finder_26_range(&rivers4, range_lo, range_hi);
+
+    // All the seeds of the bruteforce have the same rivers, so check the other biomes first to
+    // reject the wrong seeds sooner
+    biomes4.sort_by_key(|(biome, _)| *biome == BiomeId(biome_id::river));
+
+    let mut candidates_64 = vec![];
+    for x in candidates_26 {
+        for start in range_48.clone().step_by(QUARTER_SCALE_BATCH_SIZE as usize) {
+            let end = range_48.end.min(start + QUARTER_SCALE_BATCH_SIZE);
+            let seeds_48: Vec<u64> = (start..end).map(|seed| x as u64 | (seed << 26)).collect();
+            candidates_64.extend(JavaRng::extend_long_48_batch(&seeds_48).into_iter().map(|(_, s)| s as i64).filter(|s| seed_matches_biomes4(*s, &biomes4, version)));
+        }
+    }
+    candidates_64.sort_unstable();
+    debug!("{:016X?}", candidates_64);
+    debug!("64 bit candidates: {}", candidates_64.len());
+
+    candidates_64
+}
+
 /// Treasure Map River Seed Finder
 ///
 /// range_lo: 0
 /// range_hi: 1 << 24
 /// Even though this is a 26-bit bruteforce, we check 4 seeds at a time
 pub fn treasure_map_river_seed_finder(treasure_map: &Map, version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<i64> {
-    // Naming
-    // _tm: treasure_map, indicates 1:2 scale
-    // _pm: previous_map, indicates 1:4 scale, obtained as ReverseMapZoom(treasure_map)
-    // _hv: half_voronoi, indicates 1:2 scale sliced as MapZoom(ReverseMapZoom(treasure_map))
-    // _hd: indicates 1:1 scale
+    let candidates_26 = treasure_map_river_seed_finder_26(treasure_map, range_lo, range_hi);
+
+    let candidates = if version < MinecraftVersion::Java1_15 {
+        let target_maps = TreasureMapTargets::new(treasure_map);
+        // Now use voronoi zoom to bruteforce the remaining (34-26 = 8 bits)
+        let candidates_34 = extend_candidates_34(&candidates_26, &target_maps, 0..(1 << (34 - 26)));
+        debug!("{:09X?}", candidates_34);
+        debug!("34 bit candidates: {}", candidates_34.len());
+        candidates_34
+    } else {
+        // Starting from 1.15, we need the seed hash to continue the bruteforce, so just return
+        // the 26-bit candidates
+        candidates_26
+    };
 
+    return candidates;
+}
+
+// Rivers of the treasure map, at 1:1 scale and at 1:2 scale
+fn treasure_map_river_coords(treasure_map: &Map) -> (Vec<Point>, Vec<Point2>) {
     let mut river_coords_hd = vec![];
     let mut river_coords_tm = vec![];
     let tarea = treasure_map.area();
@@ -5315,12 +6873,33 @@ pub fn treasure_map_river_seed_finder(treasure_map: &Map, version: MinecraftVers
         }
     }
 
+    (river_coords_hd, river_coords_tm)
+}
+
+/// First stage of `treasure_map_river_seed_finder`: the 26-bit candidates in the given range
+pub fn treasure_map_river_seed_finder_26(treasure_map: &Map, range_lo: u32, range_hi: u32) -> Vec<i64> {
+    let (river_coords_hd, _) = treasure_map_river_coords(treasure_map);
     let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&river_coords_hd);
-    let candidates_26 = river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_hi);
 
-    let candidates = if version < MinecraftVersion::Java1_15 {
+    river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_hi)
+}
+
+/// Rivers of a treasure map prepared for the 34-bit stage of `treasure_map_river_seed_finder`.
+/// Only valid for versions before 1.15.
+pub struct TreasureMapTargets {
+    // _tm: treasure_map, indicates 1:2 scale
+    // _pm: previous_map, indicates 1:4 scale, obtained as ReverseMapZoom(treasure_map)
+    // _hv: half_voronoi, indicates 1:2 scale sliced as MapZoom(ReverseMapZoom(treasure_map))
+    target_map_pm: Map,
+    target_map_hv_borders: Map,
+    target_score_hv: u32,
+}
+
+impl TreasureMapTargets {
+    pub fn new(treasure_map: &Map) -> Self {
+        let (_, river_coords_tm) = treasure_map_river_coords(treasure_map);
         let area_tm = Area::from_coords2(river_coords_tm.iter().copied());
-        let target_map_tm = map_with_river_at2(&river_coords_tm, area_tm);
+        let target_map_tm = map_with_river_at2(&river_coords_tm, area_tm).to_map();
         // Reversing from a HalfVoronoiZoom is more or less equivalent to reversing a MapZoom
         let target_map_pm = reverse_map_half_voronoi(&target_map_tm);
 
@@ -5332,143 +6911,304 @@ pub fn treasure_map_river_seed_finder(treasure_map: &Map, version: MinecraftVers
 
         debug!("{}", draw_map(&target_map_tm));
         debug!("{}", draw_map(&target_map_pm));
-        //debug!("{}", draw_map(&target_map_hv));
 
         // Actually, we only want to compare borders, so use HelperMapRiverAll, which is actually an
         // edge detector
         let target_map_hv_borders = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&target_map_hv);
-        //debug!("area_hv_borders: {:?}", area_hv_borders);
         let target_score_hv = count_rivers(&target_map_hv_borders);
-
         debug!("Target voronoi score: {}", target_score_hv);
-        // Now use voronoi zoom to bruteforce the remaining (34-26 = 8 bits)
-        let candidates_34 = candidates_26.into_iter().flat_map(|x| {
-            let mut v = vec![];
-            for seed in 0..(1 << (34 - 26)) {
-                let world_seed = x | (seed << 26);
-                let g43 = MapHalfVoronoiZoom::new(10, world_seed);
-                let candidate_voronoi = g43.get_map_from_pmap(&target_map_pm);
-                //debug!("{}", draw_map(&candidate_voronoi));
-                let candidate_voronoi_borders = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&candidate_voronoi);
-                //debug!("expected, found");
-                //debug!("{}", draw_map(&target_map_hv_borders));
-                //debug!("{}", draw_map(&candidate_voronoi_borders));
-                let candidate_score = count_rivers_exact(&candidate_voronoi_borders, &target_map_hv_borders);
-                if candidate_score >= target_score_hv * 90 / 100 {
-                    debug!("{:09X}: {}", world_seed, candidate_score);
-                    v.push(world_seed);
-                }
+
+        Self { target_map_pm, target_map_hv_borders, target_score_hv }
+    }
+}
+
+/// Second stage of `treasure_map_river_seed_finder`: extend the 26-bit candidates to 34 bits
+/// using the voronoi zoom. Only the values of the bits 26 to 33 inside `range` are checked, so
+/// the full 0..256 range can be split between different workers. The output is sorted by
+/// candidate and then by the value of the upper bits, so concatenating the outputs of
+/// consecutive ranges gives the same result for each candidate.
+pub fn extend_candidates_34(candidates: &[i64], target_maps: &TreasureMapTargets, range: Range<u32>) -> Vec<i64> {
+    extend_candidates_34_scored(candidates, target_maps, range).into_iter().map(|(world_seed, _score)| world_seed).collect()
+}
+
+/// Same as `extend_candidates_34`, but also returns the percentage of the river borders that
+/// match the treasure map.
+pub fn extend_candidates_34_scored(candidates: &[i64], target_maps: &TreasureMapTargets, range: Range<u32>) -> Vec<(i64, u32)> {
+    extend_candidates_34_scored_cancellable(candidates, target_maps, range, &CancelToken::new()).results
+}
+
+/// Same as `extend_candidates_34_scored`, but stops early if `cancel` is cancelled, returning the
+/// candidates found so far. The cancellation is checked before extending each 26-bit candidate.
+pub fn extend_candidates_34_scored_cancellable(candidates: &[i64], target_maps: &TreasureMapTargets, range: Range<u32>, cancel: &CancelToken) -> Partial<(i64, u32)> {
+    let mut truncated = false;
+    let results = candidates.iter().take_while(|_| {
+        truncated = cancel.is_cancelled();
+        !truncated
+    }).flat_map(|x| {
+        let x = *x;
+        range.clone().filter_map(move |seed| {
+            let world_seed = x | (i64::from(seed) << 26);
+            let g43 = MapHalfVoronoiZoom::new(10, world_seed);
+            let candidate_voronoi = g43.get_map_from_pmap(&target_maps.target_map_pm);
+            let candidate_voronoi_borders = HelperMapRiverAll::new(1, 0).get_map_from_pmap(&candidate_voronoi);
+            let candidate_score = count_rivers_exact(&candidate_voronoi_borders, &target_maps.target_map_hv_borders);
+            if candidate_score >= target_maps.target_score_hv * 90 / 100 {
+                debug!("{:09X}: {}", world_seed, candidate_score);
+                Some((world_seed, score_percent(candidate_score, target_maps.target_score_hv)))
+            } else {
+                None
             }
+        })
+    }).collect();
 
-            v
-        }).collect::<Vec<_>>();
-        debug!("{:09X?}", candidates_34);
-        debug!("34 bit candidates: {}", candidates_34.len());
-        candidates_34
+    Partial { results, truncated }
+}
+
+// Percentage of hits, where an empty target is a perfect match
+fn score_percent(hits: u32, total: u32) -> u32 {
+    if total == 0 {
+        100
     } else {
-        // Starting from 1.15, we need the seed hash to continue the bruteforce, so just return
-        // the 26-bit candidates
-        candidates_26
+        (u64::from(hits) * 100 / u64::from(total)).min(100) as u32
+    }
+}
+
+/// Candidate found by `treasure_map_river_seed_finder_scored`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TreasureMapCandidate {
+    /// Lower bits of the world seed: 34 bits before 1.15, 26 bits starting from 1.15
+    pub world_seed: i64,
+    /// Percentage of the rivers that match the treasure map
+    pub score: u32,
+    /// The similar biome seed of `world_seed`, if it was also a candidate. Both seeds generate
+    /// the same rivers, so the treasure map cannot tell them apart and only one of them is
+    /// returned.
+    pub similar_seed: Option<i64>,
+}
+
+/// Same as `treasure_map_river_seed_finder`, but with the candidates sorted by score, best
+/// first. Pairs of similar biome seeds are merged into one candidate.
+pub fn treasure_map_river_seed_finder_scored(treasure_map: &Map, version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<TreasureMapCandidate> {
+    let candidates_26 = treasure_map_river_seed_finder_26(treasure_map, range_lo, range_hi);
+
+    let (scored, bits) = if version < MinecraftVersion::Java1_15 {
+        let target_maps = TreasureMapTargets::new(treasure_map);
+
+        (extend_candidates_34_scored(&candidates_26, &target_maps, 0..(1 << (34 - 26))), 34)
+    } else {
+        let (river_coords_hd, _) = treasure_map_river_coords(treasure_map);
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&river_coords_hd);
+        let (target_maps_26, bad_map_target) = river_target_maps_26(&river_coords_quarter_scale);
+        let max_score = target_maps_26.iter().map(|(_map, rivers)| rivers).sum();
+        let scored = candidates_26.into_iter().filter_map(|world_seed| {
+            // Use 0% as the threshold to always get the score
+            let score = candidate_26_score(world_seed, &target_maps_26, bad_map_target, 0)?;
+
+            Some((world_seed, score_percent(score, max_score)))
+        }).collect();
+
+        (scored, 26)
     };
 
-    return candidates;
+    merge_similar_biome_seeds(scored, bits)
+}
+
+/// Offsets in pixels tried by `treasure_map_river_seed_finder_any_alignment`. The offset is added
+/// to the coordinates of the treasure map.
+pub const TREASURE_MAP_ALIGNMENTS: [(i64, i64); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+/// Same as `treasure_map_river_seed_finder_scored`, but also trying the treasure map moved by one
+/// pixel in each axis, because a map copied without the 1 pixel border is easy to misalign by one
+/// pixel, and a misaligned map changes the parity of each pixel in the half voronoi zoom, which
+/// can reject the correct seed. Each alignment of `TREASURE_MAP_ALIGNMENTS` is scored
+/// independently and each candidate keeps its best score, together with the offset of that
+/// score. This is 4 times slower than `treasure_map_river_seed_finder_scored`.
+pub fn treasure_map_river_seed_finder_any_alignment(treasure_map: &Map, version: MinecraftVersion, range_lo: u32, range_hi: u32) -> Vec<(TreasureMapCandidate, (i64, i64))> {
+    let mut best: HashMap<i64, (TreasureMapCandidate, (i64, i64))> = HashMap::new();
+    for &(dx, dz) in TREASURE_MAP_ALIGNMENTS.iter() {
+        let mut moved = treasure_map.clone();
+        moved.x += dx;
+        moved.z += dz;
+        for c in treasure_map_river_seed_finder_scored(&moved, version, range_lo, range_hi) {
+            let better = best.get(&c.world_seed).map_or(true, |(b, _)| c.score > b.score);
+            if better {
+                best.insert(c.world_seed, (c, (dx, dz)));
+            }
+        }
+    }
+
+    let mut candidates: Vec<_> = best.into_iter().map(|(_, c)| c).collect();
+    candidates.sort_unstable_by_key(|(c, _)| (std::cmp::Reverse(c.score), c.world_seed));
+
+    candidates
 }
 
-fn count_rivers(m: &Map) -> u32 {
-    m.a.fold(0, |acc, &x| if x == biome_id::river { acc + 1 } else { acc })
+// Merge the candidates that are similar biome seeds of each other, keeping the lowest seed and
+// the best score, and sort them by score
+fn merge_similar_biome_seeds(scored: Vec<(i64, u32)>, bits: u8) -> Vec<TreasureMapCandidate> {
+    use crate::java_rng::mask;
+    let mut merged: HashMap<i64, TreasureMapCandidate> = HashMap::new();
+    for (world_seed, score) in scored {
+        let similar = McRng::similar_biome_seed(world_seed) & mask(bits) as i64;
+        let (lo, hi) = (world_seed.min(similar), world_seed.max(similar));
+        merged.entry(lo).and_modify(|c| {
+            c.world_seed = lo;
+            c.score = c.score.max(score);
+            c.similar_seed = Some(hi);
+        }).or_insert(TreasureMapCandidate { world_seed, score, similar_seed: None });
+    }
+
+    let mut candidates: Vec<_> = merged.into_iter().map(|(_, c)| c).collect();
+    candidates.sort_unstable_by_key(|c| (std::cmp::Reverse(c.score), c.world_seed));
+
+    candidates
 }
 
-// The first map should have more rivers than the second one
-fn count_rivers_and(a: &Map, b: &Map) -> u32 {
-    assert_eq!(a.area(), b.area());
-    ndarray::Zip::from(&a.a).and(&b.a).fold(0, |mut acc, &v11_a, &v11_b| {
-        acc += if v11_b == biome_id::river && v11_a == v11_b {
-            1
-        } else {
-            0
-        };
-        acc
-    })
+/// River mask with rivers at these coordinates. Use `RiverMask::to_map` to get a `Map`.
+pub fn map_with_river_at(c: &[Point], area: Area) -> RiverMask {
+    RiverMask::from_points(area, c.iter().map(|p| (p.x, p.z)))
 }
 
-fn count_rivers_exact(a: &Map, b: &Map) -> u32 {
-    assert_eq!(a.area(), b.area());
-    let acc = ndarray::Zip::from(&a.a).and(&b.a).fold(0, |mut acc, &v11_a, &v11_b| {
-        acc += if v11_a == biome_id::river && v11_a == v11_b {
-            1
-        } else if v11_a == biome_id::river || v11_b == biome_id::river {
-            -1
-        } else {
-            0
-        };
-        acc
-    });
+pub fn map_with_river_at2(c: &[Point2], area: Area) -> RiverMask {
+    RiverMask::from_points(area, c.iter().map(|p| (p.x, p.z)))
+}
 
-    if acc < 0 { 0 } else { acc as u32 }
+pub fn map_with_river_at4(c: &[Point4], area: Area) -> RiverMask {
+    RiverMask::from_points(area, c.iter().map(|p| (p.x, p.z)))
 }
 
-pub fn map_with_river_at(c: &[Point], area: Area) -> Map {
-    let mut m = Map::new(area);
-    for Point {x, z} in c {
-        m.a[((x - area.x) as usize, (z - area.z) as usize)] = biome_id::river;
+/// Cost of generating a candidate map for this quarter-scale area using
+/// `CandidateRiverMapGenerator`: the total number of tiles of all the intermediate maps.
+pub fn candidate_river_map_cost(area: Area) -> u64 {
+    // MapSmooth and HelperMapRiverAll need a border of 1 tile each
+    let mut w = area.w + 4;
+    let mut h = area.h + 4;
+    let mut cost = area.w * area.h + (area.w + 2) * (area.h + 2) + w * h;
+    // 6 MapZoom layers
+    for _ in 0..6 {
+        w = (w >> 1) + 2;
+        h = (h >> 1) + 2;
+        cost += w * h;
     }
-    m
+
+    cost
 }
 
-pub fn map_with_river_at2(c: &[Point2], area: Area) -> Map {
-    let mut m = Map::new(area);
-    for Point2 {x, z} in c {
-        m.a[((x - area.x) as usize, (z - area.z) as usize)] = biome_id::river;
-    }
-    m
+/// Cost of comparing a 1:1 river map using the voronoi zoom, as done in the 34-bit phase of
+/// `river_seed_finder_range`: the 1:1 map and the reversed 1:4 map.
+pub fn voronoi_river_map_cost(area: Area) -> u64 {
+    area.w * area.h + ((area.w >> 2) + 2) * ((area.h >> 2) + 2)
 }
 
-pub fn map_with_river_at4(c: &[Point4], area: Area) -> Map {
-    let mut m = Map::new(area);
-    for Point4 {x, z} in c {
-        m.a[((x - area.x) as usize, (z - area.z) as usize)] = biome_id::river;
+/// Split points into fragments, trying to minimize the total cost of generating one map for each
+/// fragment.
+///
+/// Points are first split into fragments of size 64x64. Then each fragment is recursively split
+/// in two halves while the cost of the two halves is lower than the cost of the fragment, and
+/// both halves have at least `min_points` points. This results in small fragments for sparse
+/// observations, like a long diagonal river, and keeps dense observations together.
+pub fn plan_fragments<P, F, C>(points: &[P], coords: F, cost: C, min_points: usize) -> Vec<Vec<P>>
+where
+    P: Copy,
+    F: Fn(P) -> Point,
+    C: Fn(Area) -> u64,
+{
+    fn split<P: Copy>(ps: Vec<P>, coords: &dyn Fn(P) -> Point, cost: &dyn Fn(Area) -> u64, min_points: usize, r: &mut Vec<Vec<P>>) {
+        let area = Area::from_coords(ps.iter().map(|p| coords(*p)));
+        let mut best: Option<(u64, Vec<P>, Vec<P>)> = None;
+        if ps.len() >= 2 * min_points {
+            // Try to split by the middle of each axis
+            let mid_x = area.x + (area.w / 2) as i64;
+            let mid_z = area.z + (area.h / 2) as i64;
+            let by_x = |p: &P| coords(*p).x < mid_x;
+            let by_z = |p: &P| coords(*p).z < mid_z;
+            for in_first_half in [&by_x as &dyn Fn(&P) -> bool, &by_z] {
+                let (a, b): (Vec<P>, Vec<P>) = ps.iter().partition(|p| in_first_half(p));
+                if a.len() < min_points || b.len() < min_points {
+                    continue;
+                }
+                let split_cost = cost(Area::from_coords(a.iter().map(|p| coords(*p)))) + cost(Area::from_coords(b.iter().map(|p| coords(*p))));
+                if split_cost < best.as_ref().map_or(cost(area), |x| x.0) {
+                    best = Some((split_cost, a, b));
+                }
+            }
+        }
+
+        match best {
+            Some((_, a, b)) => {
+                split(a, coords, cost, min_points, r);
+                split(b, coords, cost, min_points, r);
+            }
+            None => r.push(ps),
+        }
     }
-    m
-}
 
-/// Segregate a list of river coordinates into small maps
-pub fn split_rivers_into_fragments(points: &[Point]) -> Vec<Map> {
-    let mut h: HashMap<(i64, i64), Vec<Point>> = HashMap::new();
+    let mut h: HashMap<(i64, i64), Vec<P>> = HashMap::new();
 
     let frag_size_log2 = 6;
     // Split points into fragments of size 64x64
     for p in points {
-        let (frag_x, frag_z) = (p.x >> frag_size_log2, p.z >> frag_size_log2);
+        let Point { x, z } = coords(*p);
+        let (frag_x, frag_z) = (x >> frag_size_log2, z >> frag_size_log2);
         h.entry((frag_x, frag_z)).or_default().push(*p);
     }
 
-    // Convert that fragments into maps
+    // Sort to make the output deterministic
+    let mut fragments: Vec<_> = h.into_iter().collect();
+    fragments.sort_unstable_by_key(|(k, _)| *k);
     let mut r = vec![];
-    for ps in h.values() {
-        let a = Area::from_coords(ps.iter().copied());
-        let m = map_with_river_at(ps, a);
-        r.push(m);
+    for (_, ps) in fragments {
+        split(ps, &coords, &cost, min_points, &mut r);
     }
 
     r
 }
 
+/// Cost of generating a map of this area to compare it against sparse observations, for
+/// `plan_fragments`. Each map has a fixed cost because of the borders needed by the parent
+/// layers, so the observations are only split into different maps when they are far away from
+/// each other.
+pub fn observation_map_cost(area: Area) -> u64 {
+    (area.w + 16) * (area.h + 16)
+}
+
+/// Split the observations into groups that can be generated as separate maps, see
+/// `observation_map_cost`. This avoids generating the huge bounding box of observations that
+/// are far away from each other.
+pub fn cluster_observations<P, F>(points: &[P], coords: F) -> Vec<Vec<P>>
+where
+    P: Copy,
+    F: Fn(P) -> Point,
+{
+    plan_fragments(points, coords, observation_map_cost, 1)
+}
+
 /// Segregate a list of river coordinates into small maps
-pub fn split_rivers_into_fragments4(points: &[Point4]) -> Vec<Map> {
-    let mut h: HashMap<(i64, i64), Vec<Point4>> = HashMap::new();
+pub fn split_rivers_into_fragments(points: &[Point]) -> Vec<Map> {
+    // The 34-bit phase ignores maps with less than 40 rivers
+    let fragments = plan_fragments(points, |p| p, voronoi_river_map_cost, 40);
 
-    let frag_size_log2 = 6;
-    // Split points into fragments of size 64x64
-    for p in points {
-        let (frag_x, frag_z) = (p.x >> frag_size_log2, p.z >> frag_size_log2);
-        h.entry((frag_x, frag_z)).or_default().push(*p);
+    // Convert that fragments into maps
+    let mut r = vec![];
+    for ps in fragments {
+        let a = Area::from_coords(ps.iter().copied());
+        let m = map_with_river_at(&ps, a).to_map();
+        r.push(m);
     }
 
+    r
+}
+
+/// Segregate a list of river coordinates into small river masks
+pub fn split_rivers_into_fragments4(points: &[Point4]) -> Vec<RiverMask> {
+    // The 26-bit phase ignores maps with less than 10 rivers
+    let fragments = plan_fragments(points, |p| Point { x: p.x, z: p.z }, candidate_river_map_cost, 10);
+
     // Convert that fragments into maps
     let mut r = vec![];
-    for ps in h.values() {
+    for ps in fragments {
         let a = Area::from_coords4(ps.iter().copied());
-        let m = map_with_river_at4(ps, a);
+        let m = map_with_river_at4(&ps, a);
         r.push(m);
     }
 
@@ -5560,22 +7300,56 @@ fn can_generate_river_near_steps(pre_voronoi_point: Point, world_seed: i64) -> u
         return 5;
     }
 
-    0
+    0
+}
+
+/// River layers of the lower 26 bits of a world seed, without the land layers. The parent of the
+/// first `MapZoom` is `TestMapCheckers` instead of `MapRiverInit`, so every tile is different from
+/// its neighbours, and `HelperMapRiverAll` generates all the rivers that may exist in any world
+/// seed with these lower 26 bits. The layers are 6 `MapZoom`, `HelperMapRiverAll` and `MapSmooth`.
+///
+/// Same as layer 141 of `generate_up_to_layer_1_7`.
+pub fn checkers_river_stack(world_seed: i64) -> impl GetMap {
+    let g22 = TestMapCheckers;
+    let mut g34 = MapZoom::new(1000, world_seed);
+    g34.parent = Some(Rc::new(g22));
+    let mut g35 = MapZoom::new(1001, world_seed);
+    g35.parent = Some(Rc::new(g34));
+    let mut g36 = MapZoom::new(1000, world_seed);
+    g36.parent = Some(Rc::new(g35));
+    let mut g37 = MapZoom::new(1001, world_seed);
+    g37.parent = Some(Rc::new(g36));
+    let mut g38 = MapZoom::new(1002, world_seed);
+    g38.parent = Some(Rc::new(g37));
+    let mut g39 = MapZoom::new(1003, world_seed);
+    g39.parent = Some(Rc::new(g38));
+    let mut g40 = HelperMapRiverAll::new(1, world_seed);
+    g40.parent = Some(Rc::new(g39));
+    let mut g41 = MapSmooth::new(1000, world_seed);
+    g41.parent = Some(Rc::new(g40));
+
+    g41
 }
 
-pub fn candidate_river_map_generator(world_seed: i64) -> impl GetMap {
+/// Same as `checkers_river_stack`, but using `HelperMapZoomAllEdges` instead of `MapZoom`, so the
+/// center tile of each zoom is different from all its neighbours and there are more edges where a
+/// river could be generated. This does not generate all the possible rivers, but it is useful to
+/// visualize how the zoom layers move the edges.
+///
+/// Same as layer 241 of `generate_up_to_layer_1_7`.
+pub fn all_edges_river_stack(world_seed: i64) -> impl GetMap {
     let g22 = TestMapCheckers;
-    let mut g34 = MapZoom::new(1000, world_seed);
+    let mut g34 = HelperMapZoomAllEdges::new(1000, world_seed);
     g34.parent = Some(Rc::new(g22));
-    let mut g35 = MapZoom::new(1001, world_seed);
+    let mut g35 = HelperMapZoomAllEdges::new(1001, world_seed);
     g35.parent = Some(Rc::new(g34));
-    let mut g36 = MapZoom::new(1000, world_seed);
+    let mut g36 = HelperMapZoomAllEdges::new(1000, world_seed);
     g36.parent = Some(Rc::new(g35));
-    let mut g37 = MapZoom::new(1001, world_seed);
+    let mut g37 = HelperMapZoomAllEdges::new(1001, world_seed);
     g37.parent = Some(Rc::new(g36));
-    let mut g38 = MapZoom::new(1002, world_seed);
+    let mut g38 = HelperMapZoomAllEdges::new(1002, world_seed);
     g38.parent = Some(Rc::new(g37));
-    let mut g39 = MapZoom::new(1003, world_seed);
+    let mut g39 = HelperMapZoomAllEdges::new(1003, world_seed);
     g39.parent = Some(Rc::new(g38));
     let mut g40 = HelperMapRiverAll::new(1, world_seed);
     g40.parent = Some(Rc::new(g39));
@@ -5585,6 +7359,86 @@ pub fn candidate_river_map_generator(world_seed: i64) -> impl GetMap {
     g41
 }
 
+/// Rivers that may be generated by a 26-bit candidate, see `checkers_river_stack`
+pub fn candidate_river_map_generator(world_seed: i64) -> impl GetMap {
+    checkers_river_stack(world_seed)
+}
+
+/// Same as `candidate_river_map_generator`, but the intermediate maps are kept between calls to
+/// avoid allocations in the 26-bit bruteforce. The buffers are only reused if the size of the
+/// area does not change, so use one generator for each target map.
+pub struct CandidateRiverMapGenerator {
+    zooms: [MapZoom; 6],
+    river: HelperMapRiverAll,
+    smooth: MapSmooth,
+    // checkers, 6 zooms, river, smooth
+    buffers: RefCell<[Map; 9]>,
+}
+
+impl CandidateRiverMapGenerator {
+    pub fn new(world_seed: i64) -> Self {
+        Self {
+            zooms: [1000, 1001, 1000, 1001, 1002, 1003].map(|base_seed| MapZoom::new(base_seed, world_seed)),
+            river: HelperMapRiverAll::new(1, world_seed),
+            smooth: MapSmooth::new(1000, world_seed),
+            buffers: Default::default(),
+        }
+    }
+
+    pub fn set_world_seed(&mut self, world_seed: i64) {
+        for g in self.zooms.iter_mut() {
+            g.world_seed = world_seed;
+        }
+        self.river.world_seed = world_seed;
+        self.smooth.world_seed = world_seed;
+    }
+
+    pub fn get_map_into(&self, area: Area, map: &mut Map) {
+        self.generate_buffers(area);
+        let buffers = self.buffers.borrow();
+        let smooth_map = &buffers[8];
+        let (nx, nz) = ((area.x - smooth_map.x) as usize, (area.z - smooth_map.z) as usize);
+        map.reset(area);
+        map.a.assign(&smooth_map.a.slice(s![nx..nx + area.w as usize, nz..nz + area.h as usize]));
+    }
+
+    // Generate all the layers, the last one is stored in buffers[8] and contains the area
+    fn generate_buffers(&self, area: Area) {
+        // The layers are not cropped, so each map is larger than needed, but its size only
+        // depends on the size of area
+        let river_area = Area { x: area.x - 1, z: area.z - 1, w: area.w + 2, h: area.h + 2 };
+        let zoom_area = Area { x: river_area.x - 1, z: river_area.z - 1, w: river_area.w + 2, h: river_area.h + 2 };
+        let mut checkers_area = zoom_area;
+        for _ in 0..self.zooms.len() {
+            checkers_area = Area { x: checkers_area.x >> 1, z: checkers_area.z >> 1, w: (checkers_area.w >> 1) + 2, h: (checkers_area.h >> 1) + 2 };
+        }
+
+        let mut buffers = self.buffers.borrow_mut();
+        // Same as TestMapCheckers
+        buffers[0].reset(checkers_area);
+        for ((x, z), v) in buffers[0].a.indexed_iter_mut() {
+            let rx = (checkers_area.x + x as i64) as usize % 4;
+            let rz = (checkers_area.z + z as i64) as usize % 4;
+            *v = (rz * 4 + rx) as i32;
+        }
+        for (i, g) in self.zooms.iter().enumerate() {
+            let (prev, next) = buffers.split_at_mut(i + 1);
+            g.get_map_from_pmap_into(&prev[i], &mut next[0]);
+        }
+        let (prev, next) = buffers.split_at_mut(7);
+        self.river.get_map_from_pmap_into(&prev[6], &mut next[0]);
+        let (prev, next) = buffers.split_at_mut(8);
+        self.smooth.get_map_from_pmap_into(&prev[7], &mut next[0]);
+    }
+
+    /// Same as `get_map_into`, but only the rivers are stored, packed into a bitset. The output
+    /// of the last layer is packed directly, without copying it into a smaller map first.
+    pub fn get_mask_into(&self, area: Area, mask: &mut RiverMask) {
+        self.generate_buffers(area);
+        mask.set_from_map_area(&self.buffers.borrow()[8], area);
+    }
+}
+
 pub fn candidate_river_map(a: Area, world_seed: i64) -> Map {
     candidate_river_map_generator(world_seed).get_map(a)
 }
@@ -5602,21 +7456,60 @@ pub fn candidate_river_map_bit_25_undefined(a: Area, world_seed: i64) -> Map {
     map_or.get_map(a)
 }
 
+/// Symbols used by `draw_map_with_legend` to represent each biome
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapLegend {
+    /// Symbols for specific ids, these take precedence over the biome classes
+    pub symbols: HashMap<i32, char>,
+    pub ocean: char,
+    pub river: char,
+    /// Snowy land biomes
+    pub ice: char,
+    pub land: char,
+    /// Ids not in `symbols` which are not valid biomes
+    pub unknown: char,
+}
+
+impl Default for MapLegend {
+    fn default() -> Self {
+        // The first layers use 0 for ocean, 1 for land, and 2 and 3 for the climate
+        let symbols = [(0, '_'), (1, '#'), (2, '2'), (3, '3'), (biome_id::river, 'R')].into_iter().collect();
+
+        Self { symbols, ocean: '~', river: 'r', ice: '*', land: '.', unknown: '?' }
+    }
+}
+
+impl MapLegend {
+    pub fn symbol(&self, id: i32) -> char {
+        if let Some(c) = self.symbols.get(&id) {
+            return *c;
+        }
+        if id < 0 || !biome_exists(id) {
+            return self.unknown;
+        }
+
+        if id == biome_id::river || id == biome_id::frozenRiver {
+            self.river
+        } else if is_oceanic(id) {
+            self.ocean
+        } else if is_biome_snowy(id) {
+            self.ice
+        } else {
+            self.land
+        }
+    }
+}
+
 pub fn draw_map(map: &Map) -> String {
+    draw_map_with_legend(map, &MapLegend::default())
+}
+
+pub fn draw_map_with_legend(map: &Map, legend: &MapLegend) -> String {
     let (w, h) = map.a.dim();
     let mut s = format!("MAP: x: {}, z: {}, {}x{}\n", map.x, map.z, w, h);
     for z in 0..h {
         for x in 0..w {
-            //let c = if map.a[(x, z)] != 0 { "#" } else { "_" };
-            let c = match map.a[(x, z)] {
-                0 => "_",
-                1 => "#",
-                2 => "2",
-                3 => "3",
-                7 => "R",
-                _ => "?",
-            };
-            s.push_str(c);
+            s.push(legend.symbol(map.a[(x, z)]));
         }
         s.push_str("\n");
     }
@@ -5624,40 +7517,201 @@ pub fn draw_map(map: &Map) -> String {
     s
 }
 
-pub fn draw_map_image(map: &Map) -> Vec<u8> {
+/// Same as `draw_map_with_legend`, but also set the background color of each symbol to the biome
+/// color using ANSI escape codes. Needs a terminal with true color support.
+#[cfg(feature = "ansi_colors")]
+pub fn draw_map_colored(map: &Map, legend: &MapLegend) -> String {
+    let (w, h) = map.a.dim();
+    let mut s = format!("MAP: x: {}, z: {}, {}x{}\n", map.x, map.z, w, h);
+    for z in 0..h {
+        for x in 0..w {
+            let id = map.a[(x, z)];
+            let [r, g, b, _a] = biome_to_color(id);
+            // Use black or white text depending on the brightness of the background
+            let fg = if (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000 >= 128 { 30 } else { 97 };
+            s.push_str(&format!("\x1b[{};48;2;{};{};{}m{}", fg, r, g, b, legend.symbol(id)));
+        }
+        s.push_str("\x1b[0m\n");
+    }
+
+    s
+}
+
+/// Order of the pixels in the images returned by the drawing functions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageOrientation {
+    /// x increases to the right and z increases downwards, like the in-game map. Each row of the
+    /// image has constant z, so the pixel at (x, z) has index `z * width + x`.
+    XRight,
+    /// z increases to the right and x increases downwards, the map is transposed. Each row of the
+    /// image has constant x, so the pixel at (x, z) has index `x * width + z`.
+    ZRight,
+}
+
+impl Default for ImageOrientation {
+    fn default() -> Self {
+        ImageOrientation::XRight
+    }
+}
+
+/// RGBA image, stored row by row
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageBuffer {
+    pub width: usize,
+    pub height: usize,
+    /// 4 bytes per pixel, `width * height * 4` bytes in total
+    pub data: Vec<u8>,
+}
+
+impl ImageBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, data: vec![0; width * height * 4] }
+    }
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]]
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let i = (y * self.width + x) * 4;
+        self.data[i..i + 4].copy_from_slice(&color);
+    }
+    pub fn into_raw(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Convert each value of the map into a pixel
+pub fn draw_map_image_with<F>(map: &Map, orientation: ImageOrientation, f: F) -> ImageBuffer
+where
+    F: Fn(i32) -> [u8; 4],
+{
     let (w, h) = map.a.dim();
-    let mut v = vec![0; w*h*4];
+    let mut img = match orientation {
+        ImageOrientation::XRight => ImageBuffer::new(w, h),
+        ImageOrientation::ZRight => ImageBuffer::new(h, w),
+    };
     for x in 0..w {
         for z in 0..h {
-            let color = biome_to_color(map.a[(x, z)]);
-            let i = z * w + x;
-            v[i*4+0] = color[0];
-            v[i*4+1] = color[1];
-            v[i*4+2] = color[2];
-            v[i*4+3] = color[3];
+            let color = f(map.a[(x, z)]);
+            match orientation {
+                ImageOrientation::XRight => img.set_pixel(x, z, color),
+                ImageOrientation::ZRight => img.set_pixel(z, x, color),
+            }
         }
     }
 
-    v
+    img
 }
 
-pub fn draw_map_image_noise(map: &Map) -> Vec<u8> {
-    let (w, h) = map.a.dim();
-    let mut v = vec![0; w*h*4];
+pub fn draw_map_image(map: &Map) -> ImageBuffer {
+    draw_map_image_with(map, ImageOrientation::XRight, biome_to_color)
+}
+
+/// Compare two maps of the same area, for example the observed biomes and the biomes generated by
+/// a candidate seed. Tiles that are different are red, rivers in both maps are blue, and the
+/// other equal tiles use a darker version of their biome color. Tiles that are unknown in any of
+/// the two maps are transparent. The image has the same orientation as `draw_map_image`.
+pub fn draw_diff_image(a: &Map, b: &Map) -> ImageBuffer {
+    assert_eq!(a.area(), b.area(), "Maps must have the same area");
+    let (w, h) = a.a.dim();
+    let mut img = ImageBuffer::new(w, h);
     for x in 0..w {
         for z in 0..h {
-            let a = map.a[(x, z)];
-            let gray = a as u8;
-            let color = [gray, gray, gray, 0xFF];
-            let i = z * w + x;
-            v[i*4+0] = color[0];
-            v[i*4+1] = color[1];
-            v[i*4+2] = color[2];
-            v[i*4+3] = color[3];
+            let (va, vb) = (a.a[(x, z)], b.a[(x, z)]);
+            let color = if va == UNKNOWN_BIOME_ID || vb == UNKNOWN_BIOME_ID {
+                [0, 0, 0, 0]
+            } else if va != vb {
+                [255, 0, 0, 255]
+            } else if va == biome_id::river {
+                [0, 0, 255, 255]
+            } else {
+                let [r, g, b, _] = biome_to_color(va);
+                [r / 3, g / 3, b / 3, 255]
+            };
+            img.set_pixel(x, z, color);
         }
     }
 
-    v
+    img
+}
+
+pub fn draw_map_image_noise(map: &Map) -> ImageBuffer {
+    draw_map_image_with(map, ImageOrientation::XRight, |a| {
+        let gray = a as u8;
+        [gray, gray, gray, 0xFF]
+    })
+}
+
+// Distinct colors for small integers such as the TestMapCheckers colors, reduce_id classes and
+// pretty_biome_map_hills values
+static HELPER_CLASS_COLORS: [u32; 16] = [
+    0x1F77B4,
+    0xFF7F0E,
+    0x2CA02C,
+    0xD62728,
+    0x9467BD,
+    0x8C564B,
+    0xE377C2,
+    0x7F7F7F,
+    0xBCBD22,
+    0x17BECF,
+    0xAEC7E8,
+    0xFFBB78,
+    0x98DF8A,
+    0xFF9896,
+    0xC5B0D5,
+    0xC49C94,
+];
+
+/// Color of the output of a helper layer which does not contain biome ids. Negative values are
+/// black, the other values use a palette of 16 colors.
+pub fn helper_class_to_color(id: i32) -> [u8; 4] {
+    if id < 0 {
+        return [0, 0, 0, 0xFF];
+    }
+    let [_, r, g, b] = HELPER_CLASS_COLORS[(id & 0xF) as usize].to_be_bytes();
+
+    [r, g, b, 0xFF]
+}
+
+/// Color of the output of HelperMapRiverAll: rivers use the biome color, anything else is black
+pub fn helper_river_to_color(id: i32) -> [u8; 4] {
+    if id == biome_id::river {
+        biome_to_color(id)
+    } else {
+        [0, 0, 0, 0xFF]
+    }
+}
+
+/// Palette used by `generate_image_up_to_layer`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LayerPalette {
+    Biomes,
+    Noise,
+    /// `helper_class_to_color`
+    HelperClasses,
+    /// `helper_river_to_color`
+    HelperRivers,
+}
+
+/// Select the palette that makes sense for this layer. The layers in the 100 and 200 ranges are
+/// helper layers used to debug the river seed finder, most of them contain TestMapCheckers colors
+/// instead of biome ids.
+pub fn layer_palette(version: MinecraftVersion, layer: u32) -> LayerPalette {
+    match (version, layer) {
+        // Layers [0, 7] are used to visualize noise.
+        // Layers 8 and 9 can use the default draw_map_image.
+        // Layer 50 is similar to noise, except it should be easy to spot low values.
+        // Layer 51 is a binary map of biome ids that are different in search_bruteforce and
+        // search_tree
+        (MinecraftVersion::Java1_18, 0..=7 | 50 | 51) => LayerPalette::Noise,
+        // No helper layers in these versions
+        (MinecraftVersion::Java1_3 | MinecraftVersion::Java1_18, _) => LayerPalette::Biomes,
+        (_, 140 | 141 | 240 | 241) => LayerPalette::HelperRivers,
+        // Layer 170 is a binary map of can_generate_river_near_steps
+        (_, 100..=139 | 170 | 200..=239) => LayerPalette::HelperClasses,
+        _ => LayerPalette::Biomes,
+    }
 }
 
 static TREASURE_MAP_COLORS: [u32; 64] = [
@@ -5777,28 +7831,15 @@ pub fn treasure_map_to_color(id: i32) -> [u8; 4] {
 }
 
 
-pub fn draw_treasure_map_image(map: &Map) -> Vec<u8> {
-    let (w, h) = map.a.dim();
-    let mut v = vec![0; w*h*4];
-    for x in 0..w {
-        for z in 0..h {
-            let color = treasure_map_to_color(map.a[(x, z)]);
-            let i = z * w + x;
-            v[i*4+0] = color[0];
-            v[i*4+1] = color[1];
-            v[i*4+2] = color[2];
-            v[i*4+3] = color[3];
-        }
-    }
-
-    v
+pub fn draw_treasure_map_image(map: &TreasureMapColors) -> ImageBuffer {
+    draw_map_image_with(map.map(), ImageOrientation::XRight, treasure_map_to_color)
 }
 
 /// Generate terrain with the same style as unexplored treasure maps.
 pub fn generate_image_treasure_map(version: MinecraftVersion, area: Area, seed: i64) -> Vec<u8> {
     let map = generate_fragment_treasure_map(version, area, seed);
 
-    draw_treasure_map_image(&map)
+    draw_treasure_map_image(&map).into_raw()
 }
 
 /// Generate a treasure map with the same scale and aligment as ingame maps.
@@ -5814,20 +7855,48 @@ pub fn generate_image_treasure_map_at(version: MinecraftVersion, seed: i64, frag
     // Generate a 128x128 treasure map
     let mut map = generate_fragment_treasure_map(version, parea, seed);
     // But treasure maps have 126x126 resulution, so delete border pixels
-    set_pixels_at_margin(&mut map, 0);
+    set_pixels_at_margin(&mut map.0, 0);
 
     // And convert the resulting map to a RGBA image
     // We could generate a 126x126 map and add the padding during the conversion to image, but that
     // would require a draw_treasure_map_image_with_padding function
-    draw_treasure_map_image(&map)
+    draw_treasure_map_image(&map).into_raw()
+}
+
+pub fn generate_fragment_treasure_map(version: MinecraftVersion, area: Area, seed: i64) -> TreasureMapColors {
+    let mt = MapTreasure {
+        parent: generator_half_voronoi(version, seed),
+        sin_mode: SinMode::default(),
+    };
+
+    mt.get_colors(area)
+}
+
+/// Cheaper version of `generate_fragment_treasure_map` which skips the voronoi zoom and samples
+/// the 1:4 biomes directly, so it only needs to generate one fourth of the tiles. The borders
+/// between biomes are straight, so the result will differ from the real treasure map near the
+/// borders.
+pub fn generate_fragment_treasure_map_quarter_scale(version: MinecraftVersion, area: Area, seed: i64) -> TreasureMapColors {
+    let parent: Rc<dyn GetMap> = match version {
+        MinecraftVersion::Java1_13 => Rc::from(generator_up_to_layer_1_13(seed, 50)),
+        MinecraftVersion::Java1_14 => Rc::from(generator_up_to_layer_1_14(seed, 50)),
+        MinecraftVersion::Java1_15 | MinecraftVersion::Java1_16_1 | MinecraftVersion::Java1_16 | MinecraftVersion::Java1_17 => {
+            Rc::from(generator_up_to_layer_1_15(seed, 50, version))
+        }
+        _ => panic!("Version {:?} not implemented", version),
+    };
+    let mt = MapTreasureQuarterScale { parent, sin_mode: SinMode::default() };
+
+    mt.get_colors(area)
 }
 
-pub fn generate_fragment_treasure_map(version: MinecraftVersion, area: Area, seed: i64) -> Map {
+/// Biomes at 1:2 scale, as seen in treasure maps.
+fn generator_half_voronoi(version: MinecraftVersion, seed: i64) -> Rc<dyn GetMap> {
     // mhv: MapHalfVoronoi
     // Its the result of replacing the last layer (MapVoronoiZoom) which performs a 1:4 scale
     // operation, with MapHalfVoronoiZoom which performs a 1:2 scale. This should be equivalent to
     // doing a 2:1 scale after MapVoronoiZoom, but MapHalfVoronoiZoom can be optimized.
-    let mhv: Rc<dyn GetMap> = match version {
+    match version {
         MinecraftVersion::Java1_13 => {
             let mut mhv = MapHalfVoronoiZoom::new(10, seed);
             let parent = Rc::from(generator_up_to_layer_1_13(seed, 50));
@@ -5850,12 +7919,62 @@ pub fn generate_fragment_treasure_map(version: MinecraftVersion, area: Area, see
             Rc::from(mhv)
         }
         _ => panic!("Treasure map generation in version {:?} is not implemented", version),
+    }
+}
+
+/// Errors introduced by `simulate_treasure_map_input`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TreasureMapNoise {
+    /// Fraction of the pixels that are replaced with one of the other two classes
+    pub misclassified: f64,
+    /// Fraction of the pixels that are replaced with unknown
+    pub missing: f64,
+    /// Seed used to choose the affected pixels, so the same noise can be reproduced
+    pub seed: i64,
+}
+
+/// Simulate the input of a user that copies the treasure map of a known seed, to test the
+/// robustness of `treasure_map_river_seed_finder`. The output has the same format as the input of
+/// that function: a 128x128 map at 1:2 scale with ocean, plains, river, or 255 for unknown pixels.
+/// Like ingame maps, the 1 pixel margin is always unknown.
+pub fn simulate_treasure_map_input(version: MinecraftVersion, world_seed: i64, fragment_x: i64, fragment_z: i64, noise: &TreasureMapNoise) -> Map {
+    const UNKNOWN: i32 = 255;
+    const CLASSES: [i32; 3] = [biome_id::ocean, biome_id::plains, biome_id::river];
+    let area = Area {
+        x: (fragment_x * 256 - 64) >> 1,
+        z: (fragment_z * 256 - 64) >> 1,
+        w: 128,
+        h: 128,
     };
-    let mt = MapTreasure {
-        parent: mhv,
-    };
+    let mut map = generator_half_voronoi(version, world_seed).get_map(area);
+    for v in map.a.iter_mut() {
+        *v = if *v == biome_id::river {
+            biome_id::river
+        } else if is_oceanic(*v) {
+            biome_id::ocean
+        } else {
+            biome_id::plains
+        };
+    }
+
+    let mut r = JavaRng::with_seed(noise.seed as u64);
+    for v in map.a.iter_mut() {
+        // Always use the same number of calls per pixel, so that increasing one kind of noise
+        // does not change the pixels affected by the other kind
+        let misclassified = r.next_double() < noise.misclassified;
+        let missing = r.next_double() < noise.missing;
+        let other = r.next_int_n(2) as usize;
+        if misclassified {
+            let class = CLASSES.iter().position(|c| c == v).unwrap();
+            *v = CLASSES[(class + 1 + other) % 3];
+        }
+        if missing {
+            *v = UNKNOWN;
+        }
+    }
+    set_pixels_at_margin(&mut map, UNKNOWN);
 
-    mt.get_map(area)
+    map
 }
 
 pub fn generate_image(version: MinecraftVersion, area: Area, seed: i64, y_offset: u32) -> Vec<u8> {
@@ -5863,25 +7982,456 @@ pub fn generate_image(version: MinecraftVersion, area: Area, seed: i64, y_offset
     generate_image_up_to_layer(version, area, seed, num_layers, y_offset)
 }
 
-pub fn generate_image_up_to_layer(version: MinecraftVersion, area: Area, seed: i64, layer: u32, y_offset: u32) -> Vec<u8> {
-    let map = generate_up_to_layer(version, area, seed, layer, y_offset);
+pub fn generate_image_up_to_layer(version: MinecraftVersion, area: Area, seed: i64, layer: u32, y_offset: u32) -> Vec<u8> {
+    let map = generate_up_to_layer(version, area, seed, layer, y_offset);
+
+    draw_layer_image(&map, version, layer)
+}
+
+/// Draw a map generated by `generate_up_to_layer`, using the palette of that layer
+pub fn draw_layer_image(map: &Map, version: MinecraftVersion, layer: u32) -> Vec<u8> {
+    match layer_palette(version, layer) {
+        LayerPalette::Biomes => draw_map_image(map).into_raw(),
+        LayerPalette::Noise => draw_map_image_noise(map).into_raw(),
+        LayerPalette::HelperClasses => {
+            draw_map_image_with(map, ImageOrientation::XRight, helper_class_to_color).into_raw()
+        }
+        LayerPalette::HelperRivers => {
+            draw_map_image_with(map, ImageOrientation::XRight, helper_river_to_color).into_raw()
+        }
+    }
+}
+
+/// Generate `area` at once and also in fragments of at most `fragment_size` tiles in each
+/// direction, and compare the results. Returns the first tile where the fragments do not match
+/// the full map.
+///
+/// Layers must generate the same value for a tile regardless of the area used to request it, so
+/// this is only useful to find bugs in the margins and offsets of the layers. Use a
+/// `fragment_size` of 1 to compare against each tile generated alone.
+pub fn check_fragment_stitching(layer: &dyn GetMap, area: Area, fragment_size: u64) -> Result<(), Point> {
+    assert!(fragment_size > 0);
+    let full = layer.get_map(area);
+    if full.area() != area {
+        return Err(Point { x: area.x, z: area.z });
+    }
+
+    for fx in (0..area.w).step_by(fragment_size as usize) {
+        for fz in (0..area.h).step_by(fragment_size as usize) {
+            let farea = Area {
+                x: area.x + fx as i64,
+                z: area.z + fz as i64,
+                w: u64::min(fragment_size, area.w - fx),
+                h: u64::min(fragment_size, area.h - fz),
+            };
+            let fragment = layer.get_map(farea);
+            if fragment.area() != farea {
+                return Err(Point { x: farea.x, z: farea.z });
+            }
+            for x in farea.x..farea.x + farea.w as i64 {
+                for z in farea.z..farea.z + farea.h as i64 {
+                    if fragment.get(x, z) != full.get(x, z) {
+                        return Err(Point { x, z });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Size of the fragments used by `GeneratorCache::set_check_stitching`. It is odd, so the
+/// fragments start at all the possible offsets modulo 2 and 4.
+const CHECK_STITCHING_FRAGMENT_SIZE: u64 = 7;
+
+enum CachedGenerator {
+    Map(Rc<dyn GetMap>),
+    Map3D(Rc<dyn GetMap3D>),
+}
+
+/// Same as `generate_up_to_layer`, but keeping the generators of the last few (world seed,
+/// version, layer) combinations, so generating many small areas of the same world does not need
+/// to create the layers again.
+pub struct GeneratorCache {
+    generators: LruCache<(i64, MinecraftVersion, u32), CachedGenerator>,
+    check_stitching: bool,
+}
+
+impl GeneratorCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { generators: LruCache::new(capacity), check_stitching: false }
+    }
+
+    /// When enabled, every map generated by `generate_up_to_layer` is also generated in small
+    /// fragments, and this panics if the fragments do not match. This is slow, it is only
+    /// intended for debugging rendering artifacts.
+    pub fn set_check_stitching(&mut self, check_stitching: bool) {
+        self.check_stitching = check_stitching;
+    }
+
+    pub fn generate_up_to_layer(&mut self, version: MinecraftVersion, area: Area, seed: i64, num_layers: u32, y_offset: u32) -> Map {
+        let key = (seed, version, num_layers);
+        if self.generators.get(&key).is_none() {
+            match Self::new_generator(version, seed, num_layers) {
+                Some(generator) => self.generators.insert(key, generator),
+                // The layers that are not implemented as a generator are never cached
+                None => return generate_up_to_layer(version, area, seed, num_layers, y_offset),
+            }
+        }
+
+        match self.generators.get(&key) {
+            Some(CachedGenerator::Map(g)) => {
+                if self.check_stitching {
+                    if let Err(p) = check_fragment_stitching(&**g, area, CHECK_STITCHING_FRAGMENT_SIZE) {
+                        panic!("Fragment stitching mismatch at {:?} when generating {:?} of layer {} in {:?}", p, area, num_layers, version);
+                    }
+                }
+                g.get_map(area)
+            }
+            Some(CachedGenerator::Map3D(g)) => {
+                let y_level = y_offset as i64 - 16;
+                g.get_map_3d(Area3D::from_area2d_and_y_level(area, y_level)).into_map2d()
+            }
+            // Cache with capacity 0
+            None => generate_up_to_layer(version, area, seed, num_layers, y_offset),
+        }
+    }
+
+    // Same layers as generate_up_to_layer, or None if the layer does not use a generator
+    fn new_generator(version: MinecraftVersion, seed: i64, layer: u32) -> Option<CachedGenerator> {
+        let extra_layer = layer >= 100;
+        Some(CachedGenerator::Map(match version {
+            MinecraftVersion::Java1_3 if !extra_layer => Rc::from(generator_up_to_layer_1_3(seed, layer)),
+            MinecraftVersion::Java1_7 | MinecraftVersion::Java1_9 | MinecraftVersion::Java1_11 if !extra_layer => {
+                Rc::from(generator_up_to_layer_1_7(seed, layer, version))
+            }
+            MinecraftVersion::Java1_13 if !extra_layer => Rc::from(generator_up_to_layer_1_13(seed, layer)),
+            MinecraftVersion::Java1_14 if !extra_layer => Rc::from(generator_up_to_layer_1_14(seed, layer)),
+            MinecraftVersion::Java1_15 | MinecraftVersion::Java1_16_1 | MinecraftVersion::Java1_16 | MinecraftVersion::Java1_17 if !extra_layer => {
+                Rc::from(generator_up_to_layer_1_15(seed, layer, version))
+            }
+            MinecraftVersion::Java1_18 if !matches!(layer, 0..=8 | 50 | 51) => {
+                return Some(CachedGenerator::Map3D(Rc::from(generator_up_to_layer_1_18(seed, layer, version))));
+            }
+            _ => return None,
+        }))
+    }
+}
+
+/// Graphviz description of the layers used by `generate`. There is one node for each layer with
+/// its base seed and scale, and one edge from each parent to its child.
+///
+/// Render it with `dot -Tsvg`.
+pub fn layer_graph_dot(version: MinecraftVersion) -> String {
+    // The world seed does not change the layers
+    let info = match GeneratorCache::new_generator(version, 0, version.num_layers()) {
+        Some(CachedGenerator::Map(g)) => g.layer_info(),
+        Some(CachedGenerator::Map3D(g)) => g.layer_info(),
+        None => panic!("Biome generation in version {:?} is not implemented", version),
+    };
+
+    // Parents are visited first, and layers shared by more than one child are only added once
+    fn add_node(info: &LayerInfo, scale: u64, nodes: &mut Vec<usize>, lines: &mut Vec<String>) -> usize {
+        if let Some(i) = nodes.iter().position(|id| *id == info.id) {
+            return i;
+        }
+        let parent_nodes: Vec<usize> = info.parents.iter().map(|p| add_node(p, scale * info.zoom, nodes, lines)).collect();
+        let i = nodes.len();
+        nodes.push(info.id);
+        let mut label = info.name.to_string();
+        if let Some(base_seed) = info.base_seed {
+            label.push_str(&format!("\\nbase seed {}", base_seed));
+        }
+        label.push_str(&format!("\\n1:{}", scale));
+        lines.push(format!("    n{} [label=\"{}\"];", i, label));
+        for (j, p) in parent_nodes.iter().enumerate() {
+            if parent_nodes.len() > 1 {
+                lines.push(format!("    n{} -> n{} [label=\"parent{}\"];", p, i, j + 1));
+            } else {
+                lines.push(format!("    n{} -> n{};", p, i));
+            }
+        }
+
+        i
+    }
+
+    let mut nodes = vec![];
+    let mut lines = vec![];
+    add_node(&info, 1, &mut nodes, &mut lines);
+
+    format!("digraph layers {{\n    node [shape=box];\n{}\n}}\n", lines.join("\n"))
+}
+
+/// Name and base seed of each layer number of `generate_up_to_layer`. The layers that only change
+/// the colors of their parent use the name and base seed of their parent, and the layers that are
+/// not implemented as a generator are skipped.
+pub fn layer_base_seeds(version: MinecraftVersion) -> Vec<(u32, &'static str, Option<i64>)> {
+    (0..=version.num_layers()).filter_map(|layer| {
+        let mut info = match GeneratorCache::new_generator(version, 0, layer)? {
+            CachedGenerator::Map(g) => g.layer_info(),
+            CachedGenerator::Map3D(g) => g.layer_info(),
+        };
+        while info.name == "MapMap" {
+            info = info.parents.swap_remove(0);
+        }
+
+        Some((layer, info.name, info.base_seed))
+    }).collect()
+}
+
+/// Bits of the world seed used by one layer. `McRng::next_int_n(n)` only depends on the lower
+/// `24 + log2(n)` bits of the world seed when `n` is a power of two, and on all the bits otherwise.
+/// The ordering goes from fewer to more bits, so the bits needed by a set of layers is the maximum.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SeedBits {
+    /// The layer does not use the world seed, it only transforms the output of its parents
+    Independent,
+    /// The layer only depends on this number of lower bits of the world seed
+    Lower(u8),
+    /// The layer uses the full 64-bit world seed
+    Full,
+}
+
+/// Bits of the world seed used by each layer type, as returned by `LayerInfo::name`. This does not
+/// include the bits used by the parents of the layer. Returns `None` for unknown layers.
+pub fn layer_seed_bits(layer_name: &str) -> Option<SeedBits> {
+    use SeedBits::*;
+    let bits = match layer_name {
+        "MapCoolWarm" | "MapHeatIce" | "MapDeepOcean" | "MapBiomeEdge" | "MapShore" | "MapRiver" | "HelperMapRiverAll"
+        | "MapRiverMix" | "MapOceanMix" | "MapSkip" | "MapMushroomShore" | "MapRiver13" | "MapRiverMix13" | "MapMap"
+        | "MapMap2" | "MapFn" | "MapParentFn" | "CachedMap" | "MapTreasure" | "MapTreasureQuarterScale" | "TestMapZero"
+        | "TestMapCheckers" | "TestMapXhz" | "PanicMap" => Independent,
+        // next_int(2)
+        "MapRemoveTooMuchOcean" | "MapSmooth" | "MapRiverInit13" => Lower(25),
+        // next_int(4) to select one of the 4 parents
+        "MapZoom" | "MapZoomFuzzy" | "HelperMapZoomAllEdges" => Lower(26),
+        // next_int(1024)
+        "MapVoronoiZoom" | "MapHalfVoronoiZoom" => Lower(34),
+        // The perlin noise uses a JavaRng initialized with the world seed
+        "MapOceanTemp" | "MapEndQuarterScale" => Lower(48),
+        // Calls to next_int(n) where n is not a power of two, or the seed is hashed
+        "MapIsland" | "MapAddIsland" | "MapAddSnow" | "MapSpecial" | "MapAddMushroomIsland" | "MapBiome" | "MapRiverInit"
+        | "MapHills" | "MapRareBiome" | "MapAddBamboo" | "MapAddIsland13" | "MapIcePlains" | "MapBiome13"
+        | "MapRegionHills" | "MapSwampRivers" | "MapHalfVoronoiZoom115" | "MapVoronoiZoom115" | "MapVoronoiZoom118"
+        | "MapGenBiomeNoise3D118" => Full,
+        _ => return None,
+    };
+
+    Some(bits)
+}
+
+/// Bits of the world seed used by the layer number `layer` of `generate_up_to_layer`, without
+/// the bits used by its parents. So the layers that use `SeedBits::Lower(26)` can be checked
+/// using a 26-bit candidate, as long as the output of their parents is known. The layers that
+/// only change the colors of their parent use the bits of their parent. Returns `None` if the
+/// layer is not implemented as a generator.
+pub fn bits_required_for_layer(version: MinecraftVersion, layer: u32) -> Option<SeedBits> {
+    let mut info = match GeneratorCache::new_generator(version, 0, layer)? {
+        CachedGenerator::Map(g) => g.layer_info(),
+        CachedGenerator::Map3D(g) => g.layer_info(),
+    };
+    while info.name == "MapMap" {
+        info = info.parents.swap_remove(0);
+    }
+
+    layer_seed_bits(info.name)
+}
+
+pub fn generate(version: MinecraftVersion, a: Area, world_seed: i64, y_offset: u32) -> Map {
+    let num_layers = version.num_layers();
+    generate_up_to_layer(version, a, world_seed, num_layers, y_offset)
+}
+
+/// Same as `generate`, but split into tiles of `tile` x `tile`, so the whole map does not need to
+/// be kept in memory. The tiles are returned in row-major order: all the tiles with the lowest z
+/// first, sorted by x. The tiles at the right and bottom edges are smaller if the area is not a
+/// multiple of `tile`. The layers are only initialized once.
+pub fn generate_iter(version: MinecraftVersion, area: Area, world_seed: i64, tile: u64) -> impl Iterator<Item = Map> {
+    assert!(tile > 0, "Tile size must be greater than 0");
+    let num_layers = version.num_layers();
+    let mut cache = GeneratorCache::new(1);
+    let tiles = (0..area.h).step_by(tile as usize).flat_map(move |tz| {
+        (0..area.w).step_by(tile as usize).map(move |tx| Area {
+            x: area.x + tx as i64,
+            z: area.z + tz as i64,
+            w: u64::min(tile, area.w - tx),
+            h: u64::min(tile, area.h - tz),
+        })
+    });
+
+    tiles.map(move |tile_area| cache.generate_up_to_layer(version, tile_area, world_seed, num_layers, 0))
+}
+
+/// Same as `generate`, but the independent branches of the layer graph are generated in parallel:
+/// the biomes, the rivers, and since 1.13 the ocean temperature, each one in its own thread. The
+/// layers use `Rc`, so each thread builds its own copy. The branches are then mixed and zoomed in
+/// the current thread. This is only worth it for large areas, for small areas the cost of building
+/// the layers dominates. Versions that do not use the layer graph fall back to `generate`.
+pub fn generate_parallel(version: MinecraftVersion, area: Area, world_seed: i64) -> Map {
+    let (biome_layer, river_layer) = match river_mix_parent_layers(version) {
+        Some(layers) => layers,
+        None => return generate(version, area, world_seed, 0),
+    };
+    let num_layers = version.num_layers();
+    let spawn_branch = |layer: u32, area: Area| {
+        std::thread::spawn(move || generate_up_to_layer(version, area, world_seed, layer, 0))
+    };
+
+    // Area of the 1:4 map used by the voronoi zoom
+    let parea = zoom_parent_area(area, 2, 2);
+    let pmap = if version >= MinecraftVersion::Java1_13 {
+        // The land map is only needed around warm and frozen oceans, but that is not known until
+        // the ocean map is generated, so use the full 8 tile margin
+        let land_area = Area { x: parea.x - 8, z: parea.z - 8, w: parea.w + 16, h: parea.h + 16 };
+        let biomes = spawn_branch(biome_layer, land_area);
+        let rivers = spawn_branch(river_layer, land_area);
+        let oceans = spawn_branch(49, parea);
+        let (biomes, rivers, oceans) = (biomes.join().unwrap(), rivers.join().unwrap(), oceans.join().unwrap());
+        let land = MapRiverMix::new(100, world_seed).get_map_from_pmap12(&biomes, &rivers);
+
+        MapOceanMix::new(100, world_seed).get_map_from_pmap12(&land, &oceans)
+    } else {
+        let biomes = spawn_branch(biome_layer, parea);
+        let rivers = spawn_branch(river_layer, parea);
+        let (biomes, rivers) = (biomes.join().unwrap(), rivers.join().unwrap());
+        match version.river_layers() {
+            Some(RiverLayers::Java1_3) => MapRiverMix13::new(100, world_seed).get_map_from_pmap12(&biomes, &rivers),
+            _ => MapRiverMix::new(100, world_seed).get_map_from_pmap12(&biomes, &rivers),
+        }
+    };
+
+    // Only the voronoi zoom of the last layer is used, its parents are not evaluated
+    let voronoi = match GeneratorCache::new_generator(version, world_seed, num_layers) {
+        Some(CachedGenerator::Map(g)) => g,
+        _ => unreachable!(),
+    };
+    let mut map = voronoi.get_map_from_pmap(&pmap);
+    map.crop(area);
+
+    map
+}
+
+/// Versions that can be detected by `guess_version`: all the versions that use the layer based
+/// biome generation. Some of them generate the same biomes, for example 1.15 and 1.16.1.
+pub const GUESSABLE_VERSIONS: [MinecraftVersion; 10] = [
+    MinecraftVersion::Java1_3,
+    MinecraftVersion::Java1_7,
+    MinecraftVersion::Java1_9,
+    MinecraftVersion::Java1_11,
+    MinecraftVersion::Java1_13,
+    MinecraftVersion::Java1_14,
+    MinecraftVersion::Java1_15,
+    MinecraftVersion::Java1_16_1,
+    MinecraftVersion::Java1_16,
+    MinecraftVersion::Java1_17,
+];
+
+/// Generate the area of the observed 1:1 map using each version in `GUESSABLE_VERSIONS`, and
+/// count how many tiles are equal. Tiles set to `UNKNOWN_BIOME_ID` are ignored. Returns the
+/// versions sorted by score, best first. Versions that generate the same map have the same score
+/// and are kept in release order.
+pub fn guess_version(observed: &Map, world_seed: i64) -> Vec<(MinecraftVersion, u32)> {
+    let area = observed.area();
+    let mut v: Vec<_> = GUESSABLE_VERSIONS.iter().map(|&version| {
+        let m = generate(version, area, world_seed, 0);
+        let score = observed.a.iter().zip(m.a.iter()).filter(|(o, g)| **o != UNKNOWN_BIOME_ID && o == g).count();
+
+        (version, score as u32)
+    }).collect();
+    v.sort_by(|a, b| b.1.cmp(&a.1));
+
+    v
+}
+
+/// Differences between the 1:1 biomes generated by two versions, see `version_diff`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionDiffStats {
+    /// Number of random areas compared
+    pub samples: u32,
+    /// Number of areas with at least one different tile
+    pub samples_with_diff: u32,
+    pub tiles: u64,
+    pub different_tiles: u64,
+    /// Number of different tiles for each pair of (biome in version a, biome in version b), most
+    /// common first
+    pub changes: Vec<((i32, i32), u64)>,
+}
+
+impl VersionDiffStats {
+    /// Fraction of the tiles that are different in both versions
+    pub fn different_fraction(&self) -> f64 {
+        if self.tiles == 0 {
+            0.0
+        } else {
+            self.different_tiles as f64 / self.tiles as f64
+        }
+    }
+}
+
+/// Compare the biomes generated by two versions in `samples` random areas of `size` x `size`
+/// blocks, each one with a random world seed and located at most 10000 blocks away from the
+/// origin. The random areas only depend on `rng_seed`, so the results are reproducible. Useful
+/// to check whether evidence from one version can be used to find a seed in another version.
+pub fn version_diff(a: MinecraftVersion, b: MinecraftVersion, samples: u32, size: u64, rng_seed: i64) -> VersionDiffStats {
+    let mut r = JavaRng::with_seed(rng_seed as u64);
+    let mut stats = VersionDiffStats { samples, ..Default::default() };
+    let mut changes: HashMap<(i32, i32), u64> = HashMap::new();
+    for _ in 0..samples {
+        let world_seed = r.next_long();
+        let x = i64::from(r.next_int_n(20000)) - 10000;
+        let z = i64::from(r.next_int_n(20000)) - 10000;
+        let area = Area { x, z, w: size, h: size };
+        let ma = generate(a, area, world_seed, 0);
+        let mb = generate(b, area, world_seed, 0);
+        let mut different = 0;
+        for (va, vb) in ma.a.iter().zip(mb.a.iter()) {
+            if va != vb {
+                different += 1;
+                *changes.entry((*va, *vb)).or_insert(0) += 1;
+            }
+        }
+        stats.tiles += size * size;
+        stats.different_tiles += different;
+        if different > 0 {
+            stats.samples_with_diff += 1;
+        }
+    }
+    stats.changes = changes.into_iter().collect();
+    // Sort by count, and then by biome ids to make the output deterministic
+    stats.changes.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+
+    stats
+}
+
+/// For each tile of the area, the fraction of candidates that generate the most common biome at
+/// that tile. A value of 1.0 means that all the candidates agree, so more evidence at that tile
+/// will not help to discard candidates. Returns all zeros if there are no candidates.
+pub fn certainty_map(candidates: &[i64], version: MinecraftVersion, area: Area) -> Array2<f32> {
+    let mut certainty = Array2::zeros((area.w as usize, area.h as usize));
+    if candidates.is_empty() {
+        return certainty;
+    }
 
-    match (version, layer) {
-        // Layers [0, 7] are used to visualize noise.
-        // Layers 8 and 9 can use the default draw_map_image.
-        // Layer 50 is similar to noise, except it should be easy to spot low values.
-        // Layer 51 is a binary map of biome ids that are different in search_bruteforce and
-        // search_tree
-        (MinecraftVersion::Java1_18, 0..=7 | 50 | 51) => {
-            draw_map_image_noise(&map)
+    let maps: Vec<Map> = candidates.iter().map(|&world_seed| generate(version, area, world_seed, 0)).collect();
+    let mut biomes = Vec::with_capacity(maps.len());
+    for ((x, z), c) in certainty.indexed_iter_mut() {
+        biomes.clear();
+        biomes.extend(maps.iter().map(|m| m.a[(x, z)]));
+        biomes.sort_unstable();
+        let mut modal_count = 0;
+        let mut run = 0;
+        let mut prev = None;
+        for &biome in &biomes {
+            run = if prev == Some(biome) { run + 1 } else { 1 };
+            prev = Some(biome);
+            modal_count = modal_count.max(run);
         }
-        _ => draw_map_image(&map),
+        *c = modal_count as f32 / maps.len() as f32;
     }
-}
 
-pub fn generate(version: MinecraftVersion, a: Area, world_seed: i64, y_offset: u32) -> Map {
-    let num_layers = version.num_layers();
-    generate_up_to_layer(version, a, world_seed, num_layers, y_offset)
+    certainty
 }
 
 pub fn generate_up_to_layer(version: MinecraftVersion, area: Area, seed: i64, num_layers: u32, y_offset: u32) -> Map {
@@ -5913,6 +8463,68 @@ pub fn generate_up_to_layer(version: MinecraftVersion, area: Area, seed: i64, nu
     }
 }
 
+/// Number of blocks of each biome in the area, at 1:1 scale.
+///
+/// The area is first generated at 1:4 scale, before the voronoi zoom. The voronoi zoom can only
+/// choose one of the neighboring tiles, so a 4x4 block whose 8 neighbors have the same biome must
+/// be filled with that biome, and the last layer is only generated near the biome borders.
+pub fn biomes_in_area(world_seed: i64, version: MinecraftVersion, area: Area) -> HashMap<i32, u64> {
+    let mut counts = HashMap::new();
+    if area.w == 0 || area.h == 0 {
+        return counts;
+    }
+    let last_layer = version.num_layers();
+    let add_map = |counts: &mut HashMap<i32, u64>, m: &Map| {
+        for v in m.a.iter() {
+            *counts.entry(*v).or_insert(0) += 1;
+        }
+    };
+    if version >= MinecraftVersion::Java1_18 {
+        // 3D biomes, there is no 1:4 layer
+        add_map(&mut counts, &generate_up_to_layer(version, area, world_seed, last_layer, 0));
+        return counts;
+    }
+
+    let (x0, z0) = (area.x >> 2, area.z >> 2);
+    let (x1, z1) = ((area.x + area.w as i64 - 1) >> 2, (area.z + area.h as i64 - 1) >> 2);
+    // 1 tile margin to check the neighbors
+    let parea = Area { x: x0 - 1, z: z0 - 1, w: (x1 - x0 + 3) as u64, h: (z1 - z0 + 3) as u64 };
+    let pmap = generate_up_to_layer(version, parea, world_seed, last_layer - 1, 0);
+    // Intersection of the area with the blocks from tile tx0 to tile tx1, in row tz
+    let blocks = |tx0: i64, tx1: i64, tz: i64| {
+        let bx0 = (tx0 * 4).max(area.x);
+        let bx1 = (tx1 * 4 + 3).min(area.x + area.w as i64 - 1);
+        let bz0 = (tz * 4).max(area.z);
+        let bz1 = (tz * 4 + 3).min(area.z + area.h as i64 - 1);
+
+        Area { x: bx0, z: bz0, w: (bx1 - bx0 + 1) as u64, h: (bz1 - bz0 + 1) as u64 }
+    };
+
+    for tz in z0..=z1 {
+        // Consecutive tiles near a border are generated together
+        let mut border_start = None;
+        for tx in x0..=x1 {
+            let (i, j) = ((tx - parea.x) as usize, (tz - parea.z) as usize);
+            let v = pmap.a[(i, j)];
+            let uniform = (i - 1..=i + 1).all(|pi| (j - 1..=j + 1).all(|pj| pmap.a[(pi, pj)] == v));
+            if uniform {
+                if let Some(start) = border_start.take() {
+                    add_map(&mut counts, &generate_up_to_layer(version, blocks(start, tx - 1, tz), world_seed, last_layer, 0));
+                }
+                let b = blocks(tx, tx, tz);
+                *counts.entry(v).or_insert(0) += b.w * b.h;
+            } else if border_start.is_none() {
+                border_start = Some(tx);
+            }
+        }
+        if let Some(start) = border_start {
+            add_map(&mut counts, &generate_up_to_layer(version, blocks(start, x1, tz), world_seed, last_layer, 0));
+        }
+    }
+
+    counts
+}
+
 pub fn generate_up_to_layer_1_3(a: Area, world_seed: i64, layer: u32) -> Map {
     if layer >= 200 {
         //return generate_up_to_layer_1_7_extra_2(a, world_seed, layer);
@@ -6037,6 +8649,8 @@ pub fn generator_up_to_layer_1_3(world_seed: i64, layer: u32) -> Box<dyn GetMap>
     Box::new(g33)
 }
 
+/// Intermediate layers of `all_edges_river_stack`: layer 222 is `TestMapCheckers`, and layers 234
+/// to 241 are the layers 34 to 41 of the river branch
 pub fn generate_up_to_layer_1_7_extra_2(a: Area, world_seed: i64, layer: u32) -> Map {
     let g22 = TestMapCheckers;
     if layer == 222 { return g22.get_map(a); }
@@ -6068,6 +8682,8 @@ pub fn generate_up_to_layer_1_7_extra_2(a: Area, world_seed: i64, layer: u32) ->
     TestMapZero.get_map(a)
 }
 
+/// Helper layers to debug the river seed finder. Layers 122 and 134 to 141 are the intermediate
+/// layers of `checkers_river_stack`
 pub fn generate_up_to_layer_1_7_extra(a: Area, world_seed: i64, layer: u32) -> Map {
     /* RIVER LAYERS */
     let g22 = TestMapCheckers;
@@ -6199,6 +8815,7 @@ pub fn generate_up_to_layer_1_7(a: Area, world_seed: i64, layer: u32, version: M
 }
 
 pub fn generator_up_to_layer_1_7(world_seed: i64, layer: u32, version: MinecraftVersion) -> Box<dyn GetMap> {
+    let quirks = version.quirks();
     let g0 = MapIsland::new(1, world_seed);
     if layer == 0 { return Box::new(g0); }
     let mut g1 = MapZoomFuzzy::new(2000, world_seed);
@@ -6253,7 +8870,7 @@ pub fn generator_up_to_layer_1_7(world_seed: i64, layer: u32, version: Minecraft
     g17.parent = Some(Rc::new(g16));
     if layer == 17 { return Box::new(g17); }
     let g17 = Rc::new(g17);
-    let mut g18 = MapBiome::new(200, world_seed);
+    let mut g18 = MapBiome::new(200, world_seed, version);
     g18.parent = Some(g17.clone());
     if layer == 18 { return Box::new(g18); }
     let mut g19 = MapZoom::new(1000, world_seed);
@@ -6262,7 +8879,7 @@ pub fn generator_up_to_layer_1_7(world_seed: i64, layer: u32, version: Minecraft
     let mut g20 = MapZoom::new(1001, world_seed);
     g20.parent = Some(Rc::new(g19));
     if layer == 20 { return Box::new(g20); }
-    let mut g21 = MapBiomeEdge::new(1000, world_seed);
+    let mut g21 = MapBiomeEdge::new(1000, world_seed, version);
     g21.parent = Some(Rc::new(g20));
     if layer == 21 { return Box::new(g21); }
     let mut g22 = MapRiverInit::new(100, world_seed);
@@ -6273,11 +8890,11 @@ pub fn generator_up_to_layer_1_7(world_seed: i64, layer: u32, version: Minecraft
     //if layer == 23 { return Box::new(MapMap { parent: Rc::new(g23), f: pretty_biome_map_hills }); }
     let mut g23 = MapZoom::new(1000, world_seed);
     g23.parent = Some(g22.clone());
-    g23.bug_world_seed_not_set = true;
+    g23.bug_world_seed_not_set = quirks.map_hills_zoom_world_seed_not_set;
     if layer == 23 { return Box::new(MapMap { parent: Rc::new(g23), f: pretty_biome_map_hills }); }
     let mut g24 = MapZoom::new(1001, world_seed);
     g24.parent = Some(Rc::new(g23));
-    g24.bug_world_seed_not_set = true;
+    g24.bug_world_seed_not_set = quirks.map_hills_zoom_world_seed_not_set;
     if layer == 24 { return Box::new(MapMap { parent: Rc::new(g24), f: pretty_biome_map_hills }); }
     let mut g25 = MapHills::new(1000, world_seed, version);
     g25.parent1 = Some(Rc::new(g21));
@@ -6341,6 +8958,33 @@ pub fn generator_up_to_layer_1_7(world_seed: i64, layer: u32, version: Minecraft
     Box::new(g43)
 }
 
+/// Ocean temperature layers used from 1.13 to 1.17: MapOceanTemp followed by 6 MapZoom.
+/// Equivalent to layer 49 of `generator_up_to_layer_1_13`, without building the land layers.
+pub fn generator_ocean_temp(world_seed: i64) -> MapZoom {
+    let g43 = MapOceanTemp::new(2, world_seed);
+    let mut g44 = MapZoom::new(2001, world_seed);
+    g44.parent = Some(Rc::new(g43));
+    let mut g45 = MapZoom::new(2002, world_seed);
+    g45.parent = Some(Rc::new(g44));
+    let mut g46 = MapZoom::new(2003, world_seed);
+    g46.parent = Some(Rc::new(g45));
+    let mut g47 = MapZoom::new(2004, world_seed);
+    g47.parent = Some(Rc::new(g46));
+    let mut g48 = MapZoom::new(2005, world_seed);
+    g48.parent = Some(Rc::new(g47));
+    let mut g49 = MapZoom::new(2006, world_seed);
+    g49.parent = Some(Rc::new(g48));
+
+    g49
+}
+
+/// Ocean temperature of each tile at 1:4 scale, from 1.13 to 1.17. The output contains only
+/// warmOcean, lukewarmOcean, ocean, coldOcean and frozenOcean: the final biome is only an ocean
+/// variant if the land layers generate an ocean at that tile.
+pub fn generate_ocean_temp_map(world_seed: i64, area: Area) -> Map {
+    generator_ocean_temp(world_seed).get_map(area)
+}
+
 pub fn generate_up_to_layer_1_13(a: Area, world_seed: i64, layer: u32) -> Map {
     if layer >= 200 {
         //return generate_up_to_layer_1_7_extra_2(a, world_seed, layer);
@@ -6348,7 +8992,7 @@ pub fn generate_up_to_layer_1_13(a: Area, world_seed: i64, layer: u32) -> Map {
     if layer >= 100 && layer <= 142 {
         // The first 42 layers are almost equal in 1.7 and 1.13
         // The main difference being the MapHills bug, which does
-        // not affect the river generation code
+        // not affect the river generation code: see RiverLayers
         return generate_up_to_layer_1_7_extra(a, world_seed, layer);
     }
 
@@ -6410,7 +9054,7 @@ pub fn generator_up_to_layer_1_13(world_seed: i64, layer: u32) -> Box<dyn GetMap
     g17.parent = Some(Rc::new(g16));
     if layer == 17 { return Box::new(g17); }
     let g17 = Rc::new(g17);
-    let mut g18 = MapBiome::new(200, world_seed);
+    let mut g18 = MapBiome::new(200, world_seed, MinecraftVersion::Java1_13);
     g18.parent = Some(g17.clone());
     if layer == 18 { return Box::new(g18); }
     let mut g19 = MapZoom::new(1000, world_seed);
@@ -6419,7 +9063,7 @@ pub fn generator_up_to_layer_1_13(world_seed: i64, layer: u32) -> Box<dyn GetMap
     let mut g20 = MapZoom::new(1001, world_seed);
     g20.parent = Some(Rc::new(g19));
     if layer == 20 { return Box::new(g20); }
-    let mut g21 = MapBiomeEdge::new(1000, world_seed);
+    let mut g21 = MapBiomeEdge::new(1000, world_seed, MinecraftVersion::Java1_13);
     g21.parent = Some(Rc::new(g20));
     if layer == 21 { return Box::new(g21); }
     let mut g22 = MapRiverInit::new(100, world_seed);
@@ -6531,7 +9175,7 @@ pub fn generate_up_to_layer_1_14(a: Area, world_seed: i64, layer: u32) -> Map {
     if layer >= 100 && layer <= 142 {
         // The first 42 layers are almost equal in 1.7 and 1.13
         // The main difference being the MapHills bug, which does
-        // not affect the river generation code
+        // not affect the river generation code: see RiverLayers
         return generate_up_to_layer_1_7_extra(a, world_seed, layer);
     }
 
@@ -6593,7 +9237,7 @@ pub fn generator_up_to_layer_1_14(world_seed: i64, layer: u32) -> Box<dyn GetMap
     g17.parent = Some(Rc::new(g16));
     if layer == 17 { return Box::new(g17); }
     let g17 = Rc::new(g17);
-    let mut g18 = MapBiome::new(200, world_seed);
+    let mut g18 = MapBiome::new(200, world_seed, MinecraftVersion::Java1_14);
     g18.parent = Some(g17.clone());
     //if layer == 18 { return Box::new(g18); }
     // 1.14: bamboo
@@ -6606,7 +9250,7 @@ pub fn generator_up_to_layer_1_14(world_seed: i64, layer: u32) -> Box<dyn GetMap
     let mut g20 = MapZoom::new(1001, world_seed);
     g20.parent = Some(Rc::new(g19));
     if layer == 20 { return Box::new(g20); }
-    let mut g21 = MapBiomeEdge::new(1000, world_seed);
+    let mut g21 = MapBiomeEdge::new(1000, world_seed, MinecraftVersion::Java1_14);
     g21.parent = Some(Rc::new(g20));
     if layer == 21 { return Box::new(g21); }
     let mut g22 = MapRiverInit::new(100, world_seed);
@@ -6718,7 +9362,7 @@ pub fn generate_up_to_layer_1_15(a: Area, world_seed: i64, layer: u32, version:
     if layer >= 100 && layer <= 142 {
         // The first 42 layers are almost equal in 1.7 and 1.13
         // The main difference being the MapHills bug, which does
-        // not affect the river generation code
+        // not affect the river generation code: see RiverLayers
         return generate_up_to_layer_1_7_extra(a, world_seed, layer);
     }
 
@@ -6726,6 +9370,7 @@ pub fn generate_up_to_layer_1_15(a: Area, world_seed: i64, layer: u32, version:
 }
 
 pub fn generator_up_to_layer_1_15(world_seed: i64, layer: u32, version: MinecraftVersion) -> Box<dyn GetMap> {
+    let quirks = version.quirks();
     let g0 = MapIsland::new(1, world_seed);
     if layer == 0 { return Box::new(g0); }
     let mut g1 = MapZoomFuzzy::new(2000, world_seed);
@@ -6780,7 +9425,7 @@ pub fn generator_up_to_layer_1_15(world_seed: i64, layer: u32, version: Minecraf
     g17.parent = Some(Rc::new(g16));
     if layer == 17 { return Box::new(g17); }
     let g17 = Rc::new(g17);
-    let mut g18 = MapBiome::new(200, world_seed);
+    let mut g18 = MapBiome::new(200, world_seed, version);
     g18.parent = Some(g17.clone());
     //if layer == 18 { return Box::new(g18); }
     // 1.14: bamboo
@@ -6793,7 +9438,7 @@ pub fn generator_up_to_layer_1_15(world_seed: i64, layer: u32, version: Minecraf
     let mut g20 = MapZoom::new(1001, world_seed);
     g20.parent = Some(Rc::new(g19));
     if layer == 20 { return Box::new(g20); }
-    let mut g21 = MapBiomeEdge::new(1000, world_seed);
+    let mut g21 = MapBiomeEdge::new(1000, world_seed, version);
     g21.parent = Some(Rc::new(g20));
     if layer == 21 { return Box::new(g21); }
     let mut g22 = MapRiverInit::new(100, world_seed);
@@ -6804,9 +9449,11 @@ pub fn generator_up_to_layer_1_15(world_seed: i64, layer: u32, version: Minecraf
     //if layer == 23 { return Box::new(MapMap { parent: Rc::new(g23), f: pretty_biome_map_hills }); }
     let mut g23 = MapZoom::new(1000, world_seed);
     g23.parent = Some(g22.clone());
+    g23.bug_world_seed_not_set = quirks.map_hills_zoom_world_seed_not_set;
     if layer == 23 { return Box::new(MapMap { parent: Rc::new(g23), f: pretty_biome_map_hills }); }
     let mut g24 = MapZoom::new(1001, world_seed);
     g24.parent = Some(Rc::new(g23));
+    g24.bug_world_seed_not_set = quirks.map_hills_zoom_world_seed_not_set;
     if layer == 24 { return Box::new(MapMap { parent: Rc::new(g24), f: pretty_biome_map_hills }); }
     let mut g25 = MapHills::new(1000, world_seed, version);
     g25.parent1 = Some(Rc::new(g21));
@@ -6930,6 +9577,256 @@ mod tests {
     use super::*;
     use crate::seed_info::BiomeId;
 
+    #[test]
+    fn voronoi_cell_source_matches_voronoi_zoom() {
+        // Parent map where each cell has a different value
+        let parea = Area { x: -3, z: 5, w: 6, h: 7 };
+        let pmap = Map::from_area_fn(parea, |(x, z)| (x * 100 + z) as i32);
+        let cell_value = |p: Point4| ((p.x - parea.x) * 100 + (p.z - parea.z)) as i32;
+        let world_seed = 1234;
+
+        for version in [MinecraftVersion::Java1_13, MinecraftVersion::Java1_15] {
+            let map = if version >= MinecraftVersion::Java1_15 {
+                MapVoronoiZoom115::new(world_seed).get_map_from_pmap(&pmap)
+            } else {
+                MapVoronoiZoom::new(10, world_seed).get_map_from_pmap(&pmap)
+            };
+            let area = map.area();
+            for x in area.x..area.x + area.w as i64 {
+                for z in area.z..area.z + area.h as i64 {
+                    let p = voronoi_cell_source(world_seed, version, x, z);
+                    assert_eq!(map.get(x, z), cell_value(p), "{:?} at ({}, {})", version, x, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn observation_clusters() {
+        let ps = vec![Point { x: 0, z: 0 }, Point { x: 5, z: 3 }, Point { x: 10, z: 0 }, Point { x: 100000, z: -100000 }, Point { x: 100003, z: -99990 }, Point { x: -40, z: 0 }];
+        let clusters = cluster_observations(&ps, |p| p);
+        assert_eq!(clusters, vec![
+            vec![Point { x: -40, z: 0 }],
+            vec![Point { x: 0, z: 0 }, Point { x: 5, z: 3 }, Point { x: 10, z: 0 }],
+            vec![Point { x: 100000, z: -100000 }, Point { x: 100003, z: -99990 }],
+        ]);
+
+        // Dense observations are kept together
+        let square: Vec<_> = (0..32).flat_map(|x| (0..32).map(move |z| Point { x, z })).collect();
+        assert_eq!(cluster_observations(&square, |p| p).len(), 1);
+        assert_eq!(cluster_observations(&Vec::<Point>::new(), |p| p), Vec::<Vec<Point>>::new());
+    }
+
+    #[test]
+    fn river_mask_same_as_map() {
+        let ps = vec![Point { x: -3, z: 7 }, Point { x: 2, z: 7 }, Point { x: 2, z: 90 }];
+        let area = Area::from_coords(ps.iter().copied());
+        let mask = map_with_river_at(&ps, area);
+        let m = mask.to_map();
+        for p in &ps {
+            assert_eq!(m.a[((p.x - area.x) as usize, (p.z - area.z) as usize)], biome_id::river);
+        }
+        assert_eq!(count_rivers(&m), 3);
+        assert_eq!(RiverMask::from_map(&m), mask);
+        assert_eq!(mask.count_and(&mask), 3);
+    }
+
+    #[test]
+    fn river_mix_candidates() {
+        let area = Area { x: 3, z: 4, w: 5, h: 1 };
+        let pmap1 = Map::from_area_fn(area, |(x, _)| {
+            [biome_id::icePlains, biome_id::mushroomIsland, biome_id::mushroomIslandShore, biome_id::plains, biome_id::ocean][x]
+        });
+        let river_map = Map::from_area_fn(area, |(x, _)| if x != 2 { biome_id::river } else { -1 });
+        let mut m = MapRiverMix::new(100, 1234).get_map_from_pmap12(&pmap1, &river_map);
+        m.a[(2, 0)] = UNKNOWN_BIOME_ID;
+        let c = decompose_map_river_mix_candidates(&m);
+        assert_eq!(c.area(), area);
+        assert_eq!(c.a[(0, 0)], vec![(biome_id::icePlains, true)]);
+        assert!(c.a[(1, 0)].contains(&(biome_id::mushroomIsland, true)));
+        assert!(c.a[(1, 0)].contains(&(biome_id::mushroomIslandShore, false)));
+        assert_eq!(c.a[(2, 0)], vec![]);
+        assert_eq!(c.a[(3, 0)], vec![(UNKNOWN_BIOME_ID, true)]);
+        assert!(c.a[(4, 0)].contains(&(biome_id::ocean, true)));
+        assert_eq!(certain_rivers(&c), vec![Point { x: 3, z: 4 }, Point { x: 6, z: 4 }]);
+    }
+
+    #[test]
+    fn certainty_map_agreement() {
+        let area = Area { x: 0, z: 0, w: 32, h: 16 };
+        let version = MinecraftVersion::Java1_7;
+        let c = certainty_map(&[], version, area);
+        assert_eq!(c.dim(), (32, 16));
+        assert!(c.iter().all(|x| *x == 0.0));
+        // The same seed twice always agrees
+        let c = certainty_map(&[1234, 1234], version, area);
+        assert!(c.iter().all(|x| *x == 1.0));
+        let c = certainty_map(&[1, 2, 3, 4], version, area);
+        assert!(c.iter().all(|x| [0.25, 0.5, 0.75, 1.0].contains(x)));
+    }
+
+    #[test]
+    fn guess_version_mismatch() {
+        // Mostly ocean, and 1.7 does not have the ocean variants of 1.13
+        let area = Area { x: 1000, z: 0, w: 64, h: 64 };
+        let world_seed = 1234;
+        let mut observed = generate(MinecraftVersion::Java1_14, area, world_seed, 0);
+        observed.a[(0, 0)] = UNKNOWN_BIOME_ID;
+        let ranking = guess_version(&observed, world_seed);
+        assert_eq!(ranking.len(), GUESSABLE_VERSIONS.len());
+        // 1.13 may generate the same biomes as 1.14 in this area, so only check the score
+        assert_eq!(ranking[0].1, 64 * 64 - 1);
+        assert!(ranking.contains(&(MinecraftVersion::Java1_14, 64 * 64 - 1)));
+        assert!(ranking.iter().find(|(v, _)| *v == MinecraftVersion::Java1_7).unwrap().1 < 64 * 64 - 1);
+    }
+
+    #[test]
+    fn ocean_temp_map_same_as_full_stack() {
+        let area = Area { x: -50, z: 30, w: 40, h: 30 };
+        let world_seed = 1234;
+        let m = generate_ocean_temp_map(world_seed, area);
+        for version in [MinecraftVersion::Java1_13, MinecraftVersion::Java1_14, MinecraftVersion::Java1_16] {
+            assert_eq!(m, generate_up_to_layer(version, area, world_seed, 49, 0), "{:?}", version);
+        }
+    }
+
+    #[test]
+    fn helper_layer_palettes() {
+        let version = MinecraftVersion::Java1_7;
+        assert_eq!(layer_palette(version, 43), LayerPalette::Biomes);
+        assert_eq!(layer_palette(version, 122), LayerPalette::HelperClasses);
+        assert_eq!(layer_palette(version, 140), LayerPalette::HelperRivers);
+        assert_eq!(layer_palette(version, 142), LayerPalette::Biomes);
+        assert_eq!(layer_palette(version, 239), LayerPalette::HelperClasses);
+        assert_eq!(layer_palette(MinecraftVersion::Java1_18, 5), LayerPalette::Noise);
+
+        // All the TestMapCheckers colors are different
+        let mut colors: Vec<_> = (0..16).map(helper_class_to_color).collect();
+        colors.sort();
+        colors.dedup();
+        assert_eq!(colors.len(), 16);
+        assert_eq!(helper_class_to_color(-1), [0, 0, 0, 0xFF]);
+        assert_eq!(helper_river_to_color(biome_id::river), biome_to_color(biome_id::river));
+    }
+
+    #[test]
+    fn draw_map_image_orientation() {
+        // Non-square map to detect mixing up width and height
+        let mut m = Map::new(Area { x: 0, z: 0, w: 3, h: 2 });
+        m.a[(2, 0)] = biome_id::river;
+        m.a[(0, 1)] = biome_id::desert;
+
+        let img = draw_map_image(&m);
+        assert_eq!((img.width, img.height, img.data.len()), (3, 2, 3 * 2 * 4));
+        assert_eq!(img.get_pixel(2, 0), biome_to_color(biome_id::river));
+        assert_eq!(img.get_pixel(0, 1), biome_to_color(biome_id::desert));
+        assert_eq!(img.get_pixel(1, 1), biome_to_color(biome_id::ocean));
+
+        let img = draw_map_image_with(&m, ImageOrientation::ZRight, biome_to_color);
+        assert_eq!((img.width, img.height), (2, 3));
+        assert_eq!(img.get_pixel(0, 2), biome_to_color(biome_id::river));
+        assert_eq!(img.get_pixel(1, 0), biome_to_color(biome_id::desert));
+    }
+
+    #[test]
+    fn draw_map_legend() {
+        let mut m = Map::new(Area { x: 0, z: 0, w: 6, h: 1 });
+        for (x, id) in [0, 1, biome_id::river, biome_id::deepOcean, biome_id::icePlains, 300].iter().enumerate() {
+            m.a[(x, 0)] = *id;
+        }
+        assert_eq!(draw_map(&m), "MAP: x: 0, z: 0, 6x1\n_#R~*?\n");
+
+        let mut legend = MapLegend::default();
+        legend.symbols.insert(biome_id::deepOcean, 'D');
+        legend.land = 'L';
+        m.a[(5, 0)] = biome_id::forest;
+        assert_eq!(draw_map_with_legend(&m, &legend), "MAP: x: 0, z: 0, 6x1\n_#RD*L\n");
+    }
+
+    #[test]
+    fn rivers_only() {
+        use biome_id::*;
+        let area = Area { x: -40, z: 30, w: 60, h: 50 };
+        for &version in &[MinecraftVersion::Java1_3, MinecraftVersion::Java1_7, MinecraftVersion::Java1_13, MinecraftVersion::Java1_15] {
+            let rivers = rivers_only_generator(1234, version).unwrap().get_map(area);
+            let full = generate_up_to_layer(version, area, 1234, version.num_layers() - 1, 0);
+            // All the rivers of the full map are also in the rivers map
+            for (r, f) in rivers.a.iter().zip(full.a.iter()) {
+                if *f == river {
+                    assert_eq!(*r, river, "{:?}", version);
+                }
+            }
+            assert!(rivers.a.iter().any(|r| *r == river), "{:?}", version);
+        }
+        assert!(rivers_only_generator(1234, MinecraftVersion::Java1_18).is_none());
+    }
+
+    #[test]
+    fn version_diff_stats() {
+        // 1.16.1 generates the same biomes as 1.15
+        let same = version_diff(MinecraftVersion::Java1_15, MinecraftVersion::Java1_16_1, 4, 16, 0);
+        assert_eq!(same.tiles, 4 * 16 * 16);
+        assert_eq!(same.different_tiles, 0);
+        assert_eq!(same.samples_with_diff, 0);
+        assert!(same.changes.is_empty());
+
+        // The voronoi zoom changed in 1.15
+        let diff = version_diff(MinecraftVersion::Java1_14, MinecraftVersion::Java1_15, 4, 64, 0);
+        assert!(diff.different_tiles > 0);
+        assert_eq!(diff.changes.iter().map(|(_, n)| n).sum::<u64>(), diff.different_tiles);
+        assert!(diff.changes.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!(diff.changes.iter().all(|((a, b), _)| a != b));
+        assert!(diff.different_fraction() > 0.0 && diff.different_fraction() < 1.0);
+        // Reproducible
+        assert_eq!(version_diff(MinecraftVersion::Java1_14, MinecraftVersion::Java1_15, 4, 64, 0), diff);
+    }
+
+    #[test]
+    fn information_bits() {
+        use biome_id::*;
+        let v = MinecraftVersion::Java1_15;
+        let bits = |b: i32| evidence_information_bits(BiomeId(b), v);
+        assert!(bits(mushroomIsland) > bits(jungle));
+        assert!(bits(jungle) > bits(plains));
+        assert!(bits(sunflowerPlains) > bits(plains));
+        assert!(bits(desertHills) > bits(desert));
+        assert!(bits(plains) > 0.0 && bits(plains) < 5.0);
+        // Lukewarm oceans are not generated before 1.13
+        assert!(evidence_information_bits(BiomeId(lukewarmOcean), MinecraftVersion::Java1_7) >= 64.0);
+        let sorted = sort_by_information_bits(&[(BiomeId(plains), Point { x: 0, z: 0 }), (BiomeId(mushroomIsland), Point { x: 1, z: 0 })], v);
+        assert_eq!(sorted[0].0, BiomeId(mushroomIsland));
+        assert_eq!(sorted[1].1, Point { x: 0, z: 0 });
+    }
+
+    #[test]
+    fn helper_river_stacks() {
+        let area = Area { x: -20, z: 7, w: 30, h: 25 };
+        for &world_seed in &[0, 1234, 0x2D9A_1F3] {
+            let m141 = generate_up_to_layer(MinecraftVersion::Java1_7, area, world_seed, 141, 0);
+            assert_eq!(checkers_river_stack(world_seed).get_map(area).a, m141.a);
+            let m241 = generate_up_to_layer(MinecraftVersion::Java1_7, area, world_seed, 241, 0);
+            assert_eq!(all_edges_river_stack(world_seed).get_map(area).a, m241.a);
+        }
+    }
+
+    #[test]
+    fn candidate_river_map_generator_reuse() {
+        let mut g = CandidateRiverMapGenerator::new(0);
+        let mut m = Map::default();
+        let areas = [
+            Area { x: 0, z: 0, w: 30, h: 30 },
+            Area { x: -37, z: 11, w: 30, h: 30 },
+            Area { x: 5, z: -64, w: 17, h: 40 },
+        ];
+        for world_seed in [0, 1234, -1 << 40, (1 << 25) | 7] {
+            g.set_world_seed(world_seed);
+            for area in areas.iter().copied() {
+                g.get_map_into(area, &mut m);
+                assert_eq!(m, candidate_river_map(area, world_seed), "{} {:?}", world_seed, area);
+            }
+        }
+    }
+
     #[ignore]
     #[test]
     fn all_candidate_river_maps() {
@@ -6940,6 +9837,197 @@ mod tests {
         }
     }
 
+    #[test]
+    fn filter_seeds_biomes4() {
+        let version = MinecraftVersion::Java1_15;
+        let world_seed = 1234;
+        let area = Area { x: -20, z: -20, w: 40, h: 40 };
+        let m = generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0);
+        let mut sm = SparseMap::from(m);
+        // Leave some holes
+        for x in 0..40 {
+            sm.a[(x, x)] = None;
+        }
+        let biomes4 = sparse_map_to_biomes4(&sm);
+        assert_eq!(biomes4.len(), 40 * 40 - 40);
+        assert_eq!(filter_seeds_using_biomes4(&[5678, world_seed, 1235], &biomes4, version), vec![world_seed]);
+    }
+
+    #[test]
+    fn range_search_state() {
+        let mut state = RangeSearchState::new(10, 100);
+        let mut calls = vec![];
+        while !state.is_done() {
+            state.step(32, |lo, hi| {
+                calls.push((lo, hi));
+                (lo..hi).filter(|x| x % 25 == 0).map(i64::from).collect()
+            });
+            // Round trip, as if the search was resumed later
+            state = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        }
+        assert_eq!(calls, vec![(10, 42), (42, 74), (74, 100)]);
+        assert_eq!(state.found, vec![25, 50, 75]);
+        assert_eq!(state.progress(), 1.0);
+        assert!(state.step(32, |_, _| unreachable!()).is_empty());
+        assert_eq!(RangeSearchState::new(0, 1 << 24).progress(), 0.0);
+
+        // The seeds are strings, because javascript numbers cannot represent all of them
+        state.found = vec![-7014733495468514438];
+        let s = serde_json::to_string(&state).unwrap();
+        assert!(s.contains(r#""found":["-7014733495468514438"]"#), "{}", s);
+        assert_eq!(serde_json::from_str::<RangeSearchState>(&s).unwrap(), state);
+    }
+
+    #[test]
+    fn mushroom_shores() {
+        let version = MinecraftVersion::Java1_7;
+        let world_seed = 1234;
+        let area = Area { x: -128, z: -128, w: 256, h: 256 };
+        let m = generate_up_to_layer(version, area, world_seed, 42, 0);
+        let mut observed = SparseMap::new(area);
+        // Every generated mushroom shore has one of the two explanations
+        for ((x, z), b) in m.a.indexed_iter() {
+            if *b == biome_id::mushroomIslandShore {
+                observed.a[(x, z)] = Some(*b);
+            }
+        }
+        assert_eq!(filter_seeds_using_mushroom_shores(&[world_seed], &observed, version), Some(vec![world_seed]));
+
+        // A mushroom shore observed where the seed generates plains without a river
+        let (biome_layer, river_layer) = river_mix_parent_layers(version).unwrap();
+        let p1 = generate_up_to_layer(version, area, world_seed, biome_layer, 0);
+        let p2 = generate_up_to_layer(version, area, world_seed, river_layer, 0);
+        let (x, z) = p1.a.indexed_iter().find(|((x, z), b)| **b == biome_id::plains && p2.a[(*x, *z)] != biome_id::river).unwrap().0;
+        observed.a[(x, z)] = Some(biome_id::mushroomIslandShore);
+        assert_eq!(mushroom_shore_matches(world_seed, Point4 { x: area.x + x as i64, z: area.z + z as i64 }, version), Some(false));
+        assert_eq!(filter_seeds_using_mushroom_shores(&[world_seed], &observed, version), Some(vec![]));
+
+        // No rivers before 1.3
+        assert_eq!(mushroom_shore_matches(world_seed, Point4 { x: 0, z: 0 }, MinecraftVersion::Java1_2), None);
+        assert_eq!(filter_seeds_using_mushroom_shores(&[world_seed], &observed, MinecraftVersion::Java1_2), None);
+    }
+
+    #[test]
+    fn river_seed_finder_26_bit_25_set() {
+        use crate::java_rng::mask;
+        let version = MinecraftVersion::Java1_7;
+        // The 26-bit stage iterates over the seeds with bit 25 set to 0, and checks both values of
+        // bit 25. The correct seed has bit 25 set to 1, so it must not be rejected using the map
+        // generated with bit 25 set to 0.
+        let mut r = JavaRng::with_seed(1234);
+        let world_seed = std::iter::repeat_with(|| r.next_long()).find(|s| s & (1 << 25) != 0).unwrap();
+        let area = Area { x: -30, z: -30, w: 60, h: 60 };
+        let m = generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0);
+        let rivers4: Vec<Point4> = m.a.indexed_iter().filter(|(_, b)| **b == biome_id::river).map(|((x, z), _)| {
+            Point4 { x: area.x + x as i64, z: area.z + z as i64 }
+        }).collect();
+        assert!(rivers4.len() > 20, "{}", rivers4.len());
+
+        let seed_25 = world_seed & mask(25) as i64;
+        let candidates = river_seed_finder_26_seeds_cancellable(&rivers4, vec![seed_25], &SearchConfig::default(), &CancelToken::new()).results;
+        assert!(candidates.contains(&(world_seed & mask(26) as i64)), "{:08X?}", candidates);
+    }
+
+    #[test]
+    fn river_seed_finder_quarter_scale_finds_seed() {
+        use crate::java_rng::mask;
+        let version = MinecraftVersion::Java1_7;
+        // Must be a possible output of nextLong
+        let world_seed = JavaRng::with_seed(1234).next_long();
+        let area = Area { x: -30, z: -30, w: 60, h: 60 };
+        let m = generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0);
+        // All the rivers and a sample of the other biomes
+        let mut biomes4 = SparseMap::new(area);
+        for ((x, z), b) in m.a.indexed_iter() {
+            if *b == biome_id::river || (x * 7 + z) % 31 == 0 {
+                biomes4.a[(x, z)] = Some(*b);
+            }
+        }
+
+        // Only check the 25-bit seed of world_seed, or its similar biome seed, and a few values
+        // of the bits 26 to 47 around the correct ones
+        let seeds_25 = [world_seed & mask(25) as i64, McRng::similar_biome_seed(world_seed) & mask(25) as i64];
+        let lo = McRng::similar_biome_seed_iterator_bits(25).position(|s| seeds_25.contains(&s)).unwrap() as u32;
+        let bits_48 = (world_seed as u64 & mask(48)) >> 26;
+        let range_48 = bits_48.saturating_sub(32)..bits_48 + 32;
+        let candidates = river_seed_finder_quarter_scale(&biomes4, version, lo, lo + 1, range_48);
+        assert_eq!(candidates, vec![world_seed]);
+    }
+
+    #[test]
+    fn spawn_area_filter() {
+        let version = MinecraftVersion::Java1_7;
+        let world_seed = 1234;
+        let area = Area { x: -21, z: -19, w: 40, h: 44 };
+        let m = generate(version, area, world_seed, 0);
+        let mut sm = SparseMap::from(m);
+        // Leave some holes
+        for x in 0..40 {
+            sm.a[(x, x)] = None;
+        }
+        assert_eq!(spawn_area_seed_filter(&sm, &[5678, world_seed, 1235], version), vec![world_seed]);
+        // Nothing is known, so all the candidates are valid
+        assert_eq!(spawn_area_seed_filter(&SparseMap::new(area), &[5678, world_seed], version), vec![5678, world_seed]);
+    }
+
+    #[test]
+    fn filter_seed_list_finds_seed() {
+        let version = MinecraftVersion::Java1_7;
+        let world_seed = 1234;
+        let area = Area { x: -100, z: 50, w: 200, h: 200 };
+        let m = generate_up_to_layer(version, area, world_seed, version.num_layers(), 0);
+        let mut rivers = vec![];
+        let mut extra_biomes = vec![];
+        for ((x, z), b) in m.a.indexed_iter() {
+            let p = Point { x: area.x + x as i64, z: area.z + z as i64 };
+            if *b == biome_id::river {
+                rivers.push(p);
+            } else if (x * 7 + z) % 97 == 0 {
+                extra_biomes.push((BiomeId(*b), p));
+            }
+        }
+
+        let seeds = filter_seed_list(vec![world_seed - 1, world_seed, world_seed + 1], &rivers, &extra_biomes, version);
+        assert_eq!(seeds, vec![world_seed]);
+    }
+
+    #[test]
+    fn filter_seed_list_weighted_structures() {
+        let world_seed = 1234;
+        let version = MinecraftVersion::Java1_7;
+        let slime_chunks: Vec<_> = (0..400).map(|i| Chunk::new(i % 20, i / 20)).filter(|c| is_slime_chunk(world_seed as u64, c)).collect();
+        assert!(slime_chunks.len() >= 5);
+        let evidence = SearchEvidence {
+            structures: slime_chunks.iter().map(|c| StructureEvidence::SlimeChunk(*c)).collect(),
+            ..Default::default()
+        };
+        let config = SearchConfig::default();
+        let seeds = filter_seed_list_with_config(vec![world_seed - 1, world_seed, world_seed + 1], &evidence, version, &config);
+        assert_eq!(seeds, vec![world_seed]);
+
+        // With the default weights one wrong biome rejects the seed, but not when the structures
+        // have a larger weight
+        let mut evidence = evidence;
+        evidence.extra_biomes.push((BiomeId(biome_id::mushroomIsland), Point { x: 0, z: 0 }));
+        assert_eq!(filter_seed_list_with_config(vec![world_seed], &evidence, version, &config), Vec::<i64>::new());
+        let config = SearchConfig { structure_weight: 9, ..config };
+        assert_eq!(filter_seed_list_with_config(vec![world_seed], &evidence, version, &config), vec![world_seed]);
+    }
+
+    #[test]
+    fn score_budget_early_exit() {
+        // 2 kinds of evidence with the same weight, 10 items each, 90% needed
+        let mut budget = ScoreBudget::new(&[(1, 10), (1, 10), (5, 0)], 90);
+        assert!(!budget.is_hopeless(1, 2, 10));
+        assert!(budget.is_hopeless(1, 3, 10));
+        budget.add(1, 9, 10);
+        assert!(!budget.is_hopeless(1, 1, 10));
+        assert!(budget.is_hopeless(1, 2, 10));
+        budget.add(1, 9, 10);
+        assert!(budget.accepted());
+        assert_eq!(budget.percent(), 90);
+    }
+
     #[ignore]
     #[test]
     fn river_seed_finder() {
@@ -7372,7 +10460,7 @@ mod tests {
         fn rcoords(c: &[(i64, i64)]) -> Result<Map, ()> {
             let c: Vec<_> = c.iter().map(|c| Point { x: c.0, z: c.1 }).collect();
             let area_voronoi = Area::from_coords(c.iter().copied());
-            let target_map_voronoi = map_with_river_at(&c, area_voronoi);
+            let target_map_voronoi = map_with_river_at(&c, area_voronoi).to_map();
             reverse_map_voronoi_zoom(&target_map_voronoi)
         }
         assert!(rcoords(&[]).is_err());
@@ -7394,7 +10482,7 @@ mod tests {
         let river_coords_voronoi = &s.biomes[&BiomeId(7)];
         let river_coords_voronoi = river_coords_voronoi.iter().cloned().collect::<Vec<_>>();
         let area_voronoi = Area::from_coords(river_coords_voronoi.iter().copied());
-        let target_map_voronoi = map_with_river_at(&river_coords_voronoi, area_voronoi);
+        let target_map_voronoi = map_with_river_at(&river_coords_voronoi, area_voronoi).to_map();
         let target_map_derived = reverse_map_voronoi_zoom(&target_map_voronoi).unwrap();
         let target_map = target_map_derived;
         println!("{}", draw_map(&target_map));
@@ -7402,7 +10490,7 @@ mod tests {
         let river_coords_rv_expected_value = s.options.other["expectedRiversPreviousLayer"].clone();
         let river_coords_rv_expected: Vec<Point> = serde_json::from_value(river_coords_rv_expected_value).unwrap();
         let area_rv = Area::from_coords(river_coords_rv_expected.iter().copied());
-        let expected_rv_map = map_with_river_at(&river_coords_rv_expected, area_rv);
+        let expected_rv_map = map_with_river_at(&river_coords_rv_expected, area_rv).to_map();
         println!("{}", draw_map(&expected_rv_map));
         assert_eq!(target_map, expected_rv_map);
     }
@@ -7434,7 +10522,7 @@ mod tests {
         use crate::seed_info::SeedInfo;
         let s = SeedInfo::read("seedinfo_tests/voronoi_1_15.json").unwrap();
         let river_coords = &s.biomes[&BiomeId(7)];
-        println!("{}", draw_map(&map_with_river_at(river_coords, Area::from_coords(river_coords.iter().copied()))));
+        println!("{}", draw_map(&map_with_river_at(river_coords, Area::from_coords(river_coords.iter().copied())).to_map()));
         let y_offset = 0;
         let m = generate(MinecraftVersion::Java1_15, Area::from_coords(river_coords.iter().copied()), s.world_seed.unwrap(), y_offset);
         println!("{}", draw_map(&m));
@@ -7444,61 +10532,225 @@ mod tests {
         }
     }
 
+    #[test]
+    fn voronoi_1_15_float_orders() {
+        use crate::seed_info::SeedInfo;
+        // In-game rivers: both orders must agree with the game here
+        let s = SeedInfo::read("seedinfo_tests/voronoi_1_15.json").unwrap();
+        let world_seed = s.world_seed.unwrap();
+        let river_coords = &s.biomes[&BiomeId(7)];
+        let area = Area::from_coords(river_coords.iter().copied());
+        for &order in &[VoronoiFloatOrder::FractionFirst, VoronoiFloatOrder::OffsetFirst] {
+            let mut mv = MapVoronoiZoom115::new(world_seed);
+            mv.parent = Some(Rc::from(generator_up_to_layer_1_15(world_seed, 50, MinecraftVersion::Java1_15)));
+            mv.float_order = order;
+            let m = mv.get_map(area);
+            for r in river_coords {
+                assert_eq!(m.get(r.x, r.z), biome_id::river, "{:?} {:?}", order, r);
+            }
+        }
+    }
+
+    #[test]
+    fn voronoi_1_15_float_order_rounding() {
+        // The orders round the distance differently: offset of nextInt(1024) = 3, fraction 0.5
+        let o = (3.0 / 1024.0 - 0.5) * 0.9;
+        assert_ne!((0.5 - 1.0) + o, (o - 1.0) + 0.5);
+    }
+
+    #[test]
+    fn voronoi_1_15_float_order_statistics() {
+        // Count the blocks that depend on the order of the floating point operations. None were
+        // found in 100 seeds in a 512x512 area, so ties are very rare
+        let parea = Area { x: -16, z: -16, w: 33, h: 33 };
+        let pmap = MapFn(|Point { x, z }| (x * 7 + z * 13).rem_euclid(5) as i32).get_map(parea);
+        let total: usize = (0..20).map(|world_seed| voronoi_float_order_disagreements(world_seed, &pmap).len()).sum();
+        assert!(total < 10, "{} disagreements out of {} blocks", total, 20 * 128 * 128);
+    }
+
     #[test]
     fn sha256_byte_order() {
         let input = 2499980394650691929u64 as i64;
         let expected = 11169288594992569606u64 as i64;
 
-        assert_eq!(sha256_long_to_long(input), expected);
+        assert_eq!(sha256_long_to_long(input), expected);
+    }
+
+    #[test]
+    fn sha256_collision_34_bits() {
+        use crate::java_rng::mask;
+
+        // These values of the world seed result in the same lower 34 bits
+        // after being hashed. This doesn't seed to be useful, but here they
+        // are anyway.
+        let x = [19424995491, 55159191312, 47814887371];
+        for &i in &x {
+            assert_eq!(sha256_long_to_long(i) & mask(34) as i64, i & mask(34) as i64);
+        }
+    }
+
+    #[test]
+    fn sha256_collision_similar_biome_seeds() {
+        use crate::java_rng::mask;
+
+        // The similar biomes trick doesn't work as well in 1.15 because of the
+        // changes in MapVoronoiZoom. However, it is still possible to find
+        // some seeds that share the lower 34 bits when hashed
+        let x = [36159317779, 54081671403, 3734291918, 41695158396];
+        for &i in &x {
+            let similar = McRng::similar_biome_seed(i);
+            assert_eq!(sha256_long_to_long(i) & mask(34) as i64, sha256_long_to_long(similar) & mask(34) as i64);
+        }
+    }
+
+    #[test]
+    fn index_of_min_element_tie() {
+        assert_eq!(index_of_min_element(&[0.0, 1.0]).unwrap(), 0);
+        assert_eq!(index_of_min_element(&[1.0, 0.0]).unwrap(), 1);
+        assert_eq!(index_of_min_element(&[0.0, 0.0]).unwrap(), 0);
+        assert_eq!(index_of_min_element(&[0.1, 0.0, 0.0]).unwrap(), 1);
+    }
+
+    #[test]
+    fn candidates_26() {
+        use crate::seed_info::SeedInfo;
+        let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
+
+        let river_coords_voronoi = &s.biomes[&BiomeId(7)];
+        let river_coords_voronoi = river_coords_voronoi.iter().cloned().collect::<Vec<_>>();
+        let seed26: u32 = 0x03A1F4CC;
+        let range_lo = 0xf84c80;
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&river_coords_voronoi);
+        let candidates = river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_lo + (1 << 7));
+        assert!(candidates.contains(&(seed26 as i64)), "{:?}", candidates);
+
+        // Starting the search near the correct seed finds it in the first few seeds
+        let config = SearchConfig::default();
+        let candidates = river_seed_finder_26_seeds_cancellable(&river_coords_quarter_scale, seeds_25_range(SeedOrder::NearSeed(seed26 as i64 + 3), 0, 8), &config, &CancelToken::new()).results;
+        assert!(candidates.contains(&(seed26 as i64)), "{:?}", candidates);
+    }
+
+    #[test]
+    fn candidates_26_or_map_same_as_single_seed() {
+        use crate::seed_info::SeedInfo;
+        let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
+
+        // The OR map of the two seeds of a pair must not reject any seed that passes when it is
+        // checked alone
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&s.biomes[&BiomeId(7)]);
+        let (target_maps, bad_map_target) = river_target_maps_26(&river_coords_quarter_scale);
+        let config = SearchConfig::default();
+        let range_lo = 0xf84c80;
+        let seeds: Vec<i64> = McRng::similar_biome_seed_iterator_bits(25).skip(range_lo).take(1 << 7).collect();
+        let mut expected = vec![];
+        for &world_seed in &seeds {
+            for world_seed in [world_seed, world_seed ^ (1 << 25)] {
+                if candidate_26_score(world_seed, &target_maps, bad_map_target, config.river_percent_26).is_some() {
+                    expected.push(world_seed);
+                    expected.push(McRng::similar_biome_seed(world_seed) & ((1 << 26) - 1));
+                }
+            }
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(river_seed_finder_26_seeds_cancellable(&river_coords_quarter_scale, seeds, &config, &CancelToken::new()).results, expected);
+    }
+
+    #[test]
+    fn candidates_26_cancelled() {
+        use crate::seed_info::SeedInfo;
+        let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
+
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&s.biomes[&BiomeId(7)]);
+        let range_lo = 0xf84c80;
+        let config = SearchConfig::default();
+        let found = river_seed_finder_26_seeds_cancellable(&river_coords_quarter_scale, seeds_25_range(SeedOrder::Sequential, range_lo, range_lo + (1 << 7)), &config, &CancelToken::new());
+        assert!(!found.truncated);
+        assert_eq!(found.results, river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_lo + (1 << 7)));
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let found = river_seed_finder_26_seeds_cancellable(&river_coords_quarter_scale, seeds_25_range(SeedOrder::Sequential, range_lo, range_lo + (1 << 7)), &config, &cancel);
+        assert!(found.truncated);
+        assert!(found.results.is_empty());
+        assert_eq!(river_seed_finder_26_iter(river_coords_quarter_scale, range_lo, range_lo + (1 << 7), config, cancel).count(), 0);
     }
 
     #[test]
-    fn sha256_collision_34_bits() {
-        use crate::java_rng::mask;
+    fn river_seed_finder_cancelled() {
+        use crate::seed_info::SeedInfo;
+        let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
+        let rivers = &s.biomes[&BiomeId(7)];
+        let range_lo = 0xf84c80;
 
-        // These values of the world seed result in the same lower 34 bits
-        // after being hashed. This doesn't seed to be useful, but here they
-        // are anyway.
-        let x = [19424995491, 55159191312, 47814887371];
-        for &i in &x {
-            assert_eq!(sha256_long_to_long(i) & mask(34) as i64, i & mask(34) as i64);
-        }
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let found = river_seed_finder_range_cancellable(rivers, &[], MinecraftVersion::Java1_7, range_lo, range_lo + (1 << 7), &cancel);
+        assert!(found.truncated);
+        assert!(found.results.is_empty());
+
+        // The 34-bit and 64-bit stages also stop early
+        let config = SearchConfig::default();
+        let seeds: Vec<i64> = (0..1000).collect();
+        assert_eq!(extend_candidates_with_hd_rivers_iter(seeds.iter().copied(), rivers, &config, cancel.clone()).count(), 0);
+        let fragments = [(FragmentVersion::Known(MinecraftVersion::Java1_7), SearchEvidence { rivers: rivers.clone(), extra_biomes: vec![], structures: vec![] })];
+        let candidates = seeds.iter().map(|&world_seed| SeedCandidate { world_seed, score: 100 });
+        assert_eq!(score_candidates_mixed_version_iter(candidates, &fragments, &config, cancel).count(), 0);
     }
 
     #[test]
-    fn sha256_collision_similar_biome_seeds() {
-        use crate::java_rng::mask;
+    fn candidates_26_iter() {
+        use crate::seed_info::SeedInfo;
+        let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
 
-        // The similar biomes trick doesn't work as well in 1.15 because of the
-        // changes in MapVoronoiZoom. However, it is still possible to find
-        // some seeds that share the lower 34 bits when hashed
-        let x = [36159317779, 54081671403, 3734291918, 41695158396];
-        for &i in &x {
-            let similar = McRng::similar_biome_seed(i);
-            assert_eq!(sha256_long_to_long(i) & mask(34) as i64, sha256_long_to_long(similar) & mask(34) as i64);
-        }
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&s.biomes[&BiomeId(7)]);
+        let range_lo = 0xf84c80;
+        let found: Vec<i64> = river_seed_finder_26_iter(river_coords_quarter_scale.clone(), range_lo, range_lo + (1 << 7), SearchConfig::default(), CancelToken::new()).collect();
+        assert_eq!(found, river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_lo + (1 << 7)));
+        assert!(!found.is_empty());
     }
 
     #[test]
-    fn index_of_min_element_tie() {
-        assert_eq!(index_of_min_element(&[0.0, 1.0]).unwrap(), 0);
-        assert_eq!(index_of_min_element(&[1.0, 0.0]).unwrap(), 1);
-        assert_eq!(index_of_min_element(&[0.0, 0.0]).unwrap(), 0);
-        assert_eq!(index_of_min_element(&[0.1, 0.0, 0.0]).unwrap(), 1);
+    fn extend_candidates_hd_rivers() {
+        use crate::java_rng::mask;
+        let version = MinecraftVersion::Java1_7;
+        let world_seed = 1234;
+        // Big enough to have some river fragments with at least 40 rivers
+        let area = Area { x: -300, z: -300, w: 600, h: 600 };
+        let m = generate(version, area, world_seed, 0);
+        let river_coords_voronoi: Vec<Point> = m.a.indexed_iter().filter(|(_, b)| **b == biome_id::river).map(|((x, z), _)| Point { x: area.x + x as i64, z: area.z + z as i64 }).collect();
+        let seed26 = world_seed & mask(26) as i64;
+        let config = SearchConfig::default();
+        let candidates = extend_candidates_with_hd_rivers(&[seed26], &river_coords_voronoi, version, &config);
+        assert!(candidates.contains(&(world_seed & mask(34) as i64)), "{:09X?}", candidates);
+        for c in &candidates {
+            assert_eq!(c & ((1 << 26) - 1), seed26, "{:X}", c);
+            assert!(*c < (1 << 34));
+        }
+        // Only the lowest 26 bits of the input candidates are used
+        let with_high_bits = extend_candidates_with_hd_rivers(&[seed26 | (1 << 40), seed26], &river_coords_voronoi, MinecraftVersion::Java1_7, &config);
+        assert_eq!(with_high_bits, candidates);
     }
 
     #[test]
-    fn candidates_26() {
+    fn replay_trace() {
         use crate::seed_info::SeedInfo;
         let s = SeedInfo::read("seedinfo_tests/long_river_1_7.json").unwrap();
 
         let river_coords_voronoi = &s.biomes[&BiomeId(7)];
         let river_coords_voronoi = river_coords_voronoi.iter().cloned().collect::<Vec<_>>();
-        let seed26: u32 = 0x03A1F4CC;
-        let range_lo = 0xf84c80;
-        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&river_coords_voronoi);
-        let candidates = river_seed_finder_26_range(&river_coords_quarter_scale, range_lo, range_lo + (1 << 7));
-        assert!(candidates.contains(&(seed26 as i64)), "{:?}", candidates);
+        let seed26 = 0x03A1F4CC;
+        // The score is wrong, so replaying this event reports the correct one
+        let event = TraceEvent { stage: Stage::Bits26, world_seed: seed26, accepted: true, score: 0 };
+        let trace = Trace { events: vec![event] };
+        let mismatches = replay_river_seed_finder_trace(&trace, &river_coords_voronoi, &[], MinecraftVersion::Java1_7);
+        assert_eq!(mismatches.len(), 1);
+        let (recorded, replayed) = mismatches[0];
+        assert_eq!(recorded, event);
+        assert!(replayed.accepted);
+        assert!(replayed.score > 0);
+
+        let trace = Trace { events: vec![replayed] };
+        assert_eq!(replay_river_seed_finder_trace(&trace, &river_coords_voronoi, &[], MinecraftVersion::Java1_7), vec![]);
     }
 
     #[test]
@@ -7510,6 +10762,68 @@ mod tests {
         assert_eq!(x.len(), 2);
     }
 
+    #[test]
+    fn plan_fragments_diagonal() {
+        // A dense 32x32 square is kept as one fragment
+        let square: Vec<_> = (0..32).flat_map(|x| (0..32).map(move |z| Point4 { x, z })).collect();
+        assert_eq!(split_rivers_into_fragments4(&square).len(), 1);
+
+        // A diagonal line is split into smaller fragments, which are cheaper to generate
+        let diagonal: Vec<_> = (0..60).flat_map(|i| (0..2).map(move |d| Point4 { x: i, z: i + d })).collect();
+        let fragments = split_rivers_into_fragments4(&diagonal);
+        assert!(fragments.len() > 1);
+        let total_cost: u64 = fragments.iter().map(|m| candidate_river_map_cost(m.area())).sum();
+        assert!(total_cost < candidate_river_map_cost(Area::from_coords4(diagonal.iter().copied())));
+        // No points are lost, and all the fragments have enough rivers
+        assert_eq!(fragments.iter().map(RiverMask::count).sum::<u32>(), diagonal.len() as u32);
+        assert!(fragments.iter().all(|m| m.count() >= 10));
+    }
+
+    #[test]
+    fn map_zoom_evidence() {
+        let world_seed = 0x2A5C3B1;
+        let pmap = TestMapCheckers.get_map(Area { x: -3, z: 2, w: 10, h: 10 });
+        let map = MapZoom::new(2000, world_seed).get_map_from_pmap(&pmap);
+        let observed = SparseMap::from(map);
+
+        let candidates: Vec<i64> = (0..64).map(|i| world_seed ^ (i << 20)).collect();
+        assert_eq!(filter_map_zoom_seeds(candidates.iter().copied(), 2000, &pmap, &observed, 26), vec![world_seed]);
+        // Seeds that only differ in bit 25 cannot be distinguished using 25 bits
+        let candidates = vec![world_seed, world_seed ^ (1 << 25), world_seed ^ (1 << 24)];
+        assert_eq!(filter_map_zoom_seeds(candidates, 2000, &pmap, &observed, 25), vec![world_seed, world_seed ^ (1 << 25)]);
+    }
+
+    #[test]
+    fn zoom_choices_prior() {
+        let world_seed = 0x2A5C3B1;
+        let pmap = TestMapCheckers.get_map(Area { x: -3, z: 2, w: 10, h: 10 });
+        let map = MapZoom::new(2000, world_seed).get_map_from_pmap(&pmap);
+        let observed = SparseMap::from(map);
+        let choices = zoom_choices_from_straight_borders(&pmap, &observed, 1);
+        assert!(!choices.is_empty());
+        let max_score: f32 = choices.iter().map(|c| c.weight).sum();
+        assert_eq!(zoom_prior_score(2000, world_seed, &choices), max_score);
+
+        let candidates: Vec<i64> = (0..64).map(|i| world_seed ^ (i << 20)).collect();
+        assert_eq!(order_seeds_by_zoom_prior(2000, &choices, candidates)[0], world_seed);
+    }
+
+    #[test]
+    fn zoom_choices_straight_border() {
+        let area = Area { x: 0, z: 0, w: 10, h: 10 };
+        // Vertical border between x=4 and x=5
+        let pmap = Map::from_area_fn(area, |(x, _z)| if x < 5 { 0 } else { 1 });
+        let mut observed = SparseMap::new(Area { x: 0, z: 0, w: 18, h: 18 });
+        for z in 0..9 {
+            // The border is straight: the tile between the two parents is always 0
+            observed.a[(9, z * 2)] = Some(0);
+        }
+        let choices = zoom_choices_from_straight_borders(&pmap, &observed, 4);
+        assert_eq!(choices.len(), 9);
+        assert!(choices.iter().all(|c| c.second_call && !c.value && c.weight == 1.0));
+        assert_eq!(zoom_choices_from_straight_borders(&pmap, &observed, 10), vec![]);
+    }
+
     #[test]
     fn split_rivers_into_fragments4_integer_division() {
         let p = vec![Point4 { x: 0, z: 0 }, Point4 { x: -1, z: 0 }];
@@ -7553,6 +10867,435 @@ mod tests {
         assert_eq!(r.to_vec(), vec![22, 23, 27, 71, 87, 221, 197, 105, 176, 3, 90, 34, 222, 117, 239, 165, 169, 117, 157, 35, 0, 177, 27, 253, 76, 154, 247, 248, 197, 175, 50, 246]);
     }
 
+    #[test]
+    fn zoom_layers_any_area() {
+        let world_seed = 1234;
+        let parent: Rc<dyn GetMap> = Rc::new(MapFn(|Point { x, z }| (x * 7 + z * 13).rem_euclid(5) as i32));
+        let mut mhv = MapHalfVoronoiZoom::new(10, world_seed);
+        mhv.parent = Some(Rc::clone(&parent));
+        let mut mhv115 = MapHalfVoronoiZoom115::new(world_seed);
+        mhv115.parent = Some(Rc::clone(&parent));
+        let mut mv = MapVoronoiZoom::new(10, world_seed);
+        mv.parent = Some(Rc::clone(&parent));
+        let mut mv115 = MapVoronoiZoom115::new(world_seed);
+        mv115.parent = Some(Rc::clone(&parent));
+        let mut mz = MapZoom::new(2000, world_seed);
+        mz.parent = Some(Rc::clone(&parent));
+        let layers: Vec<&dyn GetMap> = vec![&mhv, &mhv115, &mv, &mv115, &mz];
+
+        let full_area = Area { x: -12, z: -12, w: 32, h: 32 };
+        for layer in layers {
+            let full = layer.get_map(full_area);
+            for x in -4..4 {
+                for z in -4..4 {
+                    for w in 0..10 {
+                        // All the offsets modulo 4, and also the areas that end at a tile border
+                        let area = Area { x, z, w, h: 9 - w };
+                        let m = layer.get_map(area);
+                        assert_eq!(m.area(), area);
+                        let mut expected = full.clone();
+                        expected.crop(area);
+                        assert_eq!(m, expected, "{:?}", area);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn biome_edge_bamboo_jungle_from_1_14() {
+        use biome_id::*;
+        // Swampland with a bambooJungle neighbor
+        let mut pmap = Map::from_area_fn(Area { x: 0, z: 0, w: 3, h: 3 }, |_| swampland);
+        pmap.a[(1, 0)] = bambooJungle;
+        for &(version, expected) in &[
+            (MinecraftVersion::Java1_13, swampland),
+            (MinecraftVersion::Java1_14, jungleEdge),
+            (MinecraftVersion::Java1_17, jungleEdge),
+        ] {
+            let m = MapBiomeEdge::new(1000, 1234, version).get_map_from_pmap(&pmap);
+            assert_eq!(m.a[(0, 0)], expected, "{:?}", version);
+        }
+        assert_eq!(BiomeTables::new(MinecraftVersion::Java1_7), BiomeTables::new(MinecraftVersion::Java1_13));
+    }
+
+    #[test]
+    fn biome_tables_before_1_7() {
+        use biome_id::*;
+        let t13 = BiomeTables::new(MinecraftVersion::Java1_3);
+        let t17 = BiomeTables::new(MinecraftVersion::Java1_7);
+        assert_ne!(t13, t17);
+        // 1.7 added savannas and split the land by climate
+        assert!(!t13.land.contains(&savanna));
+        assert!(t17.warm.contains(&savanna));
+        assert!(t13.land.contains(&jungle));
+        assert!(t17.land.is_empty() && t13.warm.is_empty());
+        // MapBiome13 only generates biomes of the 1.3 table, or icePlains in snowy areas
+        let pmap = Map::from_area_fn(Area { x: 0, z: 0, w: 40, h: 40 }, |(x, z)| [0, 1, 2, mushroomIsland][(x + z) % 4]);
+        let m = MapBiome13::new(200, 1234).get_map_from_pmap(&pmap);
+        assert!(m.a.iter().all(|b| t13.land.contains(b) || [ocean, mushroomIsland, icePlains].contains(b)));
+        assert!(m.a.iter().any(|b| *b == jungle));
+    }
+
+    #[test]
+    fn biome_edge_mesa_plateau_category() {
+        use biome_id::*;
+        // mesaPlateau_F with a mesa neighbor. Before 1.16.2 both are in the same category, so the
+        // plateau does not get an edge.
+        let mut pmap = Map::from_area_fn(Area { x: 0, z: 0, w: 3, h: 3 }, |_| mesaPlateau_F);
+        pmap.a[(1, 0)] = mesa;
+        for &(version, expected) in &[
+            (MinecraftVersion::Java1_7, mesaPlateau_F),
+            (MinecraftVersion::Java1_13, mesaPlateau_F),
+            (MinecraftVersion::Java1_16_1, mesaPlateau_F),
+            (MinecraftVersion::Java1_16, mesa),
+            (MinecraftVersion::Java1_17, mesa),
+        ] {
+            let m = MapBiomeEdge::new(1000, 1234, version).get_map_from_pmap(&pmap);
+            assert_eq!(m.a[(0, 0)], expected, "{:?}", version);
+        }
+    }
+
+    #[test]
+    fn zoom_layers_same_as_1x1() {
+        let world_seed = 1234;
+        let parent: Rc<dyn GetMap> = Rc::new(MapFn(|Point { x, z }| (x * 7 + z * 13).rem_euclid(5) as i32));
+        let mut mz = MapZoom::new(2000, world_seed);
+        mz.parent = Some(Rc::clone(&parent));
+        let mut mzf = MapZoomFuzzy::new(2000, world_seed);
+        mzf.parent = Some(Rc::clone(&parent));
+        let mut mzae = HelperMapZoomAllEdges::new(2000, world_seed);
+        mzae.parent = Some(Rc::clone(&parent));
+        let ms = MapSkip::new(Rc::clone(&parent), 1);
+        let mut mv = MapVoronoiZoom::new(10, world_seed);
+        mv.parent = Some(Rc::clone(&parent));
+        let mut mv115 = MapVoronoiZoom115::new(world_seed);
+        mv115.parent = Some(Rc::clone(&parent));
+        let layers: Vec<&dyn GetMap> = vec![&mz, &mzf, &mzae, &ms, &mv, &mv115];
+
+        for layer in layers {
+            // All the offsets and sizes modulo 4
+            for x in -4..0 {
+                for z in -4..0 {
+                    for w in 1..5 {
+                        let area = Area { x, z, w, h: 5 - w };
+                        assert_eq!(check_fragment_stitching(layer, area, 1), Ok(()), "{:?}", area);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generator_layers_same_as_1x1() {
+        let world_seed = 1234;
+        let areas = [Area { x: -3, z: 5, w: 3, h: 2 }, Area { x: 6, z: -2, w: 2, h: 3 }];
+        for &version in &[MinecraftVersion::Java1_7, MinecraftVersion::Java1_15] {
+            for layer in 0..=version.num_layers() {
+                let g = match GeneratorCache::new_generator(version, world_seed, layer) {
+                    Some(CachedGenerator::Map(g)) => g,
+                    _ => panic!("Layer {} of {:?} is not a 2D generator", layer, version),
+                };
+                for &area in &areas {
+                    assert_eq!(check_fragment_stitching(&*g, area, 1), Ok(()), "{:?} layer {} {:?}", version, layer, area);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generator_cache_check_stitching() {
+        let mut cache = GeneratorCache::new(2);
+        cache.set_check_stitching(true);
+        let area = Area { x: -21, z: 7, w: 17, h: 9 };
+        let version = MinecraftVersion::Java1_7;
+        assert_eq!(cache.generate_up_to_layer(version, area, 1234, 43, 0), generate_up_to_layer(version, area, 1234, 43, 0));
+    }
+
+    #[test]
+    fn custom_layer_stack() {
+        let version = MinecraftVersion::Java1_7;
+        let builder = LayerStackBuilder::new(move |world_seed| {
+            let parent = Rc::from(generator_up_to_layer_1_7(world_seed, 29, version));
+            let shore = MapShore::new(1000, world_seed).with_parent(parent);
+            Rc::new(MapZoom::new(1002, world_seed).with_parent(Rc::new(shore)))
+        });
+        let area = Area { x: -30, z: 12, w: 20, h: 20 };
+        let map = std::thread::spawn(move || builder.build(1234).get_map(area)).join().unwrap();
+        assert_eq!(map, generate_up_to_layer_1_7(area, 1234, 31, version));
+
+        let builder = LayerStackBuilder::from_version(version, 42).unwrap();
+        let g25 = MapHills::new(1000, 1234, version).with_parents(
+            Rc::from(generator_up_to_layer_1_7(1234, 21, version)),
+            Rc::from(generator_up_to_layer_1_7(1234, 24, version)),
+        );
+        assert_eq!(g25.get_map(area), generate_up_to_layer_1_7(area, 1234, 25, version));
+        assert_eq!(builder.build(1234).get_map(area), generate_up_to_layer_1_7(area, 1234, 42, version));
+        assert!(LayerStackBuilder::from_version(MinecraftVersion::Java1_18, 9).is_none());
+    }
+
+    #[test]
+    fn ocean_mix_land_margin() {
+        use biome_id::*;
+        let version = MinecraftVersion::Java1_15;
+        let world_seed = 1234;
+        let land: Rc<dyn GetMap> = Rc::from(generator_up_to_layer_1_15(world_seed, 42, version));
+        let oceans: Rc<dyn GetMap> = Rc::from(generator_up_to_layer_1_15(world_seed, 49, version));
+        let g = MapOceanMix::new(100, world_seed).with_parents(land.clone(), oceans.clone());
+        for &area in &[Area { x: -200, z: 100, w: 64, h: 48 }, Area { x: 3000, z: -5000, w: 100, h: 100 }] {
+            // Same result as always using the full margin
+            let pmap2 = oceans.get_map(area);
+            let pmap1 = land.get_map(Area { x: area.x - 8, z: area.z - 8, w: area.w + 17, h: area.h + 17 });
+            assert_eq!(g.get_map(area), g.get_map_from_pmap12(&pmap1, &pmap2));
+        }
+
+        // No margin without warm and frozen oceans
+        let area = Area { x: 10, z: 20, w: 4, h: 3 };
+        let mut pmap2 = Map::from_area_fn(area, |_| lukewarmOcean);
+        assert_eq!(MapOceanMix::land_area(&pmap2), area);
+        pmap2.a[(1, 2)] = warmOcean;
+        assert_eq!(MapOceanMix::land_area(&pmap2), Area { x: 3, z: 14, w: 17, h: 17 });
+    }
+
+    #[test]
+    fn base_seeds() {
+        let seeds = layer_base_seeds(MinecraftVersion::Java1_7);
+        assert_eq!(seeds.len(), 44);
+        assert_eq!(seeds[0], (0, "MapIsland", Some(1)));
+        assert_eq!(seeds[23], (23, "MapZoom", Some(1000)));
+        assert_eq!(seeds[30], (30, "MapShore", Some(1000)));
+        assert_eq!(seeds[43], (43, "MapVoronoiZoom", Some(10)));
+        let seeds = layer_base_seeds(MinecraftVersion::Java1_18);
+        assert_eq!(seeds, vec![(9, "MapVoronoiZoom118", None)]);
+    }
+
+    #[test]
+    fn seed_bits() {
+        let v = MinecraftVersion::Java1_7;
+        assert_eq!(bits_required_for_layer(v, 0), Some(SeedBits::Full));
+        assert_eq!(bits_required_for_layer(v, 22), Some(SeedBits::Full));
+        assert_eq!(bits_required_for_layer(v, 23), Some(SeedBits::Lower(26)));
+        assert_eq!(bits_required_for_layer(v, 30), Some(SeedBits::Independent));
+        assert_eq!(bits_required_for_layer(v, 43), Some(SeedBits::Lower(34)));
+        assert_eq!(bits_required_for_layer(MinecraftVersion::Java1_15, 51), Some(SeedBits::Full));
+        assert_eq!(bits_required_for_layer(MinecraftVersion::Java1_18, 9), Some(SeedBits::Full));
+        assert!(SeedBits::Independent < SeedBits::Lower(25));
+        assert!(SeedBits::Lower(25) < SeedBits::Lower(26));
+        assert!(SeedBits::Lower(48) < SeedBits::Full);
+
+        // All the layers used by the implemented versions are known
+        for version in GUESSABLE_VERSIONS.iter().copied().chain(Some(MinecraftVersion::Java1_18)) {
+            for (layer, name, _) in layer_base_seeds(version) {
+                assert!(bits_required_for_layer(version, layer).is_some(), "{:?} layer {} {}", version, layer, name);
+            }
+        }
+    }
+
+    #[test]
+    fn layer_graph() {
+        let dot = layer_graph_dot(MinecraftVersion::Java1_7);
+        // Layers 0 to 43. MapDeepOcean and MapRiverInit are shared by two children, and MapHills
+        // and MapRiverMix have two parents
+        assert_eq!(dot.matches(" [label=\"Map").count(), 44);
+        assert_eq!(dot.matches(" -> ").count(), 45);
+        assert!(dot.contains("n0 [label=\"MapIsland\\nbase seed 1\\n1:4096\"];"), "{}", dot);
+        assert!(dot.contains("[label=\"MapVoronoiZoom\\nbase seed 10\\n1:1\"];"), "{}", dot);
+
+        let dot = layer_graph_dot(MinecraftVersion::Java1_18);
+        assert!(dot.contains("n0 [label=\"MapGenBiomeNoise3D118\\n1:4\"];"), "{}", dot);
+        assert!(dot.contains("n0 -> n1;"), "{}", dot);
+    }
+
+    #[test]
+    fn layer_info_forwards_parents() {
+        // Wrapper layers can use the default layer_info
+        let island: Rc<dyn GetMap> = Rc::new(MapIsland::new(1, 1234));
+        let m = MapMap2 { parent1: Rc::clone(&island), parent2: Rc::new(MapSkip::new(Rc::clone(&island), 1)), f: |a, _| a };
+        let info = m.layer_info();
+        assert_eq!(info.name, "MapMap2");
+        let parents: Vec<&str> = info.parents.iter().map(|p| p.name).collect();
+        assert_eq!(parents, vec!["MapIsland", "MapSkip"]);
+        assert_eq!(info.parents[1].parents[0].id, info.parents[0].id);
+    }
+
+    #[test]
+    fn generator_cache_same_as_generate() {
+        let mut cache = GeneratorCache::new(2);
+        let area = Area { x: -20, z: 7, w: 16, h: 9 };
+        for _ in 0..2 {
+            for &(version, layer) in &[(MinecraftVersion::Java1_7, 43), (MinecraftVersion::Java1_7, 142), (MinecraftVersion::Java1_15, 50)] {
+                for &seed in &[1234, -5] {
+                    assert_eq!(cache.generate_up_to_layer(version, area, seed, layer, 0), generate_up_to_layer(version, area, seed, layer, 0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn biomes_in_area_same_as_full_map() {
+        let world_seed = 1234;
+        for &version in &[MinecraftVersion::Java1_7, MinecraftVersion::Java1_15] {
+            for &area in &[Area { x: -37, z: 13, w: 70, h: 45 }, Area { x: 2, z: -1, w: 1, h: 3 }] {
+                let map = generate_up_to_layer(version, area, world_seed, version.num_layers(), 0);
+                let mut expected = HashMap::new();
+                for v in map.a.iter() {
+                    *expected.entry(*v).or_insert(0) += 1;
+                }
+                assert_eq!(biomes_in_area(world_seed, version, area), expected, "{:?} {:?}", version, area);
+            }
+        }
+    }
+
+    #[test]
+    fn sin_lookup_table() {
+        assert_eq!(SinMode::default(), SinMode::LookupTable);
+        assert_eq!(fast_sin(0.0, SinMode::LookupTable), 0.0);
+        assert_eq!(fast_sin(std::f32::consts::FRAC_PI_2, SinMode::LookupTable), 1.0);
+        // Only the angles used by treasure maps
+        for zf in 0..128 {
+            let x = zf as f32;
+            let diff = (fast_sin(x, SinMode::LookupTable) - fast_sin(x, SinMode::Native)).abs();
+            assert!(diff < 1e-3, "sin({}): {}", x, diff);
+        }
+        // Out of range values do not panic
+        assert_eq!(fast_sin(f32::NAN, SinMode::LookupTable), 0.0);
+        fast_sin(f32::MAX, SinMode::LookupTable);
+        fast_sin(f32::MIN, SinMode::LookupTable);
+    }
+
+    #[test]
+    fn treasure_map_quarter_scale_parent() {
+        // Island pattern at 1:4 scale, and the same pattern scaled to 1:2
+        let f = |Point { x, z }: Point| {
+            if (x * x + 3 * z).rem_euclid(5) == 0 { biome_id::ocean } else { biome_id::plains }
+        };
+        let mt = MapTreasure {
+            parent: Rc::new(MapFn(move |Point { x, z }| f(Point { x: x >> 1, z: z >> 1 }))),
+            sin_mode: SinMode::default(),
+        };
+        let mtq = MapTreasureQuarterScale {
+            parent: Rc::new(MapFn(f)),
+            sin_mode: SinMode::default(),
+        };
+        for &(x, z) in &[(0, 0), (-7, 3), (4, -12), (-33, -33)] {
+            for &(w, h) in &[(1, 1), (8, 5), (13, 20)] {
+                let area = Area { x, z, w, h };
+                assert_eq!(mtq.get_map(area), mt.get_map(area), "{:?}", area);
+            }
+        }
+
+        let parea = Area { x: -3, z: 2, w: 6, h: 5 };
+        let pmap = MapFn(f).get_map(parea);
+        let m = mtq.get_map_from_pmap(&pmap);
+        assert_eq!(m.area(), Area { x: -5, z: 5, w: 10, h: 8 });
+        assert_eq!(m, mt.get_map(m.area()));
+    }
+
+    fn treasure_map_finder_finds_seed(noise: &TreasureMapNoise) -> bool {
+        use crate::java_rng::mask;
+        // The lower 26 bits and the range are the same as in the candidates_26 test
+        let world_seed = 0x03A1F4CC | (0x5A << 26);
+        let range_lo = 0xf84c80;
+        let version = MinecraftVersion::Java1_13;
+        let treasure_map = simulate_treasure_map_input(version, world_seed, 1, 0, noise);
+        let candidates = treasure_map_river_seed_finder(&treasure_map, version, range_lo, range_lo + (1 << 7));
+
+        candidates.contains(&(world_seed & mask(34) as i64))
+    }
+
+    #[test]
+    fn treasure_map_river_seed_finder_clean() {
+        assert!(treasure_map_finder_finds_seed(&TreasureMapNoise::default()));
+    }
+
+    #[test]
+    fn treasure_map_scored_candidates() {
+        use crate::java_rng::mask;
+        let world_seed = 0x03A1F4CC | (0x5A << 26);
+        let range_lo = 0xf84c80;
+        let version = MinecraftVersion::Java1_13;
+        let treasure_map = simulate_treasure_map_input(version, world_seed, 1, 0, &TreasureMapNoise::default());
+        let candidates = treasure_map_river_seed_finder_scored(&treasure_map, version, range_lo, range_lo + (1 << 7));
+
+        let expected = world_seed & mask(34) as i64;
+        let expected_similar = McRng::similar_biome_seed(expected) & mask(34) as i64;
+        let found = candidates.iter().find(|c| c.world_seed == expected || c.world_seed == expected_similar).unwrap();
+        assert!(found.score >= 90);
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn treasure_map_misaligned() {
+        use crate::java_rng::mask;
+        let world_seed = 0x03A1F4CC | (0x5A << 26);
+        let range_lo = 0xf84c80;
+        let version = MinecraftVersion::Java1_13;
+        let mut treasure_map = simulate_treasure_map_input(version, world_seed, 1, 0, &TreasureMapNoise::default());
+        // Off by one pixel in the x axis
+        treasure_map.x -= 1;
+        let candidates = treasure_map_river_seed_finder_any_alignment(&treasure_map, version, range_lo, range_lo + (1 << 7));
+
+        let expected = world_seed & mask(34) as i64;
+        let expected_similar = McRng::similar_biome_seed(expected) & mask(34) as i64;
+        let (found, _offset) = candidates.iter().find(|(c, _)| c.world_seed == expected || c.world_seed == expected_similar).unwrap();
+        assert!(found.score >= 90);
+        assert!(candidates.windows(2).all(|w| w[0].0.score >= w[1].0.score));
+    }
+
+    #[test]
+    fn merge_similar_biome_seeds_pairs() {
+        let a = 1133184;
+        let b = McRng::similar_biome_seed(a) & crate::java_rng::mask(26) as i64;
+        let c = 12345;
+        let merged = merge_similar_biome_seeds(vec![(b, 90), (c, 95), (a, 80)], 26);
+        assert_eq!(merged, vec![
+            TreasureMapCandidate { world_seed: c, score: 95, similar_seed: None },
+            TreasureMapCandidate { world_seed: a.min(b), score: 90, similar_seed: Some(a.max(b)) },
+        ]);
+    }
+
+    #[test]
+    fn extend_candidates_34_split_range() {
+        use crate::java_rng::mask;
+        let world_seed = 0x03A1F4CC | (0x5A << 26);
+        let treasure_map = simulate_treasure_map_input(MinecraftVersion::Java1_13, world_seed, 1, 0, &TreasureMapNoise::default());
+        let target_maps = TreasureMapTargets::new(&treasure_map);
+        let candidates_26 = vec![world_seed & mask(26) as i64];
+
+        let full = extend_candidates_34(&candidates_26, &target_maps, 0..256);
+        assert!(full.contains(&(world_seed & mask(34) as i64)));
+        let mut split = extend_candidates_34(&candidates_26, &target_maps, 0..100);
+        split.extend(extend_candidates_34(&candidates_26, &target_maps, 100..256));
+        assert_eq!(split, full);
+    }
+
+    #[test]
+    fn treasure_map_river_seed_finder_noise() {
+        // Unknown pixels are treated as not river, so they break the river borders the same way
+        // as misclassified pixels. With 0.1% misclassified or 5% missing pixels the seed is
+        // usually not found.
+        for seed in 1..=5 {
+            let noise = TreasureMapNoise { misclassified: 0.0002, missing: 0.01, seed };
+            assert!(treasure_map_finder_finds_seed(&noise), "{:?}", noise);
+        }
+    }
+
+    #[test]
+    fn simulated_treasure_map_noise() {
+        let version = MinecraftVersion::Java1_13;
+        let clean = simulate_treasure_map_input(version, 1234, 0, 0, &TreasureMapNoise::default());
+        assert_eq!(clean.area(), Area { x: -32, z: -32, w: 128, h: 128 });
+        assert_eq!(clean.a[(0, 0)], 255);
+        assert!(clean.a.iter().all(|v| [biome_id::ocean, biome_id::plains, biome_id::river, 255].contains(v)));
+
+        let noise = TreasureMapNoise { misclassified: 0.1, missing: 0.2, seed: 5 };
+        let noisy = simulate_treasure_map_input(version, 1234, 0, 0, &noise);
+        let missing = noisy.a.iter().filter(|v| **v == 255).count() as f64 / (128.0 * 128.0);
+        assert!(missing > 0.15 && missing < 0.25, "{}", missing);
+        let changed = clean.a.iter().zip(noisy.a.iter()).filter(|(c, n)| c != n && **n != 255).count() as f64 / (128.0 * 128.0);
+        assert!(changed > 0.05 && changed < 0.1, "{}", changed);
+    }
+
     #[test]
     fn reverse_treasure_map() {
         let seed = 1239;
@@ -7583,25 +11326,19 @@ mod tests {
             parent: Rc::clone(&mhv),
             f: is_land_biome_12,
         };
-        let parea2 = Area {
-            // TODO: the x,z coords are wrong?
-            x: -34,
-            z: -34,
-            w: 128,
-            h: 128,
-        };
-        let mut pmap = mm.get_map(parea2);
+        let mut pmap = mm.get_map(parea);
         // Set the pixels at margin to unknown, we will ignore them anyway
         set_pixels_at_margin(&mut pmap, 2);
 
         let mt = MapTreasure {
             parent: mhv,
+            sin_mode: SinMode::default(),
         };
 
-        let mut map = mt.get_map(parea);
+        let mut map = mt.get_colors(parea);
 
         // But treasure maps have 126x126 resulution, so delete border pixels
-        set_pixels_at_margin(&mut map, 0);
+        set_pixels_at_margin(&mut map.0, 0);
 
         let mut reversed_map = reverse_map_treasure(&map);
         // Set the pixels at margin to unknown, we will ignore them anyway
@@ -7610,11 +11347,39 @@ mod tests {
         println!("{}", draw_map(&pmap));
         println!("{}", draw_map(&reversed_map));
 
-        // TODO: the x,z coords are wrong?
-        // The bug is probably in MapHalfVoronoiZoom or in reverse_map_treasure
-        // Compare map array only, ignore coords
+        assert_eq!(reversed_map, pmap);
+    }
 
-        assert_eq!(reversed_map.a, pmap.a);
+    #[test]
+    fn half_voronoi_same_as_voronoi() {
+        let seed = 1239;
+        let parent: Rc<dyn GetMap> = Rc::from(generator_up_to_layer_1_13(seed, 50));
+        let mut mhv = MapHalfVoronoiZoom::new(10, seed);
+        mhv.parent = Some(Rc::clone(&parent));
+        let mut mv = MapVoronoiZoom::new(10, seed);
+        mv.parent = Some(Rc::clone(&parent));
+        let mut mhv115 = MapHalfVoronoiZoom115::new(seed);
+        mhv115.parent = Some(Rc::clone(&parent));
+        let mut mv115 = MapVoronoiZoom115::new(seed);
+        mv115.parent = Some(Rc::clone(&parent));
+
+        // Test all the parities, the old implementation was wrong when x or z were even
+        for (x, z) in [(-32, -32), (-33, -32), (-32, -33), (7, 9), (0, 0), (1, 1)] {
+            for (w, h) in [(1, 1), (2, 3), (17, 16)] {
+                let area = Area { x, z, w, h };
+                let varea = Area { x: x * 2, z: z * 2, w: w * 2, h: h * 2 };
+                for (hv, v) in [
+                    (mhv.get_map(area), mv.get_map(varea)),
+                    (mhv115.get_map(area), mv115.get_map(varea)),
+                ] {
+                    assert_eq!(hv.area(), area);
+                    let expected = Map::from_area_fn(area, |(i, j)| {
+                        v.a[(i as usize * 2, j as usize * 2)]
+                    });
+                    assert_eq!(hv, expected, "{:?}", area);
+                }
+            }
+        }
     }
 
     #[test]
@@ -7643,6 +11408,84 @@ mod tests {
         assert_eq!(m.a[(0, 0)], biome_id::swampland);
     }
 
+    #[test]
+    fn map_hills_world_seed_not_set_quirk() {
+        // Rebuild the parent2 of MapHills by hand, with the bug flag set to a known value
+        fn zoom_river_init(g22: Rc<dyn GetMap>, world_seed: i64, bug_world_seed_not_set: bool) -> MapMap {
+            let mut g23 = MapZoom::new(1000, world_seed);
+            g23.parent = Some(g22);
+            g23.bug_world_seed_not_set = bug_world_seed_not_set;
+            let mut g24 = MapZoom::new(1001, world_seed);
+            g24.parent = Some(Rc::new(g23));
+            g24.bug_world_seed_not_set = bug_world_seed_not_set;
+
+            MapMap { parent: Rc::new(g24), f: pretty_biome_map_hills }
+        }
+        let area = Area { x: -30, z: -30, w: 60, h: 60 };
+        let world_seed = 1234;
+        let y_offset = 0;
+        for version in [MinecraftVersion::Java1_7, MinecraftVersion::Java1_11, MinecraftVersion::Java1_15] {
+            let g22: Rc<dyn GetMap> = if version < MinecraftVersion::Java1_13 {
+                Rc::from(generator_up_to_layer_1_7(world_seed, 22, version))
+            } else {
+                Rc::from(generator_up_to_layer_1_15(world_seed, 22, version))
+            };
+            let quirk = version.quirks().map_hills_zoom_world_seed_not_set;
+            let expected = generate_up_to_layer(version, area, world_seed, 24, y_offset);
+            assert_eq!(zoom_river_init(g22.clone(), world_seed, quirk).get_map(area), expected, "{:?}", version);
+            assert_ne!(zoom_river_init(g22, world_seed, !quirk).get_map(area), expected, "{:?}", version);
+        }
+    }
+
+    #[test]
+    fn river_layers_same_in_all_versions() {
+        // The river layers of 1.7 applied to the output of MapRiverInit of each version
+        fn river_layers_1_7(g22: Rc<dyn GetMap>, world_seed: i64) -> MapSmooth {
+            let mut g = MapZoom::new(1000, world_seed);
+            g.parent = Some(g22);
+            for base_seed in [1001, 1000, 1001, 1002, 1003] {
+                let mut next = MapZoom::new(base_seed, world_seed);
+                next.parent = Some(Rc::new(g));
+                g = next;
+            }
+            let mut g40 = MapRiver::new(1, world_seed);
+            g40.parent = Some(Rc::new(g));
+            let mut g41 = MapSmooth::new(1000, world_seed);
+            g41.parent = Some(Rc::new(g40));
+
+            g41
+        }
+        let area = Area { x: -40, z: 25, w: 50, h: 60 };
+        let y_offset = 0;
+        for world_seed in [1234, -5678] {
+            for version in [MinecraftVersion::Java1_7, MinecraftVersion::Java1_9, MinecraftVersion::Java1_11, MinecraftVersion::Java1_13, MinecraftVersion::Java1_14, MinecraftVersion::Java1_15, MinecraftVersion::Java1_16_1, MinecraftVersion::Java1_16, MinecraftVersion::Java1_17] {
+                assert_eq!(version.river_layers(), Some(RiverLayers::Java1_7));
+                let g22: Rc<dyn GetMap> = match version {
+                    MinecraftVersion::Java1_13 => Rc::from(generator_up_to_layer_1_13(world_seed, 22)),
+                    MinecraftVersion::Java1_14 => Rc::from(generator_up_to_layer_1_14(world_seed, 22)),
+                    v if v < MinecraftVersion::Java1_13 => Rc::from(generator_up_to_layer_1_7(world_seed, 22, version)),
+                    _ => Rc::from(generator_up_to_layer_1_15(world_seed, 22, version)),
+                };
+                let expected = generate_up_to_layer(version, area, world_seed, 41, y_offset);
+                assert_eq!(river_layers_1_7(g22, world_seed).get_map(area), expected, "{:?}", version);
+                // The input and the output of the river layers are the same as in 1.7
+                let g22_1_7 = generate_up_to_layer(MinecraftVersion::Java1_7, area, world_seed, 22, y_offset);
+                assert_eq!(generate_up_to_layer(version, area, world_seed, 22, y_offset), g22_1_7, "{:?}", version);
+                let rivers_1_7 = generate_up_to_layer(MinecraftVersion::Java1_7, area, world_seed, 41, y_offset);
+                assert_eq!(expected, rivers_1_7, "{:?}", version);
+            }
+        }
+        assert_eq!(MinecraftVersion::Java1_3.river_layers(), Some(RiverLayers::Java1_3));
+        assert_eq!(MinecraftVersion::Java1_18.river_layers(), None);
+        // The river seed finder returns an error instead of panicking
+        for version in [MinecraftVersion::Java1_3, MinecraftVersion::Java1_18] {
+            let fragments = [(FragmentVersion::Known(version), SearchEvidence::default())];
+            assert!(river_seed_finder_check_versions(&fragments).is_err());
+            assert!(river_seed_finder_mixed_version_iter(&fragments, 0, 1, &SearchConfig::default()).is_err());
+        }
+        assert!(river_seed_finder_check_versions(&[(FragmentVersion::Any, SearchEvidence::default())]).is_ok());
+    }
+
     #[test]
     fn test_generation_1_16_1() {
         // This is a regression test for
@@ -7937,4 +11780,145 @@ mod tests {
         let m = generate_up_to_layer(version, a, world_seed, version.num_layers() - 1, y_offset);
         assert_eq!(m.a[(0, 0)], 4);
     }
+
+    #[test]
+    fn generate_iter_tiles() {
+        let version = MinecraftVersion::Java1_15;
+        let area = Area { x: -37, z: 15, w: 70, h: 45 };
+        let full = generate(version, area, 1234, 0);
+        let tiles: Vec<Map> = generate_iter(version, area, 1234, 32).collect();
+        // 3 columns and 2 rows
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[1].area(), Area { x: -5, z: 15, w: 32, h: 32 });
+        assert_eq!(tiles[5].area(), Area { x: 27, z: 47, w: 6, h: 13 });
+        for t in &tiles {
+            let a = t.area();
+            for x in a.x..a.x + a.w as i64 {
+                for z in a.z..a.z + a.h as i64 {
+                    assert_eq!(t.get(x, z), full.get(x, z));
+                }
+            }
+        }
+        assert_eq!(generate_iter(version, Area { x: 0, z: 0, w: 0, h: 10 }, 1234, 8).count(), 0);
+    }
+
+    #[test]
+    fn generate_parallel_same_as_generate() {
+        let area = Area { x: -150, z: 43, w: 120, h: 97 };
+        for &version in &[MinecraftVersion::Java1_3, MinecraftVersion::Java1_9, MinecraftVersion::Java1_13, MinecraftVersion::Java1_16] {
+            assert_eq!(generate_parallel(version, area, 1234).a, generate(version, area, 1234, 0).a, "{:?}", version);
+        }
+        // No layers to parallelize, same as generate
+        let area = Area { x: 3, z: -5, w: 8, h: 8 };
+        assert_eq!(generate_parallel(MinecraftVersion::Java1_18, area, 1234).a, generate(MinecraftVersion::Java1_18, area, 1234, 0).a);
+    }
+
+    #[test]
+    fn diff_image() {
+        use biome_id::*;
+        let area = Area { x: 5, z: -3, w: 2, h: 2 };
+        let a = Map::from_area_fn(area, |(x, z)| [[plains, river], [UNKNOWN_BIOME_ID, desert]][x][z]);
+        let b = Map::from_area_fn(area, |(x, z)| [[plains, river], [desert, forest]][x][z]);
+        let img = draw_diff_image(&a, &b);
+        assert_eq!((img.width, img.height), (2, 2));
+        assert_eq!(img.get_pixel(0, 0), [47, 59, 32, 255]);
+        assert_eq!(img.get_pixel(0, 1), [0, 0, 255, 255]);
+        assert_eq!(img.get_pixel(1, 0), [0, 0, 0, 0]);
+        assert_eq!(img.get_pixel(1, 1), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn biome_colors() {
+        use biome_id::*;
+        assert_eq!(biome_to_color(plains), [141, 179, 96, 255]);
+        // Mutated biomes use the color of the base biome + 40
+        assert_eq!(biome_to_color(sunflowerPlains), [181, 219, 136, 255]);
+        assert_eq!(biome_to_color(desertLakes), [255, 188, 64, 255]);
+        // 1.18 biomes are hardcoded
+        assert_eq!(biome_to_color(174), [78, 48, 18, 255]);
+        assert_eq!(biome_to_color(193), [0x83, 0x38, 0x06, 255]);
+    }
+
+    #[test]
+    fn treasure_colors() {
+        // Transparent map color, using each of the 4 variants
+        assert_eq!(treasure_map_to_color(0), [154, 139, 121, 255]);
+        assert_eq!(treasure_map_to_color(2), [218, 197, 171, 255]);
+        assert_eq!(color_variant(255, 0, 128, 3), (134, 0, 68));
+    }
+
+    // Golden images: the hash of each rendered image is stored in `GOLDEN_IMAGES_PATH`. A changed
+    // or missing hash fails the test, unless it is run with `UPDATE_GOLDEN_IMAGES=1`, which
+    // writes the new hashes to the file after an intentional change of the colors.
+    const GOLDEN_IMAGES_PATH: &str = "golden_tests/images.txt";
+
+    // FNV-1a, the hash only needs to be stable across platforms
+    fn image_hash(width: usize, height: usize, data: &[u8]) -> String {
+        let mut h: u64 = 0xcbf29ce484222325;
+        for b in (width as u64).to_le_bytes().iter().chain(&(height as u64).to_le_bytes()).chain(data) {
+            h ^= u64::from(*b);
+            h = h.wrapping_mul(0x100000001b3);
+        }
+
+        format!("{}x{} {:016x}", width, height, h)
+    }
+
+    #[test]
+    fn golden_images() {
+        let mut images = vec![];
+        // Mostly ocean, where 1.13 adds the ocean variants
+        let area = Area { x: 900, z: -60, w: 200, h: 120 };
+        for &(version, seed) in &[(MinecraftVersion::Java1_7, 1234), (MinecraftVersion::Java1_13, 1234), (MinecraftVersion::Java1_15, -5678)] {
+            let img = draw_map_image(&generate(version, area, seed, 0));
+            images.push((format!("draw_map_image {:?} {}", version, seed), image_hash(img.width, img.height, &img.data)));
+            // Treasure maps only exist since 1.13
+            if version != MinecraftVersion::Java1_7 {
+                let img = draw_treasure_map_image(&generate_fragment_treasure_map(version, area, seed));
+                images.push((format!("draw_treasure_map_image {:?} {}", version, seed), image_hash(img.width, img.height, &img.data)));
+            }
+        }
+        assert_ne!(images[0].1, images[1].1, "Java1_7 and Java1_13 should render different images");
+        let version = MinecraftVersion::Java1_13;
+        let data = generate_image_treasure_map_at(version, 1234, 3, -2);
+        images.push((format!("generate_image_treasure_map_at {:?} 1234", version), image_hash(128, 128, &data)));
+        let version = MinecraftVersion::Java1_7;
+        for &layer in &[1, 22, 30] {
+            let map = generate_up_to_layer(version, area, 1234, layer, 0);
+            let data = draw_layer_image(&map, version, layer);
+            images.push((format!("draw_layer_image {:?} 1234 layer {}", version, layer), image_hash(map.a.dim().0, map.a.dim().1, &data)));
+        }
+
+        let update = std::env::var_os("UPDATE_GOLDEN_IMAGES").is_some();
+        let stored = std::fs::read_to_string(GOLDEN_IMAGES_PATH).unwrap_or_default();
+        // Format: one image per line, "name: WxH hash"
+        let mut golden: Vec<(String, String)> = stored.lines().filter(|l| !l.is_empty() && !l.starts_with('#')).map(|l| {
+            let (name, hash) = l.split_at(l.rfind(": ").expect("Invalid golden image line"));
+            (name.to_string(), hash[2..].to_string())
+        }).collect();
+        let mut changed = false;
+        let mut mismatches = vec![];
+        for (name, hash) in images {
+            match golden.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, h)) if *h == hash => {}
+                Some((_, h)) if update => {
+                    *h = hash;
+                    changed = true;
+                }
+                Some((_, h)) => mismatches.push(format!("{}: expected {}, got {}", name, h, hash)),
+                None if update => {
+                    golden.push((name, hash));
+                    changed = true;
+                }
+                // A missing hash would make the test pass without checking anything
+                None => mismatches.push(format!("{}: missing, got {}", name, hash)),
+            }
+        }
+        if changed {
+            let lines: Vec<String> = golden.iter().map(|(n, h)| format!("{}: {}", n, h)).collect();
+            let header = "# Hashes of the images rendered by the golden_images test in src/biome_layers.rs\n";
+            std::fs::write(GOLDEN_IMAGES_PATH, format!("{}{}\n", header, lines.join("\n"))).unwrap();
+        }
+
+        assert!(mismatches.is_empty(), "Rendered images changed, run with UPDATE_GOLDEN_IMAGES=1 if this is intended:\n{}", mismatches.join("\n"));
+    }
 }
@@ -0,0 +1,252 @@
+//! Classification of biomes
+//!
+//! Predicates used by the biome layers to decide which biomes are similar, and the temperature
+//! category of each biome. These are also useful to validate evidence, so they are public.
+
+use crate::biome_info::biome_id;
+use crate::biome_info::BIOME_INFO;
+use crate::seed_info::MinecraftVersion;
+
+/// Category of the biome, used by the biome layers to decide if two biomes are similar. Returns
+/// `None` for unknown biomes. The category of the mesa plateaus depends on the version.
+pub fn get_category(v: MinecraftVersion, id: i32) -> Option<i32> {
+    use biome_id::*;
+
+    let category = match id {
+        ocean | frozenOcean | deepOcean | warmOcean | lukewarmOcean | coldOcean | warmDeepOcean
+        | lukewarmDeepOcean | coldDeepOcean | frozenDeepOcean => Ocean,
+        plains | sunflowerPlains => Plains,
+        desert | desertHills | desertLakes => Desert,
+        extremeHills
+        | extremeHillsEdge
+        | extremeHillsPlus
+        | gravellyMountains
+        | modifiedGravellyMountains => Hills,
+        forest | forestHills | birchForest | birchForestHills | roofedForest | flowerForest
+        | tallBirchForest | tallBirchHills | darkForestHills => Forest,
+        taiga
+        | taigaHills
+        | coldTaiga
+        | coldTaigaHills
+        | megaTaiga
+        | megaTaigaHills
+        | taigaMountains
+        | snowyTaigaMountains
+        | giantSpruceTaiga
+        | giantSpruceTaigaHills => Taiga,
+        swampland | swampHills => Swamp,
+        river | frozenRiver => River,
+        hell => Hell,
+        sky | skyIslandLow | skyIslandMedium | skyIslandHigh | skyIslandBarren => Sky,
+        icePlains | iceMountains | iceSpikes => Snow,
+        mushroomIsland | mushroomIslandShore => MushroomIsland,
+        beach | coldBeach => Beach,
+        jungle | jungleHills | jungleEdge | modifiedJungle | modifiedJungleEdge | bambooJungle
+        | bambooJungleHills => Jungle,
+        stoneBeach => StoneBeach,
+        savanna | savannaPlateau | shatteredSavanna | shatteredSavannaPlateau => Savanna,
+        mesa | erodedBadlands | modifiedWoodedBadlandsPlateau | modifiedBadlandsPlateau => Mesa,
+        mesaPlateau_F | mesaPlateau => {
+            if v.quirks().mesa_plateau_own_category {
+                mesaPlateau
+            } else {
+                Mesa
+            }
+        }
+        _ => return None,
+    };
+
+    Some(category)
+}
+
+/// Returns true if the biome id is one of the biomes that existed before 1.18
+pub fn biome_exists(id: i32) -> bool {
+    if (0..=0xFF).contains(&id) {
+        BIOME_INFO[id as usize].id & (!0xFF) == 0
+    } else {
+        false
+    }
+}
+
+/// Returns true for all the ocean biomes, including deep oceans and the 1.13 ocean variants
+pub fn is_oceanic(id: i32) -> bool {
+    use biome_id::*;
+    match id {
+        ocean | deepOcean | warmOcean | warmDeepOcean | lukewarmOcean | lukewarmDeepOcean
+        | coldOcean | coldDeepOcean | frozenOcean | frozenDeepOcean => true,
+        _ => false,
+    }
+}
+
+/// Returns true for the oceans that are not deep
+pub fn is_shallow_ocean(id: i32) -> bool {
+    use biome_id::*;
+    match id {
+        ocean | warmOcean | lukewarmOcean | coldOcean | frozenOcean => true,
+        _ => false,
+    }
+}
+
+/// Returns true for the deep oceans
+pub fn is_deep_ocean(id: i32) -> bool {
+    use biome_id::*;
+    match id {
+        deepOcean | warmDeepOcean | lukewarmDeepOcean | coldDeepOcean | frozenDeepOcean => true,
+        _ => false,
+    }
+}
+
+/// Returns true for all the mesa (badlands) biomes
+pub fn is_mesa(id: i32) -> bool {
+    use biome_id::*;
+    match id {
+        mesa
+        | mesaPlateau_F
+        | mesaPlateau
+        | erodedBadlands
+        | modifiedWoodedBadlandsPlateau
+        | modifiedBadlandsPlateau => true,
+        _ => false,
+    }
+}
+
+/// Returns true for the jungle, forest, taiga and ocean biomes. A jungle surrounded by other
+/// biomes becomes a jungle edge.
+#[allow(non_snake_case)]
+pub fn is_biome_JFTO(id: i32) -> bool {
+    use biome_id::*;
+    biome_exists(id)
+        && (get_category(MinecraftVersion::Java1_16, id) == Some(Jungle)
+            || id == forest
+            || id == taiga
+            || is_oceanic(id))
+}
+
+/// Returns true if it snows in this biome
+pub fn is_biome_snowy(id: i32) -> bool {
+    biome_exists(id) && BIOME_INFO[(id & 0xff) as usize].temp < 0.1
+}
+
+/// Returns true if `id1` and `id2` have the same category. This is not symmetric, because it
+/// simulates a bug in the Java code: some mutated biomes are only equal to themselves.
+pub fn equal_or_plateau(version: MinecraftVersion, id1: i32, id2: i32) -> bool {
+    use biome_id::*;
+    if id1 == id2 {
+        return true;
+    }
+    if id1 == mesaPlateau_F || id1 == mesaPlateau {
+        return id2 == mesaPlateau_F || id2 == mesaPlateau;
+    }
+    if !biome_exists(id1) || !biome_exists(id2) {
+        return false;
+    }
+    // adjust for asymmetric equality (workaround to simulate a bug in the MC java code)
+    if id1 >= 128 || id2 >= 128 {
+        // skip biomes that did not overload the isEqualTo() method
+        if id2 == 130
+            || id2 == 133
+            || id2 == 134
+            || id2 == 149
+            || id2 == 151
+            || id2 == 155
+            || id2 == 156
+            || id2 == 157
+            || id2 == 158
+            || id2 == 163
+            || id2 == 164
+        {
+            return false;
+        }
+    }
+
+    get_category(version, id1) == get_category(version, id2)
+}
+
+/// Returns true if this biome exists in this version. Always false since 1.18, which renamed many
+/// biomes.
+pub fn biome_exists_in_version(version: MinecraftVersion, id: i32) -> bool {
+    use biome_id::*;
+    use MinecraftVersion::*;
+    if !biome_exists(id) || version >= Java1_18 {
+        return false;
+    }
+    match id {
+        skyIslandLow | skyIslandMedium | skyIslandHigh | skyIslandBarren => version >= Java1_13,
+        jungle | jungleHills => version >= Java1_2,
+        warmOcean | lukewarmOcean | coldOcean | warmDeepOcean | lukewarmDeepOcean
+        | coldDeepOcean | frozenDeepOcean => version >= Java1_13,
+        bambooJungle | bambooJungleHills => version >= Java1_14,
+        // All the biomes added in 1.7, including the mutated biomes
+        _ if id > jungleHills => version >= Java1_7,
+        _ => true,
+    }
+}
+
+/// Temperature category of the biome in this version: `Oceanic`, `Warm`, `Lush` or `Cold`, see
+/// `biome_id`. Returns `None` if the biome does not exist in this version.
+///
+/// This is the temperature used by the game to place structures, not the climate of the biome
+/// layers: for example, a frozen ocean is `Oceanic`.
+pub fn temperature_category(
+    version: MinecraftVersion,
+    id: i32,
+) -> Option<biome_id::BiomeTempCategory> {
+    if biome_exists_in_version(version, id) {
+        Some(BIOME_INFO[id as usize].tempCat)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_categories() {
+        use biome_id::*;
+        let v = MinecraftVersion::Java1_16;
+        assert_eq!(temperature_category(v, desert), Some(Warm));
+        assert_eq!(temperature_category(v, plains), Some(Lush));
+        assert_eq!(temperature_category(v, icePlains), Some(Cold));
+        assert_eq!(temperature_category(v, frozenOcean), Some(Oceanic));
+        assert_eq!(temperature_category(v, warmOcean), Some(Oceanic));
+        assert_eq!(temperature_category(v, -1), None);
+
+        // Biomes added in later versions
+        assert_eq!(
+            temperature_category(MinecraftVersion::Java1_7, warmOcean),
+            None
+        );
+        assert_eq!(
+            temperature_category(MinecraftVersion::Java1_13, bambooJungle),
+            None
+        );
+        assert_eq!(
+            temperature_category(MinecraftVersion::Java1_3, savanna),
+            None
+        );
+        assert_eq!(
+            temperature_category(MinecraftVersion::Java1_3, jungle),
+            Some(Lush)
+        );
+    }
+
+    #[test]
+    fn jfto_and_plateau() {
+        use biome_id::*;
+        assert!(is_biome_JFTO(jungleEdge));
+        assert!(is_biome_JFTO(deepOcean));
+        assert!(!is_biome_JFTO(plains));
+        assert!(is_biome_snowy(icePlains));
+        assert!(!is_biome_snowy(plains));
+
+        let v = MinecraftVersion::Java1_16;
+        assert!(equal_or_plateau(v, mesaPlateau, mesaPlateau_F));
+        assert!(equal_or_plateau(v, forest, birchForest));
+        assert!(!equal_or_plateau(v, forest, plains));
+        // Not symmetric
+        assert!(equal_or_plateau(v, desertLakes, desert));
+        assert!(!equal_or_plateau(v, desert, desertLakes));
+    }
+}
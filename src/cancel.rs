@@ -0,0 +1,88 @@
+//! Stop long searches before they are done
+//!
+//! The cancellable search functions check a `CancelToken` in their outer loops, and when it is
+//! cancelled they return the results found so far, marked as truncated. This is needed in wasm,
+//! where a long search freezes the tab, but it can also be used to implement a timeout.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Cancellation flag shared between the search and the caller. Clones share the same flag.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    check: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+}
+
+impl CancelToken {
+    /// A token that is only cancelled by calling `cancel`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is also cancelled when `f` returns true. The function is called often, so it
+    /// should be fast.
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        Self {
+            flag: Arc::default(),
+            check: Some(Arc::new(f)),
+        }
+    }
+
+    /// A token that is cancelled after `deadline`. Not available in wasm, because `Instant` is
+    /// not supported there: use `from_fn` with a javascript timer instead.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self::from_fn(move || Instant::now() >= deadline)
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        if self.flag.load(Ordering::Relaxed) {
+            return true;
+        }
+        match &self.check {
+            Some(f) if f() => {
+                // Remember the result, the function does not need to be called again
+                self.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Results of a search that may have been cancelled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partial<T> {
+    pub results: Vec<T>,
+    /// True if the search was cancelled before checking all the seeds, so `results` may be
+    /// missing some seeds
+    pub truncated: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_clones() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+
+        let token = CancelToken::from_fn(|| true);
+        assert!(token.is_cancelled());
+        let token = CancelToken::with_deadline(Instant::now());
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,332 @@
+//! Export the candidates found by the seed finders as CSV or JSON lines
+//!
+//! CSV files can be opened in a spreadsheet, and both formats can be imported again to rank the
+//! candidates using new evidence, without repeating the bruteforce. The CSV format has a header
+//! line followed by one `seed,score,class,version` line per candidate, where the score is empty
+//! if unknown. The JSON lines format has one `ExportedCandidate` object per line, with the seed as
+//! a string.
+
+use crate::biome_layers::score_seed_list_with_config;
+use crate::biome_layers::SearchConfig;
+use crate::biome_layers::SearchEvidence;
+use crate::biome_layers::SeedCandidate;
+use crate::java_rng::parse_seed;
+use crate::java_rng::JavaRng;
+use crate::seed_info::MinecraftVersion;
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+const CSV_HEADER: &str = "seed,score,class,version";
+
+/// How a world seed could have been created
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeedClass {
+    /// The seed can be the output of Java `Random.nextLong()`, so it can be a random seed
+    Random,
+    /// The seed fits in an i32, so it can be the hash code of a text seed
+    Text,
+    /// Any other seed, which can only be created by typing the number
+    Other,
+}
+
+impl SeedClass {
+    pub fn of(world_seed: i64) -> Self {
+        if JavaRng::create_from_long(world_seed as u64).is_some() {
+            SeedClass::Random
+        } else if i32::try_from(world_seed).is_ok() {
+            SeedClass::Text
+        } else {
+            SeedClass::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeedClass::Random => "random",
+            SeedClass::Text => "text",
+            SeedClass::Other => "other",
+        }
+    }
+}
+
+impl FromStr for SeedClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "random" => SeedClass::Random,
+            "text" => SeedClass::Text,
+            "other" => SeedClass::Other,
+            _ => return Err(s.to_string()),
+        })
+    }
+}
+
+/// One candidate of an exported file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedCandidate {
+    #[serde(with = "crate::seed_info::seed_string")]
+    pub seed: i64,
+    /// Score of the seed finder, as a percentage of the maximum score. `None` if the seed finder
+    /// does not compute a score
+    pub score: Option<u32>,
+    pub class: SeedClass,
+    /// Minecraft version used to check the candidate, in the same format as `SeedInfo::version`
+    pub version: String,
+}
+
+impl ExportedCandidate {
+    pub fn new(seed: i64, score: Option<u32>, version: &str) -> Self {
+        Self {
+            seed,
+            score,
+            class: SeedClass::of(seed),
+            version: version.to_string(),
+        }
+    }
+
+    pub fn from_seed_candidate(c: &SeedCandidate, version: &str) -> Self {
+        Self::new(c.world_seed, Some(c.score), version)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    /// Format of a file with this path: CSV if the extension is ".csv", JSON lines otherwise
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::JsonLines,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "csv" => ExportFormat::Csv,
+            "jsonl" => ExportFormat::JsonLines,
+            _ => return Err(s.to_string()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    /// Line number (starting at 1) and contents of a line that could not be parsed
+    Parse(usize, String),
+    /// Version of a candidate that is not supported
+    Version(String),
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(x: std::io::Error) -> Self {
+        ExportError::Io(x)
+    }
+}
+
+/// Write the candidates using the given format
+pub fn write_candidates<W: Write>(
+    mut w: W,
+    candidates: &[ExportedCandidate],
+    format: ExportFormat,
+) -> Result<(), std::io::Error> {
+    match format {
+        ExportFormat::Csv => {
+            writeln!(w, "{}", CSV_HEADER)?;
+            for c in candidates {
+                let score = c.score.map(|s| s.to_string()).unwrap_or_default();
+                writeln!(w, "{},{},{},{}", c.seed, score, c.class.as_str(), c.version)?;
+            }
+        }
+        ExportFormat::JsonLines => {
+            for c in candidates {
+                serde_json::to_writer(&mut w, c)?;
+                writeln!(w)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_csv_line(line: &str) -> Option<ExportedCandidate> {
+    let mut parts = line.split(',').map(|x| x.trim());
+    let seed = parse_seed(parts.next()?).ok()?;
+    let score = match parts.next()? {
+        "" => None,
+        x => Some(x.parse().ok()?),
+    };
+    // The class can always be calculated from the seed, so an edited class is ignored
+    let _class: SeedClass = parts.next()?.parse().ok()?;
+    let version = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(ExportedCandidate::new(seed, score, version))
+}
+
+/// Read the candidates written by `write_candidates`. The format is detected for each line: lines
+/// starting with `{` are JSON, and the other lines are CSV. Empty lines and the CSV header are
+/// ignored.
+pub fn read_candidates<R: BufRead>(r: R) -> Result<Vec<ExportedCandidate>, ExportError> {
+    let mut candidates = vec![];
+
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line == CSV_HEADER {
+            continue;
+        }
+        let c = if line.starts_with('{') {
+            serde_json::from_str(line).ok()
+        } else {
+            parse_csv_line(line)
+        };
+        match c {
+            Some(c) => candidates.push(c),
+            None => return Err(ExportError::Parse(i + 1, line.to_string())),
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Check the candidates again using new evidence, with the same criteria as the last step of
+/// `river_seed_finder_with_config`. Each candidate is checked using its own version. Returns the
+/// accepted candidates with the new score, sorted by score, best first.
+pub fn rerank(
+    candidates: &[ExportedCandidate],
+    evidence: &SearchEvidence,
+    config: &SearchConfig,
+) -> Result<Vec<ExportedCandidate>, ExportError> {
+    let mut versions: Vec<&str> = candidates.iter().map(|c| c.version.as_str()).collect();
+    versions.sort_unstable();
+    versions.dedup();
+
+    let mut ranked = vec![];
+    for version_str in versions {
+        let version: MinecraftVersion = version_str
+            .parse()
+            .map_err(|_| ExportError::Version(version_str.to_string()))?;
+        let seeds = candidates
+            .iter()
+            .filter(|c| c.version == version_str)
+            .map(|c| c.seed);
+        ranked.extend(
+            score_seed_list_with_config(seeds, evidence, version, config)
+                .iter()
+                .map(|c| ExportedCandidate::from_seed_candidate(c, version_str)),
+        );
+    }
+    ranked.sort_by_key(|c| (std::cmp::Reverse(c.score), c.seed));
+
+    Ok(ranked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_candidates() -> Vec<ExportedCandidate> {
+        vec![
+            ExportedCandidate::new(1234, Some(95), "1.7"),
+            ExportedCandidate::new(-3587056394556294711, None, "1.16.1"),
+            ExportedCandidate::new(i64::MIN, Some(100), "1.15"),
+        ]
+    }
+
+    #[test]
+    fn seed_class() {
+        assert_eq!(SeedClass::of(1234), SeedClass::Text);
+        assert_eq!(SeedClass::of(i64::MIN), SeedClass::Other);
+        let random_seed = JavaRng::with_seed(1).next_long();
+        assert_eq!(SeedClass::of(random_seed), SeedClass::Random);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let candidates = example_candidates();
+        let mut buf = vec![];
+        write_candidates(&mut buf, &candidates, ExportFormat::Csv).unwrap();
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert!(
+            text.starts_with("seed,score,class,version\n1234,95,text,1.7\n"),
+            "{}",
+            text
+        );
+        assert_eq!(read_candidates(&buf[..]).unwrap(), candidates);
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let candidates = example_candidates();
+        let mut buf = vec![];
+        write_candidates(&mut buf, &candidates, ExportFormat::JsonLines).unwrap();
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        // Seeds are strings, javascript numbers lose precision above 2^53
+        assert!(text.starts_with("{\"seed\":\"1234\","), "{}", text);
+        assert_eq!(read_candidates(&buf[..]).unwrap(), candidates);
+    }
+
+    #[test]
+    fn read_errors() {
+        assert!(matches!(
+            read_candidates("seed,score,class,version\n1,,text\n".as_bytes()),
+            Err(ExportError::Parse(2, _))
+        ));
+        assert!(matches!(
+            read_candidates("{\"seed\": 1}\n".as_bytes()),
+            Err(ExportError::Parse(1, _))
+        ));
+        assert!(read_candidates("\n".as_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(ExportFormat::from_path("seeds.CSV"), ExportFormat::Csv);
+        assert_eq!(
+            ExportFormat::from_path("seeds.jsonl"),
+            ExportFormat::JsonLines
+        );
+        assert_eq!(ExportFormat::from_path("seeds"), ExportFormat::JsonLines);
+    }
+
+    #[test]
+    fn rerank_without_evidence_keeps_all() {
+        let candidates = example_candidates();
+        let ranked = rerank(
+            &candidates,
+            &SearchEvidence::default(),
+            &SearchConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(ranked.len(), candidates.len());
+        // Without evidence all the scores are 100, so the candidates are sorted by seed
+        assert!(ranked.iter().all(|c| c.score == Some(100)));
+        assert_eq!(ranked[0].seed, i64::MIN);
+        let bad_version = vec![ExportedCandidate::new(1, None, "beta")];
+        assert!(matches!(
+            rerank(
+                &bad_version,
+                &SearchEvidence::default(),
+                &SearchConfig::default()
+            ),
+            Err(ExportError::Version(_))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+//! Population RNG used to generate ores and decorations, equivalent to `ChunkRandom` in
+//! Minecraft Java >= 1.13.
+//!
+//! The population seed depends on the world seed and the block coordinates of the chunk. Each
+//! feature then seeds its own RNG using the population seed, the index of the feature and the
+//! generation step.
+
+use crate::java_rng::mask;
+use crate::java_rng::JavaRng;
+
+/// Calculate the population seed of the chunk whose first block is at (block_x, block_z).
+/// Equivalent to `setDecorationSeed`. Only the lower 48 bits of the result are meaningful.
+pub fn population_seed(world_seed: i64, block_x: i32, block_z: i32) -> u64 {
+    let mut r = JavaRng::with_seed(world_seed as u64);
+    let a = r.next_long() | 1;
+    let b = r.next_long() | 1;
+
+    // (x * a + z * b) ^ world_seed
+    (((block_x as i64)
+        .wrapping_mul(a)
+        .wrapping_add((block_z as i64).wrapping_mul(b)))
+        ^ world_seed) as u64
+        & mask(48)
+}
+
+/// Calculate the seed used by the feature with this index in this generation step.
+/// Equivalent to `setFeatureSeed`. Only the lower 48 bits of the result are meaningful.
+pub fn feature_seed(pop_seed: u64, index: u32, step: u32) -> u64 {
+    pop_seed
+        .wrapping_add(u64::from(index))
+        .wrapping_add(10000 * u64::from(step))
+        & mask(48)
+}
+
+/// Returns a `JavaRng` initialized with the seed of this feature, ready to generate it.
+pub fn feature_rng(pop_seed: u64, index: u32, step: u32) -> JavaRng {
+    JavaRng::with_seed(feature_seed(pop_seed, index, step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::population::world_seed_to_chunk_population_seed_1_13;
+
+    #[test]
+    fn population_seed_same_as_1_13() {
+        let world_seed = 1234;
+        for (x, z) in [(0, 0), (16, -32), (-160, 48)] {
+            assert_eq!(
+                population_seed(world_seed, x, z),
+                world_seed_to_chunk_population_seed_1_13(world_seed, x, z) & mask(48)
+            );
+        }
+    }
+
+    #[test]
+    fn feature_seed_steps() {
+        let pop_seed = population_seed(1234, 16, 16);
+        assert_eq!(feature_seed(pop_seed, 0, 0), pop_seed);
+        assert_eq!(feature_seed(pop_seed, 3, 2), (pop_seed + 20003) & mask(48));
+        assert_eq!(
+            feature_rng(pop_seed, 3, 2).get_seed(),
+            feature_seed(pop_seed, 3, 2)
+        );
+        // Overflow wraps around the 48 bits
+        assert_eq!(feature_seed(mask(48), 1, 0), 0);
+    }
+}
@@ -0,0 +1,238 @@
+//! Import evidence from lists of labeled coordinates
+//!
+//! Evidence gathered by several players is often collected as a spreadsheet of coordinates with
+//! a label, for example `120,64,-300,river`. Each line has the block coordinates and a label,
+//! separated by commas, semicolons or whitespace: either `x,y,z,label` or `x,z,label`, where the
+//! y coordinate is ignored. Empty lines, lines starting with `#`, and a header line that does not
+//! start with a number are ignored.
+//!
+//! The label can be a biome name, such as "river" or "warm_ocean", or a structure, such as
+//! "village" or "slime_chunk". Structures with a `not_` prefix, like "not_slime_chunk", are added
+//! as negative evidence.
+//!
+//! Litematica material lists only contain block counts, without coordinates, so they cannot be
+//! imported. Use the coordinates of the schematic placement instead.
+
+use crate::biome_info::biome_id_from_name;
+use crate::chunk::Chunk;
+use crate::chunk::Point;
+use crate::seed_info::BiomeId;
+use crate::seed_info::SeedInfo;
+use crate::seed_info::SeedStructures;
+use std::io::BufRead;
+
+#[derive(Debug)]
+pub enum CoordinateListError {
+    Io(std::io::Error),
+    /// Line number (starting at 1) and contents of a line that could not be parsed
+    Parse(usize, String),
+    /// Line number (starting at 1) and label that is not a biome or a structure
+    UnknownLabel(usize, String),
+}
+
+impl From<std::io::Error> for CoordinateListError {
+    fn from(x: std::io::Error) -> Self {
+        CoordinateListError::Io(x)
+    }
+}
+
+/// One line of a coordinate list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabeledPoint {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub x: i64,
+    pub y: Option<i64>,
+    pub z: i64,
+    pub label: String,
+}
+
+fn parse_line(line: &str) -> Option<(i64, Option<i64>, i64, String)> {
+    let parts: Vec<&str> = line
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .collect();
+    // The label can contain spaces, so it is everything after the coordinates
+    let num_coords = parts
+        .iter()
+        .take(3)
+        .take_while(|x| x.parse::<i64>().is_ok())
+        .count();
+    if num_coords < 2 || parts.len() <= num_coords {
+        return None;
+    }
+    let coords: Vec<i64> = parts[..num_coords]
+        .iter()
+        .map(|x| x.parse().unwrap())
+        .collect();
+    let label = parts[num_coords..].join(" ");
+
+    Some(match coords[..] {
+        [x, z] => (x, None, z, label),
+        [x, y, z] => (x, Some(y), z, label),
+        _ => unreachable!(),
+    })
+}
+
+/// Read a coordinate list
+pub fn read_coordinate_list<R: BufRead>(r: R) -> Result<Vec<LabeledPoint>, CoordinateListError> {
+    let mut points = vec![];
+
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (x, y, z, label) = match parse_line(line) {
+            Some(x) => x,
+            // Allow a header in the first line
+            None if i == 0 => continue,
+            None => return Err(CoordinateListError::Parse(i + 1, line.to_string())),
+        };
+        points.push(LabeledPoint {
+            line: i + 1,
+            x,
+            y,
+            z,
+            label,
+        });
+    }
+
+    Ok(points)
+}
+
+fn normalize_label(label: &str) -> String {
+    let label = label.trim().to_lowercase();
+    let label = label.strip_prefix("minecraft:").unwrap_or(&label);
+
+    label.replace(|c: char| c == ' ' || c == '-', "_")
+}
+
+/// List of `SeedStructures` that stores this kind of structure
+fn structure_list<'a>(s: &'a mut SeedStructures, label: &str) -> Option<&'a mut Vec<Chunk>> {
+    Some(match label {
+        "slime_chunk" => &mut s.slime_chunks,
+        "mineshaft" => &mut s.mineshafts,
+        "nether_fort" | "nether_fortress" | "fortress" => &mut s.nether_forts,
+        "stronghold" => &mut s.strongholds,
+        "desert_temple" | "desert_pyramid" => &mut s.desert_temples,
+        "jungle_temple" | "jungle_pyramid" => &mut s.jungle_temples,
+        "witch_hut" | "swamp_hut" => &mut s.witch_huts,
+        "village" => &mut s.villages,
+        "ocean_monument" | "monument" => &mut s.ocean_monuments,
+        "igloo" => &mut s.igloos,
+        "woodland_mansion" | "mansion" => &mut s.woodland_mansions,
+        "end_city" => &mut s.end_cities,
+        "ocean_ruin" => &mut s.ocean_ruins,
+        "shipwreck" => &mut s.shipwrecks,
+        "buried_treasure" => &mut s.buried_treasures,
+        "pillager_outpost" => &mut s.pillager_outposts,
+        _ => return None,
+    })
+}
+
+/// Add the points of a coordinate list to a `SeedInfo`. Biomes are added to `biomes`, and
+/// structures are added to `positive` or `negative` using the chunk that contains the point.
+pub fn add_to_seed_info(
+    points: &[LabeledPoint],
+    seed_info: &mut SeedInfo,
+) -> Result<(), CoordinateListError> {
+    for p in points {
+        let label = normalize_label(&p.label);
+        let (structures, structure) = match label.strip_prefix("not_") {
+            Some(structure) => (&mut seed_info.negative, structure),
+            None => (&mut seed_info.positive, label.as_str()),
+        };
+        if let Some(list) = structure_list(structures, structure) {
+            let chunk = match (i32::try_from(p.x >> 4), i32::try_from(p.z >> 4)) {
+                (Ok(x), Ok(z)) => Chunk::new(x, z),
+                _ => return Err(CoordinateListError::Parse(p.line, p.label.clone())),
+            };
+            if !list.contains(&chunk) {
+                list.push(chunk);
+            }
+        } else if let Some(biome_id) = biome_id_from_name(&label) {
+            let point = Point { x: p.x, z: p.z };
+            let biome_points = seed_info.biomes.entry(BiomeId(biome_id)).or_default();
+            if !biome_points.contains(&point) {
+                biome_points.push(point);
+            }
+        } else {
+            return Err(CoordinateListError::UnknownLabel(p.line, p.label.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a coordinate list and add it to a `SeedInfo`
+pub fn read_into_seed_info<R: BufRead>(
+    r: R,
+    seed_info: &mut SeedInfo,
+) -> Result<(), CoordinateListError> {
+    let points = read_coordinate_list(r)?;
+
+    add_to_seed_info(&points, seed_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_info::biome_id;
+
+    #[test]
+    fn read_list() {
+        let list = "x,y,z,label\n120,64,-300,river\n\n# comment\n-17 8 Warm Ocean\n5;70;6;minecraft:village\n";
+        let points = read_coordinate_list(list.as_bytes()).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(
+            points[0],
+            LabeledPoint {
+                line: 2,
+                x: 120,
+                y: Some(64),
+                z: -300,
+                label: "river".to_string()
+            }
+        );
+        assert_eq!(points[1].y, None);
+        assert_eq!(points[1].label, "Warm Ocean");
+
+        let mut seed_info = SeedInfo::default();
+        add_to_seed_info(&points, &mut seed_info).unwrap();
+        assert_eq!(
+            seed_info.biomes[&BiomeId(biome_id::river)],
+            vec![Point { x: 120, z: -300 }]
+        );
+        assert_eq!(
+            seed_info.biomes[&BiomeId(biome_id::warmOcean)],
+            vec![Point { x: -17, z: 8 }]
+        );
+        assert_eq!(seed_info.positive.villages, vec![Chunk::new(0, 0)]);
+    }
+
+    #[test]
+    fn structures() {
+        let list = "-1,-5,slime_chunk\n-16,-1,Slime Chunk\n40,-20,not_slime_chunk\n0,0,swamp-hut\n";
+        let mut seed_info = SeedInfo::default();
+        read_into_seed_info(list.as_bytes(), &mut seed_info).unwrap();
+        assert_eq!(seed_info.positive.slime_chunks, vec![Chunk::new(-1, -1)]);
+        assert_eq!(seed_info.negative.slime_chunks, vec![Chunk::new(2, -2)]);
+        assert_eq!(seed_info.positive.witch_huts, vec![Chunk::new(0, 0)]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut seed_info = SeedInfo::default();
+        assert!(matches!(
+            read_into_seed_info("0,0,river\n1,2,ancient_city\n".as_bytes(), &mut seed_info),
+            Err(CoordinateListError::UnknownLabel(2, _))
+        ));
+        assert!(matches!(
+            read_coordinate_list("0,0,river\n1,river\n".as_bytes()),
+            Err(CoordinateListError::Parse(2, _))
+        ));
+        assert!(read_coordinate_list("".as_bytes()).unwrap().is_empty());
+    }
+}
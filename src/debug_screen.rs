@@ -0,0 +1,173 @@
+//! Parse biome evidence copied from the debug screen (F3)
+//!
+//! The debug screen shows the position of the player and the biome, for example:
+//!
+//! ```text
+//! XYZ: 123.456 / 64.00000 / -789.012
+//! Block: 123 64 -790
+//! Biome: minecraft:plains
+//! ```
+//!
+//! Pressing F3+C copies the position to the clipboard as a command instead:
+//!
+//! ```text
+//! /execute in minecraft:overworld run tp @s 123.46 64.00 -789.01 12.34 56.78
+//! ```
+//!
+//! Each biome line is paired with the last position before it, or with the next position if
+//! the biome comes first, so the lines of many screens can be pasted together.
+
+use crate::biome_info::biome_id_for_version;
+use crate::biome_info::biome_id_from_name;
+use crate::chunk::Point;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DebugScreenError {
+    /// Line number (starting at 1) and name of a biome that does not exist
+    UnknownBiome(usize, String),
+    /// Line number (starting at 1) of a position or biome that could not be parsed
+    Parse(usize, String),
+}
+
+enum DebugLine {
+    Position(Point),
+    Biome(i32),
+}
+
+// Block coordinate from a floating point player coordinate
+fn parse_coord(x: &str) -> Option<i64> {
+    let x: f64 = x.trim().parse().ok()?;
+    if !x.is_finite() {
+        return None;
+    }
+
+    Some(x.floor() as i64)
+}
+
+// i: line number, starting at 1
+fn parse_line(i: usize, line: &str) -> Result<Option<DebugLine>, DebugScreenError> {
+    let parse_error = || DebugScreenError::Parse(i, line.to_string());
+    let line = line.trim();
+    if let Some(xyz) = line.strip_prefix("XYZ:") {
+        let mut parts = xyz.split('/');
+        let x = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let _y = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let z = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+
+        return Ok(Some(DebugLine::Position(Point { x, z })));
+    }
+    if let Some(block) = line.strip_prefix("Block:") {
+        let mut parts = block.split_whitespace();
+        let x = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let _y = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let z = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+
+        return Ok(Some(DebugLine::Position(Point { x, z })));
+    }
+    if line.starts_with("/execute") || line.starts_with("/tp") {
+        // The coordinates are the 3 numbers after the target selector
+        let mut parts = line
+            .split_whitespace()
+            .skip_while(|x| !x.starts_with('@'))
+            .skip(1);
+        let x = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let _y = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+        let z = parts.next().and_then(parse_coord).ok_or_else(parse_error)?;
+
+        return Ok(Some(DebugLine::Position(Point { x, z })));
+    }
+    if let Some(name) = line.strip_prefix("Biome:") {
+        let id = biome_id_from_name(name)
+            .ok_or_else(|| DebugScreenError::UnknownBiome(i, name.trim().to_string()))?;
+
+        return Ok(Some(DebugLine::Biome(id)));
+    }
+
+    // Other lines of the debug screen are ignored
+    Ok(None)
+}
+
+/// Extract the biomes and their positions from text copied from the debug screen. Lines that
+/// are not a position or a biome are ignored.
+pub fn parse_debug_screen(
+    text: &str,
+    version: MinecraftVersion,
+) -> Result<Vec<(BiomeId, Point)>, DebugScreenError> {
+    let mut biomes = vec![];
+    let mut last_position = None;
+    let mut pending_biome = None;
+
+    for (i, line) in text.lines().enumerate() {
+        match parse_line(i + 1, line)? {
+            Some(DebugLine::Position(p)) => {
+                if let Some(biome) = pending_biome.take() {
+                    biomes.push((biome, p));
+                } else {
+                    last_position = Some(p);
+                }
+            }
+            Some(DebugLine::Biome(id)) => {
+                let biome = BiomeId(biome_id_for_version(id, version));
+                if let Some(p) = last_position.take() {
+                    biomes.push((biome, p));
+                } else {
+                    pending_biome = Some(biome);
+                }
+            }
+            None => {}
+        }
+    }
+
+    Ok(biomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_info::biome_id;
+
+    #[test]
+    fn debug_screen_lines() {
+        let text = "Minecraft 1.16.5 (1.16.5/vanilla)\n\
+                    XYZ: -123.700 / 64.00000 / 45.300\n\
+                    Block: -124 64 45\n\
+                    Chunk: 4 0 13 in -8 4 2\n\
+                    Biome: minecraft:plains\n\
+                    \n\
+                    Biome: minecraft:snowy_tundra\n\
+                    /execute in minecraft:overworld run tp @s 10.50 70.00 -3.20 90.00 0.00\n";
+        let biomes = parse_debug_screen(text, MinecraftVersion::Java1_16).unwrap();
+        assert_eq!(
+            biomes,
+            vec![
+                (BiomeId(biome_id::plains), Point { x: -124, z: 45 }),
+                (BiomeId(biome_id::icePlains), Point { x: 10, z: -4 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_screen_errors() {
+        assert_eq!(
+            parse_debug_screen("XYZ: 1 / 2\nBiome: plains", MinecraftVersion::Java1_16),
+            Err(DebugScreenError::Parse(1, "XYZ: 1 / 2".to_string()))
+        );
+        assert_eq!(
+            parse_debug_screen(
+                "Block: 1 2 3\nBiome: minecraft:not_a_biome",
+                MinecraftVersion::Java1_16
+            ),
+            Err(DebugScreenError::UnknownBiome(
+                2,
+                "minecraft:not_a_biome".to_string()
+            ))
+        );
+        // A position without a biome is ignored
+        assert_eq!(
+            parse_debug_screen("Block: 1 2 3", MinecraftVersion::Java1_16),
+            Ok(vec![])
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Synthetic seed infos generated from a real seed, for demos and end-to-end tests
+//!
+//! The rivers and biomes are taken from the world generated by the seed, optionally with some
+//! observation errors added to them, like the ones made by a human copying coordinates. The same
+//! arguments always return the same `SeedInfo`, so frontends can ship an interactive demo and
+//! tests can check that the finders recover the seed.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers;
+use crate::biome_layers::Area;
+use crate::chunk::Point;
+use crate::java_rng::JavaRng;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+use crate::seed_info::SeedInfo;
+use crate::slime::generate_slime_chunks_and_not;
+
+/// Settings of `demo_world_with_config`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DemoWorldConfig {
+    /// Minecraft version used to generate the world, for example "1.7"
+    pub mc_version: String,
+    /// Where to start looking for rivers, in block coordinates
+    pub center: Point,
+    /// Side of the square region with rivers, in blocks
+    pub river_area_size: u64,
+    /// A region is only used if it has at least this many river blocks
+    pub min_river_blocks: usize,
+    /// Number of blocks of other biomes, sampled at random
+    pub num_biome_samples: usize,
+    /// Maximum distance from `center` of the biome samples, in blocks
+    pub biome_sample_radius: i64,
+    pub num_slime_chunks: usize,
+    pub num_non_slime_chunks: usize,
+    /// Probability that each river block is missing, and that each biome sample is reported at
+    /// the wrong coordinates, up to 4 blocks away
+    pub noise: f64,
+    /// Seed of the random number generator used to choose the samples and add the noise
+    pub rng_seed: u64,
+}
+
+impl Default for DemoWorldConfig {
+    fn default() -> Self {
+        Self {
+            mc_version: "1.7".to_string(),
+            center: Point { x: 0, z: 0 },
+            river_area_size: 256,
+            min_river_blocks: 400,
+            num_biome_samples: 20,
+            biome_sample_radius: 1000,
+            num_slime_chunks: 0,
+            num_non_slime_chunks: 0,
+            noise: 0.0,
+            rng_seed: 0,
+        }
+    }
+}
+
+// Maximum number of regions checked while looking for rivers
+const MAX_RIVER_AREAS: i64 = 64;
+// Maximum error of the coordinates of a noisy biome sample
+const MAX_SAMPLE_OFFSET: i32 = 4;
+
+/// Synthetic seed info of this world seed, using the default config
+pub fn demo_world(world_seed: i64) -> SeedInfo {
+    demo_world_with_config(world_seed, &DemoWorldConfig::default())
+        .expect("Default demo world config is valid")
+}
+
+/// Synthetic seed info of this world seed. The rivers are copied from the first region near
+/// `config.center` with enough rivers, looking at most 64 regions. Returns an error if the
+/// version is not valid.
+pub fn demo_world_with_config(
+    world_seed: i64,
+    config: &DemoWorldConfig,
+) -> Result<SeedInfo, String> {
+    let version: MinecraftVersion = config.mc_version.parse()?;
+    let mut r = JavaRng::with_seed(config.rng_seed);
+    let mut seed_info = SeedInfo::default();
+    seed_info.version = config.mc_version.clone();
+    seed_info.description = "Demo world".to_string();
+
+    let size = config.river_area_size as i64;
+    for i in 0..MAX_RIVER_AREAS {
+        // Check the regions in rows of 8, starting at the center
+        let area = Area {
+            x: config.center.x + (i % 8) * size,
+            z: config.center.z + (i / 8) * size,
+            w: config.river_area_size,
+            h: config.river_area_size,
+        };
+        let map = biome_layers::generate(version, area, world_seed, 0);
+        let rivers: Vec<Point> = map
+            .a
+            .indexed_iter()
+            .filter(|(_, b)| **b == biome_id::river)
+            .map(|((x, z), _)| Point {
+                x: area.x + x as i64,
+                z: area.z + z as i64,
+            })
+            .collect();
+        if rivers.len() >= config.min_river_blocks {
+            let rivers = rivers
+                .into_iter()
+                .filter(|_| r.next_double() >= config.noise)
+                .collect();
+            seed_info.biomes.insert(BiomeId(biome_id::river), rivers);
+            break;
+        }
+    }
+
+    let radius = i32::try_from(config.biome_sample_radius).unwrap_or(i32::MAX / 2);
+    for _ in 0..config.num_biome_samples {
+        let p = Point {
+            x: config.center.x + i64::from(r.next_int_n(2 * radius + 1) - radius),
+            z: config.center.z + i64::from(r.next_int_n(2 * radius + 1) - radius),
+        };
+        let area = Area {
+            x: p.x,
+            z: p.z,
+            w: 1,
+            h: 1,
+        };
+        let biome = biome_layers::generate(version, area, world_seed, 0).get(p.x, p.z);
+        if biome == biome_id::river {
+            // The rivers are already in a separate region
+            continue;
+        }
+        let p = if r.next_double() < config.noise {
+            let max = 2 * MAX_SAMPLE_OFFSET + 1;
+            Point {
+                x: p.x + i64::from(r.next_int_n(max) - MAX_SAMPLE_OFFSET),
+                z: p.z + i64::from(r.next_int_n(max) - MAX_SAMPLE_OFFSET),
+            }
+        } else {
+            p
+        };
+        seed_info.biomes.entry(BiomeId(biome)).or_default().push(p);
+    }
+
+    if config.num_slime_chunks > 0 || config.num_non_slime_chunks > 0 {
+        let (c, nc) = generate_slime_chunks_and_not(
+            world_seed,
+            config.num_slime_chunks,
+            config.num_non_slime_chunks,
+        );
+        seed_info.positive.slime_chunks = c;
+        seed_info.negative.slime_chunks = nc;
+    }
+
+    Ok(seed_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_layers::filter_seed_list;
+    use crate::evidence::search_evidence;
+
+    #[test]
+    fn deterministic() {
+        let world_seed = 1234;
+        let a = demo_world(world_seed);
+        assert_eq!(a, demo_world(world_seed));
+        assert!(a.biomes[&BiomeId(biome_id::river)].len() >= 400);
+        assert_ne!(a, demo_world(world_seed + 1));
+
+        // The evidence matches the seed used to create it
+        let evidence = search_evidence(&a);
+        let found = filter_seed_list(
+            vec![world_seed, world_seed + 1],
+            &evidence.rivers,
+            &evidence.extra_biomes,
+            MinecraftVersion::Java1_7,
+        );
+        assert_eq!(found, vec![world_seed]);
+    }
+
+    #[test]
+    fn noise() {
+        let world_seed = 1234;
+        let config = DemoWorldConfig {
+            noise: 0.2,
+            num_slime_chunks: 5,
+            ..Default::default()
+        };
+        let clean = demo_world(world_seed);
+        let noisy = demo_world_with_config(world_seed, &config).unwrap();
+        let rivers = |s: &SeedInfo| s.biomes[&BiomeId(biome_id::river)].len();
+        assert!(rivers(&noisy) < rivers(&clean));
+        assert_eq!(noisy.positive.slime_chunks.len(), 5);
+
+        let bad_version = DemoWorldConfig {
+            mc_version: "0.0".to_string(),
+            ..Default::default()
+        };
+        assert!(demo_world_with_config(world_seed, &bad_version).is_err());
+    }
+}
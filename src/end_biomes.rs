@@ -0,0 +1,289 @@
+//! Biomes of The End
+//!
+//! Since 1.13 the End has 5 biomes: the main island is `sky`, and the outer islands are
+//! `skyIslandHigh`, `skyIslandMedium`, `skyIslandLow` or `skyIslandBarren`, depending on the
+//! height of the terrain at the center of each chunk. The height only depends on a simplex noise
+//! initialized using the lower 48 bits of the world seed.
+//!
+//! In 1.13 and 1.14 each chunk has one biome. Since 1.15 the biomes are generated at 1:4 scale
+//! and then zoomed using the same voronoi zoom as the overworld at y = 0, which uses the hashed
+//! world seed. So the biomes of the End can be used to check the lower 48 bits of a candidate, and
+//! since 1.15 the borders between biomes also depend on the upper 16 bits.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers::Area;
+use crate::biome_layers::GetMap;
+use crate::biome_layers::Map;
+use crate::biome_layers::MapVoronoiZoom115;
+use crate::chunk::Point;
+use crate::java_rng::JavaRng;
+use crate::noise_generator::SimplexNoise;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+use std::rc::Rc;
+
+/// Terrain height of the End, used to decide the biome of each chunk
+pub struct EndBiomes {
+    noise: SimplexNoise,
+}
+
+impl EndBiomes {
+    pub fn new(world_seed: i64) -> Self {
+        let mut r = JavaRng::with_seed(world_seed as u64);
+        r.next_n_calls(17292);
+
+        Self {
+            noise: SimplexNoise::new(&mut r),
+        }
+    }
+
+    /// Height of the terrain, from -100 to 80. The coordinates are in units of 8 blocks, so the
+    /// center of the chunk `(x, z)` is at `(2 * x + 1, 2 * z + 1)`.
+    pub fn height(&self, x: i32, z: i32) -> f32 {
+        fn clamp(v: f32) -> f32 {
+            if v < -100.0 {
+                -100.0
+            } else if v > 80.0 {
+                80.0
+            } else {
+                v
+            }
+        }
+
+        let (hx, hz) = (x / 2, z / 2);
+        let (oddx, oddz) = (x % 2, z % 2);
+        // Java int arithmetic, this can overflow
+        let d = x.wrapping_mul(x).wrapping_add(z.wrapping_mul(z));
+        let mut h = clamp(100.0 - (d as f32).sqrt() * 8.0);
+
+        for i in -12..=12 {
+            for j in -12..=12 {
+                let rx = i64::from(hx) + i64::from(i);
+                let rz = i64::from(hz) + i64::from(j);
+                if rx * rx + rz * rz > 4096
+                    && self.noise.sample_2d(rx as f64, rz as f64) < f64::from(-0.9f32)
+                {
+                    let size =
+                        ((rx as f32).abs() * 3439.0 + (rz as f32).abs() * 147.0) % 13.0 + 9.0;
+                    let dx = (oddx - i * 2) as f32;
+                    let dz = (oddz - j * 2) as f32;
+                    let island = clamp(100.0 - (dx * dx + dz * dz).sqrt() * size);
+                    h = h.max(island);
+                }
+            }
+        }
+
+        h
+    }
+
+    /// Biome of the chunk `(x, z)`
+    pub fn biome_at_chunk(&self, x: i32, z: i32) -> i32 {
+        if i64::from(x) * i64::from(x) + i64::from(z) * i64::from(z) <= 4096 {
+            return biome_id::sky;
+        }
+        let h = self.height(
+            x.wrapping_mul(2).wrapping_add(1),
+            z.wrapping_mul(2).wrapping_add(1),
+        );
+        if h > 40.0 {
+            biome_id::skyIslandHigh
+        } else if h >= 0.0 {
+            biome_id::skyIslandMedium
+        } else if h < -20.0 {
+            biome_id::skyIslandLow
+        } else {
+            biome_id::skyIslandBarren
+        }
+    }
+
+    // Biomes of an area where each chunk is 2^shift tiles wide
+    fn chunk_map(&self, area: Area, shift: u32) -> Map {
+        if area.w == 0 || area.h == 0 {
+            return Map::new(area);
+        }
+        let (cx0, cz0) = (area.x >> shift, area.z >> shift);
+        let cx1 = (area.x + area.w as i64 - 1) >> shift;
+        let cz1 = (area.z + area.h as i64 - 1) >> shift;
+        let chunk_area = Area {
+            x: cx0,
+            z: cz0,
+            w: (cx1 - cx0 + 1) as u64,
+            h: (cz1 - cz0 + 1) as u64,
+        };
+        let chunks = Map::from_area_fn(chunk_area, |(x, z)| {
+            self.biome_at_chunk((cx0 + x as i64) as i32, (cz0 + z as i64) as i32)
+        });
+
+        Map::from_area_fn(area, |(x, z)| {
+            chunks.get((area.x + x as i64) >> shift, (area.z + z as i64) >> shift)
+        })
+    }
+}
+
+/// Biomes of the End at 1:4 scale, since 1.15
+pub struct MapEndQuarterScale {
+    biomes: EndBiomes,
+}
+
+impl MapEndQuarterScale {
+    pub fn new(world_seed: i64) -> Self {
+        Self {
+            biomes: EndBiomes::new(world_seed),
+        }
+    }
+}
+
+impl GetMap for MapEndQuarterScale {
+    fn get_map(&self, area: Area) -> Map {
+        self.biomes.chunk_map(area, 2)
+    }
+    fn get_map_from_pmap(&self, pmap: &Map) -> Map {
+        self.get_map(pmap.area())
+    }
+}
+
+/// Biomes of the End in 1.13 and 1.14, at 1:1 scale
+pub fn generate_end_1_13(area: Area, world_seed: i64) -> Map {
+    EndBiomes::new(world_seed).chunk_map(area, 4)
+}
+
+/// Biomes of the End from 1.15 to 1.17, at 1:1 scale
+pub fn generate_end_1_15(area: Area, world_seed: i64) -> Map {
+    let mut voronoi = MapVoronoiZoom115::new(world_seed);
+    voronoi.parent = Some(Rc::new(MapEndQuarterScale::new(world_seed)));
+
+    voronoi.get_map(area)
+}
+
+/// Biomes of the End at 1:1 scale. Panics if the version is not implemented: before 1.13 the
+/// whole End is `sky`, and 1.18 is not implemented yet.
+pub fn generate_end(version: MinecraftVersion, area: Area, world_seed: i64) -> Map {
+    match version {
+        MinecraftVersion::Java1_13 | MinecraftVersion::Java1_14 => {
+            generate_end_1_13(area, world_seed)
+        }
+        MinecraftVersion::Java1_15
+        | MinecraftVersion::Java1_16_1
+        | MinecraftVersion::Java1_16
+        | MinecraftVersion::Java1_17 => generate_end_1_15(area, world_seed),
+        _ => panic!("End biomes in version {:?} are not implemented", version),
+    }
+}
+
+/// Keep the candidates that generate these biomes in the End
+pub fn filter_seeds_using_end_biomes(
+    candidates: &[i64],
+    biomes: &[(BiomeId, Point)],
+    version: MinecraftVersion,
+) -> Vec<i64> {
+    candidates
+        .iter()
+        .copied()
+        .filter(|&world_seed| {
+            biomes.iter().all(|(biome_id, p)| {
+                let area = Area {
+                    x: p.x,
+                    z: p.z,
+                    w: 1,
+                    h: 1,
+                };
+                generate_end(version, area, world_seed).a[(0, 0)] == biome_id.0
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_end_biome(id: i32) -> bool {
+        [
+            biome_id::sky,
+            biome_id::skyIslandLow,
+            biome_id::skyIslandMedium,
+            biome_id::skyIslandHigh,
+            biome_id::skyIslandBarren,
+        ]
+        .contains(&id)
+    }
+
+    #[test]
+    fn main_island() {
+        let area = Area {
+            x: -300,
+            z: -300,
+            w: 600,
+            h: 600,
+        };
+        let map = generate_end_1_13(area, 1234);
+        assert!(map.a.iter().all(|&v| v == biome_id::sky));
+    }
+
+    #[test]
+    fn outer_islands() {
+        let world_seed = 1234;
+        // 64x64 chunks far away from the main island
+        let area = Area {
+            x: 4000,
+            z: -4000,
+            w: 256,
+            h: 256,
+        };
+        let quarter = MapEndQuarterScale::new(world_seed).get_map(area);
+        assert!(quarter
+            .a
+            .iter()
+            .all(|&v| is_end_biome(v) && v != biome_id::sky));
+        assert!(quarter.a.iter().any(|&v| v == biome_id::skyIslandLow));
+        assert!(quarter.a.iter().any(|&v| v == biome_id::skyIslandHigh));
+
+        // Each chunk has one biome in 1.13, and the same biome as the 1:4 map
+        let area = Area {
+            x: 16000,
+            z: -16000,
+            w: 64,
+            h: 64,
+        };
+        let map = generate_end_1_13(area, world_seed);
+        let quarter = MapEndQuarterScale::new(world_seed).get_map(Area {
+            x: 4000,
+            z: -4000,
+            w: 16,
+            h: 16,
+        });
+        for ((x, z), v) in map.a.indexed_iter() {
+            assert_eq!(*v, quarter.a[(x / 4, z / 4)]);
+        }
+
+        // The voronoi zoom only moves the borders
+        let map = generate_end_1_15(area, world_seed);
+        assert!(map.a.iter().all(|&v| is_end_biome(v)));
+    }
+
+    #[test]
+    fn filter_seeds() {
+        let world_seed = 1234;
+        let version = MinecraftVersion::Java1_15;
+        let biomes: Vec<(BiomeId, Point)> = (0..20)
+            .map(|i| {
+                let p = Point {
+                    x: 3000 + i * 301,
+                    z: -2000 - i * 97,
+                };
+                let area = Area {
+                    x: p.x,
+                    z: p.z,
+                    w: 1,
+                    h: 1,
+                };
+                (
+                    BiomeId(generate_end(version, area, world_seed).a[(0, 0)]),
+                    p,
+                )
+            })
+            .collect();
+        let found = filter_seeds_using_end_biomes(&[5678, world_seed], &biomes, version);
+        assert!(found.contains(&world_seed));
+    }
+}
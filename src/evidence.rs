@@ -0,0 +1,1345 @@
+//! Sanity checks for the evidence in a `SeedInfo`
+//!
+//! Searching for a seed can take hours, so it is worth detecting common input mistakes before
+//! starting the search. Each detected problem is reported as a `Diagnostic`, which can be shown
+//! to the user.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers::can_generate_river_near;
+use crate::biome_layers::cluster_observations;
+use crate::biome_layers::convert_hd_coords_into_quarter_scale;
+use crate::biome_layers::generate_up_to_layer;
+use crate::biome_layers::river_seed_finder_26_iter;
+use crate::biome_layers::score_seed_list_with_config;
+use crate::biome_layers::Area;
+use crate::biome_layers::Map;
+use crate::biome_layers::SearchConfig;
+use crate::biome_layers::SearchEvidence;
+use crate::biome_layers::StructureEvidence;
+use crate::biome_layers::GUESSABLE_VERSIONS;
+use crate::biome_traits::is_oceanic;
+use crate::cancel::CancelToken;
+use crate::chunk::Chunk;
+use crate::chunk::Point;
+use crate::chunk::Point4;
+use crate::java_rng::JavaRng;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+use crate::seed_info::RiverLayers;
+use crate::seed_info::SeedInfo;
+use crate::seed_info::ANY_VERSION;
+use crate::slime::is_slime_chunk;
+use crate::structures::region_structures;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The version string cannot be parsed
+    UnknownVersion(String),
+    /// None of the tested world seeds can generate a river near this point
+    ImpossibleRiver(Point),
+    /// The same point appears more than once with the same biome
+    DuplicatePoint(Point, BiomeId),
+    /// The same point appears more than once with different biomes
+    ContradictoryPoint(Point, Vec<BiomeId>),
+    /// The same quarter-scale point appears more than once with the same biome
+    DuplicatePoint4(Point4, BiomeId),
+    /// The same quarter-scale point appears more than once with different biomes
+    ContradictoryPoint4(Point4, Vec<BiomeId>),
+    /// The biomes change too often for 1:1 coordinates, they were probably entered in 1:4 scale
+    LooksQuarterScale,
+    /// This biome does not exist in this version
+    BiomeNotInVersion(BiomeId, MinecraftVersion),
+    /// All the points have the same biome, this looks like a single biome world and any seed
+    /// will match. Not reported for rivers, which are used by the river seed finder.
+    SingleBiome(BiomeId),
+    /// All the points are oceans, which match too many seeds
+    OnlyOcean,
+    /// Ocean near the world origin, but `MapIsland` forces land at (0, 0) and none of the tested
+    /// world seeds generate an ocean at this point
+    OceanAtSpawn(Point, MinecraftVersion),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::UnknownVersion(v) => write!(f, "Unknown minecraft version {:?}", v),
+            Diagnostic::ImpossibleRiver(p) => write!(
+                f,
+                "None of the {} tested seeds can generate a river near ({}, {}), check the coordinates",
+                RIVER_CHECK_SEEDS, p.x, p.z
+            ),
+            Diagnostic::DuplicatePoint(p, b) => write!(
+                f,
+                "Point ({}, {}) with biome {} appears more than once",
+                p.x, p.z, b.0
+            ),
+            Diagnostic::ContradictoryPoint(p, bs) => write!(
+                f,
+                "Point ({}, {}) has more than one biome: {:?}, remove the wrong ones",
+                p.x,
+                p.z,
+                bs.iter().map(|b| b.0).collect::<Vec<_>>()
+            ),
+            Diagnostic::DuplicatePoint4(p, b) => write!(
+                f,
+                "Quarter-scale point ({}, {}) with biome {} appears more than once",
+                p.x, p.z, b.0
+            ),
+            Diagnostic::ContradictoryPoint4(p, bs) => write!(
+                f,
+                "Quarter-scale point ({}, {}) has more than one biome: {:?}, remove the wrong ones",
+                p.x,
+                p.z,
+                bs.iter().map(|b| b.0).collect::<Vec<_>>()
+            ),
+            Diagnostic::LooksQuarterScale => write!(
+                f,
+                "Biomes change too often for block coordinates, were they entered in 1:4 scale? Use biomesQuarterScale instead"
+            ),
+            Diagnostic::BiomeNotInVersion(b, v) => write!(
+                f,
+                "Biome {} does not exist in {:?}, check the version",
+                b.0, v
+            ),
+            Diagnostic::SingleBiome(b) => write!(
+                f,
+                "All the points have biome {}, single biome worlds cannot be used to find the seed",
+                b.0
+            ),
+            Diagnostic::OnlyOcean => write!(
+                f,
+                "All the points are oceans, add some points from land biomes"
+            ),
+            Diagnostic::OceanAtSpawn(p, v) => write!(
+                f,
+                "Point ({}, {}) is an ocean, but {:?} forces an island at the world origin and none of {} tested seeds generate an ocean there, check the coordinates",
+                p.x, p.z, v, SPAWN_ISLAND_SEEDS
+            ),
+        }
+    }
+}
+
+/// Returns the first version that can generate this biome in the overworld, or `None` if this is
+/// not a known overworld biome id: the nether and end biomes also return `None`.
+///
+/// Alpha worlds have no biomes, only oceans and land. Beta worlds use a different set of biomes,
+/// so only the ones with an equivalent biome id are accepted.
+pub fn biome_min_version(biome: BiomeId) -> Option<MinecraftVersion> {
+    Some(match biome.0 {
+        biome_id::ocean => MinecraftVersion::JavaAlpha1_2_5,
+        biome_id::plains
+        | biome_id::desert
+        | biome_id::forest
+        | biome_id::taiga
+        | biome_id::swampland
+        | biome_id::icePlains => MinecraftVersion::JavaBeta,
+        biome_id::hell | biome_id::sky => return None,
+        0..=22 => MinecraftVersion::Java1_2,
+        23..=39 | 129..=167 => MinecraftVersion::Java1_7,
+        // End biomes
+        40..=43 => return None,
+        // Ocean temperatures
+        44..=50 => MinecraftVersion::Java1_13,
+        127 => MinecraftVersion::Java1_9,
+        biome_id::bambooJungle | biome_id::bambooJungleHills => MinecraftVersion::Java1_14,
+        // Nether biomes
+        170..=173 => return None,
+        174..=193 => MinecraftVersion::Java1_18,
+        _ => return None,
+    })
+}
+
+/// Detect common input mistakes in the evidence of a `SeedInfo`. This does not search for seeds,
+/// so it should be fast enough to run before every search.
+pub fn validate_evidence(seed_info: &SeedInfo) -> Vec<Diagnostic> {
+    let mut r = vec![];
+
+    let version = match seed_info.version() {
+        Ok(x) => Some(x),
+        // Regions generated by an unknown version, see `FragmentVersion::Any`
+        Err(_) if seed_info.version == ANY_VERSION => None,
+        Err(v) => {
+            r.push(Diagnostic::UnknownVersion(v));
+            None
+        }
+    };
+
+    let biomes = check_duplicates(
+        &seed_info.biomes,
+        Diagnostic::DuplicatePoint,
+        Diagnostic::ContradictoryPoint,
+        &mut r,
+    );
+    check_duplicates(
+        &seed_info.biomes_quarter_scale,
+        Diagnostic::DuplicatePoint4,
+        Diagnostic::ContradictoryPoint4,
+        &mut r,
+    );
+
+    if let Some(version) = version {
+        let mut biome_ids: Vec<BiomeId> = seed_info
+            .biomes
+            .keys()
+            .chain(seed_info.biomes_quarter_scale.keys())
+            .copied()
+            .collect();
+        biome_ids.sort();
+        biome_ids.dedup();
+        for b in biome_ids {
+            if let Some(min_version) = biome_min_version(b) {
+                if version < min_version {
+                    r.push(Diagnostic::BiomeNotInVersion(b, version));
+                }
+            }
+        }
+    }
+
+    if looks_quarter_scale(&biomes) {
+        r.push(Diagnostic::LooksQuarterScale);
+    }
+
+    if let Some(d) = check_degenerate_biomes(seed_info) {
+        r.push(d);
+    }
+
+    // Only the rivers from the 1:4 biome layers can be checked
+    if matches!(version, Some(v) if v.river_layers() == Some(RiverLayers::Java1_7)) {
+        let rivers: Vec<Point> = [biome_id::river, biome_id::frozenRiver]
+            .iter()
+            .flat_map(|river_id| seed_info.biomes.get(&BiomeId(*river_id)))
+            .flatten()
+            .copied()
+            .collect();
+        r.extend(
+            impossible_rivers(&rivers, &river_check_seeds())
+                .into_iter()
+                .map(Diagnostic::ImpossibleRiver),
+        );
+    }
+
+    if let Some(version) = version {
+        r.extend(check_ocean_at_spawn(
+            seed_info,
+            version,
+            &spawn_island_seeds(),
+        ));
+    }
+
+    r
+}
+
+// Blocks near the world origin affected by the island that `MapIsland` forces at (0, 0)
+const SPAWN_ISLAND_RADIUS: i64 = 64;
+// Number of world seeds used to check the oceans near the world origin
+const SPAWN_ISLAND_SEEDS: usize = 1024;
+
+/// World seeds used to check the oceans near the world origin
+pub(crate) fn spawn_island_seeds() -> Vec<i64> {
+    let mut rng = JavaRng::with_seed(0);
+    (0..SPAWN_ISLAND_SEEDS).map(|_| rng.next_long()).collect()
+}
+
+// The first layer of the 1:4 biome generation forces land at (0, 0). The later layers can still
+// erode that island, so this cannot be checked for all the seeds, but the ocean points near the
+// origin that are not generated by any of the tested seeds are probably mistakes.
+fn check_ocean_at_spawn(
+    seed_info: &SeedInfo,
+    version: MinecraftVersion,
+    seeds: &[i64],
+) -> Vec<Diagnostic> {
+    let oceans: Vec<Point> = seed_info
+        .biomes
+        .iter()
+        .filter(|(b, _)| is_oceanic(b.0))
+        .flat_map(|(_, points)| points.iter().copied())
+        .collect();
+
+    impossible_oceans_at_spawn(&oceans, version, seeds)
+        .into_iter()
+        .map(|p| Diagnostic::OceanAtSpawn(p, version))
+        .collect()
+}
+
+/// Returns the ocean points near the world origin that none of these world seeds can generate.
+/// The points far from the origin are ignored, and so are the versions that do not force an
+/// island at (0, 0). The scorers ignore the returned points, see `Diagnostic::OceanAtSpawn`.
+pub(crate) fn impossible_oceans_at_spawn(
+    points: &[Point],
+    version: MinecraftVersion,
+    seeds: &[i64],
+) -> Vec<Point> {
+    // 1.18 does not use MapIsland
+    if !GUESSABLE_VERSIONS.contains(&version) {
+        return vec![];
+    }
+    let mut oceans: Vec<Point> = points
+        .iter()
+        .copied()
+        .filter(|p| p.x.abs() <= SPAWN_ISLAND_RADIUS && p.z.abs() <= SPAWN_ISLAND_RADIUS)
+        .collect();
+    if oceans.is_empty() {
+        return vec![];
+    }
+    oceans.sort();
+    oceans.dedup();
+
+    // The voronoi zoom chooses one of the neighbors of the 1:4 tile, so a block can be an ocean
+    // if any of the 3x3 tiles around it is an ocean
+    let area = Area::from_coords(oceans.iter().flat_map(|p| {
+        [
+            Point {
+                x: (p.x >> 2) - 1,
+                z: (p.z >> 2) - 1,
+            },
+            Point {
+                x: (p.x >> 2) + 1,
+                z: (p.z >> 2) + 1,
+            },
+        ]
+    }));
+    let mut possible = vec![false; oceans.len()];
+    for &world_seed in seeds {
+        let map = generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0);
+        for (p, possible) in oceans.iter().zip(possible.iter_mut()) {
+            *possible = *possible
+                || (-1..=1).any(|dx| {
+                    (-1..=1).any(|dz| is_oceanic(map.get((p.x >> 2) + dx, (p.z >> 2) + dz)))
+                });
+        }
+        if possible.iter().all(|x| *x) {
+            return vec![];
+        }
+    }
+
+    oceans
+        .into_iter()
+        .zip(possible)
+        .filter(|(_, possible)| !possible)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+// Number of 26-bit world seeds used to check the rivers
+const RIVER_CHECK_SEEDS: usize = 256;
+
+// Random 25-bit seeds, can_generate_river_near also checks them with bit 25 set
+fn river_check_seeds() -> Vec<i64> {
+    let mut rng = JavaRng::with_seed(0);
+    (0..RIVER_CHECK_SEEDS / 2)
+        .map(|_| rng.next_long() & ((1 << 25) - 1))
+        .collect()
+}
+
+// Returns the points where none of these seeds can generate a river. Checking all the 2^26 seeds
+// would be too slow, but most points can have a river for many seeds, so the real mistakes are
+// the points that fail for all the tested seeds.
+fn impossible_rivers(points: &[Point], seeds: &[i64]) -> Vec<Point> {
+    let mut tiles: Vec<Point> = points
+        .iter()
+        .map(|p| Point {
+            x: p.x >> 2,
+            z: p.z >> 2,
+        })
+        .collect();
+    tiles.sort();
+    tiles.dedup();
+    let possible_tiles: Vec<Point> = tiles
+        .into_iter()
+        .filter(|t| {
+            seeds
+                .iter()
+                .any(|world_seed| can_generate_river_near(*t, *world_seed))
+        })
+        .collect();
+
+    points
+        .iter()
+        .copied()
+        .filter(|p| {
+            let t = Point {
+                x: p.x >> 2,
+                z: p.z >> 2,
+            };
+            possible_tiles.binary_search(&t).is_err()
+        })
+        .collect()
+}
+
+// A few points with the same biome are common, but many points with the same biome usually
+// mean that the world is a single biome world, or that only the ocean was explored
+fn check_degenerate_biomes(seed_info: &SeedInfo) -> Option<Diagnostic> {
+    const MIN_POINTS: usize = 16;
+    let num_points: usize = seed_info
+        .biomes
+        .values()
+        .map(|v| v.len())
+        .chain(seed_info.biomes_quarter_scale.values().map(|v| v.len()))
+        .sum();
+    if num_points < MIN_POINTS {
+        return None;
+    }
+
+    let mut biome_ids: Vec<BiomeId> = seed_info
+        .biomes
+        .iter()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(b, _)| *b)
+        .chain(
+            seed_info
+                .biomes_quarter_scale
+                .iter()
+                .filter(|(_, v)| !v.is_empty())
+                .map(|(b, _)| *b),
+        )
+        .collect();
+    biome_ids.sort();
+    biome_ids.dedup();
+
+    if biome_ids.len() == 1 {
+        // Only rivers is the expected input of the river seed finder
+        if matches!(biome_ids[0].0, biome_id::river | biome_id::frozenRiver) {
+            return None;
+        }
+        Some(Diagnostic::SingleBiome(biome_ids[0]))
+    } else if biome_ids.iter().all(|b| is_oceanic(b.0)) {
+        Some(Diagnostic::OnlyOcean)
+    } else {
+        None
+    }
+}
+
+/// Rough estimate of the information contained in the evidence, in bits. A world seed has 64
+/// bits, so with less than 64 bits of evidence many seeds will match.
+///
+/// This is only a heuristic used to warn the user before starting a search: it ignores that
+/// some searches only need the lower 48 bits, and that the evidence is not independent.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EvidenceStrength {
+    /// Bits from the biomes, including rivers
+    pub biome_bits: f64,
+    /// Bits from the structures
+    pub structure_bits: f64,
+    /// Bits from the other evidence: end pillars and the hashed seed
+    pub other_bits: f64,
+}
+
+impl EvidenceStrength {
+    pub fn total_bits(&self) -> f64 {
+        self.biome_bits + self.structure_bits + self.other_bits
+    }
+
+    /// Returns true if the evidence is probably enough to find a unique seed
+    pub fn is_enough(&self) -> bool {
+        self.total_bits() >= 64.0
+    }
+}
+
+/// Estimate how many bits of information the evidence of this `SeedInfo` contains
+pub fn estimate_evidence_strength(seed_info: &SeedInfo) -> EvidenceStrength {
+    // The biome of a point is very likely to be the same as the biome of its neighbors, so
+    // only the quarter-scale tiles at the border between two biomes are counted. Single biome
+    // worlds and ocean-only evidence give 0 bits.
+    let biome_bits = if check_degenerate_biomes(seed_info).is_some() {
+        0.0
+    } else {
+        let mut tiles: HashMap<Point4, Vec<BiomeId>> = HashMap::new();
+        for (b, points) in &seed_info.biomes {
+            for p in points {
+                tiles
+                    .entry(Point4 {
+                        x: p.x >> 2,
+                        z: p.z >> 2,
+                    })
+                    .or_default()
+                    .push(*b);
+            }
+        }
+        for (b, points) in &seed_info.biomes_quarter_scale {
+            for p in points {
+                tiles.entry(*p).or_default().push(*b);
+            }
+        }
+        let border_tiles = tiles
+            .iter()
+            .filter(|(p, bs)| {
+                [(1, 0), (-1, 0), (0, 1), (0, -1)].iter().any(|(dx, dz)| {
+                    tiles
+                        .get(&Point4 {
+                            x: p.x + dx,
+                            z: p.z + dz,
+                        })
+                        .is_some_and(|n| n.iter().any(|b| !bs.contains(b)))
+                })
+            })
+            .count();
+        // Each border tile is worth about 1 bit, the interior tiles are worth much less
+        border_tiles as f64 + (tiles.len() - border_tiles) as f64 * 0.05
+    };
+
+    // A structure appears in one out of N chunks, so a positive observation is worth log2(N)
+    // bits and a negative observation is worth log2(N / (N - 1)) bits
+    let positive_bits = |n: f64| n.log2();
+    let negative_bits = |n: f64| (n / (n - 1.0)).log2();
+    // Ocean ruins and shipwrecks have one start chunk in each region, which has between 144 and
+    // 400 possible positions depending on the version
+    let structure_bits = seed_info.positive.slime_chunks.len() as f64 * positive_bits(10.0)
+        + seed_info.negative.slime_chunks.len() as f64 * negative_bits(10.0)
+        + seed_info.treasure_maps.len() as f64 * positive_bits(100.0)
+        + seed_info.positive.buried_treasures.len() as f64 * positive_bits(100.0)
+        + seed_info.positive.ocean_ruins.len() as f64 * positive_bits(144.0)
+        + seed_info.positive.shipwrecks.len() as f64 * positive_bits(144.0);
+
+    // The end pillars use a 16-bit seed
+    let end_pillar_bits = if seed_info.end_pillars.is_empty() {
+        0.0
+    } else {
+        16.0
+    };
+    let hash_bits = if seed_info.world_seed_hash.is_some() {
+        64.0
+    } else {
+        0.0
+    };
+
+    EvidenceStrength {
+        biome_bits,
+        structure_bits,
+        other_bits: end_pillar_bits + hash_bits,
+    }
+}
+
+/// Evidence of a `SeedInfo` used by the river seed finder. The nested `and` seed infos are not
+/// included, see `SeedInfo::version_fragments`.
+pub fn search_evidence(seed_info: &SeedInfo) -> SearchEvidence {
+    let rivers = seed_info
+        .biomes
+        .get(&BiomeId(biome_id::river))
+        .cloned()
+        .unwrap_or_default();
+    let extra_biomes = seed_info
+        .biomes
+        .iter()
+        .flat_map(|(id, vec_xz)| {
+            if *id == BiomeId(biome_id::river) {
+                vec![]
+            } else {
+                vec_xz.iter().map(|p| (*id, *p)).collect()
+            }
+        })
+        .collect();
+    // Slime chunks only add to the score of the last step, so a few wrong ones are tolerated
+    let structures = seed_info
+        .positive
+        .slime_chunks
+        .iter()
+        .map(|c| StructureEvidence::SlimeChunk(*c))
+        .chain(
+            region_structures(&seed_info.positive)
+                .into_iter()
+                .map(|(s, c)| StructureEvidence::Region(s, c)),
+        )
+        .collect();
+
+    SearchEvidence {
+        rivers,
+        extra_biomes,
+        structures,
+    }
+}
+
+/// 26-bit candidates of the rivers of a `SeedInfo`, returned as soon as they are found, so they
+/// can be used before the whole search is done. The rivers of the nested `and` seed infos are also
+/// used, and the rivers stored at quarter scale are used as is. Regions generated by an unknown
+/// version are allowed, because all the candidate versions have the same rivers.
+///
+/// Returns an error if a version is unknown or the river seed finder is not implemented for it.
+pub fn candidate_26_iter(seed_info: &SeedInfo) -> Result<impl Iterator<Item = i64>, String> {
+    let mut rivers = vec![];
+    for (version, s) in seed_info.mixed_version_fragments()? {
+        for version in version.versions() {
+            if version.river_layers() != Some(RiverLayers::Java1_7) {
+                return Err(format!(
+                    "River seed finder not implemented for version {:?}",
+                    version
+                ));
+            }
+        }
+        rivers.extend(convert_hd_coords_into_quarter_scale(
+            &search_evidence(s).rivers,
+        ));
+        if let Some(r) = s.biomes_quarter_scale.get(&BiomeId(biome_id::river)) {
+            rivers.extend(r.iter().copied());
+        }
+    }
+    rivers.sort_unstable();
+    rivers.dedup();
+
+    Ok(river_seed_finder_26_iter(
+        rivers,
+        0,
+        1 << 24,
+        SearchConfig::default(),
+        CancelToken::new(),
+    ))
+}
+
+/// One piece of evidence that does not match the world generated using a seed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The biome generated at this block is different
+    Biome {
+        point: Point,
+        expected: BiomeId,
+        generated: BiomeId,
+    },
+    /// The biome generated at this quarter-scale tile is different
+    BiomeQuarterScale {
+        point: Point4,
+        expected: BiomeId,
+        generated: BiomeId,
+    },
+    /// This chunk should be a slime chunk, or should not be one if `expected` is false
+    SlimeChunk { chunk: Chunk, expected: bool },
+    /// The structure cannot generate at this chunk, or not in this biome
+    Structure(StructureEvidence),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Biome {
+                point,
+                expected,
+                generated,
+            } => write!(
+                f,
+                "Expected biome {} at ({}, {}), generated {}",
+                expected.0, point.x, point.z, generated.0
+            ),
+            Mismatch::BiomeQuarterScale {
+                point,
+                expected,
+                generated,
+            } => write!(
+                f,
+                "Expected biome {} at quarter-scale ({}, {}), generated {}",
+                expected.0, point.x, point.z, generated.0
+            ),
+            Mismatch::SlimeChunk { chunk, expected } => write!(
+                f,
+                "Chunk ({}, {}) {} a slime chunk",
+                chunk.x,
+                chunk.z,
+                if *expected { "is not" } else { "is" }
+            ),
+            Mismatch::Structure(s) => write!(f, "Structure {:?} cannot generate there", s),
+        }
+    }
+}
+
+// Generate one map for each cluster of points, instead of one map with the bounding box of all the
+// points, which can be huge when the observations are far away from each other
+fn generate_clusters<I>(
+    version: MinecraftVersion,
+    world_seed: i64,
+    points: I,
+    layer: u32,
+) -> Vec<Map>
+where
+    I: IntoIterator<Item = Point>,
+{
+    let points: Vec<Point> = points.into_iter().collect();
+    cluster_observations(&points, |p| p)
+        .into_iter()
+        .map(|ps| generate_up_to_layer(version, Area::from_coords(ps), world_seed, layer, 0))
+        .collect()
+}
+
+// Biome at (x, z), which must be inside one of the maps returned by `generate_clusters`
+fn biome_in_clusters(maps: &[Map], x: i64, z: i64) -> BiomeId {
+    let map = maps
+        .iter()
+        .find(|m| m.area().contains(x, z))
+        .expect("point not inside any cluster");
+
+    map.get_biome(x, z)
+}
+
+/// Result of `verify_seed`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationReport {
+    pub world_seed: i64,
+    pub version: MinecraftVersion,
+    /// Number of checked pieces of evidence
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+    /// Weighted score of the river seed finder, or `None` if the seed finder would reject this
+    /// seed
+    pub score: Option<u32>,
+}
+
+impl VerificationReport {
+    /// Returns true if all the evidence matches
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Check all the evidence of a `SeedInfo` using a known world seed, and list everything that
+/// does not match. This checks the biomes, including rivers, the slime chunks, and the
+/// structures that can be used by the seed finder. The nested `and` seed infos are not checked.
+pub fn verify_seed(
+    world_seed: i64,
+    seed_info: &SeedInfo,
+    version: MinecraftVersion,
+) -> VerificationReport {
+    let mut checked = 0;
+    let mut mismatches = vec![];
+
+    // Sort to make the output deterministic
+    let mut biomes: Vec<(BiomeId, Point)> = seed_info
+        .biomes
+        .iter()
+        .flat_map(|(b, ps)| ps.iter().map(move |p| (*b, *p)))
+        .collect();
+    biomes.sort_by_key(|(b, p)| (*p, *b));
+    let maps = generate_clusters(
+        version,
+        world_seed,
+        biomes.iter().map(|(_, p)| *p),
+        version.num_layers(),
+    );
+    for (expected, point) in biomes {
+        checked += 1;
+        let generated = biome_in_clusters(&maps, point.x, point.z);
+        if generated != expected {
+            mismatches.push(Mismatch::Biome {
+                point,
+                expected,
+                generated,
+            });
+        }
+    }
+
+    let mut biomes4: Vec<(BiomeId, Point4)> = seed_info
+        .biomes_quarter_scale
+        .iter()
+        .flat_map(|(b, ps)| ps.iter().map(move |p| (*b, *p)))
+        .collect();
+    biomes4.sort_by_key(|(b, p)| (*p, *b));
+    let maps = generate_clusters(
+        version,
+        world_seed,
+        biomes4.iter().map(|(_, p)| Point { x: p.x, z: p.z }),
+        version.num_layers() - 1,
+    );
+    for (expected, point) in biomes4 {
+        checked += 1;
+        let generated = biome_in_clusters(&maps, point.x, point.z);
+        if generated != expected {
+            mismatches.push(Mismatch::BiomeQuarterScale {
+                point,
+                expected,
+                generated,
+            });
+        }
+    }
+
+    for (chunks, expected) in [
+        (&seed_info.positive.slime_chunks, true),
+        (&seed_info.negative.slime_chunks, false),
+    ] {
+        for chunk in chunks {
+            checked += 1;
+            if is_slime_chunk(world_seed as u64, chunk) != expected {
+                mismatches.push(Mismatch::SlimeChunk {
+                    chunk: *chunk,
+                    expected,
+                });
+            }
+        }
+    }
+
+    let structures = seed_info
+        .positive
+        .buried_treasures
+        .iter()
+        .map(|c| StructureEvidence::BuriedTreasure(*c))
+        .chain(
+            region_structures(&seed_info.positive)
+                .into_iter()
+                .map(|(s, c)| StructureEvidence::Region(s, c)),
+        );
+    for s in structures {
+        checked += 1;
+        if !s.matches(world_seed, version) {
+            mismatches.push(Mismatch::Structure(s));
+        }
+    }
+
+    let score = score_seed_list_with_config(
+        [world_seed],
+        &search_evidence(seed_info),
+        version,
+        &SearchConfig::default(),
+    )
+    .first()
+    .map(|c| c.score);
+
+    VerificationReport {
+        world_seed,
+        version,
+        checked,
+        mismatches,
+        score,
+    }
+}
+
+// Bits of the 26-bit stage given by each quarter-scale river tile. Neighboring river tiles are
+// not independent, so this is less than 1.
+const RIVER_TILE_BITS_26: f64 = 0.5;
+// Bits of the 34-bit stage given by each quarter-scale river tile: only the river borders at 1:1
+// scale depend on these 8 bits
+const RIVER_TILE_BITS_34: f64 = 0.25;
+// Bits of a typical structure, used to suggest how many structures to add
+const STRUCTURE_BITS: f64 = 6.6;
+// Maximum number of 64-bit candidates that can be checked using biomes in a reasonable time
+const MAX_CANDIDATES_64_LOG2: f64 = 28.0;
+
+/// Expected number of candidates that survive each stage of the river seed finder, as estimated
+/// by `estimate_search_difficulty`. The numbers are the log2 of the expected number of wrong
+/// candidates, so 0 means that only the correct seed is expected to survive.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SearchDifficulty {
+    /// 26-bit candidates found using the rivers at 1:4 scale
+    pub candidates_26_log2: f64,
+    /// 34-bit candidates found using the rivers at 1:1 scale
+    pub candidates_34_log2: f64,
+    /// 64-bit candidates that survive the check of the other biomes and the structures
+    pub candidates_64_log2: f64,
+    /// The 64-bit stage needs to check a reasonable number of candidates
+    pub tractable: bool,
+    /// Suggested number of extra river tiles at 1:4 scale, 0 if the rivers are enough
+    pub more_river_tiles: u32,
+    /// Suggested number of extra structures, as an alternative to more rivers. This is 0 when
+    /// the structures cannot help, because the search is not tractable.
+    pub more_structures: u32,
+}
+
+impl SearchDifficulty {
+    /// Returns true if the search is expected to find only the correct seed
+    pub fn is_unique(&self) -> bool {
+        self.candidates_64_log2 <= 0.0
+    }
+}
+
+impl fmt::Display for SearchDifficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.tractable {
+            write!(
+                f,
+                "Not enough rivers, the search would need to check about 2^{:.0} candidates: need ~{} more river tiles",
+                self.candidates_34_log2 + 14.0,
+                self.more_river_tiles
+            )
+        } else if !self.is_unique() {
+            write!(
+                f,
+                "Expect about 2^{:.0} wrong candidates: need ~{} more river tiles or {} structures",
+                self.candidates_64_log2, self.more_river_tiles, self.more_structures
+            )
+        } else {
+            write!(f, "The evidence is probably enough to find the seed")
+        }
+    }
+}
+
+// Bits of the 26-bit and 34-bit stages given by this number of quarter-scale river tiles
+fn river_stage_bits(river_tiles: usize) -> (f64, f64) {
+    let b26 = (river_tiles as f64 * RIVER_TILE_BITS_26).min(26.0);
+    let b34 = (river_tiles as f64 * RIVER_TILE_BITS_34).min(8.0);
+
+    (b26, b34)
+}
+
+/// Estimate how many candidates will survive each stage of the river seed finder, and how much
+/// more evidence is needed if the search is not expected to find a unique seed.
+///
+/// The river seed finder checks 2^26 seeds using the rivers at 1:4 scale, extends each candidate
+/// with 8 bits checked using the rivers at 1:1 scale, and then with 14 bits to get 2^14 seeds
+/// that can be returned by `Random.nextLong()`, which are checked using all the evidence. Like
+/// `estimate_evidence_strength`, this is only a heuristic.
+pub fn estimate_search_difficulty(seed_info: &SeedInfo) -> SearchDifficulty {
+    let mut river_tiles: Vec<Point4> = [biome_id::river, biome_id::frozenRiver]
+        .iter()
+        .flat_map(|id| {
+            let points = seed_info
+                .biomes
+                .get(&BiomeId(*id))
+                .into_iter()
+                .flatten()
+                .map(|p| Point4 {
+                    x: p.x >> 2,
+                    z: p.z >> 2,
+                });
+            let points4 = seed_info
+                .biomes_quarter_scale
+                .get(&BiomeId(*id))
+                .into_iter()
+                .flatten()
+                .copied();
+            points.chain(points4)
+        })
+        .collect();
+    river_tiles.sort();
+    river_tiles.dedup();
+
+    // The information used by the river stages cannot be used again in the last stage
+    let (b26, b34) = river_stage_bits(river_tiles.len());
+    let b64 = (estimate_evidence_strength(seed_info).total_bits() - b26 - b34).max(0.0);
+    let candidates_26_log2 = 26.0 - b26;
+    let candidates_34_log2 = candidates_26_log2 + 8.0 - b34;
+    let candidates_64_log2 = (candidates_34_log2 + 14.0 - b64).max(0.0);
+    let is_tractable = |b26: f64, b34: f64| 26.0 - b26 + 8.0 - b34 + 14.0 <= MAX_CANDIDATES_64_LOG2;
+    let tractable = is_tractable(b26, b34);
+
+    // Add river tiles until the search is tractable and unique, or the rivers cannot give more
+    // information
+    let mut more_river_tiles = 0;
+    loop {
+        let (new_b26, new_b34) = river_stage_bits(river_tiles.len() + more_river_tiles);
+        if is_tractable(new_b26, new_b34) && new_b26 + new_b34 + b64 >= 48.0 {
+            break;
+        }
+        if new_b26 >= 26.0 && new_b34 >= 8.0 {
+            break;
+        }
+        more_river_tiles += 1;
+    }
+    let more_structures = if tractable {
+        (candidates_64_log2 / STRUCTURE_BITS).ceil() as u32
+    } else {
+        0
+    };
+
+    SearchDifficulty {
+        candidates_26_log2,
+        candidates_34_log2,
+        candidates_64_log2,
+        tractable,
+        more_river_tiles: more_river_tiles as u32,
+        more_structures,
+    }
+}
+
+// Find points that appear more than once, and return the biome of each point
+fn check_duplicates<P, D, C>(
+    biomes: &HashMap<BiomeId, Vec<P>>,
+    duplicate: D,
+    contradictory: C,
+    r: &mut Vec<Diagnostic>,
+) -> HashMap<P, BiomeId>
+where
+    P: Copy + Ord + std::hash::Hash,
+    D: Fn(P, BiomeId) -> Diagnostic,
+    C: Fn(P, Vec<BiomeId>) -> Diagnostic,
+{
+    let mut all: HashMap<P, Vec<BiomeId>> = HashMap::new();
+    for (biome, points) in biomes {
+        for p in points {
+            all.entry(*p).or_default().push(*biome);
+        }
+    }
+
+    // Sort to make the output deterministic
+    let mut all: Vec<_> = all.into_iter().collect();
+    all.sort_by_key(|(p, _)| *p);
+    let mut unique = HashMap::new();
+    for (p, mut bs) in all {
+        bs.sort();
+        if bs.len() > 1 {
+            let first = bs[0];
+            bs.dedup();
+            if bs.len() == 1 {
+                r.push(duplicate(p, first));
+            } else {
+                r.push(contradictory(p, bs));
+                continue;
+            }
+        }
+        unique.insert(p, bs[0]);
+    }
+
+    unique
+}
+
+// Biomes are at least 4x4 blocks, so with 1:1 coordinates most of the points whose 4 neighbors
+// are known have the same biome as all of them. With 1:4 coordinates, the borders between
+// biomes are 4 times longer relative to the area.
+fn looks_quarter_scale(biomes: &HashMap<Point, BiomeId>) -> bool {
+    // Not enough data to decide
+    const MIN_INTERIOR_POINTS: usize = 16;
+    let mut interior = 0;
+    let mut borders = 0;
+    for (p, b) in biomes {
+        let neighbors = [(1, 0), (-1, 0), (0, 1), (0, -1)].map(|(dx, dz)| {
+            biomes.get(&Point {
+                x: p.x + dx,
+                z: p.z + dz,
+            })
+        });
+        if neighbors.iter().any(|n| n.is_none()) {
+            continue;
+        }
+        interior += 1;
+        if neighbors.iter().any(|n| *n != Some(b)) {
+            borders += 1;
+        }
+    }
+
+    interior >= MIN_INTERIOR_POINTS && borders * 2 > interior
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_info_with_biomes(version: &str, points: &[(i64, i64, i32)]) -> SeedInfo {
+        let mut seed_info = SeedInfo {
+            version: version.to_string(),
+            ..Default::default()
+        };
+        for (x, z, b) in points.iter().copied() {
+            seed_info
+                .biomes
+                .entry(BiomeId(b))
+                .or_default()
+                .push(Point { x, z });
+        }
+
+        seed_info
+    }
+
+    #[test]
+    fn duplicate_and_contradictory_points() {
+        let seed_info = seed_info_with_biomes(
+            "1.7",
+            &[(0, 0, 1), (0, 0, 1), (5, 5, 1), (5, 5, 4), (10, 10, 4)],
+        );
+        assert_eq!(
+            validate_evidence(&seed_info),
+            vec![
+                Diagnostic::DuplicatePoint(Point { x: 0, z: 0 }, BiomeId(1)),
+                Diagnostic::ContradictoryPoint(Point { x: 5, z: 5 }, vec![BiomeId(1), BiomeId(4)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_version() {
+        let seed_info = seed_info_with_biomes("1.7", &[(0, 0, biome_id::bambooJungle)]);
+        assert_eq!(
+            validate_evidence(&seed_info),
+            vec![Diagnostic::BiomeNotInVersion(
+                BiomeId(biome_id::bambooJungle),
+                MinecraftVersion::Java1_7
+            )]
+        );
+        let seed_info = seed_info_with_biomes("1.14", &[(0, 0, biome_id::bambooJungle)]);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+        let seed_info = seed_info_with_biomes("beta", &[]);
+        assert_eq!(
+            validate_evidence(&seed_info),
+            vec![Diagnostic::UnknownVersion("beta".to_string())]
+        );
+        // Regions generated by an unknown version are allowed
+        let seed_info = seed_info_with_biomes(ANY_VERSION, &[(0, 0, biome_id::bambooJungle)]);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+    }
+
+    #[test]
+    fn biome_min_versions() {
+        let min_version = |b| biome_min_version(BiomeId(b));
+        assert_eq!(
+            min_version(biome_id::ocean),
+            Some(MinecraftVersion::JavaAlpha1_2_5)
+        );
+        assert_eq!(
+            min_version(biome_id::desert),
+            Some(MinecraftVersion::JavaBeta)
+        );
+        assert_eq!(
+            min_version(biome_id::river),
+            Some(MinecraftVersion::Java1_2)
+        );
+        assert_eq!(
+            min_version(biome_id::jungle),
+            Some(MinecraftVersion::Java1_2)
+        );
+        assert_eq!(
+            min_version(biome_id::warmOcean),
+            Some(MinecraftVersion::Java1_13)
+        );
+        // Nether and end biomes are not overworld biomes
+        for b in [
+            biome_id::hell,
+            biome_id::sky,
+            biome_id::skyIslandLow,
+            170,
+            173,
+        ] {
+            assert_eq!(min_version(b), None, "{}", b);
+        }
+    }
+
+    #[test]
+    fn impossible_river() {
+        // The lowest 26 bits of this seed generate a river at this block
+        let world_seed = 1234;
+        let area = Area {
+            x: -200,
+            z: -200,
+            w: 400,
+            h: 400,
+        };
+        let map = crate::biome_layers::generate(MinecraftVersion::Java1_7, area, world_seed, 0);
+        let ((x, z), _) = map
+            .a
+            .indexed_iter()
+            .find(|(_, b)| **b == biome_id::river)
+            .unwrap();
+        let p = Point {
+            x: area.x + x as i64,
+            z: area.z + z as i64,
+        };
+        assert!(impossible_rivers(&[p], &[world_seed & ((1 << 25) - 1)]).is_empty());
+        // With no tested seeds, all the rivers are impossible
+        assert_eq!(impossible_rivers(&[p, p], &[]), vec![p, p]);
+
+        let d = Diagnostic::ImpossibleRiver(p);
+        assert!(d.to_string().contains("tested seeds"), "{}", d);
+        // Real rivers are not reported
+        let seed_info = seed_info_with_biomes("1.7", &[(p.x, p.z, biome_id::river)]);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+    }
+
+    #[test]
+    fn quarter_scale_coordinates() {
+        // Two biomes separated by a straight border: fine
+        let mut points = vec![];
+        for x in 0..16 {
+            for z in 0..16 {
+                points.push((x, z, if x < 8 { 1 } else { 4 }));
+            }
+        }
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+
+        // Biomes change every 2 blocks
+        let mut points = vec![];
+        for x in 0..16 {
+            for z in 0..16 {
+                points.push((x, z, if (x / 2 + z / 2) % 2 == 0 { 1 } else { 4 }));
+            }
+        }
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert_eq!(
+            validate_evidence(&seed_info),
+            vec![Diagnostic::LooksQuarterScale]
+        );
+    }
+
+    #[test]
+    fn degenerate_biomes() {
+        let points: Vec<_> = (0..20).map(|x| (x * 4, 0, biome_id::plains)).collect();
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert_eq!(
+            validate_evidence(&seed_info),
+            vec![Diagnostic::SingleBiome(BiomeId(biome_id::plains))]
+        );
+        assert_eq!(estimate_evidence_strength(&seed_info).total_bits(), 0.0);
+
+        let points: Vec<_> = (0..20)
+            .map(|x| {
+                let b = if x < 10 {
+                    biome_id::ocean
+                } else {
+                    biome_id::deepOcean
+                };
+                (x * 4, 0, b)
+            })
+            .collect();
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert_eq!(validate_evidence(&seed_info), vec![Diagnostic::OnlyOcean]);
+
+        // Only rivers is valid evidence for the river seed finder
+        let points: Vec<_> = (0..20).map(|x| (x, 0, biome_id::river)).collect();
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert!(validate_evidence(&seed_info).is_empty());
+        let points: Vec<_> = (0..20).map(|x| (x, 0, biome_id::frozenRiver)).collect();
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert!(validate_evidence(&seed_info).is_empty());
+
+        // A border between two land biomes is useful
+        let points: Vec<_> = (0..20)
+            .map(|x| {
+                let b = if x < 10 {
+                    biome_id::plains
+                } else {
+                    biome_id::forest
+                };
+                (x * 4, 0, b)
+            })
+            .collect();
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+        let strength = estimate_evidence_strength(&seed_info);
+        assert!(strength.biome_bits >= 2.0);
+        assert!(!strength.is_enough());
+    }
+
+    #[test]
+    fn search_difficulty() {
+        // No evidence: the search is not tractable
+        let seed_info = seed_info_with_biomes("1.7", &[]);
+        let d = estimate_search_difficulty(&seed_info);
+        assert!(!d.tractable);
+        assert!(!d.is_unique());
+        assert!(d.more_river_tiles > 0);
+        assert_eq!(d.more_structures, 0);
+        assert!(d.to_string().contains("more river tiles"), "{}", d);
+
+        // A long river with plains around it
+        let mut points = vec![];
+        for x in 0..40 {
+            points.push((x * 4, 0, biome_id::river));
+            points.push((x * 4, 4, biome_id::plains));
+            points.push((x * 4, -4, biome_id::forest));
+        }
+        let seed_info = seed_info_with_biomes("1.7", &points);
+        let d2 = estimate_search_difficulty(&seed_info);
+        assert!(d2.tractable);
+        assert!(d2.candidates_64_log2 < d.candidates_64_log2);
+        assert!(d2.more_river_tiles < d.more_river_tiles);
+
+        // The hashed seed is enough to find a unique seed
+        let mut seed_info = seed_info;
+        seed_info.world_seed_hash = Some(1234);
+        let d3 = estimate_search_difficulty(&seed_info);
+        assert!(d3.is_unique());
+        assert_eq!(d3.more_river_tiles, 0);
+        assert_eq!(d3.more_structures, 0);
+    }
+
+    #[test]
+    fn verify_known_seed() {
+        let world_seed = 1234;
+        let version = MinecraftVersion::Java1_7;
+        let area = Area {
+            x: 100,
+            z: -50,
+            w: 8,
+            h: 8,
+        };
+        let map = generate_up_to_layer(version, area, world_seed, version.num_layers(), 0);
+        let mut points = vec![];
+        for x in 0..8 {
+            for z in 0..8 {
+                points.push((area.x + x, area.z + z, map.get(area.x + x, area.z + z)));
+            }
+        }
+        let mut seed_info = seed_info_with_biomes("1.7", &points);
+        let report = verify_seed(world_seed, &seed_info, version);
+        assert!(report.is_match(), "{:?}", report.mismatches);
+        assert_eq!(report.checked, 64);
+        assert!(report.score.is_some());
+
+        // One wrong biome and one wrong slime chunk
+        let wrong = if map.get(100, -50) == biome_id::plains {
+            biome_id::desert
+        } else {
+            biome_id::plains
+        };
+        seed_info
+            .biomes
+            .entry(BiomeId(wrong))
+            .or_default()
+            .push(Point { x: 100, z: -50 });
+        let chunk = (0..)
+            .map(|x| Chunk::new(x, 0))
+            .find(|c| !is_slime_chunk(world_seed as u64, c))
+            .unwrap();
+        seed_info.positive.slime_chunks.push(chunk);
+        let report = verify_seed(world_seed, &seed_info, version);
+        assert_eq!(report.checked, 66);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                Mismatch::Biome {
+                    point: Point { x: 100, z: -50 },
+                    expected: BiomeId(wrong),
+                    generated: BiomeId(map.get(100, -50)),
+                },
+                Mismatch::SlimeChunk {
+                    chunk,
+                    expected: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ocean_at_spawn() {
+        // Only the oceans near the origin are checked
+        let seed_info = seed_info_with_biomes(
+            "1.7",
+            &[(0, 0, biome_id::plains), (5000, 5000, biome_id::ocean)],
+        );
+        let seeds = [1234];
+        assert!(check_ocean_at_spawn(&seed_info, MinecraftVersion::Java1_7, &seeds).is_empty());
+        // 1.18 does not force an island at the origin
+        let seed_info = seed_info_with_biomes("1.18", &[(0, 0, biome_id::ocean)]);
+        assert!(check_ocean_at_spawn(&seed_info, MinecraftVersion::Java1_18, &seeds).is_empty());
+
+        // An ocean near the origin where the only tested seed generates land in all the 1:4
+        // tiles around it
+        let version = MinecraftVersion::Java1_7;
+        let area = Area {
+            x: -SPAWN_ISLAND_RADIUS / 4 - 1,
+            z: -SPAWN_ISLAND_RADIUS / 4 - 1,
+            w: (SPAWN_ISLAND_RADIUS / 2 + 3) as u64,
+            h: (SPAWN_ISLAND_RADIUS / 2 + 3) as u64,
+        };
+        let map = generate_up_to_layer(version, area, seeds[0], version.num_layers() - 1, 0);
+        let land = (-SPAWN_ISLAND_RADIUS / 4..=SPAWN_ISLAND_RADIUS / 4)
+            .flat_map(|x| (-SPAWN_ISLAND_RADIUS / 4..=SPAWN_ISLAND_RADIUS / 4).map(move |z| (x, z)))
+            .find(|&(x, z)| {
+                (-1..=1).all(|dx| (-1..=1).all(|dz| !is_oceanic(map.get(x + dx, z + dz))))
+            })
+            .map(|(x, z)| Point { x: x * 4, z: z * 4 })
+            .expect("no land near the world origin");
+        let seed_info = seed_info_with_biomes("1.7", &[(land.x, land.z, biome_id::ocean)]);
+        assert_eq!(
+            check_ocean_at_spawn(&seed_info, version, &seeds),
+            vec![Diagnostic::OceanAtSpawn(land, version)]
+        );
+        // Points far from the origin are not checked
+        let far = Point {
+            x: land.x + 10_000,
+            z: land.z,
+        };
+        assert!(impossible_oceans_at_spawn(&[far], version, &seeds).is_empty());
+
+        let d = Diagnostic::OceanAtSpawn(Point { x: 1, z: 2 }, MinecraftVersion::Java1_7);
+        assert!(d.to_string().contains("(1, 2)"), "{}", d);
+    }
+
+    #[test]
+    fn possible_river() {
+        let seed_info = seed_info_with_biomes("1.7", &[(100, -200, biome_id::river)]);
+        assert_eq!(validate_evidence(&seed_info), vec![]);
+    }
+}
@@ -1,35 +1,105 @@
 #![allow(unused_labels)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate ndarray;
 
+pub mod nostd_core;
+pub use crate::nostd_core::java_math;
+pub use crate::nostd_core::java_rng;
+pub use crate::nostd_core::mc_rng;
+pub use crate::nostd_core::xoroshiro128plusplus;
+
+#[cfg(feature = "std")]
 #[rustfmt::skip]
 pub mod anvil;
+#[cfg(feature = "std")]
+pub mod biome_csv;
+#[cfg(feature = "std")]
+pub mod biome_export;
+#[cfg(feature = "std")]
+pub mod biome_image;
+#[cfg(feature = "std")]
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
 #[rustfmt::skip]
 pub mod biome_info;
+#[cfg(feature = "std")]
 pub mod biome_info_118;
+#[cfg(feature = "std")]
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
 #[rustfmt::skip]
 pub mod biome_layers;
+#[cfg(feature = "std")]
+#[allow(non_upper_case_globals)]
+pub mod biome_traits;
+#[cfg(feature = "std")]
+pub mod cancel;
+#[cfg(feature = "std")]
+pub mod candidate_export;
+#[cfg(feature = "std")]
 pub mod chunk;
+#[cfg(feature = "std")]
+pub mod chunk_rand;
+#[cfg(feature = "std")]
 pub mod climate;
+#[cfg(feature = "std")]
+pub mod coordinate_list;
+#[cfg(feature = "std")]
+pub mod debug_screen;
+#[cfg(feature = "std")]
+pub mod demo_world;
+#[cfg(feature = "std")]
+pub mod end_biomes;
+#[cfg(feature = "std")]
+pub mod evidence;
+#[cfg(feature = "std")]
 pub mod fastanvil_ext;
+#[cfg(feature = "std")]
 pub mod gen_pairs3;
-pub mod java_rng;
-#[rustfmt::skip]
-pub mod mc_rng;
+#[cfg(feature = "std")]
+pub mod live_session;
+#[cfg(feature = "std")]
+pub mod lru_cache;
+#[cfg(feature = "std")]
+pub mod map_metrics;
+#[cfg(feature = "std")]
 pub mod noise_generator;
+#[cfg(feature = "std")]
 pub mod population;
+#[cfg(feature = "std")]
+pub mod river_fingerprints;
+#[cfg(feature = "std")]
+pub mod screenshot;
+#[cfg(feature = "std")]
+pub mod search_checkpoint;
+#[cfg(feature = "std")]
+pub mod seed_filter;
+#[cfg(feature = "std")]
 #[rustfmt::skip]
 pub mod seed_info;
+#[cfg(feature = "std")]
 pub mod slime;
+#[cfg(feature = "std")]
 pub mod spline;
+#[cfg(feature = "std")]
 pub mod strict_parse_int;
+#[cfg(feature = "std")]
 pub mod structures;
+#[cfg(feature = "svg")]
+pub mod svg;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod triage;
+#[cfg(feature = "std")]
 pub mod voronoi;
+#[cfg(feature = "std")]
+pub mod wasm_error;
+#[cfg(feature = "std")]
 pub mod weak_alloc;
-pub mod xoroshiro128plusplus;
+#[cfg(feature = "std")]
 pub mod zip_ext;
@@ -0,0 +1,220 @@
+//! Seed cracking with evidence that arrives while playing
+//!
+//! A `LiveSession` receives the evidence one observation at a time, for example from a mod or a
+//! bot that sends the coordinates over a channel. The full river search only runs once, when
+//! there is enough evidence to get a reasonable number of candidates. After that, each new
+//! observation is only used to rescore the existing candidates, which is much faster than
+//! searching again. Every time the candidates change, they are passed to a callback.
+
+use crate::biome_layers::river_seed_finder_iter;
+use crate::biome_layers::score_seed_list_with_config;
+use crate::biome_layers::SearchConfig;
+use crate::biome_layers::SeedCandidate;
+use crate::chunk::Chunk;
+use crate::chunk::Point;
+use crate::evidence::estimate_search_difficulty;
+use crate::evidence::search_evidence;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+use crate::seed_info::SeedInfo;
+use std::sync::mpsc::Receiver;
+
+/// One piece of evidence found in game
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Observation {
+    Biome(Point, BiomeId),
+    SlimeChunk(Chunk),
+    OceanRuin(Chunk),
+    Shipwreck(Chunk),
+}
+
+/// What was done with the candidates after adding an observation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LiveUpdate {
+    /// There is not enough evidence to start the full search yet
+    Waiting,
+    /// The observation was already known, nothing changed
+    Duplicate,
+    /// The full search was run, the candidates are new
+    Searched,
+    /// The existing candidates were scored again using all the evidence
+    Rescored,
+}
+
+type Callback = Box<dyn FnMut(LiveUpdate, &[SeedCandidate]) + Send>;
+
+pub struct LiveSession {
+    version: MinecraftVersion,
+    config: SearchConfig,
+    seed_info: SeedInfo,
+    candidates: Option<Vec<SeedCandidate>>,
+    callback: Callback,
+}
+
+impl LiveSession {
+    /// Start a session with the evidence of `seed_info`, which can be empty except for the
+    /// version. The callback is called with the new candidates, sorted by score, every time that
+    /// the candidates are searched or rescored. Returns an error if the version is not valid.
+    pub fn new<F>(seed_info: SeedInfo, config: SearchConfig, callback: F) -> Result<Self, String>
+    where
+        F: FnMut(LiveUpdate, &[SeedCandidate]) + Send + 'static,
+    {
+        let version = seed_info.version()?;
+
+        Ok(Self {
+            version,
+            config,
+            seed_info,
+            candidates: None,
+            callback: Box::new(callback),
+        })
+    }
+
+    /// All the evidence added so far
+    pub fn seed_info(&self) -> &SeedInfo {
+        &self.seed_info
+    }
+
+    /// The current candidates, or `None` if the full search has not been run yet
+    pub fn candidates(&self) -> Option<&[SeedCandidate]> {
+        self.candidates.as_deref()
+    }
+
+    /// Use a list of candidates found by a different search, for example a previous session, so
+    /// the full search is never run. The candidates are rescored using the current evidence.
+    pub fn set_candidates(&mut self, seeds: &[i64]) {
+        self.rescore(seeds.iter().copied());
+    }
+
+    // Returns false if the observation was already known
+    fn insert(&mut self, observation: Observation) -> bool {
+        fn insert<T: PartialEq>(v: &mut Vec<T>, x: T) -> bool {
+            if v.contains(&x) {
+                false
+            } else {
+                v.push(x);
+                true
+            }
+        }
+
+        let positive = &mut self.seed_info.positive;
+        match observation {
+            Observation::Biome(point, biome_id) => {
+                insert(self.seed_info.biomes.entry(biome_id).or_default(), point)
+            }
+            Observation::SlimeChunk(chunk) => insert(&mut positive.slime_chunks, chunk),
+            Observation::OceanRuin(chunk) => insert(&mut positive.ocean_ruins, chunk),
+            Observation::Shipwreck(chunk) => insert(&mut positive.shipwrecks, chunk),
+        }
+    }
+
+    fn rescore<I: IntoIterator<Item = i64>>(&mut self, seeds: I) {
+        let evidence = search_evidence(&self.seed_info);
+        let mut candidates =
+            score_seed_list_with_config(seeds, &evidence, self.version, &self.config);
+        candidates.sort_by_key(|c| (std::cmp::Reverse(c.score), c.world_seed));
+        (self.callback)(LiveUpdate::Rescored, &candidates);
+        self.candidates = Some(candidates);
+    }
+
+    fn search(&mut self) {
+        let evidence = search_evidence(&self.seed_info);
+        let mut candidates: Vec<SeedCandidate> =
+            river_seed_finder_iter(&evidence, self.version, 0, 1 << 24, &self.config).collect();
+        candidates.sort_by_key(|c| (std::cmp::Reverse(c.score), c.world_seed));
+        (self.callback)(LiveUpdate::Searched, &candidates);
+        self.candidates = Some(candidates);
+    }
+
+    /// Add one observation and update the candidates. The full search runs in the calling
+    /// thread, and it can take a long time.
+    pub fn add(&mut self, observation: Observation) -> LiveUpdate {
+        if !self.insert(observation) {
+            return LiveUpdate::Duplicate;
+        }
+
+        match self.candidates.take() {
+            Some(candidates) => {
+                self.rescore(candidates.into_iter().map(|c| c.world_seed));
+                LiveUpdate::Rescored
+            }
+            None if estimate_search_difficulty(&self.seed_info).tractable => {
+                self.search();
+                LiveUpdate::Searched
+            }
+            None => LiveUpdate::Waiting,
+        }
+    }
+
+    /// Add all the observations received from a channel, until the channel is closed
+    pub fn run(&mut self, rx: Receiver<Observation>) {
+        for observation in rx {
+            self.add(observation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_layers::generate;
+    use crate::biome_layers::Area;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn rescore_candidates() {
+        let version = MinecraftVersion::Java1_7;
+        let world_seed = 1234;
+        let other_seed = 5678;
+        let updates = Arc::new(Mutex::new(vec![]));
+        let updates_callback = Arc::clone(&updates);
+        let mut seed_info = SeedInfo::default();
+        seed_info.version = "1.7".to_string();
+        let mut session = LiveSession::new(seed_info, SearchConfig::default(), move |u, c| {
+            updates_callback.lock().unwrap().push((u, c.len()));
+        })
+        .unwrap();
+
+        // One biome is not enough to start the full search
+        let biome = generate(version, Area { x: 0, z: 0, w: 1, h: 1 }, world_seed, 0).a[(0, 0)];
+        let observation = Observation::Biome(Point { x: 0, z: 0 }, BiomeId(biome));
+        assert_eq!(session.add(observation), LiveUpdate::Waiting);
+        assert_eq!(session.candidates(), None);
+        assert!(updates.lock().unwrap().is_empty());
+
+        session.set_candidates(&[world_seed, other_seed]);
+        assert_eq!(updates.lock().unwrap().len(), 1);
+
+        let (tx, rx) = channel();
+        for i in 0..20 {
+            let area = Area {
+                x: i * 97 - 800,
+                z: 600 - i * 61,
+                w: 1,
+                h: 1,
+            };
+            let biome = generate(version, area, world_seed, 0).a[(0, 0)];
+            tx.send(Observation::Biome(
+                Point {
+                    x: area.x,
+                    z: area.z,
+                },
+                BiomeId(biome),
+            ))
+            .unwrap();
+        }
+        drop(tx);
+        session.run(rx);
+
+        let candidates = session.candidates().unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].world_seed, world_seed);
+        assert_eq!(
+            updates.lock().unwrap().last(),
+            Some(&(LiveUpdate::Rescored, 1))
+        );
+        assert_eq!(session.add(observation), LiveUpdate::Duplicate);
+    }
+}
@@ -0,0 +1,183 @@
+//! Small least recently used cache
+//!
+//! Used to keep the last generated maps and generators in the web frontends, where the same
+//! fragments are requested again when panning the map.
+
+use std::collections::VecDeque;
+
+/// Cache that keeps the `capacity` most recently used values. The lookup is a linear search, so
+/// this is only intended for a small number of entries.
+#[derive(Clone, Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    // Maximum sum of the weights of the values
+    max_weight: usize,
+    weight: fn(&V) -> usize,
+    total_weight: usize,
+    // Least recently used first
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_max_weight(capacity, usize::MAX, |_| 0)
+    }
+
+    /// Cache that also limits the sum of `weight(value)`, for example the size in bytes of the
+    /// values. A value heavier than `max_weight` is not cached.
+    pub fn with_max_weight(capacity: usize, max_weight: usize, weight: fn(&V) -> usize) -> Self {
+        Self {
+            capacity,
+            max_weight,
+            weight,
+            total_weight: 0,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sum of the weights of the cached values
+    pub fn total_weight(&self) -> usize {
+        self.total_weight
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.total_weight = 0;
+    }
+
+    /// Returns the value of `key`, and marks it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(i).unwrap();
+        self.entries.push_back(entry);
+
+        self.entries.back().map(|(_, v)| v)
+    }
+
+    /// Insert a value, removing the least recently used values if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(i) = self.entries.iter().position(|(k, _)| *k == key) {
+            let (_, old) = self.entries.remove(i).unwrap();
+            self.total_weight -= (self.weight)(&old);
+        }
+        let weight = (self.weight)(&value);
+        if self.capacity == 0 || weight > self.max_weight {
+            return;
+        }
+        while self.entries.len() == self.capacity || self.total_weight + weight > self.max_weight {
+            let (_, old) = self.entries.pop_front().unwrap();
+            self.total_weight -= (self.weight)(&old);
+        }
+        self.total_weight += weight;
+        self.entries.push_back((key, value));
+    }
+
+    /// Returns a copy of the value of `key`, calling `f` to create it if it is not in the cache.
+    /// A cache with capacity 0 does not cache anything, so it calls `f` every time.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> V
+    where
+        V: Clone,
+    {
+        if let Some(value) = self.get(&key) {
+            return value.clone();
+        }
+        let value = f();
+        self.insert(key, value.clone());
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut c = LruCache::new(2);
+        c.insert(1, "a");
+        c.insert(2, "b");
+        // Use 1, so 2 is the least recently used
+        assert_eq!(c.get(&1), Some(&"a"));
+        c.insert(3, "c");
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.get(&2), None);
+        assert_eq!(c.get(&1), Some(&"a"));
+        assert_eq!(c.get(&3), Some(&"c"));
+
+        // Replace existing value
+        c.insert(1, "d");
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.get(&1), Some(&"d"));
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut c = LruCache::new(1);
+        let mut calls = 0;
+        for _ in 0..3 {
+            c.get_or_insert_with(5, || {
+                calls += 1;
+                10
+            });
+        }
+        assert_eq!(calls, 1);
+        assert_eq!(c.get_or_insert_with(6, || 12), 12);
+        assert_eq!(c.get(&5), None);
+    }
+
+    #[test]
+    fn evict_by_weight() {
+        let mut c = LruCache::with_max_weight(8, 10, |v: &Vec<u8>| v.len());
+        c.insert(1, vec![0; 4]);
+        c.insert(2, vec![0; 4]);
+        assert_eq!(c.total_weight(), 8);
+        // Does not fit, so 1 is removed
+        c.insert(3, vec![0; 4]);
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.total_weight(), 8);
+        assert_eq!(c.get(&1), None);
+
+        // Replacing a value updates the weight
+        c.insert(2, vec![0; 1]);
+        assert_eq!(c.total_weight(), 5);
+
+        // Too heavy to be cached
+        c.insert(4, vec![0; 11]);
+        assert_eq!(c.get(&4), None);
+        assert_eq!(c.total_weight(), 5);
+
+        c.clear();
+        assert_eq!(c.total_weight(), 0);
+    }
+
+    #[test]
+    fn capacity_0() {
+        let mut c = LruCache::new(0);
+        c.insert(1, 7);
+        assert!(c.is_empty());
+        assert_eq!(c.get(&1), None);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let v = c.get_or_insert_with(5, || {
+                calls += 1;
+                10
+            });
+            assert_eq!(v, 10);
+        }
+        assert_eq!(calls, 3);
+        assert!(c.is_empty());
+    }
+}
@@ -12,15 +12,19 @@ use slime_seed_finder::biome_layers::Map3D;
 use slime_seed_finder::chunk::Chunk;
 use slime_seed_finder::chunk::Point;
 use slime_seed_finder::chunk::Point3D4;
+use slime_seed_finder::java_rng::parse_seed;
 use slime_seed_finder::java_rng::JavaRng;
 use slime_seed_finder::population::MossyFloor;
+use slime_seed_finder::search_checkpoint::SearchCheckpoint;
 use slime_seed_finder::seed_info::biomes_from_map;
 use slime_seed_finder::seed_info::BiomeId;
+use slime_seed_finder::seed_info::FragmentVersion;
 use slime_seed_finder::seed_info::MinecraftVersion;
 use slime_seed_finder::seed_info::SeedInfo;
 use slime_seed_finder::slime::generate_slime_chunks_and_not;
 use slime_seed_finder::slime::seed_from_slime_chunks;
 use slime_seed_finder::slime::seed_from_slime_chunks_and_candidates;
+use slime_seed_finder::slime::SlimeChunks;
 use slime_seed_finder::*;
 use std::convert::{TryFrom, TryInto};
 use std::ffi::OsStr;
@@ -64,7 +68,7 @@ enum Opt {
         /// To avoid problems with negative seeds, use the following syntax:
         /// -s=-1234 or --seed=-1234
         /// If left blank, will generate a random seed and print it on stderr.
-        #[clap(short = 's', long)]
+        #[clap(short = 's', long, parse(try_from_str = parse_seed))]
         seed: Option<i64>,
         /// When creating a Minecraft world, if the seed field is left blank,
         /// the seed will be generated randomly using (new Random()).nextLong(),
@@ -131,6 +135,85 @@ enum Opt {
         /// Where to write the found seeds as a JSON array
         #[clap(short = 'o', long, parse(from_os_str))]
         output_file: Option<PathBuf>,
+        /// Where to write the found seeds with their score, class and
+        /// version. The format is CSV if the extension is ".csv", and JSON
+        /// lines otherwise.
+        #[clap(long, parse(from_os_str))]
+        export_file: Option<PathBuf>,
+        /// Save the progress to this file, and continue from it if it
+        /// exists. The checkpoints of different machines searching disjoint
+        /// ranges can be joined using "merge-checkpoints".
+        #[clap(long, parse(from_os_str))]
+        checkpoint: Option<PathBuf>,
+        /// Start of the range of the 26-bit stage to search
+        #[clap(long, default_value = "0")]
+        range_lo: u32,
+        /// End of the range of the 26-bit stage to search, exclusive
+        #[clap(long, default_value = "16777216")]
+        range_hi: u32,
+    },
+
+    /// Join the checkpoints saved by the "rivers" command on different
+    /// machines into one checkpoint
+    #[clap(name = "merge-checkpoints")]
+    MergeCheckpoints {
+        /// Where to write the merged checkpoint
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: PathBuf,
+        /// Checkpoints to merge, created using the same evidence and config
+        #[clap(parse(from_os_str))]
+        checkpoints: Vec<PathBuf>,
+    },
+
+    /// Use rivers to find the 26-bit candidates, and print each one as soon
+    /// as it is found, so they can be piped into other tools
+    #[clap(name = "candidates-26")]
+    Candidates26 {
+        /// File containing the SeedInfo
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+    },
+
+    /// Check the candidates exported by the "rivers" command again using
+    /// the evidence of a SeedInfo, and sort them by the new score
+    #[clap(name = "rerank")]
+    Rerank {
+        /// File containing the SeedInfo
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// File containing the exported candidates, as CSV or JSON lines
+        #[clap(long, parse(from_os_str))]
+        candidates: PathBuf,
+        /// Where to write the accepted candidates. The format is CSV if the
+        /// extension is ".csv", and JSON lines otherwise.
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+    },
+
+    /// Check all the evidence of a SeedInfo using a known seed, and list
+    /// everything that does not match
+    #[clap(name = "verify")]
+    Verify {
+        /// File containing the SeedInfo
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// The seed to check.
+        /// To avoid problems with negative seeds, use the following syntax:
+        /// -s=-1234 or --seed=-1234
+        #[clap(short = 's', long, parse(try_from_str = parse_seed))]
+        seed: i64,
+    },
+
+    /// Group the candidates that generate the same biomes far away, and
+    /// suggest one coordinate to visit in game to split the largest group
+    #[clap(name = "triage")]
+    Triage {
+        /// File containing the list of candidates as a JSON array
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// Minecraft version to use (Java edition).
+        #[clap(long)]
+        mc_version: String,
     },
 
     /// Use rivers from an unexplored treasure map to find the seed
@@ -142,6 +225,106 @@ enum Opt {
         /// Where to write the found seeds as a JSON array
         #[clap(short = 'o', long, parse(from_os_str))]
         output_file: Option<PathBuf>,
+        /// Also try the treasure map moved by one pixel in each axis, in case
+        /// it is not aligned correctly. This is 4 times slower.
+        #[clap(long)]
+        any_alignment: bool,
+    },
+
+    /// Use a CSV file of quarter-scale biomes to find the seed. The biomes
+    /// already have 1:4 resolution, so they are used as evidence directly.
+    /// This is slow: each 26-bit candidate needs a 22-bit bruteforce.
+    #[clap(name = "biome-csv-rivers")]
+    BiomeCsvRivers {
+        /// CSV file with one "x,z,biome_id" line per 4x4 block area, where
+        /// x and z are block coordinates
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// Where to write the found seeds as a JSON array
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// Minecraft version to use (Java edition).
+        /// Supported values: from 1.7 to 1.17
+        #[clap(long)]
+        mc_version: String,
+        /// Also reject the seeds that cannot generate the mushroom island
+        /// shores of the export, with or without a river
+        #[clap(long)]
+        check_mushroom_shores: bool,
+    },
+
+    /// Convert a biome map image into biome evidence. The image can be
+    /// rendered by the "rendermap" command or by AMIDST.
+    #[clap(name = "image-evidence")]
+    ImageEvidence {
+        /// Image with one pixel per block, or one pixel per 4x4 blocks with
+        /// --quarter-scale
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// Where to write the SeedInfo
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// x block coordinate of the top-left pixel.
+        /// To avoid problems with negative coordinates, use the following
+        /// syntax: -x=-2
+        #[clap(short = 'x', default_value = "0")]
+        x: i64,
+        /// z block coordinate of the top-left pixel
+        #[clap(short = 'z', default_value = "0")]
+        z: i64,
+        /// Each pixel is a 4x4 block area, as in the AMIDST map
+        #[clap(long)]
+        quarter_scale: bool,
+        /// Minecraft version to use (Java edition).
+        #[clap(long)]
+        mc_version: String,
+    },
+
+    /// Find the rivers of an overhead screenshot of the world, using the
+    /// colors of water, snow and land, and write them as a SeedInfo
+    #[clap(name = "screenshot-evidence")]
+    ScreenshotEvidence {
+        /// Screenshot of the world seen from above, with x to the right and z
+        /// down
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// Where to write the SeedInfo
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// Approximate x block coordinate of the top-left corner of the
+        /// screenshot, with an error of at most half a block.
+        /// To avoid problems with negative coordinates, use the following
+        /// syntax: -x=-2
+        #[clap(short = 'x', default_value = "0")]
+        x: f64,
+        /// Approximate z block coordinate of the top-left corner
+        #[clap(short = 'z', default_value = "0")]
+        z: f64,
+        /// Width of one pixel in blocks, for example 0.25 if each block is
+        /// 4 pixels wide
+        #[clap(long)]
+        blocks_per_pixel: f64,
+        /// Minecraft version to use (Java edition).
+        #[clap(long)]
+        mc_version: String,
+    },
+
+    /// Add the observations of a coordinate list, with one "x,y,z,label" line
+    /// per observation, to a SeedInfo
+    #[clap(name = "import-coordinates")]
+    ImportCoordinates {
+        /// Coordinate list, where each label is a biome or a structure
+        #[clap(short = 'i', long, parse(from_os_str))]
+        input_file: PathBuf,
+        /// SeedInfo to extend. Defaults to an empty SeedInfo
+        #[clap(long, parse(from_os_str))]
+        seed_info: Option<PathBuf>,
+        /// Where to write the SeedInfo
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// Minecraft version to use (Java edition), if there is no SeedInfo
+        #[clap(long)]
+        mc_version: Option<String>,
     },
 
     #[clap(name = "extend48")]
@@ -160,7 +343,7 @@ enum Opt {
         /// The seed for which to generate the biome map.
         /// To avoid problems with negative seeds, use the following syntax:
         /// -s=-1234 or --seed=-1234
-        #[clap(short = 's', long)]
+        #[clap(short = 's', long, parse(try_from_str = parse_seed))]
         seed: i64,
         /// x position of the top-left coordinate of the map.
         /// To avoid problems with negative coordinates, use the following
@@ -194,13 +377,54 @@ enum Opt {
         last_layer: Option<u32>,
     },
 
+    /// Export the biomes of an area as CSV or JSON lines, one line per
+    /// block
+    #[clap(name = "export-biomes")]
+    ExportBiomes {
+        /// The seed for which to generate the biome map.
+        /// To avoid problems with negative seeds, use the following syntax:
+        /// -s=-1234 or --seed=-1234
+        #[clap(short = 's', long, parse(try_from_str = parse_seed))]
+        seed: i64,
+        /// x position of the top-left coordinate of the map.
+        /// To avoid problems with negative coordinates, use the following
+        /// syntax: -x=-2
+        #[clap(short = 'x', default_value = "0")]
+        x: i64,
+        /// z position of the top-left coordinate of the map
+        #[clap(short = 'z', default_value = "0")]
+        z: i64,
+        /// width
+        #[clap(short = 'w', long, default_value = "16")]
+        width: u32,
+        /// height
+        #[clap(short = 'h', long, default_value = "16")]
+        height: u32,
+        /// Output filename. The format is CSV if the extension is ".csv",
+        /// and JSON lines otherwise. Defaults to CSV on stdout.
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// Minecraft version to use (Java edition).
+        /// Supported values: from 1.3 to 1.18
+        #[clap(long)]
+        mc_version: String,
+        /// Write the numeric biome ids instead of the "minecraft:*" names
+        /// used since 1.18
+        #[clap(long)]
+        numeric_ids: bool,
+        /// Generate the independent branches of the layers in parallel.
+        /// Faster for large areas
+        #[clap(long)]
+        parallel: bool,
+    },
+
     /// Generate an unexplored treasure map, but without the treasure marker.
     #[clap(name = "treasure")]
     Treasure {
         /// The seed for which to generate the treasure map.
         /// To avoid problems with negative seeds, use the following syntax:
         /// -s=-1234 or --seed=-1234
-        #[clap(short = 's', long)]
+        #[clap(short = 's', long, parse(try_from_str = parse_seed))]
         seed: i64,
         /// x position of the map as "fragment" coordinate.
         /// The formula to convert between fragment coordinates and
@@ -293,10 +517,10 @@ enum Opt {
     /// Given a seed, calculate the seed hash
     #[clap(name = "seed-hash")]
     SeedHash {
-        /// Seed as 64-bit signed integer.
+        /// Seed as 64-bit signed or unsigned integer, or as hexadecimal number starting with "0x".
         /// To avoid problems with negative seeds, use the following
         /// syntax: --seed=-2
-        #[clap(long)]
+        #[clap(long, parse(try_from_str = parse_seed))]
         seed: i64,
     },
 
@@ -374,7 +598,7 @@ enum Opt {
         limit_steps_back: u64,
         #[clap(long)]
         dungeon_seed: String,
-        #[clap(long)]
+        #[clap(long, parse(try_from_str = parse_seed))]
         world_seed: i64,
     },
 
@@ -433,6 +657,37 @@ enum Opt {
         #[clap(long)]
         block: String,
     },
+
+    /// Print the layers of the biome generator as a graphviz graph
+    #[clap(name = "layer-graph")]
+    LayerGraph {
+        /// Minecraft version to use (Java edition).
+        /// Supported values: from 1.3 to 1.18
+        #[clap(long)]
+        mc_version: String,
+    },
+
+    /// Compare the biomes generated by consecutive versions in random
+    /// areas, and print where they differ. For example:
+    /// version-diff 1.13 1.14 1.15
+    #[clap(name = "version-diff")]
+    VersionDiff {
+        /// Number of random areas to compare
+        #[clap(short = 'n', long, default_value = "100")]
+        samples: u32,
+        /// Size of each area, in blocks
+        #[clap(long, default_value = "64")]
+        size: u64,
+        /// Seed used to choose the random world seeds and areas
+        #[clap(long, default_value = "0")]
+        rng_seed: i64,
+        /// Number of biome changes to print for each pair of versions
+        #[clap(long, default_value = "10")]
+        top: usize,
+        /// Minecraft versions to compare (Java edition), at least 2.
+        /// Supported values: from 1.3 to 1.18
+        mc_versions: Vec<String>,
+    },
 }
 
 fn main() {
@@ -512,24 +767,46 @@ fn main() {
             output_file,
         } => {
             let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
-            let c = seed_info.positive.slime_chunks;
-            let nc = seed_info.negative.slime_chunks;
+            let observations = seed_info.slime_observations();
+            let c = seed_info.positive.slime_chunks.clone();
+            let nc = seed_info.negative.slime_chunks.clone();
             let false_c = seed_info.options.error_margin_slime_chunks as usize;
             let false_nc = seed_info.options.error_margin_slime_chunks_negative as usize;
 
-            if c.is_empty() && nc.is_empty() {
+            if observations.is_empty() {
                 // Can't find seed without slime chunks
                 println!("Not enough slime chunks");
                 return;
             }
 
             // All possible 48 bit seeds
-            let seeds = if let Some(path) = candidate_seeds {
+            let mut seeds = if let Some(model) = &seed_info.options.slime_evidence_model {
+                let sc = SlimeChunks::new_probabilistic(&observations, model);
+                if let Some(path) = candidate_seeds {
+                    let candidates = read_seeds_from_file(path).expect("Error reading candidates");
+                    candidates
+                        .into_iter()
+                        .filter(|&seed| sc.try_seed(seed))
+                        .collect()
+                } else {
+                    sc.find_seed()
+                }
+            } else if let Some(path) = candidate_seeds {
                 let candidates = read_seeds_from_file(path).expect("Error reading candidates");
                 seed_from_slime_chunks_and_candidates(&c, false_c, &nc, false_nc, candidates)
             } else {
                 seed_from_slime_chunks(&c, false_c, &nc, false_nc)
             };
+            // Ocean ruins and shipwrecks only depend on the lower 48 bits of the seed
+            let region_structures = structures::region_structures(&seed_info.positive);
+            if !region_structures.is_empty() {
+                let version = seed_info.version.parse().expect("Invalid version");
+                seeds = structures::filter_seeds_48_using_region_structures(
+                    &seeds,
+                    &region_structures,
+                    version,
+                );
+            }
             println!(
                 "Found {} 48-bit seeds:\n{}",
                 seeds.len(),
@@ -568,29 +845,122 @@ fn main() {
         Opt::Rivers {
             input_file,
             output_file,
+            export_file,
+            checkpoint,
+            range_lo,
+            range_hi,
         } => {
             let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
-            let version = seed_info.version.parse().unwrap();
+            // Fail fast when the evidence cannot be used to find the seed
+            let mut degenerate = false;
+            for d in evidence::validate_evidence(&seed_info) {
+                warn!("{}", d);
+                degenerate |= matches!(
+                    d,
+                    evidence::Diagnostic::SingleBiome(_) | evidence::Diagnostic::OnlyOcean
+                );
+            }
+            if degenerate {
+                error!("Not enough evidence to find the seed");
+                return;
+            }
+            let strength = evidence::estimate_evidence_strength(&seed_info);
+            if !strength.is_enough() {
+                warn!(
+                    "The evidence has about {:.0} bits of information, expect many candidates",
+                    strength.total_bits()
+                );
+            }
+            let difficulty = evidence::estimate_search_difficulty(&seed_info);
+            if !difficulty.is_unique() {
+                warn!("{}", difficulty);
+            }
             // TODO: integrate the river seed finder into the "find" subcommand
-            let extra_biomes: Vec<_> = seed_info
-                .biomes
+            // Regions generated by a different version are stored in the nested seed infos.
+            // Regions generated by an unknown version use the version "any".
+            let mixed_fragments: Vec<_> = seed_info
+                .mixed_version_fragments()
+                .unwrap_or_else(|e| {
+                    error!("{}", e);
+                    std::process::exit(1);
+                })
+                .into_iter()
+                .map(|(version, s)| (version, evidence::search_evidence(s)))
+                .collect();
+            if let Err(e) = biome_layers::river_seed_finder_check_versions(&mixed_fragments) {
+                error!("{}", e);
+                return;
+            }
+            let known_fragments: Option<Vec<_>> = mixed_fragments
                 .iter()
-                .flat_map(|(id, vec_xz)| {
-                    if *id == BiomeId(biome_id::river) {
-                        vec![]
-                    } else {
-                        vec_xz.iter().map(|p| (*id, *p)).collect()
-                    }
+                .map(|(version, evidence)| match version {
+                    FragmentVersion::Known(v) => Some((*v, evidence.clone())),
+                    FragmentVersion::Any => None,
                 })
                 .collect();
 
             // All possible 64 bit seeds
-            let seeds = if let Some(rivers) = seed_info.biomes.get(&BiomeId(biome_id::river)) {
-                biome_layers::river_seed_finder(rivers, &extra_biomes, version)
+            let candidates = if mixed_fragments.iter().any(|(_, e)| !e.rivers.is_empty()) {
+                if mixed_fragments.len() > 1 {
+                    info!(
+                        "Using evidence from {} regions generated by different versions",
+                        mixed_fragments.len()
+                    );
+                }
+                let any_version = known_fragments.is_none();
+                if any_version {
+                    info!(
+                        "The version of some regions is unknown, trying versions {:?}",
+                        FragmentVersion::Any.versions()
+                    );
+                }
+                let config = biome_layers::SearchConfig::default();
+                if let (Some(path), Some(fragments)) = (checkpoint.as_ref(), known_fragments) {
+                    let mut state = if path.exists() {
+                        let state = SearchCheckpoint::load(path).unwrap_or_else(|e| {
+                            error!("Error reading checkpoint: {}", e);
+                            std::process::exit(1);
+                        });
+                        if let Err(e) = state.check_compatible(&fragments, &config) {
+                            error!("{}", e);
+                            std::process::exit(1);
+                        }
+                        info!(
+                            "Continuing from checkpoint, {:.2}% done",
+                            state.progress() * 100.0
+                        );
+                        state
+                    } else {
+                        SearchCheckpoint::new(&fragments, range_lo, range_hi, &config)
+                    };
+                    while !state.is_done() {
+                        state.step(&fragments, 1 << 16);
+                        state.save(path).expect("Error writing checkpoint");
+                        info!("{:.2}% done", state.progress() * 100.0);
+                    }
+                    state.candidates
+                } else {
+                    if checkpoint.is_some() {
+                        error!("Checkpoints can only be used if the version of all the regions is known");
+                        return;
+                    }
+                    let mut candidates: Vec<_> =
+                        biome_layers::river_seed_finder_mixed_version_iter(
+                            &mixed_fragments,
+                            range_lo,
+                            range_hi,
+                            &config,
+                        )
+                        .expect("Versions already checked")
+                        .collect();
+                    candidates.sort_unstable_by_key(|c| c.world_seed);
+                    candidates
+                }
             } else {
                 error!("No rivers in seedInfo");
                 vec![]
             };
+            let seeds: Vec<i64> = candidates.iter().map(|c| c.world_seed).collect();
 
             println!(
                 "Found {} 64-bit seeds:\n{}",
@@ -601,11 +971,154 @@ fn main() {
             if let Some(of) = output_file {
                 write_seeds_to_file(&seeds, of).expect("Error writing seeds to file");
             }
+            if let Some(ef) = export_file {
+                let exported: Vec<_> = candidates
+                    .iter()
+                    .map(|c| {
+                        candidate_export::ExportedCandidate::from_seed_candidate(
+                            c,
+                            &seed_info.version,
+                        )
+                    })
+                    .collect();
+                let format = candidate_export::ExportFormat::from_path(&ef);
+                let w = std::io::BufWriter::new(File::create(ef).expect("Error creating file"));
+                candidate_export::write_candidates(w, &exported, format)
+                    .expect("Error writing candidates to file");
+            }
+        }
+
+        Opt::MergeCheckpoints {
+            output_file,
+            checkpoints,
+        } => {
+            let mut merged: Option<SearchCheckpoint> = None;
+            for path in checkpoints {
+                let c = SearchCheckpoint::load(&path).unwrap_or_else(|e| {
+                    error!("Error reading {}: {}", path.display(), e);
+                    std::process::exit(1);
+                });
+                match merged.as_mut() {
+                    None => merged = Some(c),
+                    Some(m) => {
+                        if let Err(e) = m.merge(&c) {
+                            error!("Cannot merge {}: {}", path.display(), e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+            let merged = merged.unwrap_or_else(|| {
+                error!("No checkpoints to merge");
+                std::process::exit(1);
+            });
+            println!(
+                "{:.2}% done, {} candidates, remaining ranges: {:?}",
+                merged.progress() * 100.0,
+                merged.candidates.len(),
+                merged.remaining()
+            );
+            merged.save(output_file).expect("Error writing checkpoint");
+        }
+
+        Opt::Candidates26 { input_file } => {
+            let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
+            let candidates = evidence::candidate_26_iter(&seed_info).unwrap_or_else(|e| {
+                error!("{}", e);
+                std::process::exit(1);
+            });
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            for seed in candidates {
+                // Stop when the output is closed, for example when piped into head
+                if writeln!(stdout, "{:07X}", seed)
+                    .and_then(|()| stdout.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+
+        Opt::Rerank {
+            input_file,
+            candidates,
+            output_file,
+        } => {
+            let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
+            let file = File::open(candidates).expect("Error opening candidates file");
+            let candidates = candidate_export::read_candidates(std::io::BufReader::new(file))
+                .expect("Error reading candidates");
+            let ranked = candidate_export::rerank(
+                &candidates,
+                &evidence::search_evidence(&seed_info),
+                &biome_layers::SearchConfig::default(),
+            )
+            .expect("Error checking candidates");
+            println!(
+                "{} out of {} candidates accepted",
+                ranked.len(),
+                candidates.len()
+            );
+            for c in ranked.iter().take(10) {
+                println!("{}: {:?}", c.seed, c.score);
+            }
+
+            if let Some(of) = output_file {
+                let format = candidate_export::ExportFormat::from_path(&of);
+                let w = std::io::BufWriter::new(File::create(of).expect("Error creating file"));
+                candidate_export::write_candidates(w, &ranked, format)
+                    .expect("Error writing candidates to file");
+            }
+        }
+
+        Opt::Verify { input_file, seed } => {
+            let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
+            for (version, s) in seed_info.version_fragments().unwrap() {
+                let report = evidence::verify_seed(seed, s, version);
+                for m in &report.mismatches {
+                    println!("{}", m);
+                }
+                println!(
+                    "{:?}: {} out of {} checks match, seed finder score: {:?}",
+                    version,
+                    report.checked - report.mismatches.len(),
+                    report.checked,
+                    report.score
+                );
+            }
+        }
+
+        Opt::Triage {
+            input_file,
+            mc_version,
+        } => {
+            let version = mc_version.parse().unwrap();
+            let seeds = read_seeds_from_file_i64(input_file).expect("Error reading input file");
+            let triage = triage::triage_candidates(&seeds, version);
+            println!(
+                "{} candidates, {} groups with different biomes",
+                seeds.len(),
+                triage.groups.len()
+            );
+            for g in &triage.groups {
+                println!("{} candidates: {:?}", g.seeds.len(), g.seeds);
+            }
+            if let Some(split) = triage.split {
+                println!(
+                    "Check the biome at x={}, z={} to split the largest group:",
+                    split.point.x, split.point.z
+                );
+                for (biome, seeds) in &split.seeds_by_biome {
+                    println!("  biome {}: {:?}", biome.0, seeds);
+                }
+            }
         }
 
         Opt::TreasureRivers {
             input_file,
             output_file,
+            any_alignment,
         } => {
             let seed_info = SeedInfo::read(input_file).expect("Error reading seed info");
             let version = seed_info.version.parse().unwrap();
@@ -631,7 +1144,23 @@ fn main() {
             }
 
             // All possible 26 bit seeds
-            let seeds = biome_layers::treasure_map_river_seed_finder(&pmap, version, 0, 1 << 24);
+            let seeds = if any_alignment {
+                let candidates = biome_layers::treasure_map_river_seed_finder_any_alignment(
+                    &pmap,
+                    version,
+                    0,
+                    1 << 24,
+                );
+                for (c, (dx, dz)) in &candidates {
+                    info!(
+                        "{}: {}% of rivers match with offset ({}, {})",
+                        c.world_seed, c.score, dx, dz
+                    );
+                }
+                candidates.into_iter().map(|(c, _)| c.world_seed).collect()
+            } else {
+                biome_layers::treasure_map_river_seed_finder(&pmap, version, 0, 1 << 24)
+            };
             println!(
                 "Found {} 26-bit seeds:\n{}",
                 seeds.len(),
@@ -643,6 +1172,169 @@ fn main() {
             }
         }
 
+        Opt::BiomeCsvRivers {
+            input_file,
+            output_file,
+            mc_version,
+            check_mushroom_shores,
+        } => {
+            let version = mc_version.parse().unwrap();
+            let file = File::open(input_file).expect("Error opening biome CSV");
+            let biomes4 = biome_csv::read_biomes_csv_sparse_map(std::io::BufReader::new(file))
+                .expect("Error reading biome CSV");
+
+            let seeds = if let Some(biomes4) = biomes4 {
+                let seeds = biome_layers::river_seed_finder_quarter_scale_range(
+                    &biomes4,
+                    version,
+                    0,
+                    1 << 24,
+                );
+                if check_mushroom_shores {
+                    match biome_layers::filter_seeds_using_mushroom_shores(
+                        &seeds, &biomes4, version,
+                    ) {
+                        Some(seeds) => seeds,
+                        None => {
+                            error!("Rivers not implemented in version {:?}", version);
+                            vec![]
+                        }
+                    }
+                } else {
+                    seeds
+                }
+            } else {
+                error!("Biome CSV is empty");
+                vec![]
+            };
+
+            println!(
+                "Found {} 64-bit seeds:\n{}",
+                seeds.len(),
+                serde_json::to_string(&seeds).unwrap()
+            );
+
+            if let Some(of) = output_file {
+                write_seeds_to_file(&seeds, of).expect("Error writing seeds to file");
+            }
+        }
+
+        Opt::ImageEvidence {
+            input_file,
+            output_file,
+            x,
+            z,
+            quarter_scale,
+            mc_version,
+        } => {
+            let img = image::open(input_file)
+                .expect("Error opening image")
+                .into_rgba8();
+            let img = biome_layers::ImageBuffer {
+                width: img.width() as usize,
+                height: img.height() as usize,
+                data: img.into_raw(),
+            };
+            let (x, z) = if quarter_scale {
+                (x >> 2, z >> 2)
+            } else {
+                (x, z)
+            };
+            let m = biome_image::image_to_sparse_map(
+                &img,
+                x,
+                z,
+                biome_layers::ImageOrientation::XRight,
+                &biome_image::biome_palette(),
+            );
+            let known = biome_image::known_tiles(&m);
+            let total = img.width * img.height;
+            if known < total {
+                warn!(
+                    "{} of {} pixels do not have a known biome color",
+                    total - known,
+                    total
+                );
+            }
+
+            let mut seed_info = SeedInfo::default();
+            seed_info.version = mc_version;
+            if quarter_scale {
+                seed_info.biomes_quarter_scale =
+                    biome_image::sparse_map_to_biomes_quarter_scale(&m);
+            } else {
+                seed_info.biomes = biome_image::sparse_map_to_biomes(&m);
+            }
+
+            let buf = serde_json::to_string(&seed_info).expect("Serialization fail");
+            if let Some(output_file) = output_file {
+                fs::write(output_file, buf).expect("Error writing SeedInfo");
+            } else {
+                println!("{}", buf);
+            }
+        }
+
+        Opt::ScreenshotEvidence {
+            input_file,
+            output_file,
+            x,
+            z,
+            blocks_per_pixel,
+            mc_version,
+        } => {
+            let img = image::open(input_file)
+                .expect("Error opening image")
+                .into_rgba8();
+            let img = biome_layers::ImageBuffer {
+                width: img.width() as usize,
+                height: img.height() as usize,
+                data: img.into_raw(),
+            };
+            let seed_info =
+                screenshot::screenshot_seed_info(&img, blocks_per_pixel, x, z, mc_version)
+                    .unwrap_or_else(|e| {
+                        error!("{}", e);
+                        std::process::exit(1);
+                    });
+            info!("{}", seed_info.description);
+            match seed_info.biomes.get(&BiomeId(biome_id::river)) {
+                Some(rivers) => info!("Found {} river blocks", rivers.len()),
+                None => warn!("No rivers found in the screenshot"),
+            }
+
+            let buf = serde_json::to_string(&seed_info).expect("Serialization fail");
+            if let Some(output_file) = output_file {
+                fs::write(output_file, buf).expect("Error writing SeedInfo");
+            } else {
+                println!("{}", buf);
+            }
+        }
+
+        Opt::ImportCoordinates {
+            input_file,
+            seed_info,
+            output_file,
+            mc_version,
+        } => {
+            let mut seed_info = match seed_info {
+                Some(path) => SeedInfo::read(path).expect("Error reading seed info"),
+                None => SeedInfo::default(),
+            };
+            if let Some(mc_version) = mc_version {
+                seed_info.version = mc_version;
+            }
+            let file = File::open(input_file).expect("Error opening coordinate list");
+            coordinate_list::read_into_seed_info(std::io::BufReader::new(file), &mut seed_info)
+                .expect("Error reading coordinate list");
+
+            let buf = serde_json::to_string(&seed_info).expect("Serialization fail");
+            if let Some(output_file) = output_file {
+                fs::write(output_file, buf).expect("Error writing SeedInfo");
+            } else {
+                println!("{}", buf);
+            }
+        }
+
         Opt::Extend48 {
             input_file,
             output_file,
@@ -707,6 +1399,56 @@ fn main() {
             println!("Saved image to {}", output_file.to_string_lossy());
         }
 
+        Opt::ExportBiomes {
+            seed,
+            x,
+            z,
+            width,
+            height,
+            output_file,
+            mc_version,
+            numeric_ids,
+            parallel,
+        } => {
+            let version: MinecraftVersion = mc_version.parse().unwrap();
+            let area = Area {
+                x,
+                z,
+                w: width as u64,
+                h: height as u64,
+            };
+            let map = if parallel {
+                biome_layers::generate_parallel(version, area, seed)
+            } else {
+                biome_layers::generate(version, area, seed, 0)
+            };
+            let ids = if numeric_ids {
+                biome_export::BiomeIdFormat::Numeric
+            } else {
+                biome_export::BiomeIdFormat::ResourceName
+            };
+            match output_file {
+                Some(of) => {
+                    let format = candidate_export::ExportFormat::from_path(&of);
+                    let w = std::io::BufWriter::new(
+                        File::create(&of).expect("Error creating output file"),
+                    );
+                    biome_export::write_biome_map(w, &map, format, ids)
+                        .expect("Error writing biomes");
+                }
+                None => {
+                    let stdout = std::io::stdout();
+                    biome_export::write_biome_map(
+                        stdout.lock(),
+                        &map,
+                        candidate_export::ExportFormat::Csv,
+                        ids,
+                    )
+                    .expect("Error writing biomes");
+                }
+            }
+        }
+
         Opt::Treasure {
             seed,
             fragment_x,
@@ -1370,17 +2112,17 @@ fn main() {
                         let map_image = biome_layers::draw_map_image(&map);
                         let x = area.x;
                         let z = area.z;
-                        let width = area.w.try_into().unwrap();
-                        let height = area.h.try_into().unwrap();
+                        let width = area.w;
+                        let height = area.h;
                         let output_file = format!(
                             "biome_map_mc_{}_{}_{}_{}_{}x{}.png",
                             mc_version, world_seed, x, z, width, height
                         );
                         image::save_buffer(
                             output_file.clone(),
-                            &map_image,
-                            width,
-                            height,
+                            &map_image.data,
+                            map_image.width.try_into().unwrap(),
+                            map_image.height.try_into().unwrap(),
                             image::ColorType::Rgba8,
                         )
                         .unwrap();
@@ -1433,17 +2175,17 @@ fn main() {
                         let map_image = biome_layers::draw_map_image(&map);
                         let x = area.x;
                         let z = area.z;
-                        let width = area.w.try_into().unwrap();
-                        let height = area.h.try_into().unwrap();
+                        let width = area.w;
+                        let height = area.h;
                         let output_file = format!(
                             "biome_map_mc_{}_{}_{}_{}_{}x{}.png",
                             mc_version, world_seed, x, z, width, height
                         );
                         image::save_buffer(
                             output_file.clone(),
-                            &map_image,
-                            width,
-                            height,
+                            &map_image.data,
+                            map_image.width.try_into().unwrap(),
+                            map_image.height.try_into().unwrap(),
                             image::ColorType::Rgba8,
                         )
                         .unwrap();
@@ -1501,17 +2243,17 @@ fn main() {
                         let map_image = biome_layers::draw_map_image(&map.flatten_into_2d());
                         let x = area.x;
                         let z = area.z;
-                        let width = area.sx.try_into().unwrap();
-                        let height = area.sz.try_into().unwrap();
+                        let width = area.sx;
+                        let height = area.sz;
                         let output_file = format!(
                             "biome_map_mc_{}_{}_{}_{}_{}x{}.png",
                             mc_version, world_seed, x, z, width, height
                         );
                         image::save_buffer(
                             output_file.clone(),
-                            &map_image,
-                            width,
-                            height,
+                            &map_image.data,
+                            map_image.width.try_into().unwrap(),
+                            map_image.height.try_into().unwrap(),
                             image::ColorType::Rgba8,
                         )
                         .unwrap();
@@ -1630,6 +2372,43 @@ fn main() {
             let block_positions_json = serde_json::to_string(&block_positions).unwrap();
             println!("{}", block_positions_json);
         }
+
+        Opt::LayerGraph { mc_version } => {
+            let version: MinecraftVersion = mc_version.parse().unwrap();
+            print!("{}", biome_layers::layer_graph_dot(version));
+        }
+
+        Opt::VersionDiff {
+            samples,
+            size,
+            rng_seed,
+            top,
+            mc_versions,
+        } => {
+            let versions: Vec<MinecraftVersion> =
+                mc_versions.iter().map(|v| v.parse().unwrap()).collect();
+            if versions.len() < 2 {
+                error!("At least 2 versions are needed");
+                return;
+            }
+            for pair in versions.windows(2) {
+                let stats = biome_layers::version_diff(pair[0], pair[1], samples, size, rng_seed);
+                println!(
+                    "{:?} vs {:?}: {}/{} tiles differ ({:.2}%), {}/{} areas with differences",
+                    pair[0],
+                    pair[1],
+                    stats.different_tiles,
+                    stats.tiles,
+                    stats.different_fraction() * 100.0,
+                    stats.samples_with_diff,
+                    stats.samples
+                );
+                let name = |id: i32| biome_info::biome_name(id).unwrap_or("unknown");
+                for ((a, b), count) in stats.changes.iter().take(top) {
+                    println!("    {} -> {}: {}", name(*a), name(*b), count);
+                }
+            }
+        }
     }
 }
 
@@ -0,0 +1,301 @@
+//! Scoring functions used to compare candidate maps with the target maps
+//!
+//! The scalar functions work with any `Map`. `RiverMask` stores only the river tiles as a
+//! bitset, packed into `u64` words for each map row, so comparing two masks is a loop of AND +
+//! popcount over `u64` words, which the compiler can vectorize. This is much faster than
+//! comparing the `i32` tiles one by one, and the river seed finders spend most of their time
+//! comparing maps.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers::Area;
+use crate::biome_layers::Map;
+
+/// Number of river tiles in this map
+pub fn count_rivers(m: &Map) -> u32 {
+    m.a.fold(
+        0,
+        |acc, &x| if x == biome_id::river { acc + 1 } else { acc },
+    )
+}
+
+/// Number of tiles that are river in both maps.
+/// The first map should have more rivers than the second one.
+pub fn count_rivers_and(a: &Map, b: &Map) -> u32 {
+    assert_eq!(a.area(), b.area());
+    ndarray::Zip::from(&a.a)
+        .and(&b.a)
+        .fold(0, |mut acc, &v11_a, &v11_b| {
+            acc += if v11_b == biome_id::river && v11_a == v11_b {
+                1
+            } else {
+                0
+            };
+            acc
+        })
+}
+
+/// Number of tiles that are river in both maps, minus the number of tiles that are river in only
+/// one of the maps. Returns 0 instead of negative values.
+pub fn count_rivers_exact(a: &Map, b: &Map) -> u32 {
+    assert_eq!(a.area(), b.area());
+    let acc = ndarray::Zip::from(&a.a)
+        .and(&b.a)
+        .fold(0, |mut acc, &v11_a, &v11_b| {
+            acc += if v11_a == biome_id::river && v11_a == v11_b {
+                1
+            } else if v11_a == biome_id::river || v11_b == biome_id::river {
+                -1
+            } else {
+                0
+            };
+            acc
+        });
+
+    if acc < 0 {
+        0
+    } else {
+        acc as u32
+    }
+}
+
+/// Bitset with one bit per tile of a map, set if the tile is a river.
+///
+/// Each row of the map (all the tiles with the same x coordinate) is packed into its own `u64`
+/// words, so the mask of a tile can be found without iterating over the previous rows. The
+/// unused bits at the end of each row are always 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RiverMask {
+    area: Area,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl RiverMask {
+    /// Empty mask: no rivers
+    pub fn new(area: Area) -> Self {
+        let mut r = Self::default();
+        r.clear(area);
+        r
+    }
+
+    pub fn from_map(m: &Map) -> Self {
+        let mut r = Self::default();
+        r.set_from_map(m);
+        r
+    }
+
+    /// Mask with rivers at these coordinates. All the points must be inside the area.
+    pub fn from_points<I>(area: Area, points: I) -> Self
+    where
+        I: IntoIterator<Item = (i64, i64)>,
+    {
+        let mut r = Self::new(area);
+        for (x, z) in points {
+            r.set(x, z);
+        }
+        r
+    }
+
+    // Set the area and remove all the rivers, reusing the allocation
+    fn clear(&mut self, area: Area) {
+        self.area = area;
+        self.words_per_row = (area.h as usize + 63) / 64;
+        self.words.clear();
+        self.words.resize(self.words_per_row * area.w as usize, 0);
+    }
+
+    /// Update this mask to represent the rivers of `m`, reusing the allocation
+    pub fn set_from_map(&mut self, m: &Map) {
+        self.set_from_map_area(m, m.area());
+    }
+
+    /// Update this mask to represent the rivers of `m` inside `area`, reusing the allocation.
+    /// This avoids copying the area into a smaller map first. Panics if `area` is not inside the
+    /// area of `m`.
+    pub fn set_from_map_area(&mut self, m: &Map, area: Area) {
+        let (nx, nz) = (area.x - m.x, area.z - m.z);
+        let (w, h) = m.a.dim();
+        assert!(
+            nx >= 0 && nz >= 0 && nx as u64 + area.w <= w as u64 && nz as u64 + area.h <= h as u64,
+            "{:?} is outside of {:?}",
+            area,
+            m.area()
+        );
+        let (nx, nz) = (nx as usize, nz as usize);
+        self.clear(area);
+        for x in 0..area.w as usize {
+            let words = &mut self.words[x * self.words_per_row..(x + 1) * self.words_per_row];
+            let row = m.a.row(nx + x);
+            for z in 0..area.h as usize {
+                if row[nz + z] == biome_id::river {
+                    words[z / 64] |= 1 << (z % 64);
+                }
+            }
+        }
+    }
+
+    /// Convert back into a map, with `biome_id::river` at the river tiles and 0 elsewhere
+    pub fn to_map(&self) -> Map {
+        let mut m = Map::new(self.area);
+        for ((x, z), v) in m.a.indexed_iter_mut() {
+            if self.get_index(x, z) {
+                *v = biome_id::river;
+            }
+        }
+        m
+    }
+
+    fn get_index(&self, x: usize, z: usize) -> bool {
+        self.words[x * self.words_per_row + z / 64] & (1 << (z % 64)) != 0
+    }
+
+    /// Panics if the point is outside the area
+    fn index_of(&self, x: i64, z: i64) -> (usize, usize) {
+        let (ix, iz) = (x - self.area.x, z - self.area.z);
+        assert!(
+            ix >= 0 && iz >= 0 && (ix as u64) < self.area.w && (iz as u64) < self.area.h,
+            "({}, {}) is outside of {:?}",
+            x,
+            z,
+            self.area
+        );
+        (ix as usize, iz as usize)
+    }
+
+    /// Whether the tile at these map coordinates is a river
+    pub fn get(&self, x: i64, z: i64) -> bool {
+        let (ix, iz) = self.index_of(x, z);
+        self.get_index(ix, iz)
+    }
+
+    /// Mark the tile at these map coordinates as a river
+    pub fn set(&mut self, x: i64, z: i64) {
+        let (ix, iz) = self.index_of(x, z);
+        self.words[ix * self.words_per_row + iz / 64] |= 1 << (iz % 64);
+    }
+
+    pub fn area(&self) -> Area {
+        self.area
+    }
+
+    /// Same as `count_rivers`
+    pub fn count(&self) -> u32 {
+        self.words.iter().map(|w| w.count_ones()).sum()
+    }
+
+    /// Same as `count_rivers_and`
+    pub fn count_and(&self, other: &RiverMask) -> u32 {
+        assert_eq!(self.area, other.area);
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| (a & b).count_ones())
+            .sum()
+    }
+
+    /// Number of rivers of this mask that are also in `a` or in `b`, without building the union
+    /// of `a` and `b`
+    pub fn count_and_or(&self, a: &RiverMask, b: &RiverMask) -> u32 {
+        assert_eq!(self.area, a.area);
+        assert_eq!(self.area, b.area);
+        self.words
+            .iter()
+            .zip(a.words.iter().zip(b.words.iter()))
+            .map(|(t, (a, b))| (t & (a | b)).count_ones())
+            .sum()
+    }
+
+    /// Same as `count_rivers_exact`
+    pub fn count_exact(&self, other: &RiverMask) -> u32 {
+        assert_eq!(self.area, other.area);
+        let (both, one) = self
+            .words
+            .iter()
+            .zip(other.words.iter())
+            .fold((0, 0), |(both, one), (a, b)| {
+                (both + (a & b).count_ones(), one + (a ^ b).count_ones())
+            });
+
+        both.saturating_sub(one)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn river_mask_same_as_scalar() {
+        let area = Area {
+            x: -3,
+            z: 5,
+            w: 13,
+            h: 11,
+        };
+        let a = Map::from_area_fn(area, |(x, z)| {
+            if (x * 7 + z * 3) % 5 < 2 {
+                biome_id::river
+            } else {
+                biome_id::plains
+            }
+        });
+        let b = Map::from_area_fn(area, |(x, z)| {
+            if (x + z * 5) % 4 == 0 {
+                biome_id::river
+            } else {
+                biome_id::ocean
+            }
+        });
+        let ma = RiverMask::from_map(&a);
+        let mb = RiverMask::from_map(&b);
+        assert_eq!(ma.count(), count_rivers(&a));
+        assert_eq!(mb.count(), count_rivers(&b));
+        assert_eq!(ma.count_and(&mb), count_rivers_and(&a, &b));
+        assert_eq!(mb.count_and(&ma), count_rivers_and(&b, &a));
+        assert_eq!(ma.count_exact(&ma), count_rivers_exact(&a, &a));
+        assert_eq!(ma.count_exact(&mb), count_rivers_exact(&a, &b));
+        assert_eq!(ma.count_and_or(&mb, &RiverMask::new(area)), ma.count_and(&mb));
+        assert_eq!(ma.count_and_or(&ma, &mb), ma.count());
+
+        let mut m = RiverMask::default();
+        m.set_from_map(&a);
+        assert_eq!(m, ma);
+        m.set_from_map(&b);
+        assert_eq!(m, mb);
+    }
+
+    #[test]
+    fn river_mask_rows() {
+        // More than 64 tiles per row, so each row needs 2 words
+        let area = Area {
+            x: 10,
+            z: -70,
+            w: 3,
+            h: 100,
+        };
+        let points = [(10, -70), (11, -7), (11, -6), (12, 29)];
+        let mask = RiverMask::from_points(area, points.iter().copied());
+        assert_eq!(mask.count(), 4);
+        assert!(mask.get(11, -6));
+        assert!(!mask.get(12, -6));
+        let map = mask.to_map();
+        assert_eq!(count_rivers(&map), 4);
+        assert_eq!(map.a[(1, 64)], biome_id::river);
+        assert_eq!(RiverMask::from_map(&map), mask);
+        assert_eq!(RiverMask::new(area).count_and(&mask), 0);
+
+        // Only the rivers inside the smaller area
+        let inner = Area {
+            x: 11,
+            z: -10,
+            w: 2,
+            h: 40,
+        };
+        let mut m = RiverMask::default();
+        m.set_from_map_area(&map, inner);
+        assert_eq!(
+            m,
+            RiverMask::from_points(inner, [(11, -7), (11, -6), (12, 29)])
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::java_math::floor;
 use crate::java_rng::JavaRng;
 use crate::xoroshiro128plusplus::Xoroshiro128PlusPlus;
 
@@ -78,10 +79,80 @@ impl NoiseGeneratorPerlin {
     }
 }
 
+/// Simplex noise generator, only the 2D version
+///
+/// Uses the JavaRng, like `NoiseGeneratorPerlin`.
+pub struct SimplexNoise {
+    d: [i32; 256],
+}
+
+impl SimplexNoise {
+    pub fn new(r: &mut JavaRng) -> Self {
+        // The offsets are only used by the 3D noise, but they must be generated anyway
+        for _ in 0..3 {
+            r.next_double();
+        }
+        let mut d = [0; 256];
+
+        for (i, di) in d.iter_mut().enumerate() {
+            *di = i as i32;
+        }
+
+        // Shuffle array
+        for i in 0..256 {
+            // j: random number in range i..256
+            let j = r.next_int_n(256 - i as i32) + i as i32;
+            let j = (j & 0xFF) as usize;
+            d.swap(i, j);
+        }
+
+        Self { d }
+    }
+
+    pub fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        let sqrt_3 = 3.0f64.sqrt();
+        let skew = 0.5 * (sqrt_3 - 1.0);
+        let unskew = (3.0 - sqrt_3) / 6.0;
+
+        let hf = (x + z) * skew;
+        let hx = floor(x + hf);
+        let hz = floor(z + hf);
+        let mhxz = f64::from(hx.wrapping_add(hz)) * unskew;
+        let x0 = x - (f64::from(hx) - mhxz);
+        let z0 = z - (f64::from(hz) - mhxz);
+        let (offx, offz) = if x0 > z0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - f64::from(offx) + unskew;
+        let z1 = z0 - f64::from(offz) + unskew;
+        let x2 = x0 - 1.0 + 2.0 * unskew;
+        let z2 = z0 - 1.0 + 2.0 * unskew;
+
+        let p = |i: i32| self.d[(i & 0xFF) as usize];
+        let (ix, iz) = (hx & 0xFF, hz & 0xFF);
+        let gi0 = p(ix + p(iz)) % 12;
+        let gi1 = p(ix + offx + p(iz + offz)) % 12;
+        let gi2 = p(ix + 1 + p(iz + 1)) % 12;
+
+        70.0 * (simplex_corner(gi0, x0, z0)
+            + simplex_corner(gi1, x1, z1)
+            + simplex_corner(gi2, x2, z2))
+    }
+}
+
+// Contribution of one corner of the simplex
+fn simplex_corner(idx: i32, x: f64, z: f64) -> f64 {
+    let t = 0.5 - x * x - z * z;
+    if t < 0.0 {
+        0.0
+    } else {
+        let t = t * t;
+        t * t * indexed_lerp(idx, x, z, 0.0)
+    }
+}
+
 // Split d1 between fractional part and integer part.
 // Return integer part, and mutate argument to always be between 0 and 1
 fn split_int(d1: &mut f64) -> i32 {
-    let i1 = *d1 as i32 - if *d1 < 0.0 { 1 } else { 0 };
+    let i1 = floor(*d1);
     *d1 -= i1 as f64;
     i1
 }
@@ -172,7 +243,7 @@ impl NoiseGeneratorPerlin128 {
 
         if yamp != 0.0 {
             let yclamp = if ymin < d2 { ymin } else { d2 };
-            d2 -= (yclamp / yamp).floor() * yamp;
+            d2 -= f64::from(floor(yclamp / yamp)) * yamp;
         }
 
         let a1 = self.d[i1 as usize & 0xFF] + i2;
@@ -358,6 +429,22 @@ mod tests {
         assert_eq!(n.octaves[1].lacunarity, 0.0009765625 * 4.0);
     }
 
+    #[test]
+    fn split_int_negative() {
+        // A negative integer has no fractional part: -3.0 used to be split into -4 and 1.0
+        let mut d = -3.0;
+        assert_eq!(split_int(&mut d), -3);
+        assert_eq!(d, 0.0);
+
+        let mut d = -2.75;
+        assert_eq!(split_int(&mut d), -3);
+        assert_eq!(d, 0.25);
+
+        let mut d = 2.75;
+        assert_eq!(split_int(&mut d), 2);
+        assert_eq!(d, 0.75);
+    }
+
     #[test]
     fn len_trim_zeros() {
         assert_eq!(len_without_start_zeros_and_end_zeros(&[]), 0);
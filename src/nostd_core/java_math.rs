@@ -0,0 +1,147 @@
+//! Integer division and float to integer conversions with the same semantics as Java.
+//!
+//! Rust and Java disagree on these operations for negative values: Rust `/` and `%` truncate
+//! like Java `/` and `%`, but Minecraft often uses `Math.floorDiv`, `Math.floorMod` and
+//! `MathHelper.floor`, which round towards negative infinity. `rem_euclid` is only the same as
+//! `Math.floorMod` when the divisor is positive. Overflows wrap around like in Java instead of
+//! panicking.
+
+/// Java `Math.floorDiv(x, y)`. Panics if `y` is 0
+pub fn floor_div(x: i64, y: i64) -> i64 {
+    let q = x.wrapping_div(y);
+    if x.wrapping_rem(y) != 0 && (x ^ y) < 0 {
+        q - 1
+    } else {
+        q
+    }
+}
+
+/// Java `Math.floorMod(x, y)`. The result has the same sign as `y`. Panics if `y` is 0
+pub fn floor_mod(x: i64, y: i64) -> i64 {
+    let m = x.wrapping_rem(y);
+    if m != 0 && (m ^ y) < 0 {
+        m + y
+    } else {
+        m
+    }
+}
+
+/// Java `Math.floorDiv(x, y)` for `int`. Panics if `y` is 0
+pub fn floor_div_i32(x: i32, y: i32) -> i32 {
+    let q = x.wrapping_div(y);
+    if x.wrapping_rem(y) != 0 && (x ^ y) < 0 {
+        q - 1
+    } else {
+        q
+    }
+}
+
+/// Java `Math.floorMod(x, y)` for `int`. Panics if `y` is 0
+pub fn floor_mod_i32(x: i32, y: i32) -> i32 {
+    let m = x.wrapping_rem(y);
+    if m != 0 && (m ^ y) < 0 {
+        m + y
+    } else {
+        m
+    }
+}
+
+/// Java `(int) x` cast: rounds towards zero, saturates at the limits of `i32`, and NaN is 0.
+/// This is the same as the Rust `as` cast, this function only documents the intent.
+pub fn d2i(x: f64) -> i32 {
+    x as i32
+}
+
+/// Minecraft `MathHelper.floor(x)`. Same as `x.floor() as i32` for values that fit in an `i32`,
+/// but values below `i32::MIN` wrap around to `i32::MAX`, like in Java.
+pub fn floor(x: f64) -> i32 {
+    let i = d2i(x);
+    if x < f64::from(i) {
+        i.wrapping_sub(1)
+    } else {
+        i
+    }
+}
+
+/// Minecraft `MathHelper.lfloor(x)`, the `long` version of `floor`
+pub fn lfloor(x: f64) -> i64 {
+    let i = x as i64;
+    if x < i as f64 {
+        i.wrapping_sub(1)
+    } else {
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Slow but obviously correct implementations
+    fn floor_div_ref(x: i64, y: i64) -> i64 {
+        (x as f64 / y as f64).floor() as i64
+    }
+
+    fn floor_mod_ref(x: i64, y: i64) -> i64 {
+        x - floor_div_ref(x, y) * y
+    }
+
+    #[test]
+    fn floor_div_mod_small_values() {
+        for x in -100..=100 {
+            for y in (-13..=13).filter(|y| *y != 0) {
+                assert_eq!(floor_div(x, y), floor_div_ref(x, y), "{} / {}", x, y);
+                assert_eq!(floor_mod(x, y), floor_mod_ref(x, y), "{} % {}", x, y);
+                let (x32, y32) = (x as i32, y as i32);
+                assert_eq!(floor_div_i32(x32, y32) as i64, floor_div(x, y));
+                assert_eq!(floor_mod_i32(x32, y32) as i64, floor_mod(x, y));
+                // floor_mod and floor_div are consistent
+                assert_eq!(floor_div(x, y) * y + floor_mod(x, y), x);
+            }
+        }
+    }
+
+    #[test]
+    fn floor_div_mod_java_examples() {
+        // Examples from the Javadoc of Math.floorMod
+        assert_eq!(floor_mod(4, 3), 1);
+        assert_eq!(floor_mod(-4, 3), 2);
+        assert_eq!(floor_mod(4, -3), -2);
+        assert_eq!(floor_mod(-4, -3), -1);
+        assert_eq!(floor_div(4, 3), 1);
+        assert_eq!(floor_div(-4, 3), -2);
+        assert_eq!(floor_div(4, -3), -2);
+        assert_eq!(floor_div(-4, -3), 1);
+        // Different from rem_euclid when the divisor is negative
+        assert_eq!((4i64).rem_euclid(-3), 1);
+        // Overflow wraps around
+        assert_eq!(floor_div(i64::MIN, -1), i64::MIN);
+        assert_eq!(floor_mod(i64::MIN, -1), 0);
+        assert_eq!(floor_div_i32(i32::MIN, -1), i32::MIN);
+        assert_eq!(floor_div(i64::MIN, 1024), i64::MIN >> 10);
+        assert_eq!(floor_mod(i64::MIN + 1, 1024), 1);
+        assert_eq!(floor_mod(-1, 1024), 1023);
+        assert_eq!(floor_mod(i64::MAX, i64::MIN), -1);
+    }
+
+    #[test]
+    fn floor_negative_values() {
+        for i in -2000..=2000 {
+            let x = f64::from(i) / 16.0;
+            assert_eq!(floor(x), x.floor() as i32, "{}", x);
+            assert_eq!(lfloor(x), x.floor() as i64, "{}", x);
+        }
+        assert_eq!(floor(-0.0), 0);
+        assert_eq!(floor(-0.5), -1);
+        assert_eq!(floor(-1.0), -1);
+        assert_eq!(floor(-1.0 - f64::EPSILON), -2);
+        assert_eq!(floor(f64::NAN), 0);
+        assert_eq!(floor(1e20), i32::MAX);
+        // Java casts saturate, and then the subtraction overflows
+        assert_eq!(floor(-1e20), i32::MAX);
+        assert_eq!(floor(f64::from(i32::MIN)), i32::MIN);
+        assert_eq!(lfloor(-1e30), i64::MAX);
+        assert_eq!(d2i(-1.9), -1);
+        assert_eq!(d2i(f64::INFINITY), i32::MAX);
+    }
+}
@@ -1,3 +1,9 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::num::ParseIntError;
+
 // The constants used by the Linear Congruential Generator
 pub mod lcg_const {
     pub const A: u64 = 0x5DEECE66D;
@@ -26,32 +32,141 @@ pub const fn mask(n: u8) -> u64 {
     (1 << n) - 1
 }
 
+// Used by extend_long_48_batch. For each 16-bit k0, the low 16 bits of the previous state only
+// depend on k0, so the check done by previous_verify_16 can be rewritten as d(k0) == target(l).
+// Sorted by d, and then by k0.
+fn extend_48_table() -> Vec<(u32, u16)> {
+    let a = (lcg_const::A & mask(32)) as u32;
+    let mut v: Vec<(u32, u16)> = (0..0x10000u32)
+        .map(|k0| {
+            let p1 = JavaRng::previous_state(k0 as u64) as u16 as u32;
+            let d = p1
+                .wrapping_mul(a)
+                .wrapping_add(lcg_const::C as u32)
+                .wrapping_sub(k0);
+            (d, k0 as u16)
+        })
+        .collect();
+    v.sort_unstable();
+    v
+}
+
+// StrictMath.log from Java, which is __ieee754_log from fdlibm. The native ln may differ in the
+// last bit.
+fn strict_log(x: f64) -> f64 {
+    const LN2_HI: f64 = 6.93147180369123816490e-01;
+    const LN2_LO: f64 = 1.90821492927058770002e-10;
+    const TWO54: f64 = 1.80143985094819840000e+16;
+    const LG1: f64 = 6.666666666666735130e-01;
+    const LG2: f64 = 3.999999999940941908e-01;
+    const LG3: f64 = 2.857142874366239149e-01;
+    const LG4: f64 = 2.222219843214978396e-01;
+    const LG5: f64 = 1.818357216161805012e-01;
+    const LG6: f64 = 1.531383769920937332e-01;
+    const LG7: f64 = 1.479819860511658591e-01;
+
+    let mut x = x;
+    let mut hx = (x.to_bits() >> 32) as i32;
+    let lx = x.to_bits() as u32;
+    let mut k = 0;
+    if hx < 0x00100000 {
+        // x < 2^-1022
+        if (hx & 0x7fffffff) as u32 | lx == 0 {
+            return f64::NEG_INFINITY;
+        }
+        if hx < 0 {
+            // log of a negative number is NaN
+            return (x - x) / 0.0;
+        }
+        // Subnormal number, scale up x
+        k -= 54;
+        x *= TWO54;
+        hx = (x.to_bits() >> 32) as i32;
+    }
+    if hx >= 0x7ff00000 {
+        return x + x;
+    }
+    k += (hx >> 20) - 1023;
+    hx &= 0x000fffff;
+    let i = (hx + 0x95f64) & 0x100000;
+    // Normalize x or x / 2
+    let x = f64::from_bits((((hx | (i ^ 0x3ff00000)) as u64) << 32) | (x.to_bits() & 0xffffffff));
+    k += i >> 20;
+    let f = x - 1.0;
+    let dk = k as f64;
+    if (0x000fffff & (2 + hx)) < 3 {
+        // |f| < 2^-20
+        if f == 0.0 {
+            return if k == 0 {
+                0.0
+            } else {
+                dk * LN2_HI + dk * LN2_LO
+            };
+        }
+        let r = f * f * (0.5 - 0.33333333333333333 * f);
+        return if k == 0 {
+            f - r
+        } else {
+            dk * LN2_HI - ((r - dk * LN2_LO) - f)
+        };
+    }
+    let s = f / (2.0 + f);
+    let z = s * s;
+    let w = z * z;
+    let t1 = w * (LG2 + w * (LG4 + w * LG6));
+    let t2 = z * (LG1 + w * (LG3 + w * (LG5 + w * LG7)));
+    let i = (hx - 0x6147a) | (0x6b851 - hx);
+    let r = t2 + t1;
+    if i > 0 {
+        let hfsq = 0.5 * f * f;
+        if k == 0 {
+            f - (hfsq - s * (hfsq + r))
+        } else {
+            dk * LN2_HI - ((hfsq - (s * (hfsq + r) + dk * LN2_LO)) - f)
+        }
+    } else if k == 0 {
+        f - s * (f - r)
+    } else {
+        dk * LN2_HI - ((s * (f - r) - dk * LN2_LO) - f)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct JavaRng {
     // Actually only 48 bits of seed are used in java
     // but we use 64 internally, masking only when needed
     seed: u64,
+    // next_gaussian generates 2 values at a time, this is the second one
+    next_next_gaussian: Option<f64>,
 }
 
 impl JavaRng {
     pub fn with_seed(s: u64) -> JavaRng {
-        let mut r = JavaRng { seed: 0 };
+        let mut r = JavaRng {
+            seed: 0,
+            next_next_gaussian: None,
+        };
         r.set_seed(s);
         r
     }
 
     pub fn with_raw_seed(s: u64) -> JavaRng {
-        let mut r = JavaRng { seed: 0 };
+        let mut r = JavaRng {
+            seed: 0,
+            next_next_gaussian: None,
+        };
         r.set_raw_seed(s);
         r
     }
 
     pub fn set_seed(&mut self, s: u64) {
         self.seed = s ^ lcg_const::A;
+        self.next_next_gaussian = None;
     }
 
     pub fn set_raw_seed(&mut self, s: u64) {
         self.seed = s;
+        self.next_next_gaussian = None;
     }
 
     pub fn get_seed(&self) -> u64 {
@@ -93,15 +208,7 @@ impl JavaRng {
             }
             _ => {}
         }
-        let c = lcg_const::C;
-        let a = lcg_const::A;
-        // Modular multiplicative inverse of a-1
-        let a_1_inv = lcg_const_extra::INV_A_1;
-        let an = pow_wrapping(a, n);
-        //let aes = (an - 1) / (a - 1);
-        // a % 4 == 1, so (a^n - 1) % 4 == 0
-        let aes = (an.wrapping_sub(1) >> 2).wrapping_mul(a_1_inv);
-        let caa = c.wrapping_mul(aes);
+        let (an, caa) = lcg_jump(n);
         self.seed = self.seed.wrapping_mul(an).wrapping_add(caa);
     }
 
@@ -136,16 +243,31 @@ impl JavaRng {
     }
 
     pub fn next_int_n_10(&mut self) -> i32 {
+        self.next_int_n_const::<10>()
+    }
+
+    /// Same as `next_int_n(N)`, but with N known at compile time, so the modulo and the check
+    /// for modulo bias compile to multiplications instead of divisions.
+    #[inline]
+    pub fn next_int_n_const<const N: i32>(&mut self) -> i32 {
+        if !(N > 0) {
+            panic!("In JavaRng::next_int_n, n should be greater than zero.");
+        }
+        // If N is a power of 2
+        if (N & -N) == N {
+            return (((N as i64) * (self.next(31) as i64)) >> 31) as i32;
+        }
+
         let mut bits;
         loop {
             bits = self.next(31);
             // Check for modulo bias
-            let limit = (1u32 << 31) / 10 * 10; // last multiple of 10 < 2^31
-            if bits < limit as i32 {
+            let limit = (1u32 << 31) / N as u32 * N as u32; // last multiple of N < 2^31
+            if (bits as u32) < limit {
                 break;
             }
         }
-        bits % 10
+        bits % N
     }
 
     pub fn next_long(&mut self) -> i64 {
@@ -167,6 +289,31 @@ impl JavaRng {
         (hi + lo) as f64 / ((1u64 << 53) as f64)
     }
 
+    /// Normally distributed value with mean 0 and standard deviation 1, using the polar method
+    /// like Java. Each call to `next_gaussian` generates 2 values: the first call uses at least 4
+    /// calls to `next`, and the second call returns the cached value without advancing the
+    /// state. Setting the seed discards the cached value.
+    ///
+    /// Needs std for `sqrt`.
+    #[cfg(feature = "std")]
+    pub fn next_gaussian(&mut self) -> f64 {
+        if let Some(x) = self.next_next_gaussian.take() {
+            return x;
+        }
+
+        loop {
+            let v1 = 2.0 * self.next_double() - 1.0;
+            let v2 = 2.0 * self.next_double() - 1.0;
+            let s = v1 * v1 + v2 * v2;
+            if s < 1.0 && s != 0.0 {
+                let multiplier = (-2.0 * strict_log(s) / s).sqrt();
+                self.next_next_gaussian = Some(v2 * multiplier);
+
+                return v1 * multiplier;
+            }
+        }
+    }
+
     // The inverse of next()
     pub fn previous(&mut self) {
         //self.seed = (self.seed.wrapping_sub(lcg_const::C)).wrapping_mul(lcg_const_extra::INV_A);
@@ -312,6 +459,57 @@ impl JavaRng {
         None
     }
 
+    /// Recover the internal state from some observed `next_int()` outputs.
+    ///
+    /// Each output is a pair `(gap, value)`, where `gap` is the number of calls to `next` since
+    /// the previous output, including the call that returned `value`. For the first output the
+    /// gap is counted from the state we want to recover, so a gap of 1 means that `value` was
+    /// the result of the first call. Only calls to `next` that advance the state once are
+    /// supported, so for example a `next_long()` counts as 2 calls.
+    ///
+    /// Returns all the states that produce the observed outputs. The 16 lower bits of the state
+    /// are bruteforced, so with only one output this returns 2^16 candidates, and with two or
+    /// more outputs it usually returns only one.
+    /// ```
+    /// use slime_seed_finder::java_rng::JavaRng;
+    ///
+    /// let mut r = JavaRng::with_seed(1234);
+    /// let a = r.next_int();
+    /// r.next_long();
+    /// let b = r.next_int();
+    /// let candidates = JavaRng::recover_state(&[(1, a), (3, b)]);
+    /// assert_eq!(candidates.len(), 1);
+    /// assert_eq!(candidates[0].get_seed(), 1234);
+    /// ```
+    pub fn recover_state(outputs: &[(u64, i32)]) -> Vec<JavaRng> {
+        let (first_gap, first_value) = match outputs.first() {
+            Some(x) => *x,
+            None => return vec![],
+        };
+        let jumps: Vec<_> = outputs[1..]
+            .iter()
+            .map(|(gap, value)| (lcg_jump(*gap), *value as u32))
+            .collect();
+        let front = (first_value as u32 as u64) << 16;
+
+        (0..0x10000)
+            .map(|low16| front | low16)
+            .filter(|s0| {
+                let mut s = *s0;
+                jumps.iter().all(|((an, caa), value)| {
+                    s = s.wrapping_mul(*an).wrapping_add(*caa) & mask(48);
+                    (s >> 16) as u32 == *value
+                })
+            })
+            .map(|s| {
+                let mut r = JavaRng::with_raw_seed(s);
+                r.previous_n_calls(first_gap);
+                r.set_raw_seed(r.get_raw_seed());
+                r
+            })
+            .collect()
+    }
+
     // Returns a JavaRng r such that r.next_long() will return l
     pub fn create_from_long(l: u64) -> Option<JavaRng> {
         let (i0, i1) = Self::ints_from_long(l as i64);
@@ -351,6 +549,34 @@ impl JavaRng {
             .collect()
     }
 
+    /// Same as `extend_long_48`, but for many values at once. Returns `(l, extended)` pairs,
+    /// where `l` is the input value masked to 48 bits. The order of the input is preserved.
+    ///
+    /// The bruteforce of `extend_long_48` depends on the input only through a 32-bit
+    /// constant, so instead of checking all the 2^16 candidates for each value, this builds a
+    /// table and finds the valid candidates with a binary search. Building the table costs about
+    /// the same as one call to `extend_long_48`, so use large batches.
+    pub fn extend_long_48_batch(ls: &[u64]) -> Vec<(u64, u64)> {
+        let table = extend_48_table();
+        let mut v = vec![];
+        for &l in ls {
+            let l = l & mask(48);
+            let (i0, i1) = JavaRng::ints_from_long(l as i64);
+            let seed = ((i1 as u32 as u64) << 16) & mask(48);
+            let k = ((i0 as u16 as u32) << 16).wrapping_mul((lcg_const::A & mask(32)) as u32);
+            let target = ((i1 as u32) << 16).wrapping_sub(k);
+            let start = table.partition_point(|&(d, _)| d < target);
+            for &(_, k0) in table[start..].iter().take_while(|&&(d, _)| d == target) {
+                let mut r = JavaRng::with_raw_seed(seed | (k0 as u64));
+                r.previous();
+                r.previous();
+                v.push((l, r.next_long() as u64));
+            }
+        }
+
+        v
+    }
+
     /// How many calls to `next` are needed to transform self into other
     pub fn num_steps_to(&self, other: &Self) -> u64 {
         distance_between_rngs(self, other)
@@ -363,6 +589,22 @@ impl JavaRng {
     }
 }
 
+// Returns (a^n, c * (a^n - 1) / (a - 1)), so that n calls to next are equivalent to s * a^n +
+// c * (a^n - 1) / (a - 1)
+fn lcg_jump(n: u64) -> (u64, u64) {
+    let c = lcg_const::C;
+    let a = lcg_const::A;
+    // Modular multiplicative inverse of a-1
+    let a_1_inv = lcg_const_extra::INV_A_1;
+    let an = pow_wrapping(a, n);
+    //let aes = (an - 1) / (a - 1);
+    // a % 4 == 1, so (a^n - 1) % 4 == 0
+    let aes = (an.wrapping_sub(1) >> 2).wrapping_mul(a_1_inv);
+    let caa = c.wrapping_mul(aes);
+
+    (an, caa)
+}
+
 // Calculate base^exp (mod 2^64).
 // Copied from the standard library, but the wrapping_pow implemented there uses u32 for the
 // exponent. We could use some property like a^(b*(2^32) + c) = ((a^b)^(2^32)) * (a^c)
@@ -456,13 +698,94 @@ fn distance_between_rngs_less_than(ss: &JavaRng, se: &JavaRng, limit: u64) -> Op
     Some(d)
 }
 
+/// Java `String.hashCode()`
+pub fn java_string_hash_code(s: &str) -> i32 {
+    // Java strings are UTF-16
+    s.encode_utf16()
+        .fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(i32::from(c)))
+}
+
+/// Convert the text of the seed field of the "Create New World" screen into a world seed.
+///
+/// Returns `None` if the text is empty or "0", because in that case the seed is random.
+pub fn world_seed_from_string(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+
+    match s.parse::<i64>() {
+        Ok(0) => None,
+        Ok(x) => Some(x),
+        Err(_) => Some(i64::from(java_string_hash_code(s))),
+    }
+}
+
+/// Parse a seed entered by the user. Accepts signed decimal numbers, unsigned decimal numbers
+/// greater than `i64::MAX`, which wrap around like in `u64 as i64`, and hexadecimal numbers
+/// starting with "0x".
+///
+/// ```
+/// use slime_seed_finder::java_rng::parse_seed;
+///
+/// assert_eq!(parse_seed("-1234"), Ok(-1234));
+/// assert_eq!(parse_seed("18446744073709551615"), Ok(-1));
+/// assert_eq!(parse_seed("0xFFFFFFFFFFFFFFFF"), Ok(-1));
+/// assert!(parse_seed("seed").is_err());
+/// ```
+pub fn parse_seed(s: &str) -> Result<i64, ParseIntError> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).map(|x| x as i64);
+    }
+
+    s.parse::<i64>()
+        .or_else(|e| s.parse::<u64>().map(|x| x as i64).map_err(|_| e))
+}
+
+/// Format a seed as an unsigned decimal number, the inverse of `u64 as i64`
+pub fn format_seed_unsigned(seed: i64) -> String {
+    format!("{}", seed as u64)
+}
+
+/// Format a seed as a hexadecimal number with all the 16 digits, which can be read by
+/// `parse_seed`
+pub fn format_seed_hex(seed: i64) -> String {
+    format!("0x{:016X}", seed as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_seed_formats() {
+        assert_eq!(parse_seed(" 1234 "), Ok(1234));
+        assert_eq!(parse_seed("-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(parse_seed("9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(parse_seed("0x1234abcd"), Ok(0x1234abcd));
+        assert_eq!(parse_seed("0X8000000000000000"), Ok(i64::MIN));
+        // Too large
+        assert!(parse_seed("18446744073709551616").is_err());
+        assert!(parse_seed("0x10000000000000000").is_err());
+        assert!(parse_seed("").is_err());
+        // The error is the error of parsing as i64
+        assert_eq!(
+            parse_seed("abc").unwrap_err(),
+            "abc".parse::<i64>().unwrap_err()
+        );
+
+        for seed in [0, 1, -1, 1234, i64::MIN, i64::MAX] {
+            assert_eq!(parse_seed(&format_seed_unsigned(seed)), Ok(seed));
+            assert_eq!(parse_seed(&format_seed_hex(seed)), Ok(seed));
+        }
+        assert_eq!(format_seed_hex(-1), "0xFFFFFFFFFFFFFFFF");
+        assert_eq!(format_seed_unsigned(-1), "18446744073709551615");
+    }
+
     #[test]
     fn seed_set_get() {
-        let mut r = JavaRng { seed: 1234 };
+        let mut r = JavaRng::with_raw_seed(1234);
         assert_eq!(r.get_seed(), 1234 ^ lcg_const::A);
         r.set_seed(lcg_const::A);
         assert_eq!(r.get_seed(), lcg_const::A);
@@ -471,6 +794,39 @@ mod tests {
         assert_eq!(r.get_seed(), 0);
     }
 
+    #[test]
+    fn strict_log_special_values() {
+        assert_eq!(strict_log(1.0), 0.0);
+        assert_eq!(strict_log(0.0), f64::NEG_INFINITY);
+        assert_eq!(strict_log(f64::INFINITY), f64::INFINITY);
+        assert!(strict_log(-1.0).is_nan());
+        // Values from java.lang.StrictMath
+        assert_eq!(strict_log(2.0).to_bits(), 0x3fe62e42fefa39ef);
+        assert_eq!(strict_log(1e-310).to_bits(), 0xc0864e69394d9508);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn next_gaussian() {
+        // Values from java.util.Random
+        let mut r = JavaRng::with_seed(42);
+        assert_eq!(r.next_gaussian(), 1.1419053154730547);
+        assert_eq!(r.next_gaussian(), 0.9194079489827879);
+        assert_eq!(r.next_gaussian(), -0.9498666368908959);
+        assert_eq!(r.next_gaussian(), -1.1069902863993377);
+        assert_eq!(r.next_int(), -1436456258);
+
+        // The second value is cached, so it does not advance the state
+        let mut r = JavaRng::with_seed(1234);
+        r.next_gaussian();
+        assert_eq!(r.next_int(), -611652875);
+        r.next_gaussian();
+        assert_eq!(r.next_int(), 1362132786);
+        // Setting the seed discards the cached value
+        r.set_seed(1234);
+        assert_eq!(r.next_gaussian(), 0.14115907833078006);
+    }
+
     #[test]
     fn next_test() {
         let mut r = JavaRng::with_seed(12345);
@@ -671,6 +1027,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn next_int_n_const() {
+        fn check<const N: i32>(r: &JavaRng) {
+            let mut r0 = r.clone();
+            let mut r1 = r.clone();
+            for _ in 0..100 {
+                assert_eq!(r0.next_int_n(N), r1.next_int_n_const::<N>());
+            }
+        }
+
+        for target in 2147483630..2147483648 {
+            let mut rt = JavaRng::with_raw_seed(target << 17);
+            rt.previous();
+            check::<6>(&rt);
+            check::<13>(&rt);
+            check::<57>(&rt);
+            check::<100>(&rt);
+            check::<299999>(&rt);
+            check::<1024>(&rt);
+        }
+    }
+
     #[test]
     fn extend_48_to_64() {
         assert_eq!(
@@ -699,6 +1077,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extend_48_batch() {
+        let ls = [
+            132607203138509,
+            113453751637441,
+            18021957452394,
+            131291916928825,
+            249127199878301,
+            186701866325681,
+            0,
+            mask(48),
+            0xFFFF_1234_5678_9ABC,
+        ];
+        let expected: Vec<(u64, u64)> = ls
+            .iter()
+            .flat_map(|&l| {
+                JavaRng::extend_long_48(l)
+                    .into_iter()
+                    .map(move |x| (l & mask(48), x))
+            })
+            .collect();
+        assert_eq!(JavaRng::extend_long_48_batch(&ls), expected);
+
+        let mut r = JavaRng::with_seed(1234);
+        let ls: Vec<u64> = (0..1000).map(|_| r.next_long() as u64 & mask(48)).collect();
+        let batch = JavaRng::extend_long_48_batch(&ls);
+        for l in ls {
+            let x: Vec<u64> = batch
+                .iter()
+                .filter(|(b, _)| *b == l)
+                .map(|(_, x)| *x)
+                .collect();
+            assert_eq!(x, JavaRng::extend_long_48(l));
+        }
+    }
+
+    #[test]
+    fn seed_from_string() {
+        assert_eq!(java_string_hash_code(""), 0);
+        assert_eq!(java_string_hash_code("hello"), 99162322);
+        assert_eq!(java_string_hash_code("Glacier"), 1772835215);
+        assert_eq!(world_seed_from_string(" 1234 "), Some(1234));
+        assert_eq!(world_seed_from_string("-1234"), Some(-1234));
+        assert_eq!(world_seed_from_string("hello"), Some(99162322));
+        assert_eq!(world_seed_from_string(""), None);
+    }
+
+    #[test]
+    fn recover_state_with_gaps() {
+        let mut r = JavaRng::with_raw_seed(0x1234_5678_9ABC);
+        let initial = r.get_raw_seed();
+        r.next_n_calls(5);
+        let a = r.next_int();
+        r.next_n_calls(1000);
+        let b = r.next_int();
+        let c = r.next_int();
+
+        let candidates = JavaRng::recover_state(&[(6, a), (1001, b), (1, c)]);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].get_raw_seed(), initial);
+
+        // Only one output: all the 16 lower bits are possible
+        let candidates = JavaRng::recover_state(&[(6, a)]);
+        assert_eq!(candidates.len(), 0x10000);
+        assert!(candidates.iter().any(|r| r.get_raw_seed() == initial));
+
+        assert!(JavaRng::recover_state(&[]).is_empty());
+    }
+
     #[test]
     fn test_low_16() {
         let s = 1234;
@@ -1,5 +1,9 @@
-use std::num::Wrapping;
-use std::ops::RangeInclusive;
+use crate::nostd_core::java_math::floor_mod;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::num::Wrapping;
+use core::ops::RangeInclusive;
 
 // The constants used by the Quadratic Congruential Generator
 pub mod mc_qcg_const {
@@ -61,6 +65,19 @@ impl McRng {
         self.chunk_seed = Self::next_state(self.chunk_seed, chunk_x as i64);
         self.chunk_seed = Self::next_state(self.chunk_seed, chunk_z as i64);
     }
+    // set_chunk_seed can be split in two parts, because the first two steps are
+    // next_state(next_state(world_seed, chunk_x), 0) + chunk_z.
+    // The first part only depends on chunk_x, so it can be reused for all the
+    // chunks with the same chunk_x.
+    pub fn chunk_seed_row(&self, chunk_x: i64) -> i64 {
+        Self::next_state(Self::next_state(self.world_seed, chunk_x), 0)
+    }
+    // Equivalent to set_chunk_seed(chunk_x, chunk_z), with row_seed = chunk_seed_row(chunk_x)
+    pub fn set_chunk_seed_from_row(&mut self, row_seed: i64, chunk_x: i64, chunk_z: i64) {
+        self.chunk_seed = row_seed.wrapping_add(chunk_z);
+        self.chunk_seed = Self::next_state(self.chunk_seed, chunk_x);
+        self.chunk_seed = Self::next_state(self.chunk_seed, chunk_z);
+    }
     pub fn base_seed(&self) -> i64 {
         self.base_seed as i64
     }
@@ -81,20 +98,24 @@ impl McRng {
 
         (s * (s * Wrapping(mc_qcg_const::A) + Wrapping(mc_qcg_const::C)) + k).0
     }
-    // Equivalent to Java's Math.floorDiv(x, y)
+    // Despite the name, this is Java's Math.floorMod(x, y)
+    #[deprecated = "use java_math::floor_mod instead"]
     pub fn math_floor_div(x: i64, y: i64) -> i64 {
-        let mut ret = x % y;
+        floor_mod(x, y)
+    }
+    pub fn next_int_n(&mut self, n: i32) -> i32 {
+        let ret = floor_mod(self.chunk_seed >> 24, n as i64) as i32;
 
-        // Java % is not the same as C %, this is needed in Java because we
-        // do not want negative results:
-        if ret < 0 {
-            ret += y;
-        }
+        self.chunk_seed = Self::next_state(self.chunk_seed, self.world_seed);
 
         ret
     }
-    pub fn next_int_n(&mut self, n: i32) -> i32 {
-        let ret = Self::math_floor_div(self.chunk_seed >> 24, n as i64) as i32;
+    /// Same as `next_int_n(N)`, but with N known at compile time, so the modulo compiles to a
+    /// multiplication instead of a 64-bit division. Used by the layers that call `next_int_n`
+    /// once per tile.
+    #[inline]
+    pub fn next_int_n_const<const N: i64>(&mut self) -> i32 {
+        let ret = (self.chunk_seed >> 24).rem_euclid(N) as i32;
 
         self.chunk_seed = Self::next_state(self.chunk_seed, self.world_seed);
 
@@ -297,12 +318,257 @@ impl McRng {
 
         min ..= max
     }
+
+    /// Same seeds as `similar_biome_seed_iterator_bits(25)`, but in a different order. Useful
+    /// when there is some partial knowledge about the seed, to check the most likely seeds first.
+    pub fn similar_biome_seed_iterator_25_ordered(order: SeedOrder) -> Box<dyn Iterator<Item = i64>> {
+        const N: i64 = 1 << 25;
+        let range = Self::similar_biome_seed_iterator_bits(25);
+        // The representative of each pair of similar seeds is the one returned by the sequential
+        // iterator. Each seed is visited twice, once directly and once as the similar seed of
+        // the other one, so only return it the first time, when `first(v, similar(v))` is true.
+        let similar = |v: i64| Self::similar_biome_seed(v) & mask_up_to_bit(24);
+        let representative = move |v: i64| if range.contains(&v) { v } else { similar(v) };
+
+        match order {
+            SeedOrder::Sequential => Box::new(Self::similar_biome_seed_iterator_bits(25)),
+            SeedOrder::NearSeed(seed) => {
+                let center = seed & mask_up_to_bit(24);
+                // Position of v in the sequence center, center + 1, center - 1, center + 2, ...
+                let position = move |v: i64| {
+                    let k = (v - center).rem_euclid(N);
+                    if k <= N / 2 { 2 * k - (k != 0) as i64 } else { 2 * (N - k) }
+                };
+                Box::new((0..N).map(move |i| {
+                    let m = (i + 1) / 2;
+                    let delta = if i % 2 == 1 { m } else { -m };
+                    (center + delta).rem_euclid(N)
+                }).filter(move |&v| position(v) < position(similar(v))).map(representative))
+            }
+            SeedOrder::LowHammingWeight => {
+                let key = |v: i64| (v.count_ones(), v);
+                Box::new((0..=25).flat_map(seeds_with_hamming_weight_25)
+                    .filter(move |&v| key(v) < key(similar(v))).map(representative))
+            }
+        }
+    }
+}
+
+/// Order used by `McRng::similar_biome_seed_iterator_25_ordered`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeedOrder {
+    /// Same order as `McRng::similar_biome_seed_iterator_bits(25)`
+    Sequential,
+    /// Seeds whose lower 25 bits are close to the lower 25 bits of this seed first. For example,
+    /// `NearSeed(0)` checks first the seeds with small absolute value.
+    NearSeed(i64),
+    /// Seeds with less bits set first, or whose similar seed has less bits set.
+    LowHammingWeight,
+}
+
+// All the 25-bit numbers with `weight` bits set, in increasing order
+fn seeds_with_hamming_weight_25(weight: u32) -> impl Iterator<Item = i64> {
+    let first = if weight == 0 { 0 } else { mask_up_to_bit(weight as u8 - 1) };
+    core::iter::successors(Some(first), move |&v| {
+        if v == 0 {
+            return None;
+        }
+        // Gosper's hack: next number with the same number of bits set
+        let c = v & -v;
+        let r = v + c;
+        let next = (((r ^ v) >> 2) / c) | r;
+        if next >> 25 != 0 { None } else { Some(next) }
+    })
+}
+
+/// A call to `McRng::next_int_n` observed in a layer, after setting the chunk seed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObservedRoll {
+    /// Base seed of the layer
+    pub base_seed: i64,
+    /// Number of calls to `next_int_n` between `set_chunk_seed` and this call
+    pub call: u32,
+    pub n: i32,
+    pub value: i32,
+}
+
+impl ObservedRoll {
+    /// Number of lowest bits of the world seed that determine the value of this roll.
+    ///
+    /// `set_world_seed`, `set_chunk_seed` and `next_state` only use additions and
+    /// multiplications, so each bit of the chunk seed only depends on the same and lower bits of
+    /// the world seed. `next_int_n` uses the chunk seed starting from bit 24, so when `n` is a
+    /// power of 2 it only uses the bits `24..24 + log2(n)`, and otherwise it uses all the bits.
+    pub fn bits(&self) -> u8 {
+        if self.n > 0 && self.n & (self.n - 1) == 0 {
+            (24 + self.n.trailing_zeros()).min(64) as u8
+        } else {
+            64
+        }
+    }
+    /// Returns true if `world_seed` generates the observed value in this chunk. Only the lowest
+    /// `self.bits()` bits of the world seed are used.
+    pub fn matches(&self, world_seed: i64, chunk_x: i64, chunk_z: i64) -> bool {
+        let mut r = McRng::new(self.base_seed, world_seed);
+        r.set_chunk_seed(chunk_x, chunk_z);
+        for _ in 0..self.call {
+            // All the calls advance the state in the same way
+            r.next_int_n(1);
+        }
+
+        r.next_int_n(self.n) == self.value
+    }
+}
+
+/// Bits of the world seed constrained by a list of observed rolls, see `solve_world_seed_bits`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitConstraints {
+    /// The observed rolls only depend on the lowest `bits` bits of the world seed
+    pub bits: u8,
+    /// `ObservedRoll::bits` of each observed roll, in the same order
+    pub bits_per_roll: Vec<u8>,
+    /// Sum of `log2(n)` of all the rolls, rounded down. If the rolls are independent, this is
+    /// the number of bits of the world seed that they determine, so when it is lower than `bits`
+    /// there will be around `2^(bits - information_bits)` candidates.
+    pub information_bits: u32,
+}
+
+impl BitConstraints {
+    /// Returns true if `world_seed` matches all the rolls that only depend on the bits from
+    /// `lo_bits` to `hi_bits`, so `lo_bits < roll.bits() <= hi_bits`.
+    pub fn check(&self, observed: &[(i64, i64, ObservedRoll)], world_seed: i64, lo_bits: u8, hi_bits: u8) -> bool {
+        observed.iter().zip(&self.bits_per_roll).all(|((chunk_x, chunk_z, roll), &bits)| {
+            bits <= lo_bits || bits > hi_bits || roll.matches(world_seed, *chunk_x, *chunk_z)
+        })
+    }
+    /// Extend the candidates for the lowest `known_bits` bits of the world seed to `self.bits`
+    /// bits, adding one bit at a time and discarding the candidates that do not match the rolls
+    /// as soon as they can be checked.
+    pub fn extend(&self, observed: &[(i64, i64, ObservedRoll)], candidates: &[i64], known_bits: u8) -> Vec<i64> {
+        let mut candidates: Vec<i64> = candidates.iter().copied().filter(|&c| self.check(observed, c, 0, known_bits)).collect();
+        for bit in known_bits..self.bits {
+            candidates = candidates.into_iter().flat_map(|c| [c, c | (1 << bit)]).filter(|&c| {
+                self.check(observed, c, bit, bit + 1)
+            }).collect();
+        }
+
+        candidates
+    }
+}
+
+/// Find how many of the lowest bits of the world seed are needed to check a list of observed
+/// rolls, given as `(chunk_x, chunk_z, roll)`. For example, the random choices of `MapZoom` use
+/// `next_int_n(2)` and `next_int_n(4)`, so they can be checked using only 26 bits.
+pub fn solve_world_seed_bits(observed: &[(i64, i64, ObservedRoll)]) -> BitConstraints {
+    let bits_per_roll: Vec<u8> = observed.iter().map(|(_, _, roll)| roll.bits()).collect();
+    let bits = bits_per_roll.iter().copied().max().unwrap_or(0);
+    let information_bits = observed.iter().map(|(_, _, roll)| {
+        if roll.n > 0 { 31 - roll.n.leading_zeros() } else { 0 }
+    }).sum();
+
+    BitConstraints { bits, bits_per_roll, information_bits }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn chunk_seed_from_row() {
+        for &(base_seed, world_seed) in &[(1000, 1234), (1003, -6_504_178_349_876_432_114), (1, 0)] {
+            let mut r = McRng::new(base_seed, world_seed);
+            let mut r_row = r;
+            for chunk_x in [-1_000_000, -3, 0, 2, 77, 1 << 40].iter().copied() {
+                let row_seed = r_row.chunk_seed_row(chunk_x);
+                for chunk_z in [-1_000_000, -7, 0, 4, 1 << 40].iter().copied() {
+                    r.set_chunk_seed(chunk_x, chunk_z);
+                    r_row.set_chunk_seed_from_row(row_seed, chunk_x, chunk_z);
+                    assert_eq!(r.chunk_seed(), r_row.chunk_seed());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn next_int_n_const() {
+        fn check<const N: i64>(r: McRng) {
+            let (mut r0, mut r1) = (r, r);
+            for _ in 0..100 {
+                assert_eq!(r0.next_int_n(N as i32), r1.next_int_n_const::<N>());
+            }
+        }
+
+        for &(base_seed, world_seed) in &[(1000, 1234), (1003, -6_504_178_349_876_432_114), (1, 0)] {
+            let mut r = McRng::new(base_seed, world_seed);
+            for chunk_x in [-1_000_000, -3, 0, 2, 77, 1 << 40].iter().copied() {
+                r.set_chunk_seed(chunk_x, 5 - chunk_x);
+                check::<6>(r);
+                check::<10>(r);
+                check::<13>(r);
+                check::<57>(r);
+                check::<100>(r);
+                check::<299999>(r);
+            }
+        }
+    }
+
+    #[test]
+    fn solve_bits_of_zoom_rolls() {
+        let world_seed = 0x1234_5678_9ABC;
+        let observed: Vec<(i64, i64, ObservedRoll)> = (0..10).flat_map(|i| {
+            let (chunk_x, chunk_z) = (i * 7 - 20, 3 - i * 5);
+            let mut r = McRng::new(1000, world_seed);
+            r.set_chunk_seed(chunk_x, chunk_z);
+            let v2 = r.next_int_n(2);
+            let v4 = r.next_int_n(4);
+            [
+                (chunk_x, chunk_z, ObservedRoll { base_seed: 1000, call: 0, n: 2, value: v2 }),
+                (chunk_x, chunk_z, ObservedRoll { base_seed: 1000, call: 1, n: 4, value: v4 }),
+            ]
+        }).collect();
+
+        let c = solve_world_seed_bits(&observed);
+        assert_eq!(c.bits, 26);
+        assert_eq!(c.information_bits, 30);
+        // The bits above 26 do not change the rolls
+        for (chunk_x, chunk_z, roll) in &observed {
+            assert!(roll.matches(world_seed & mask_up_to_bit(25), *chunk_x, *chunk_z));
+            assert!(roll.matches(world_seed ^ (0x5A5A << 26), *chunk_x, *chunk_z));
+        }
+
+        let candidates = c.extend(&observed, &[world_seed & mask_up_to_bit(19)], 20);
+        assert!(candidates.contains(&(world_seed & mask_up_to_bit(25))), "{:?}", candidates);
+        assert!(candidates.len() <= 4, "{:?}", candidates);
+
+        let roll = ObservedRoll { base_seed: 1000, call: 0, n: 10, value: 0 };
+        assert_eq!(solve_world_seed_bits(&[(0, 0, roll)]).bits, 64);
+    }
+
+    #[test]
+    fn similar_biome_seed_orders() {
+        let mut sequential: Vec<i64> = McRng::similar_biome_seed_iterator_bits(25).collect();
+        sequential.sort_unstable();
+        for order in [SeedOrder::NearSeed(0), SeedOrder::NearSeed(-1234567), SeedOrder::LowHammingWeight] {
+            let mut seeds: Vec<i64> = McRng::similar_biome_seed_iterator_25_ordered(order).collect();
+            seeds.sort_unstable();
+            assert_eq!(seeds, sequential, "{:?}", order);
+        }
+
+        let rep = |v: i64| {
+            let v = v & mask_up_to_bit(24);
+            if McRng::similar_biome_seed_iterator_bits(25).contains(&v) { v } else { McRng::similar_biome_seed(v) & mask_up_to_bit(24) }
+        };
+        let mut near = McRng::similar_biome_seed_iterator_25_ordered(SeedOrder::NearSeed(-1234567));
+        assert_eq!(near.next(), Some(rep(-1234567)));
+        let first: Vec<i64> = McRng::similar_biome_seed_iterator_25_ordered(SeedOrder::NearSeed(0)).take(5).collect();
+        for s in [0, 1, -1, 2, -2] {
+            assert!(first.contains(&rep(s)), "{}", s);
+        }
+        let mut low = McRng::similar_biome_seed_iterator_25_ordered(SeedOrder::LowHammingWeight);
+        assert_eq!(low.next(), Some(rep(0)));
+        assert_eq!(seeds_with_hamming_weight_25(2).count(), 25 * 24 / 2);
+    }
+
     #[test]
     fn mc_rng_init() {
         let mut r = McRng::new(10, 1234);
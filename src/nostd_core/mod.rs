@@ -0,0 +1,12 @@
+//! Random number generators used by Minecraft, and the math needed to reverse them.
+//!
+//! This module only depends on `core` and `alloc`, so it can be used without the `std` feature,
+//! for example to embed the generators in a plugin sandbox. For now only the random number
+//! generators are here: the biome layers still use `ndarray` and the caches in `biome_layers`,
+//! so they need `std`, like the seed finders and the file formats.
+
+pub mod java_math;
+pub mod java_rng;
+#[rustfmt::skip]
+pub mod mc_rng;
+pub mod xoroshiro128plusplus;
@@ -1,5 +1,6 @@
 use crate::chunk::Chunk;
 use crate::chunk::Point;
+use crate::chunk::Point3D;
 use crate::gen_pairs3::GenPairs3L;
 use crate::java_rng::JavaRng;
 use crate::seed_info::MinecraftVersion;
@@ -29,6 +30,24 @@ pub fn dungeon_seed_finder_range(
             ((chunk_x, chunk_z), (x, y, z))
         })
         .collect();
+    let mut r = vec![];
+    for world_seed in range_lo..range_hi {
+        //let world_seed = 536274160436487309 & ((1 << 48) - 1);
+        if dungeons_match(world_seed, &chunks, version) {
+            log::info!("{} matches {} dungeons", world_seed, chunks.len());
+            r.push(world_seed);
+        }
+    }
+
+    r
+}
+
+// Returns true if the world seed can generate most of the dungeons
+fn dungeons_match(
+    world_seed: u64,
+    chunks: &[((i32, i32), (i32, i32, i32))],
+    version: MinecraftVersion,
+) -> bool {
     let check_fn = |world_seed: u64, chunk_x, chunk_z, (x, y, z)| match version {
         MinecraftVersion::JavaAlpha1_2_5 => Some(
             populate_alpha_1_0_4_check_dungeon(world_seed as i64, chunk_x, chunk_z, (x, y, z))
@@ -39,39 +58,137 @@ pub fn dungeon_seed_finder_range(
         }
         _ => unimplemented!(),
     };
-    let mut r = vec![];
-    'nextseed: for world_seed in range_lo..range_hi {
-        //let world_seed = 536274160436487309 & ((1 << 48) - 1);
-        let mut matches = 0;
-        let mut misses = 0;
-        let expected_matches = chunks.len();
-        for ((chunk_x, chunk_z), (x, y, z)) in chunks.iter().cloned() {
-            match check_fn(world_seed, chunk_x, chunk_z, (x, y, z)) {
-                None => {
-                    // This chunk has a lava lake, so we don't know how to check for dungeons
-                    // (we could check using bruteforce but it's probably not worth it)
-                    misses += 1;
-                }
-                Some(false) => {
-                    // Probably no, unless this chunk has more than one dungeon
-                    misses += 1;
-                }
-                Some(true) => {
-                    // Probably yes
-                    matches += 1;
-                }
+    let mut matches = 0;
+    let mut misses = 0;
+    for ((chunk_x, chunk_z), (x, y, z)) in chunks.iter().cloned() {
+        match check_fn(world_seed, chunk_x, chunk_z, (x, y, z)) {
+            None => {
+                // This chunk has a lava lake, so we don't know how to check for dungeons
+                // (we could check using bruteforce but it's probably not worth it)
+                misses += 1;
             }
-
-            if misses >= 3 && matches == 0 {
-                continue 'nextseed;
+            Some(false) => {
+                // Probably no, unless this chunk has more than one dungeon
+                misses += 1;
+            }
+            Some(true) => {
+                // Probably yes
+                matches += 1;
             }
         }
 
-        log::info!("{} {}/{}", world_seed, matches, expected_matches);
-        r.push(world_seed);
+        if misses >= MIN_DUNGEON_MISSES && matches == 0 {
+            return false;
+        }
     }
 
-    r
+    true
+}
+
+// A seed is only rejected after this many dungeons do not match, because a chunk can have a
+// lava lake or more than one dungeon
+const MIN_DUNGEON_MISSES: usize = 3;
+
+// Versions supported by `dungeons_match`
+fn can_check_dungeons(version: MinecraftVersion) -> bool {
+    matches!(
+        version,
+        MinecraftVersion::JavaAlpha1_2_5 | MinecraftVersion::JavaBeta
+    )
+}
+
+/// The mob of a monster spawner
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MobType {
+    Zombie,
+    Skeleton,
+    Spider,
+    /// Cave spider spawners are only generated in mineshafts
+    CaveSpider,
+}
+
+impl MobType {
+    /// Returns true if a dungeon can have a spawner with this mob
+    pub fn can_be_dungeon(&self) -> bool {
+        !matches!(self, MobType::CaveSpider)
+    }
+}
+
+/// Check candidate 48-bit world seeds using the position of the observed spawners, using the same
+/// criteria as `dungeon_seed_finder`. Spawners that cannot belong to a dungeon are ignored.
+///
+/// The mob type is not checked against the seed, because it is selected after placing the
+/// chests, and that depends on the terrain.
+///
+/// Returns an error if the version is not alpha or beta, or if there are less than 3 dungeon
+/// spawners: a seed is only rejected if at least 3 dungeons do not match, so with less
+/// spawners all the candidates would be accepted.
+pub fn dungeon_seed_filter(
+    candidates: &[u64],
+    observed_spawners: &[(Point3D, MobType)],
+    version: MinecraftVersion,
+) -> Result<Vec<u64>, String> {
+    if !can_check_dungeons(version) {
+        return Err(format!(
+            "Dungeon seed filter not implemented for version {:?}",
+            version
+        ));
+    }
+    let chunks: Vec<_> = observed_spawners
+        .iter()
+        .filter(|(_p, mob)| mob.can_be_dungeon())
+        .map(|(p, _mob)| {
+            let Chunk {
+                x: chunk_x,
+                z: chunk_z,
+            } = spawner_coordinates_to_chunk(p.x, p.z);
+            ((chunk_x, chunk_z), (p.x as i32, p.y as i32, p.z as i32))
+        })
+        .collect();
+    if chunks.len() < MIN_DUNGEON_MISSES {
+        return Err(format!(
+            "Need at least {} dungeon spawners, got {}",
+            MIN_DUNGEON_MISSES,
+            chunks.len()
+        ));
+    }
+
+    Ok(candidates
+        .iter()
+        .copied()
+        .filter(|world_seed| dungeons_match(*world_seed & ((1 << 48) - 1), &chunks, version))
+        .collect())
+}
+
+/// Returns true if a mineshaft starts at this chunk. Only the lower 48 bits of the world seed
+/// are used.
+/// Works for versions from Minecraft Java 1.7 to 1.12.
+pub fn is_mineshaft_chunk_1_7(world_seed: i64, chunk: &Chunk) -> bool {
+    let mut r = JavaRng::with_seed(world_seed as u64);
+    let j = r.next_long();
+    let k = r.next_long();
+    let x = chunk.x as i64;
+    let z = chunk.z as i64;
+    r.set_seed((x.wrapping_mul(j) ^ z.wrapping_mul(k) ^ world_seed) as u64);
+    // MapGenStructure.recursiveGenerate calls nextInt before canSpawnStructureAtCoords
+    r.next_int();
+
+    r.next_double() < 0.004 && r.next_int_n(80) < std::cmp::max(chunk.x.abs(), chunk.z.abs())
+}
+
+/// Check candidate 48-bit world seeds using the chunks where a mineshaft starts. All the
+/// mineshafts must match.
+/// Works for versions from Minecraft Java 1.7 to 1.12.
+pub fn mineshaft_seed_filter(candidates: &[u64], mineshafts: &[Chunk]) -> Vec<u64> {
+    candidates
+        .iter()
+        .copied()
+        .filter(|world_seed| {
+            mineshafts
+                .iter()
+                .all(|c| is_mineshaft_chunk_1_7(*world_seed as i64, c))
+        })
+        .collect()
 }
 
 /// Mutate the RNG in the same way as the code that tries to generate water lakes.
@@ -1381,6 +1498,101 @@ mod tests {
         }
     }
 
+    #[test]
+    fn dungeon_seed_filter_alpha() {
+        let world_seed = 1234;
+        let mut spawners = vec![];
+        for (chunk_x, chunk_z) in [(0, 0), (3, -2), (-5, 7)] {
+            // First dungeon attempt of this chunk
+            let chunk_seed = world_seed_to_chunk_population_seed(world_seed, chunk_x, chunk_z);
+            let mut r = JavaRng::with_seed(chunk_seed);
+            let x = chunk_x * 16 + r.next_int_n(16) + 8;
+            let y = r.next_int_n(128);
+            let z = chunk_z * 16 + r.next_int_n(16) + 8;
+            let p = Point3D {
+                x: x as i64,
+                y: y as i64,
+                z: z as i64,
+            };
+            spawners.push((p, MobType::Zombie));
+        }
+        // Ignored because it is not a dungeon spawner
+        spawners.push((Point3D { x: 0, y: 0, z: 0 }, MobType::CaveSpider));
+
+        assert_eq!(
+            dungeon_seed_filter(
+                &[1233, world_seed as u64, 1235],
+                &spawners,
+                MinecraftVersion::JavaAlpha1_2_5
+            ),
+            Ok(vec![world_seed as u64])
+        );
+
+        // Unsupported version
+        assert!(dungeon_seed_filter(&[1234], &spawners, MinecraftVersion::Java1_7).is_err());
+        // Not enough dungeons to reject any seed
+        assert!(
+            dungeon_seed_filter(&[1234], &spawners[..2], MinecraftVersion::JavaAlpha1_2_5).is_err()
+        );
+    }
+
+    #[test]
+    fn mineshaft_chunk_rng_calls() {
+        // Same calls as MapGenBase.generate, MapGenStructure.recursiveGenerate and
+        // MapGenMineshaft.canSpawnStructureAtCoords
+        fn java_mineshaft(world_seed: i64, x: i32, z: i32) -> bool {
+            let mut r = JavaRng::with_seed(world_seed as u64);
+            let j = r.next_long();
+            let k = r.next_long();
+            let l = i64::from(x).wrapping_mul(j);
+            let i1 = i64::from(z).wrapping_mul(k);
+            r.set_seed((l ^ i1 ^ world_seed) as u64);
+            r.next_int();
+            let chance = r.next_double();
+            chance < 0.004 && r.next_int_n(80) < x.abs().max(z.abs())
+        }
+
+        let world_seed = 1234;
+        let mut found = 0;
+        for x in -200..200 {
+            for z in -200..200 {
+                let expected = java_mineshaft(world_seed, x, z);
+                assert_eq!(
+                    is_mineshaft_chunk_1_7(world_seed, &Chunk::new(x, z)),
+                    expected,
+                    "{} {}",
+                    x,
+                    z
+                );
+                found += expected as u32;
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn mineshaft_seed_filter_finds_seed() {
+        let world_seed = 1234;
+        let mut mineshafts = vec![];
+        'search: for x in -100..100 {
+            for z in -100..100 {
+                let c = Chunk::new(x, z);
+                if is_mineshaft_chunk_1_7(world_seed, &c) {
+                    mineshafts.push(c);
+                    if mineshafts.len() == 3 {
+                        break 'search;
+                    }
+                }
+            }
+        }
+        assert_eq!(mineshafts.len(), 3);
+
+        assert_eq!(
+            mineshaft_seed_filter(&[1233, world_seed as u64, 1235], &mineshafts),
+            vec![world_seed as u64]
+        );
+    }
+
     #[test]
     fn population_seed_reverse() {
         // Benchmark: approx 2 ms per run with debug logs
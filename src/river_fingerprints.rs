@@ -0,0 +1,400 @@
+//! Precomputed river maps for the 26-bit phase of the river seed finder
+//!
+//! The 26-bit phase generates `candidate_river_map` for 2^25 seeds, and that map only depends on
+//! the seed and the area. So for a fixed grid of fragments, usually the ones near the spawn, the
+//! maps can be generated once and stored in a file. Each fragment of each seed is stored as a
+//! fingerprint: a `u64` with one bit per tile, set if the tile can be a river. After that, any
+//! search with rivers inside the grid only needs to compare the fingerprints with the rivers,
+//! without generating any maps.
+//!
+//! File format, all the integers are little endian:
+//!
+//! * Header, `HEADER_SIZE` bytes: the magic bytes `SSFRIVFP`, the format version as `u32`, the
+//!   grid as `x: i64, z: i64, w: u32, h: u32`, and the range of seed indexes as `lo: u32, hi: u32`.
+//!   The remaining bytes are 0.
+//! * For each seed index in `lo..hi`, one `u64` fingerprint for each fragment of the grid, in the
+//!   same order as `FragmentGrid::fragment_index`.
+//!
+//! The fingerprints are aligned to 8 bytes and the file is never modified after writing it, so it
+//! can be memory mapped instead of read into memory: see `RiverFingerprints::new`.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers::Area;
+use crate::biome_layers::CandidateRiverMapGenerator;
+use crate::biome_layers::Map;
+use crate::biome_layers::SearchConfig;
+use crate::chunk::Point4;
+use crate::mc_rng::McRng;
+use std::io::Read;
+use std::io::Write;
+
+/// Size of one fragment, in tiles at 1:4 scale. 8x8 tiles fit in one `u64`.
+pub const FRAGMENT_SIZE: i64 = 8;
+/// Number of seeds checked by the 26-bit phase: 2^24 pairs of similar seeds, with bit 25 set to 0
+/// and to 1
+pub const NUM_SEEDS: u32 = 1 << 25;
+/// Increased every time the file format or the fingerprints change
+pub const FORMAT_VERSION: u32 = 1;
+/// Size of the header of the file, in bytes
+pub const HEADER_SIZE: usize = 64;
+const MAGIC: [u8; 8] = *b"SSFRIVFP";
+
+#[derive(Debug)]
+pub enum RiverFingerprintsError {
+    Io(std::io::Error),
+    /// The data does not start with the magic bytes
+    BadMagic,
+    /// The data was written using a different format version
+    UnsupportedVersion(u32),
+    /// The data is shorter than the size declared in its header
+    Truncated,
+}
+
+impl From<std::io::Error> for RiverFingerprintsError {
+    fn from(x: std::io::Error) -> Self {
+        RiverFingerprintsError::Io(x)
+    }
+}
+
+/// Grid of fragments. The fragment `(i, j)` of the grid covers the tiles at 1:4 scale from
+/// `((x + i) * FRAGMENT_SIZE, (z + j) * FRAGMENT_SIZE)`, both inclusive, to
+/// `((x + i + 1) * FRAGMENT_SIZE, (z + j + 1) * FRAGMENT_SIZE)`, both exclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FragmentGrid {
+    pub x: i64,
+    pub z: i64,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl FragmentGrid {
+    /// Area covered by the grid, at 1:4 scale
+    pub fn area(&self) -> Area {
+        Area {
+            x: self.x * FRAGMENT_SIZE,
+            z: self.z * FRAGMENT_SIZE,
+            w: u64::from(self.w) * FRAGMENT_SIZE as u64,
+            h: u64::from(self.h) * FRAGMENT_SIZE as u64,
+        }
+    }
+
+    pub fn num_fragments(&self) -> usize {
+        self.w as usize * self.h as usize
+    }
+
+    /// Index of the fragment that contains this tile, and bit of the tile inside the
+    /// fingerprint. Returns `None` if the tile is outside of the grid.
+    pub fn fragment_index(&self, p: Point4) -> Option<(usize, u32)> {
+        let fx = p.x.div_euclid(FRAGMENT_SIZE) - self.x;
+        let fz = p.z.div_euclid(FRAGMENT_SIZE) - self.z;
+        if fx < 0 || fz < 0 || fx >= i64::from(self.w) || fz >= i64::from(self.h) {
+            return None;
+        }
+        let bit = p.x.rem_euclid(FRAGMENT_SIZE) * FRAGMENT_SIZE + p.z.rem_euclid(FRAGMENT_SIZE);
+
+        Some((fx as usize * self.h as usize + fz as usize, bit as u32))
+    }
+}
+
+/// Seed stored at this index, from 0 to `NUM_SEEDS`. The first half are the seeds returned by
+/// `McRng::similar_biome_seed_iterator_bits(25)`, and the second half are the same seeds with
+/// bit 25 set to 1.
+pub fn seed_at_index(index: u32) -> i64 {
+    let first = *McRng::similar_biome_seed_iterator_bits(25).start();
+    let seed = first + i64::from(index & ((1 << 24) - 1));
+    if index >> 24 == 0 {
+        seed
+    } else {
+        seed ^ (1 << 25)
+    }
+}
+
+// Set fingerprints to the rivers of map, which must have the same area as the grid
+fn fingerprints_of_map(grid: &FragmentGrid, map: &Map, fingerprints: &mut Vec<u64>) {
+    fingerprints.clear();
+    fingerprints.resize(grid.num_fragments(), 0);
+    for ((x, z), v) in map.a.indexed_iter() {
+        if *v == biome_id::river {
+            let p = Point4 {
+                x: map.x + x as i64,
+                z: map.z + z as i64,
+            };
+            let (i, bit) = grid.fragment_index(p).unwrap();
+            fingerprints[i] |= 1 << bit;
+        }
+    }
+}
+
+/// Header of a fingerprints file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RiverFingerprintsHeader {
+    pub grid: FragmentGrid,
+    /// First seed index, inclusive
+    pub index_lo: u32,
+    /// Last seed index, exclusive
+    pub index_hi: u32,
+}
+
+impl RiverFingerprintsHeader {
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut b = [0; HEADER_SIZE];
+        b[0..8].copy_from_slice(&MAGIC);
+        b[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        b[12..20].copy_from_slice(&self.grid.x.to_le_bytes());
+        b[20..28].copy_from_slice(&self.grid.z.to_le_bytes());
+        b[28..32].copy_from_slice(&self.grid.w.to_le_bytes());
+        b[32..36].copy_from_slice(&self.grid.h.to_le_bytes());
+        b[36..40].copy_from_slice(&self.index_lo.to_le_bytes());
+        b[40..44].copy_from_slice(&self.index_hi.to_le_bytes());
+        b
+    }
+
+    fn from_bytes(b: &[u8]) -> Result<Self, RiverFingerprintsError> {
+        if b.len() < HEADER_SIZE {
+            return Err(RiverFingerprintsError::Truncated);
+        }
+        if b[0..8] != MAGIC {
+            return Err(RiverFingerprintsError::BadMagic);
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+        let i64_at = |i: usize| i64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+        let version = u32_at(8);
+        if version != FORMAT_VERSION {
+            return Err(RiverFingerprintsError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            grid: FragmentGrid {
+                x: i64_at(12),
+                z: i64_at(20),
+                w: u32_at(28),
+                h: u32_at(32),
+            },
+            index_lo: u32_at(36),
+            index_hi: u32_at(40),
+        })
+    }
+
+    /// Size of the fingerprints, in bytes
+    pub fn data_len(&self) -> usize {
+        self.index_hi.saturating_sub(self.index_lo) as usize * self.grid.num_fragments() * 8
+    }
+}
+
+/// Generate the river maps of the seeds with index in `index_lo..index_hi` and write their
+/// fingerprints to `w`. Use `0..NUM_SEEDS` to precompute all the seeds, which needs 256 MiB per
+/// fragment. Smaller ranges can be used to split the work, but the search only uses one file.
+pub fn write_river_fingerprints<W: Write>(
+    grid: FragmentGrid,
+    index_lo: u32,
+    index_hi: u32,
+    mut w: W,
+) -> Result<(), RiverFingerprintsError> {
+    assert!(index_lo <= index_hi && index_hi <= NUM_SEEDS);
+    let header = RiverFingerprintsHeader {
+        grid,
+        index_lo,
+        index_hi,
+    };
+    w.write_all(&header.to_bytes())?;
+
+    let area = grid.area();
+    let mut generator = CandidateRiverMapGenerator::new(0);
+    let mut map = Map::default();
+    let mut fingerprints = vec![];
+    let mut buf = Vec::with_capacity(grid.num_fragments() * 8);
+    for index in index_lo..index_hi {
+        generator.set_world_seed(seed_at_index(index));
+        generator.get_map_into(area, &mut map);
+        fingerprints_of_map(&grid, &map, &mut fingerprints);
+        buf.clear();
+        for f in &fingerprints {
+            buf.extend_from_slice(&f.to_le_bytes());
+        }
+        w.write_all(&buf)?;
+    }
+
+    Ok(())
+}
+
+/// Fingerprints written by `write_river_fingerprints`
+pub struct RiverFingerprints<B: AsRef<[u8]>> {
+    header: RiverFingerprintsHeader,
+    data: B,
+}
+
+impl<B: AsRef<[u8]>> RiverFingerprints<B> {
+    /// Check the header of the data. The data can be the whole file read into memory, or any
+    /// other type that can be borrowed as a slice, like a memory map of the file.
+    pub fn new(data: B) -> Result<Self, RiverFingerprintsError> {
+        let bytes = data.as_ref();
+        let header = RiverFingerprintsHeader::from_bytes(bytes)?;
+        if bytes.len() < HEADER_SIZE + header.data_len() {
+            return Err(RiverFingerprintsError::Truncated);
+        }
+
+        Ok(Self { header, data })
+    }
+
+    pub fn header(&self) -> &RiverFingerprintsHeader {
+        &self.header
+    }
+
+    /// Fingerprint of one fragment of the seed at this index. Panics if the index is not in the
+    /// range of the file.
+    pub fn fingerprint(&self, index: u32, fragment: usize) -> u64 {
+        assert!(index >= self.header.index_lo && index < self.header.index_hi);
+        assert!(fragment < self.header.grid.num_fragments());
+        let i =
+            (index - self.header.index_lo) as usize * self.header.grid.num_fragments() + fragment;
+        let offset = HEADER_SIZE + i * 8;
+
+        u64::from_le_bytes(self.data.as_ref()[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Same as `river_seed_finder_26_seeds_cancellable`, but only using the rivers inside the
+    /// grid, and only checking the seeds of this file. A seed is a candidate if it has at least
+    /// `config.river_percent_26` percent of the rivers of the whole grid. The candidates include
+    /// the similar seed of each seed, like in the 26-bit phase.
+    ///
+    /// Returns `None` if there are no rivers inside the grid.
+    pub fn search(
+        &self,
+        river_coords_quarter_scale: &[Point4],
+        config: &SearchConfig,
+    ) -> Option<Vec<i64>> {
+        let grid = self.header.grid;
+        let mut targets = vec![0u64; grid.num_fragments()];
+        for p in river_coords_quarter_scale {
+            if let Some((i, bit)) = grid.fragment_index(*p) {
+                targets[i] |= 1 << bit;
+            }
+        }
+        let target_score: u32 = targets.iter().map(|t| t.count_ones()).sum();
+        if target_score == 0 {
+            return None;
+        }
+        let min_score = target_score * config.river_percent_26 / 100;
+
+        let data = &self.data.as_ref()[HEADER_SIZE..HEADER_SIZE + self.header.data_len()];
+        let mut candidates = vec![];
+        for (i, record) in data.chunks_exact(targets.len() * 8).enumerate() {
+            let score: u32 = record
+                .chunks_exact(8)
+                .zip(targets.iter())
+                .map(|(f, t)| (u64::from_le_bytes(f.try_into().unwrap()) & t).count_ones())
+                .sum();
+            if score >= min_score {
+                let seed = seed_at_index(self.header.index_lo + i as u32);
+                candidates.push(seed);
+                candidates.push(McRng::similar_biome_seed(seed) & ((1 << 26) - 1));
+            }
+        }
+
+        Some(candidates)
+    }
+}
+
+/// Read the whole file into memory
+pub fn read_river_fingerprints<R: Read>(
+    mut r: R,
+) -> Result<RiverFingerprints<Vec<u8>>, RiverFingerprintsError> {
+    let mut data = vec![];
+    r.read_to_end(&mut data)?;
+
+    RiverFingerprints::new(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_layers::candidate_river_map;
+
+    #[test]
+    fn build_and_search() {
+        let grid = FragmentGrid {
+            x: -1,
+            z: 0,
+            w: 2,
+            h: 2,
+        };
+        let (index_lo, index_hi) = (1000, 1040);
+        let mut file = vec![];
+        write_river_fingerprints(grid, index_lo, index_hi, &mut file).unwrap();
+        assert_eq!(file.len(), HEADER_SIZE + 40 * 4 * 8);
+        let fingerprints = read_river_fingerprints(&file[..]).unwrap();
+        assert_eq!(
+            *fingerprints.header(),
+            RiverFingerprintsHeader {
+                grid,
+                index_lo,
+                index_hi
+            }
+        );
+
+        let index = 1017;
+        let seed = seed_at_index(index);
+        let map = candidate_river_map(grid.area(), seed);
+        let rivers: Vec<Point4> = map
+            .a
+            .indexed_iter()
+            .filter(|(_, v)| **v == biome_id::river)
+            .map(|((x, z), _)| Point4 {
+                x: map.x + x as i64,
+                z: map.z + z as i64,
+            })
+            .collect();
+        let count: u32 = (0..4)
+            .map(|f| fingerprints.fingerprint(index, f).count_ones())
+            .sum();
+        assert_eq!(count as usize, rivers.len());
+
+        let config = SearchConfig {
+            river_percent_26: 100,
+            ..SearchConfig::default()
+        };
+        let candidates = fingerprints.search(&rivers, &config).unwrap();
+        assert!(candidates.contains(&seed));
+        assert!(candidates.contains(&(McRng::similar_biome_seed(seed) & ((1 << 26) - 1))));
+        assert_eq!(
+            fingerprints.search(&[Point4 { x: 100, z: 100 }], &config),
+            None
+        );
+    }
+
+    #[test]
+    fn seed_indexes() {
+        assert_eq!(seed_at_index(1 << 24), seed_at_index(0) ^ (1 << 25));
+        assert_eq!(
+            seed_at_index((1 << 24) - 1),
+            *McRng::similar_biome_seed_iterator_bits(25).end()
+        );
+    }
+
+    #[test]
+    fn bad_files() {
+        let grid = FragmentGrid {
+            x: 0,
+            z: 0,
+            w: 1,
+            h: 1,
+        };
+        let mut file = vec![];
+        write_river_fingerprints(grid, 0, 2, &mut file).unwrap();
+        assert!(RiverFingerprints::new(&file[..]).is_ok());
+        assert!(matches!(
+            RiverFingerprints::new(&file[..file.len() - 1]),
+            Err(RiverFingerprintsError::Truncated)
+        ));
+        let mut other_version = file.clone();
+        other_version[8] = 2;
+        assert!(matches!(
+            RiverFingerprints::new(&other_version[..]),
+            Err(RiverFingerprintsError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            RiverFingerprints::new(&file[1..]),
+            Err(RiverFingerprintsError::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,453 @@
+//! Import evidence from overhead screenshots
+//!
+//! A screenshot of the world seen from above, such as a map mod or a top-down render, does not
+//! use the biome colors of `biome_image`, so the biome of each pixel cannot be known exactly.
+//! But the color is enough to tell water, snow and land apart, and narrow water bodies surrounded
+//! by land are usually rivers, which is the evidence used by the river seed finder.
+//!
+//! The scale of the screenshot (blocks per pixel) and the approximate coordinates of the top-left
+//! pixel must be known. The exact position of the pixel grid is found by looking for the offset
+//! that makes the color of each block as uniform as possible, see `align_grid`.
+
+use crate::biome_info::biome_id;
+use crate::biome_layers::Area;
+use crate::biome_layers::ImageBuffer;
+use crate::chunk::Point;
+use crate::seed_info::BiomeId;
+use crate::seed_info::SeedInfo;
+use ndarray::Array2;
+
+/// Water narrower than this number of blocks, with land at both sides, is classified as river
+pub const MAX_RIVER_WIDTH: usize = 16;
+
+// Number of candidate offsets of the pixel grid tried in each axis, between -0.5 and 0.5 blocks
+const ALIGNMENT_STEPS: usize = 12;
+
+/// Biome classes that can be told apart by color
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoarseBiome {
+    Ocean,
+    Land,
+    Snow,
+    River,
+}
+
+/// Coarse class of a pixel. Rivers and oceans have the same color, so all the water is
+/// classified as `Ocean`, see `find_rivers`. Returns `None` for transparent pixels.
+pub fn classify_color(color: [u8; 4]) -> Option<CoarseBiome> {
+    let [r, g, b, a] = color;
+    if a == 0 {
+        return None;
+    }
+    let (r, g, b) = (i32::from(r), i32::from(g), i32::from(b));
+    if r.min(g).min(b) >= 200 {
+        // Snow and ice are bright and almost white
+        Some(CoarseBiome::Snow)
+    } else if b > r + 20 && b > g {
+        Some(CoarseBiome::Ocean)
+    } else {
+        Some(CoarseBiome::Land)
+    }
+}
+
+/// Position of the pixel grid of a screenshot in the world
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScreenshotGrid {
+    /// Block coordinates of the top-left corner of the top-left pixel
+    pub x: f64,
+    pub z: f64,
+    /// Width of one pixel in blocks. Less than 1 if each block is more than one pixel wide.
+    pub blocks_per_pixel: f64,
+}
+
+impl ScreenshotGrid {
+    /// Returns an error if the scale is not positive or a coordinate is not finite. The size of
+    /// the classified map depends on them, so invalid values would overflow it.
+    pub fn check(&self) -> Result<(), String> {
+        if !(self.blocks_per_pixel > 0.0 && self.blocks_per_pixel.is_finite()) {
+            return Err(format!(
+                "Invalid blocks per pixel: {}, must be a positive number",
+                self.blocks_per_pixel
+            ));
+        }
+        if !self.x.is_finite() || !self.z.is_finite() {
+            return Err(format!(
+                "Invalid screenshot coordinates: ({}, {})",
+                self.x, self.z
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Block that contains the center of the pixel `(i, j)`
+    pub fn block_at_pixel(&self, i: usize, j: usize) -> Point {
+        Point {
+            x: (self.x + (i as f64 + 0.5) * self.blocks_per_pixel).floor() as i64,
+            z: (self.z + (j as f64 + 0.5) * self.blocks_per_pixel).floor() as i64,
+        }
+    }
+}
+
+// Sum of squared differences between the pixels of each run of consecutive pixels that belong to
+// the same block, for pixels along one axis
+fn alignment_cost<F>(len: usize, lines: usize, pixel: F, origin: f64, blocks_per_pixel: f64) -> f64
+where
+    F: Fn(usize, usize) -> [u8; 4],
+{
+    let mut cost = 0.0;
+    for line in 0..lines {
+        let mut run_block = None;
+        let mut n = 0.0;
+        let mut sum = [0.0; 3];
+        let mut sum_sq = 0.0;
+        for i in 0..=len {
+            let block = if i < len {
+                Some((origin + (i as f64 + 0.5) * blocks_per_pixel).floor() as i64)
+            } else {
+                None
+            };
+            if block != run_block {
+                if n > 0.0 {
+                    let mean_sq: f64 = sum.iter().map(|s| s * s).sum::<f64>() / n;
+                    cost += sum_sq - mean_sq;
+                }
+                run_block = block;
+                n = 0.0;
+                sum = [0.0; 3];
+                sum_sq = 0.0;
+            }
+            if i < len {
+                let [r, g, b, _a] = pixel(i, line);
+                n += 1.0;
+                for (s, c) in sum.iter_mut().zip([r, g, b]) {
+                    let c = f64::from(c);
+                    *s += c;
+                    sum_sq += c * c;
+                }
+            }
+        }
+    }
+
+    cost
+}
+
+// Offset between -0.5 and 0.5 blocks with the lowest alignment cost along one axis
+fn align_axis<F>(len: usize, lines: usize, pixel: F, origin: f64, blocks_per_pixel: f64) -> f64
+where
+    F: Fn(usize, usize) -> [u8; 4],
+{
+    let mut best: (f64, f64) = (f64::INFINITY, 0.0);
+    for step in 0..ALIGNMENT_STEPS {
+        let offset = step as f64 / ALIGNMENT_STEPS as f64 - 0.5;
+        let cost = alignment_cost(len, lines, &pixel, origin + offset, blocks_per_pixel);
+        // Prefer the offset nearest to the guess when the cost is the same
+        if cost < best.0 || (cost == best.0 && offset.abs() < best.1.abs()) {
+            best = (cost, offset);
+        }
+    }
+
+    best.1
+}
+
+/// Find the position of the pixel grid, given the coordinates of the top-left corner of the
+/// screenshot with an error of at most half a block. Each block is usually drawn with slightly
+/// different colors, so the best offset is the one where the pixels of each block are most
+/// similar. If each pixel is one block or more, the offset cannot be found and the guess is
+/// returned.
+pub fn align_grid(img: &ImageBuffer, blocks_per_pixel: f64, x: f64, z: f64) -> ScreenshotGrid {
+    let mut grid = ScreenshotGrid {
+        x,
+        z,
+        blocks_per_pixel,
+    };
+    if blocks_per_pixel >= 1.0 {
+        return grid;
+    }
+
+    grid.x += align_axis(
+        img.width,
+        img.height,
+        |i, line| img.get_pixel(i, line),
+        x,
+        blocks_per_pixel,
+    );
+    grid.z += align_axis(
+        img.height,
+        img.width,
+        |i, line| img.get_pixel(line, i),
+        z,
+        blocks_per_pixel,
+    );
+
+    grid
+}
+
+/// Coarse biome of each block
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoarseMap {
+    pub x: i64,
+    pub z: i64,
+    pub a: Array2<Option<CoarseBiome>>,
+}
+
+impl CoarseMap {
+    pub fn area(&self) -> Area {
+        let (w, h) = self.a.dim();
+        Area {
+            x: self.x,
+            z: self.z,
+            w: w as u64,
+            h: h as u64,
+        }
+    }
+}
+
+/// Classify each block using the most common class of its pixels, and then split the water into
+/// rivers and oceans using `find_rivers`. Returns an error if the grid is not valid, see
+/// `ScreenshotGrid::check`.
+pub fn classify_screenshot(img: &ImageBuffer, grid: &ScreenshotGrid) -> Result<CoarseMap, String> {
+    grid.check()?;
+    let classes = [CoarseBiome::Ocean, CoarseBiome::Land, CoarseBiome::Snow];
+    if img.width == 0 || img.height == 0 {
+        return Ok(CoarseMap {
+            x: 0,
+            z: 0,
+            a: Array2::default((0, 0)),
+        });
+    }
+    let first = grid.block_at_pixel(0, 0);
+    let last = grid.block_at_pixel(img.width - 1, img.height - 1);
+    let (w, h) = (
+        (last.x - first.x + 1) as usize,
+        (last.z - first.z + 1) as usize,
+    );
+
+    let mut counts: Array2<[u32; 3]> = Array2::default((w, h));
+    for i in 0..img.width {
+        for j in 0..img.height {
+            if let Some(class) = classify_color(img.get_pixel(i, j)) {
+                let p = grid.block_at_pixel(i, j);
+                let k = classes.iter().position(|c| *c == class).unwrap();
+                counts[((p.x - first.x) as usize, (p.z - first.z) as usize)][k] += 1;
+            }
+        }
+    }
+
+    let a = counts.map(|c| {
+        let (k, n) = c
+            .iter()
+            .enumerate()
+            .max_by_key(|(k, n)| (**n, std::cmp::Reverse(*k)))
+            .unwrap();
+        if *n == 0 {
+            None
+        } else {
+            Some(classes[k])
+        }
+    });
+    let mut m = CoarseMap {
+        x: first.x,
+        z: first.z,
+        a,
+    };
+    find_rivers(&mut m);
+
+    Ok(m)
+}
+
+/// Reclassify as `River` the water that is at most `MAX_RIVER_WIDTH` blocks wide in the x or z
+/// axis, with land or snow at both sides. Water that touches the border of the map or an unknown
+/// block is not reclassified, because its width is unknown. Small lakes are also classified as
+/// rivers.
+pub fn find_rivers(m: &mut CoarseMap) {
+    let (w, h) = m.a.dim();
+    let mut narrow = Array2::from_elem((w, h), false);
+    let is_water =
+        |c: Option<CoarseBiome>| c == Some(CoarseBiome::Ocean) || c == Some(CoarseBiome::River);
+    let is_shore =
+        |c: Option<CoarseBiome>| c == Some(CoarseBiome::Land) || c == Some(CoarseBiome::Snow);
+
+    for (len, lines, along_x) in [(w, h, true), (h, w, false)] {
+        let index = |i: usize, line: usize| if along_x { (i, line) } else { (line, i) };
+        for line in 0..lines {
+            let mut i = 0;
+            while i < len {
+                if !is_water(m.a[index(i, line)]) {
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < len && is_water(m.a[index(i, line)]) {
+                    i += 1;
+                }
+                let bounded = start > 0
+                    && i < len
+                    && is_shore(m.a[index(start - 1, line)])
+                    && is_shore(m.a[index(i, line)]);
+                if bounded && i - start <= MAX_RIVER_WIDTH {
+                    for k in start..i {
+                        narrow[index(k, line)] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    for (c, narrow) in m.a.iter_mut().zip(narrow.iter()) {
+        if *narrow && is_water(*c) {
+            *c = Some(CoarseBiome::River);
+        }
+    }
+}
+
+/// Blocks classified as `River`
+pub fn coarse_map_rivers(m: &CoarseMap) -> Vec<Point> {
+    m.a.indexed_iter()
+        .filter(|(_, c)| **c == Some(CoarseBiome::River))
+        .map(|((i, j), _)| Point {
+            x: m.x + i as i64,
+            z: m.z + j as i64,
+        })
+        .collect()
+}
+
+/// Align and classify a screenshot, and return the rivers as a `SeedInfo`. The other classes
+/// are not included, because they do not identify one biome. Returns an error if the scale or
+/// the coordinates are not valid, see `ScreenshotGrid::check`.
+pub fn screenshot_seed_info(
+    img: &ImageBuffer,
+    blocks_per_pixel: f64,
+    x: f64,
+    z: f64,
+    version: String,
+) -> Result<SeedInfo, String> {
+    ScreenshotGrid {
+        x,
+        z,
+        blocks_per_pixel,
+    }
+    .check()?;
+    let grid = align_grid(img, blocks_per_pixel, x, z);
+    let m = classify_screenshot(img, &grid)?;
+
+    let mut seed_info = SeedInfo::default();
+    seed_info.version = version;
+    seed_info.description = format!(
+        "Imported from a screenshot, top-left corner at ({:.2}, {:.2})",
+        grid.x, grid.z
+    );
+    let rivers = coarse_map_rivers(&m);
+    if !rivers.is_empty() {
+        seed_info.biomes.insert(BiomeId(biome_id::river), rivers);
+    }
+
+    Ok(seed_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A world with an ocean at x < 20, snow at z >= 40, and a river 5 blocks wide at
+    // 40 <= x < 45 that ends at the snow. Each block has a slightly different color.
+    fn block_color(x: i64, z: i64) -> [u8; 4] {
+        let noise = ((x * 7 + z * 13).rem_euclid(5) * 6) as u8;
+        if x < 20 || ((40..45).contains(&x) && z < 40) {
+            [20 + noise, 40, 180, 255]
+        } else if z >= 40 {
+            [230 + noise, 230, 230, 255]
+        } else {
+            [60, 140 + noise, 40, 255]
+        }
+    }
+
+    // Draw the blocks from (0, 0) to (60, 60) with 3 pixels per block, where the top-left pixel
+    // starts `offset` pixels before the block (0, 0)
+    fn draw(offset: usize) -> ImageBuffer {
+        let size = 60 * 3;
+        let mut img = ImageBuffer::new(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                let x = (i as i64 - offset as i64).div_euclid(3);
+                let z = (j as i64 - offset as i64).div_euclid(3);
+                img.set_pixel(i, j, block_color(x, z));
+            }
+        }
+
+        img
+    }
+
+    #[test]
+    fn classify_colors() {
+        assert_eq!(classify_color([30, 60, 200, 255]), Some(CoarseBiome::Ocean));
+        assert_eq!(
+            classify_color([240, 245, 250, 255]),
+            Some(CoarseBiome::Snow)
+        );
+        assert_eq!(classify_color([80, 150, 50, 255]), Some(CoarseBiome::Land));
+        assert_eq!(classify_color([30, 60, 200, 0]), None);
+    }
+
+    #[test]
+    fn align_offset() {
+        let img = draw(1);
+        let grid = align_grid(&img, 1.0 / 3.0, 0.1, -0.1);
+        // The top-left pixel starts one pixel before block 0, so the guess is less than half a
+        // block away. Any offset that keeps the pixel centers inside the same blocks is equally
+        // good.
+        assert!((grid.x + 1.0 / 3.0).abs() < 1.0 / 6.0, "{:?}", grid);
+        assert!((grid.z + 1.0 / 3.0).abs() < 1.0 / 6.0, "{:?}", grid);
+        for i in 0..img.width {
+            let expected = (i as i64 - 1).div_euclid(3);
+            assert_eq!(
+                grid.block_at_pixel(i, i),
+                Point {
+                    x: expected,
+                    z: expected
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn find_river() {
+        let img = draw(1);
+        let seed_info =
+            screenshot_seed_info(&img, 1.0 / 3.0, 0.1, -0.1, "1.7".to_string()).unwrap();
+        let rivers = &seed_info.biomes[&BiomeId(biome_id::river)];
+        // The first row of blocks is at z = -1
+        assert_eq!(rivers.len(), 5 * 41);
+        assert!(rivers
+            .iter()
+            .all(|p| (40..45).contains(&p.x) && (-1..40).contains(&p.z)));
+        assert_eq!(seed_info.biomes.len(), 1);
+    }
+
+    #[test]
+    fn invalid_grid() {
+        let img = draw(0);
+        for &(blocks_per_pixel, x, z) in &[
+            (0.0, 0.0, 0.0),
+            (-1.0, 0.0, 0.0),
+            (f64::NAN, 0.0, 0.0),
+            (f64::INFINITY, 0.0, 0.0),
+            (1.0, f64::NAN, 0.0),
+            (1.0, 0.0, f64::NEG_INFINITY),
+        ] {
+            assert!(
+                screenshot_seed_info(&img, blocks_per_pixel, x, z, "1.7".to_string()).is_err(),
+                "{} {} {}",
+                blocks_per_pixel,
+                x,
+                z
+            );
+            let grid = ScreenshotGrid {
+                x,
+                z,
+                blocks_per_pixel,
+            };
+            assert!(classify_screenshot(&img, &grid).is_err());
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Save the progress of the river seed finder, to continue the search in a later run
+//!
+//! A checkpoint stores the ranges of the 26-bit stage that are already checked and the candidates
+//! found in them, together with the config and a digest of the evidence. A search can be split
+//! into disjoint ranges that run on different machines, and the checkpoints merged later by just
+//! exchanging the files, as long as all of them use the same evidence and config.
+
+use crate::biome_layers::river_seed_finder_multi_version_iter;
+use crate::biome_layers::SearchConfig;
+use crate::biome_layers::SearchEvidence;
+use crate::biome_layers::SeedCandidate;
+use crate::biome_layers::StructureEvidence;
+use crate::seed_info::MinecraftVersion;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+/// Version of the checkpoint file format. Files with a different version cannot be loaded
+pub const CHECKPOINT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    SerdeJson(serde_json::Error),
+    /// The file was written using a different format version
+    FormatVersion(u32),
+    /// The checkpoint was created using different evidence
+    DifferentEvidence,
+    /// The checkpoint was created using a different `SearchConfig`
+    DifferentConfig,
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(x: std::io::Error) -> Self {
+        CheckpointError::Io(x)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(x: serde_json::Error) -> Self {
+        CheckpointError::SerdeJson(x)
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{}", e),
+            CheckpointError::SerdeJson(e) => write!(f, "{}", e),
+            CheckpointError::FormatVersion(v) => write!(
+                f,
+                "Checkpoint format version {} is not supported, expected {}",
+                v, CHECKPOINT_FORMAT_VERSION
+            ),
+            CheckpointError::DifferentEvidence => {
+                write!(f, "The checkpoint was created using different evidence")
+            }
+            CheckpointError::DifferentConfig => {
+                write!(f, "The checkpoint was created using a different config")
+            }
+        }
+    }
+}
+
+/// Digest of the evidence used by `river_seed_finder_multi_version_iter`, as a hex string. Two
+/// searches can only be merged if they use the same evidence, with the fragments in the same
+/// order. The order of the evidence inside each fragment does not matter, because
+/// `search_evidence` iterates over a `HashMap` and the order changes on every run.
+pub fn evidence_digest(fragments: &[(MinecraftVersion, SearchEvidence)]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for (version, evidence) in fragments {
+        hasher.update(canonical_evidence(*version, evidence).as_bytes());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Text representation of the evidence of one fragment, with each kind of evidence sorted
+fn canonical_evidence(version: MinecraftVersion, evidence: &SearchEvidence) -> String {
+    let mut rivers = evidence.rivers.clone();
+    rivers.sort_unstable();
+    let mut extra_biomes = evidence.extra_biomes.clone();
+    extra_biomes.sort_unstable();
+    let mut structures: Vec<String> = evidence
+        .structures
+        .iter()
+        .map(|s| match s {
+            StructureEvidence::SlimeChunk(c) => format!("slimeChunk {} {}", c.x, c.z),
+            StructureEvidence::BuriedTreasure(c) => format!("buriedTreasure {} {}", c.x, c.z),
+            StructureEvidence::Region(r, c) => format!("region {:?} {} {}", r, c.x, c.z),
+        })
+        .collect();
+    structures.sort_unstable();
+
+    let mut out = format!("version {:?}\n", version);
+    for p in rivers {
+        out.push_str(&format!("river {} {}\n", p.x, p.z));
+    }
+    for (id, p) in extra_biomes {
+        out.push_str(&format!("biome {} {} {}\n", id.0, p.x, p.z));
+    }
+    for s in structures {
+        out.push_str(&s);
+        out.push('\n');
+    }
+    out.push_str("end\n");
+
+    out
+}
+
+/// State of a search, see the module docs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchCheckpoint {
+    pub format_version: u32,
+    /// Versions of the fragments, only for humans reading the file
+    pub versions: Vec<String>,
+    pub evidence_digest: String,
+    pub config: SearchConfig,
+    /// Range of the search, the same as the arguments of `river_seed_finder_multi_version_iter`
+    pub range_lo: u32,
+    pub range_hi: u32,
+    /// Ranges that are already checked, as `(lo, hi)` with `hi` exclusive. Sorted, and adjacent
+    /// ranges are joined.
+    pub done: Vec<(u32, u32)>,
+    /// Candidates found in the `done` ranges, sorted by seed
+    pub candidates: Vec<SeedCandidate>,
+}
+
+impl SearchCheckpoint {
+    pub fn new(
+        fragments: &[(MinecraftVersion, SearchEvidence)],
+        range_lo: u32,
+        range_hi: u32,
+        config: &SearchConfig,
+    ) -> Self {
+        Self {
+            format_version: CHECKPOINT_FORMAT_VERSION,
+            versions: fragments.iter().map(|(v, _)| format!("{:?}", v)).collect(),
+            evidence_digest: evidence_digest(fragments),
+            config: config.clone(),
+            range_lo,
+            range_hi,
+            done: vec![],
+            candidates: vec![],
+        }
+    }
+
+    /// Returns an error if this checkpoint cannot be used to continue a search with this evidence
+    /// and config
+    pub fn check_compatible(
+        &self,
+        fragments: &[(MinecraftVersion, SearchEvidence)],
+        config: &SearchConfig,
+    ) -> Result<(), CheckpointError> {
+        if self.evidence_digest != evidence_digest(fragments) {
+            return Err(CheckpointError::DifferentEvidence);
+        }
+        if self.config != *config {
+            return Err(CheckpointError::DifferentConfig);
+        }
+
+        Ok(())
+    }
+
+    /// Mark the range from `lo` to `hi` as checked, and add the candidates found in it
+    pub fn mark_done(&mut self, lo: u32, hi: u32, candidates: &[SeedCandidate]) {
+        if lo < hi {
+            self.done.push((lo, hi));
+            self.done = join_ranges(std::mem::take(&mut self.done));
+        }
+        self.candidates.extend_from_slice(candidates);
+        self.candidates.sort_unstable_by_key(|c| c.world_seed);
+        self.candidates.dedup_by_key(|c| c.world_seed);
+    }
+
+    /// Ranges of the search that are not checked yet
+    pub fn remaining(&self) -> Vec<(u32, u32)> {
+        let mut remaining = vec![];
+        let mut next = self.range_lo;
+        for &(lo, hi) in &self.done {
+            if lo > next {
+                remaining.push((next, lo.min(self.range_hi)));
+            }
+            next = next.max(hi);
+            if next >= self.range_hi {
+                break;
+            }
+        }
+        if next < self.range_hi {
+            remaining.push((next, self.range_hi));
+        }
+        remaining.retain(|(lo, hi)| lo < hi);
+
+        remaining
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    /// Fraction of the range that is already checked, from 0.0 to 1.0
+    pub fn progress(&self) -> f64 {
+        if self.range_hi <= self.range_lo {
+            return 1.0;
+        }
+        let remaining: u64 = self
+            .remaining()
+            .iter()
+            .map(|(lo, hi)| u64::from(hi - lo))
+            .sum();
+        let total = u64::from(self.range_hi - self.range_lo);
+
+        (total - remaining) as f64 / total as f64
+    }
+
+    /// Check the next `max_len` values of the first remaining range, and add the results to the
+    /// checkpoint. Returns the candidates found in this step. Does nothing if the search is done.
+    pub fn step(
+        &mut self,
+        fragments: &[(MinecraftVersion, SearchEvidence)],
+        max_len: u32,
+    ) -> Vec<SeedCandidate> {
+        assert!(max_len > 0);
+        let (lo, range_hi) = match self.remaining().first() {
+            Some(x) => *x,
+            None => return vec![],
+        };
+        let hi = lo.saturating_add(max_len).min(range_hi);
+        let found: Vec<SeedCandidate> =
+            river_seed_finder_multi_version_iter(fragments, lo, hi, &self.config).collect();
+        self.mark_done(lo, hi, &found);
+
+        found
+    }
+
+    /// Add the progress of a checkpoint created by a different run of the same search. The range
+    /// of the search is extended to include the range of `other`.
+    pub fn merge(&mut self, other: &SearchCheckpoint) -> Result<(), CheckpointError> {
+        if other.format_version != self.format_version {
+            return Err(CheckpointError::FormatVersion(other.format_version));
+        }
+        if other.evidence_digest != self.evidence_digest {
+            return Err(CheckpointError::DifferentEvidence);
+        }
+        if other.config != self.config {
+            return Err(CheckpointError::DifferentConfig);
+        }
+        self.range_lo = self.range_lo.min(other.range_lo);
+        self.range_hi = self.range_hi.max(other.range_hi);
+        for &(lo, hi) in &other.done {
+            self.mark_done(lo, hi, &[]);
+        }
+        self.mark_done(0, 0, &other.candidates);
+
+        Ok(())
+    }
+
+    /// Read a checkpoint written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CheckpointError> {
+        let r = std::io::BufReader::new(File::open(path)?);
+        let checkpoint: Self = serde_json::from_reader(r)?;
+        if checkpoint.format_version != CHECKPOINT_FORMAT_VERSION {
+            return Err(CheckpointError::FormatVersion(checkpoint.format_version));
+        }
+
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint as JSON. The file is written to a temporary path first, so an
+    /// interrupted write does not destroy the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let w = std::io::BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(w, self)?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+// Sort the ranges and join the ones that overlap or are adjacent
+fn join_ranges(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort_unstable();
+    let mut joined: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+    for (lo, hi) in ranges {
+        match joined.last_mut() {
+            Some(last) if lo <= last.1 => last.1 = last.1.max(hi),
+            _ => joined.push((lo, hi)),
+        }
+    }
+
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Point;
+
+    fn example_fragments() -> Vec<(MinecraftVersion, SearchEvidence)> {
+        vec![(
+            MinecraftVersion::Java1_7,
+            SearchEvidence {
+                rivers: vec![Point { x: 1, z: 2 }],
+                ..Default::default()
+            },
+        )]
+    }
+
+    fn candidate(world_seed: i64) -> SeedCandidate {
+        SeedCandidate {
+            world_seed,
+            score: 100,
+        }
+    }
+
+    #[test]
+    fn remaining_ranges() {
+        let fragments = example_fragments();
+        let mut c = SearchCheckpoint::new(&fragments, 0, 100, &SearchConfig::default());
+        assert_eq!(c.remaining(), vec![(0, 100)]);
+        c.mark_done(10, 20, &[candidate(5)]);
+        c.mark_done(20, 30, &[]);
+        c.mark_done(90, 120, &[]);
+        assert_eq!(c.done, vec![(10, 30), (90, 120)]);
+        assert_eq!(c.remaining(), vec![(0, 10), (30, 90)]);
+        assert_eq!(c.progress(), 0.3);
+        c.mark_done(0, 90, &[candidate(5)]);
+        assert!(c.is_done());
+        assert_eq!(c.candidates, vec![candidate(5)]);
+    }
+
+    #[test]
+    fn merge_disjoint_ranges() {
+        let fragments = example_fragments();
+        let config = SearchConfig::default();
+        let mut a = SearchCheckpoint::new(&fragments, 0, 50, &config);
+        let mut b = SearchCheckpoint::new(&fragments, 50, 100, &config);
+        a.mark_done(0, 50, &[candidate(3), candidate(-1)]);
+        b.mark_done(50, 80, &[candidate(3), candidate(7)]);
+        a.merge(&b).unwrap();
+        assert_eq!((a.range_lo, a.range_hi), (0, 100));
+        assert_eq!(a.remaining(), vec![(80, 100)]);
+        assert_eq!(
+            a.candidates,
+            vec![candidate(-1), candidate(3), candidate(7)]
+        );
+
+        // Checkpoints of different searches cannot be merged
+        let other_fragments = vec![(MinecraftVersion::Java1_7, SearchEvidence::default())];
+        let c = SearchCheckpoint::new(&other_fragments, 0, 100, &config);
+        assert!(matches!(
+            a.merge(&c),
+            Err(CheckpointError::DifferentEvidence)
+        ));
+        let config_d = SearchConfig {
+            min_score_percent: 1,
+            ..config.clone()
+        };
+        let d = SearchCheckpoint::new(&fragments, 0, 100, &config_d);
+        assert!(matches!(a.merge(&d), Err(CheckpointError::DifferentConfig)));
+        assert!(a.check_compatible(&fragments, &config).is_ok());
+    }
+
+    #[test]
+    fn digest_does_not_depend_on_hashmap_order() {
+        use crate::evidence::search_evidence;
+        use crate::seed_info::SeedInfo;
+
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.7",
+            "biomes": {
+                "1": [[0, 0], [16, 0]],
+                "2": [[100, 0]],
+                "3": [[200, 0], [200, 16]],
+                "4": [[300, 0]],
+                "5": [[400, 0]],
+                "6": [[500, 0]],
+                "7": [[600, 0], [616, 0]],
+                "21": [[700, 0]]
+            },
+            "slimeChunks": [[1, 2], [3, 4]]
+        }"#;
+        let digest = |json: &str| {
+            // Each SeedInfo has its own HashMap, with a different iteration order
+            let seed_info: SeedInfo = serde_json::from_str(json).unwrap();
+            evidence_digest(&[(MinecraftVersion::Java1_7, search_evidence(&seed_info))])
+        };
+        let d = digest(json);
+        for _ in 0..10 {
+            assert_eq!(digest(json), d);
+        }
+
+        let mut reversed = search_evidence(&serde_json::from_str(json).unwrap());
+        reversed.extra_biomes.reverse();
+        reversed.structures.reverse();
+        assert_eq!(
+            evidence_digest(&[(MinecraftVersion::Java1_7, reversed.clone())]),
+            d
+        );
+        reversed.extra_biomes.pop();
+        assert_ne!(evidence_digest(&[(MinecraftVersion::Java1_7, reversed)]), d);
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let fragments = example_fragments();
+        let mut c = SearchCheckpoint::new(&fragments, 0, 1 << 24, &SearchConfig::default());
+        c.mark_done(0, 1 << 16, &[candidate(-1234)]);
+        let s = serde_json::to_string(&c).unwrap();
+        assert!(s.contains(r#""formatVersion":1"#));
+        assert!(s.contains(r#""candidates":[{"worldSeed":-1234,"score":100}]"#));
+        let d: SearchCheckpoint = serde_json::from_str(&s).unwrap();
+        assert_eq!(c, d);
+    }
+}
@@ -0,0 +1,456 @@
+//! Search pipeline made of filters that can be replaced or extended by the user
+//!
+//! Each stage of the river seed finder is a `SeedFilter`, and a `SearchPipeline` runs them in
+//! order. `river_seed_finder_mixed_version_iter` is implemented using
+//! `SearchPipeline::river_search`. Custom filters, for example using the structure placements of
+//! a mod, can be added to the pipeline without copying the other stages.
+
+use crate::biome_layers::convert_hd_coords_into_quarter_scale;
+use crate::biome_layers::extend_candidates_with_hd_rivers_iter;
+use crate::biome_layers::river_seed_finder_26_iter;
+use crate::biome_layers::score_candidates_mixed_version_iter;
+use crate::biome_layers::SearchConfig;
+use crate::biome_layers::SearchEvidence;
+use crate::biome_layers::SeedCandidate;
+use crate::cancel::CancelToken;
+use crate::chunk::Point;
+use crate::java_rng::JavaRng;
+use crate::seed_info::FragmentVersion;
+use log::debug;
+use std::iter;
+
+/// Score of the candidates created by a filter. The filters that check evidence can only lower it
+pub const NEW_CANDIDATE_SCORE: u32 = 100;
+
+/// Number of 48-bit seeds extended at once by `Extend64`
+const EXTEND_64_BATCH_SIZE: u64 = 1 << 10;
+
+/// Candidates passed between the stages of a `SearchPipeline`
+pub type CandidateIter = Box<dyn Iterator<Item = SeedCandidate>>;
+
+/// Everything a filter can use to check the candidates
+pub struct SearchCtx<'a> {
+    /// Evidence of each region of the world, with the versions that can have generated it. See
+    /// `river_seed_finder_mixed_version_iter`
+    pub fragments: &'a [(FragmentVersion, SearchEvidence)],
+    pub config: &'a SearchConfig,
+    /// The built-in filters stop early when this token is cancelled, see
+    /// `river_seed_finder_mixed_version_cancellable`
+    pub cancel: CancelToken,
+}
+
+impl SearchCtx<'_> {
+    /// Rivers of all the fragments
+    pub fn all_rivers(&self) -> Vec<Point> {
+        self.fragments
+            .iter()
+            .flat_map(|(_, evidence)| evidence.rivers.iter().copied())
+            .collect()
+    }
+}
+
+/// One stage of a `SearchPipeline`
+pub trait SeedFilter {
+    /// Name used in the logs
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    /// Remove the candidates that do not match the evidence, or replace them with new candidates.
+    /// The filters can also lower the score of the candidates.
+    fn filter(&self, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx);
+
+    /// Same as `filter`, but the candidates are returned as soon as they are found. Used by
+    /// `SearchPipeline::run_iter`. The default implementation waits for all the candidates and
+    /// calls `filter`, the built-in filters are lazy. The returned iterator cannot borrow `ctx`,
+    /// so anything it needs must be copied.
+    fn filter_iter(&self, candidates: CandidateIter, ctx: &SearchCtx) -> CandidateIter {
+        let mut candidates = candidates.collect();
+        self.filter(&mut candidates, ctx);
+        Box::new(candidates.into_iter())
+    }
+}
+
+// Implementation of `SeedFilter::filter` for the lazy filters
+fn filter_using_iter<F: SeedFilter>(f: &F, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx) {
+    let old = std::mem::take(candidates);
+    *candidates = f.filter_iter(Box::new(old.into_iter()), ctx).collect();
+}
+
+/// Replace the candidates with the 26-bit candidates found using the rivers, like the first stage
+/// of `river_seed_finder_multi_version`. All the fragments must use the 1.7 river layers, see
+/// `river_seed_finder_check_versions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rivers26 {
+    pub range_lo: u32,
+    pub range_hi: u32,
+}
+
+impl SeedFilter for Rivers26 {
+    fn name(&self) -> &str {
+        "rivers26"
+    }
+
+    fn filter(&self, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx) {
+        filter_using_iter(self, candidates, ctx);
+    }
+
+    fn filter_iter(&self, _candidates: CandidateIter, ctx: &SearchCtx) -> CandidateIter {
+        let river_coords_quarter_scale = convert_hd_coords_into_quarter_scale(&ctx.all_rivers());
+        Box::new(
+            river_seed_finder_26_iter(
+                river_coords_quarter_scale,
+                self.range_lo,
+                self.range_hi,
+                ctx.config.clone(),
+                ctx.cancel.clone(),
+            )
+            .map(|world_seed| SeedCandidate {
+                world_seed,
+                score: NEW_CANDIDATE_SCORE,
+            }),
+        )
+    }
+}
+
+/// Extend 26-bit candidates to 34 bits using the voronoi zoom of the rivers, see
+/// `extend_candidates_with_hd_rivers`. All the fragments must use the 1.7 river layers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HdRivers34;
+
+impl SeedFilter for HdRivers34 {
+    fn name(&self) -> &str {
+        "hdRivers34"
+    }
+
+    fn filter(&self, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx) {
+        filter_using_iter(self, candidates, ctx);
+    }
+
+    fn filter_iter(&self, candidates: CandidateIter, ctx: &SearchCtx) -> CandidateIter {
+        if ctx.fragments.is_empty() {
+            return Box::new(iter::empty());
+        }
+        let seeds = candidates.map(|c| c.world_seed);
+        Box::new(
+            extend_candidates_with_hd_rivers_iter(
+                seeds,
+                &ctx.all_rivers(),
+                ctx.config,
+                ctx.cancel.clone(),
+            )
+            .map(|world_seed| SeedCandidate {
+                world_seed,
+                score: NEW_CANDIDATE_SCORE,
+            }),
+        )
+    }
+}
+
+/// Extend 34-bit candidates to all the 64-bit seeds that can be generated by Java
+/// `Random.nextLong()`. This creates 2^14 seeds per candidate, so it should be followed by a
+/// filter that rejects most of them. The seeds are created in small batches when they are needed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Extend64;
+
+impl SeedFilter for Extend64 {
+    fn name(&self) -> &str {
+        "extend64"
+    }
+
+    fn filter(&self, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx) {
+        filter_using_iter(self, candidates, ctx);
+    }
+
+    fn filter_iter(&self, candidates: CandidateIter, _ctx: &SearchCtx) -> CandidateIter {
+        // Can't use rivers to find 48 bits because rivers use 64 bits
+        // Can't use biomes because biomes also use 64 bits
+        // But we can use rivers + extend48 to end the search with a 2^14 bruteforce
+        Box::new(candidates.flat_map(|c| {
+            let low_34 = (c.world_seed as u64) & ((1 << 34) - 1);
+            (0..(1u64 << (48 - 34)))
+                .step_by(EXTEND_64_BATCH_SIZE as usize)
+                .flat_map(move |start| {
+                    let seeds_48: Vec<u64> = (start..start + EXTEND_64_BATCH_SIZE)
+                        .map(|seed| low_34 | (seed << 34))
+                        .collect();
+                    JavaRng::extend_long_48_batch(&seeds_48).into_iter().map(
+                        move |(_, world_seed)| SeedCandidate {
+                            world_seed: world_seed as i64,
+                            score: c.score,
+                        },
+                    )
+                })
+        }))
+    }
+}
+
+/// Score the candidates using the evidence of each fragment, like the last stage of
+/// `river_seed_finder_multi_version`. The candidates must be accepted by all the fragments, and
+/// their score is lowered to the lowest score of all the fragments. A fragment with an unknown
+/// version accepts the candidates accepted by any of its versions, see `FragmentVersion::Any`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvidenceScore;
+
+impl SeedFilter for EvidenceScore {
+    fn name(&self) -> &str {
+        "evidenceScore"
+    }
+
+    fn filter(&self, candidates: &mut Vec<SeedCandidate>, ctx: &SearchCtx) {
+        filter_using_iter(self, candidates, ctx);
+    }
+
+    fn filter_iter(&self, candidates: CandidateIter, ctx: &SearchCtx) -> CandidateIter {
+        Box::new(score_candidates_mixed_version_iter(
+            candidates,
+            ctx.fragments,
+            ctx.config,
+            ctx.cancel.clone(),
+        ))
+    }
+}
+
+/// Filters that run in order, each one using the candidates of the previous one
+#[derive(Default)]
+pub struct SearchPipeline {
+    pub filters: Vec<Box<dyn SeedFilter>>,
+}
+
+impl SearchPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The stages of `river_seed_finder_multi_version`. Custom filters can be added to the end
+    /// using `push`, or between the built-in stages using `insert`.
+    pub fn river_search(range_lo: u32, range_hi: u32) -> Self {
+        let mut pipeline = Self::new();
+        pipeline.push(Box::new(Rivers26 { range_lo, range_hi }));
+        pipeline.push(Box::new(HdRivers34));
+        pipeline.push(Box::new(Extend64));
+        pipeline.push(Box::new(EvidenceScore));
+
+        pipeline
+    }
+
+    pub fn push(&mut self, filter: Box<dyn SeedFilter>) {
+        self.filters.push(filter);
+    }
+
+    /// Insert a filter at this position. Panics if `index > self.filters.len()`
+    pub fn insert(&mut self, index: usize, filter: Box<dyn SeedFilter>) {
+        self.filters.insert(index, filter);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.filters.iter().map(|f| f.name()).collect()
+    }
+
+    /// Run all the filters starting with no candidates, and return the candidates sorted by seed
+    pub fn run(&self, ctx: &SearchCtx) -> Vec<SeedCandidate> {
+        self.run_with(vec![], ctx)
+    }
+
+    /// Run all the filters starting with these candidates, and return the candidates sorted by
+    /// seed
+    pub fn run_with(
+        &self,
+        mut candidates: Vec<SeedCandidate>,
+        ctx: &SearchCtx,
+    ) -> Vec<SeedCandidate> {
+        for f in &self.filters {
+            f.filter(&mut candidates, ctx);
+            debug!("{}: {} candidates", f.name(), candidates.len());
+        }
+        candidates.sort_unstable_by_key(|c| c.world_seed);
+
+        candidates
+    }
+
+    /// Same as `run_with`, but the candidates are returned as soon as they are found, and they
+    /// are not sorted. Only the filters that implement `SeedFilter::filter_iter` are lazy.
+    pub fn run_iter(&self, candidates: CandidateIter, ctx: &SearchCtx) -> CandidateIter {
+        self.filters
+            .iter()
+            .fold(candidates, |candidates, f| f.filter_iter(candidates, ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_layers::river_seed_finder_mixed_version_iter;
+    use crate::biome_layers::river_seed_finder_multi_version;
+    use crate::demo_world::demo_world;
+    use crate::demo_world::demo_world_with_config;
+    use crate::demo_world::DemoWorldConfig;
+    use crate::evidence::search_evidence;
+    use crate::mc_rng::McRng;
+    use crate::seed_info::MinecraftVersion;
+
+    // Custom filter that only accepts even seeds
+    struct EvenSeeds;
+
+    impl SeedFilter for EvenSeeds {
+        fn filter(&self, candidates: &mut Vec<SeedCandidate>, _ctx: &SearchCtx) {
+            candidates.retain(|c| c.world_seed % 2 == 0);
+        }
+    }
+
+    #[test]
+    fn custom_filter() {
+        let world_seed = 1234;
+        let fragments = vec![(
+            FragmentVersion::Known(MinecraftVersion::Java1_7),
+            search_evidence(&demo_world(world_seed)),
+        )];
+        let config = SearchConfig::default();
+        let ctx = SearchCtx {
+            fragments: &fragments,
+            config: &config,
+            cancel: CancelToken::new(),
+        };
+        let candidates: Vec<SeedCandidate> = (world_seed - 2..world_seed + 2)
+            .map(|world_seed| SeedCandidate {
+                world_seed,
+                score: NEW_CANDIDATE_SCORE,
+            })
+            .collect();
+
+        let mut pipeline = SearchPipeline::new();
+        pipeline.push(Box::new(EvidenceScore));
+        let found = pipeline.run_with(candidates.clone(), &ctx);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].world_seed, world_seed);
+
+        pipeline.insert(0, Box::new(EvenSeeds));
+        assert_eq!(pipeline.names(), vec!["custom", "evidenceScore"]);
+        assert_eq!(pipeline.run_with(candidates.clone(), &ctx), found);
+        let others: Vec<SeedCandidate> = candidates
+            .into_iter()
+            .filter(|c| c.world_seed != world_seed)
+            .collect();
+        assert!(pipeline.run_with(others, &ctx).is_empty());
+    }
+
+    #[test]
+    fn river_search_stages() {
+        let pipeline = SearchPipeline::river_search(0, 1 << 24);
+        assert_eq!(
+            pipeline.names(),
+            vec!["rivers26", "hdRivers34", "extend64", "evidenceScore"]
+        );
+
+        // Extend64 keeps the lowest 34 bits of the candidates
+        let config = SearchConfig::default();
+        let ctx = SearchCtx {
+            fragments: &[],
+            config: &config,
+            cancel: CancelToken::new(),
+        };
+        let mut candidates = vec![SeedCandidate {
+            world_seed: 0x1_2345_6789,
+            score: 50,
+        }];
+        Extend64.filter(&mut candidates, &ctx);
+        assert!(!candidates.is_empty());
+        for c in &candidates {
+            assert_eq!(c.world_seed & ((1 << 34) - 1), 0x1_2345_6789);
+            assert_eq!(c.score, 50);
+        }
+    }
+
+    // Position of the 26-bit seed of this world seed in the range of the river seed finder
+    fn river_range_index(world_seed: i64) -> u32 {
+        let range = McRng::similar_biome_seed_iterator_bits(25);
+        let low_25 = world_seed & ((1 << 25) - 1);
+        let representative = if range.contains(&low_25) {
+            low_25
+        } else {
+            McRng::similar_biome_seed(low_25) & ((1 << 25) - 1)
+        };
+
+        (representative - range.start()) as u32
+    }
+
+    #[test]
+    fn river_search_same_as_river_seed_finder() {
+        let world_seed = JavaRng::with_seed(1234).next_long();
+        let evidence = search_evidence(&demo_world(world_seed));
+        let i = river_range_index(world_seed);
+        let (range_lo, range_hi) = (i.saturating_sub(2), i + 2);
+        let config = SearchConfig::default();
+
+        let expected = river_seed_finder_multi_version(
+            &[(MinecraftVersion::Java1_7, evidence.clone())],
+            range_lo,
+            range_hi,
+            &config,
+        );
+        assert!(expected.contains(&world_seed));
+
+        let fragments = vec![(FragmentVersion::Known(MinecraftVersion::Java1_7), evidence)];
+        let ctx = SearchCtx {
+            fragments: &fragments,
+            config: &config,
+            cancel: CancelToken::new(),
+        };
+        let pipeline = SearchPipeline::river_search(range_lo, range_hi);
+        let found = pipeline.run(&ctx);
+        let found_seeds: Vec<i64> = found.iter().map(|c| c.world_seed).collect();
+        assert_eq!(found_seeds, expected);
+
+        // The lazy pipeline returns the same candidates
+        let mut lazy: Vec<SeedCandidate> =
+            pipeline.run_iter(Box::new(iter::empty()), &ctx).collect();
+        lazy.sort_unstable_by_key(|c| c.world_seed);
+        assert_eq!(lazy, found);
+        let mut mixed: Vec<SeedCandidate> =
+            river_seed_finder_mixed_version_iter(&fragments, range_lo, range_hi, &config)
+                .unwrap()
+                .collect();
+        mixed.sort_unstable_by_key(|c| c.world_seed);
+        assert_eq!(mixed, found);
+
+        // The same search works if the version of the fragment is not known
+        let fragments = vec![(FragmentVersion::Any, fragments[0].1.clone())];
+        let ctx = SearchCtx {
+            fragments: &fragments,
+            config: &config,
+            cancel: CancelToken::new(),
+        };
+        assert!(pipeline
+            .run(&ctx)
+            .iter()
+            .any(|c| c.world_seed == world_seed));
+    }
+
+    #[test]
+    fn any_version_fragment() {
+        // Evidence generated by 1.13, which has different oceans and hills than 1.7
+        let world_seed = JavaRng::with_seed(1234).next_long();
+        let demo_config = DemoWorldConfig {
+            mc_version: "1.13".to_string(),
+            num_biome_samples: 100,
+            ..Default::default()
+        };
+        let evidence = search_evidence(&demo_world_with_config(world_seed, &demo_config).unwrap());
+        let i = river_range_index(world_seed);
+        let (range_lo, range_hi) = (i.saturating_sub(2), i + 2);
+        let config = SearchConfig::default();
+        let find = |version: FragmentVersion| -> Vec<i64> {
+            let fragments = vec![(version, evidence.clone())];
+            river_seed_finder_mixed_version_iter(&fragments, range_lo, range_hi, &config)
+                .unwrap()
+                .map(|c| c.world_seed)
+                .collect()
+        };
+
+        assert!(find(FragmentVersion::Known(MinecraftVersion::Java1_13)).contains(&world_seed));
+        // The version is not known, one of the candidate versions is 1.13
+        assert!(find(FragmentVersion::Any).contains(&world_seed));
+        // The rivers match, but the biomes do not
+        assert!(!find(FragmentVersion::Known(MinecraftVersion::Java1_7)).contains(&world_seed));
+    }
+}
@@ -1,17 +1,60 @@
 use crate::chunk::Chunk;
 use crate::chunk::Point;
 use crate::chunk::Point4;
+use crate::biome_layers::cluster_observations;
 use crate::biome_layers::Area;
 use crate::biome_layers::Map;
+use crate::biome_layers::SearchConfig;
+use crate::biome_info::biome_id_for_version;
+use crate::biome_info::biome_id_from_name;
+use crate::biome_info::biome_name;
+use crate::slime::SlimeEvidenceModel;
+use crate::slime::SlimeObservation;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::io::Write;
 use std::str::FromStr;
 use std::path::Path;
 use serde::{Deserialize, Deserializer, Serialize, Serializer };
 use serde_json;
 
+/// Biome id, as used in the output of the biome layers. The maps store raw `i32` values, which
+/// can also be treasure map colors or intermediate layer values, so prefer this type in function
+/// signatures that only accept biomes.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[repr(transparent)]
 pub struct BiomeId(pub i32);
 
+impl BiomeId {
+    /// Biome name, or `None` if this id is not a biome
+    pub fn name(self) -> Option<&'static str> {
+        biome_name(self)
+    }
+}
+
+impl From<i32> for BiomeId {
+    fn from(x: i32) -> Self {
+        Self(x)
+    }
+}
+
+impl From<BiomeId> for i32 {
+    fn from(x: BiomeId) -> Self {
+        x.0
+    }
+}
+
+/// Biome name if known, or the numeric id otherwise
+impl fmt::Display for BiomeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
 impl FromStr for BiomeId {
     type Err = std::num::ParseIntError;
 
@@ -56,6 +99,54 @@ impl MinecraftVersion {
             _ => panic!("Biome generator for version {:?} is not implemented", self),
         }
     }
+
+    /// Which river layers are used by this version, or `None` if this version does not generate
+    /// rivers using layers, or it is not implemented
+    pub fn river_layers(&self) -> Option<RiverLayers> {
+        match self {
+            MinecraftVersion::Java1_3 => Some(RiverLayers::Java1_3),
+            MinecraftVersion::Java1_7 | MinecraftVersion::Java1_9 | MinecraftVersion::Java1_11 | MinecraftVersion::Java1_13 | MinecraftVersion::Java1_14 | MinecraftVersion::Java1_15 | MinecraftVersion::Java1_16_1 | MinecraftVersion::Java1_16 | MinecraftVersion::Java1_17 => Some(RiverLayers::Java1_7),
+            _ => None,
+        }
+    }
+
+    /// Version-dependent behaviour of the biome generator, including replicated Java bugs
+    pub fn quirks(&self) -> VersionQuirks {
+        VersionQuirks {
+            map_hills_zoom_world_seed_not_set: *self >= MinecraftVersion::Java1_7 && *self < MinecraftVersion::Java1_13,
+            mutated_birch_forest_mc_98995: *self == MinecraftVersion::Java1_9,
+            mesa_plateau_own_category: *self >= MinecraftVersion::Java1_16,
+        }
+    }
+}
+
+/// Implementations of the river layers, which go from MapRiverInit to the MapSmooth before
+/// MapRiverMix. Versions with the same value generate the same rivers from the same output of
+/// MapRiverInit, so the river seed finder can use evidence from all of them together.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RiverLayers {
+    /// 1.3 to 1.6: 6 zoom layers with base seeds 1000 to 1005
+    Java1_3,
+    /// 1.7 to 1.17: 6 zoom layers with base seeds 1000, 1001, 1000, 1001, 1002, 1003. The MapHills
+    /// bug fixed in 1.13 only affects the other branch of MapRiverInit, the one that goes to
+    /// MapHills, so it does not change the rivers.
+    Java1_7,
+}
+
+/// Flags that describe how the biome layers of one version differ from the others.
+///
+/// Custom layer stacks can use `MinecraftVersion::quirks` to replicate the same behaviour as the
+/// generators in `biome_layers`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionQuirks {
+    /// The two MapZoom layers between MapRiverInit and MapHills forget to call set_world_seed.
+    /// Fixed in 1.13.
+    pub map_hills_zoom_world_seed_not_set: bool,
+    /// Birch forest mutates into tall birch hills, and birch forest hills do not mutate.
+    /// https://bugs.mojang.com/browse/MC-98995
+    pub mutated_birch_forest_mc_98995: bool,
+    /// Starting from 1.16.2, mesaPlateau is no longer in the same category as mesa.
+    pub mesa_plateau_own_category: bool,
 }
 
 impl FromStr for MinecraftVersion {
@@ -89,6 +180,54 @@ impl FromStr for MinecraftVersion {
     }
 }
 
+/// Value of `SeedInfo::version` for the regions generated by an unknown version
+pub const ANY_VERSION: &str = "any";
+
+/// Versions tried for the regions generated by an unknown version: all the versions that use the
+/// same river layers as 1.7, so the rivers of these regions can be used by the river seed finder
+pub const ANY_VERSION_CANDIDATES: [MinecraftVersion; 9] = [
+    MinecraftVersion::Java1_7,
+    MinecraftVersion::Java1_9,
+    MinecraftVersion::Java1_11,
+    MinecraftVersion::Java1_13,
+    MinecraftVersion::Java1_14,
+    MinecraftVersion::Java1_15,
+    MinecraftVersion::Java1_16_1,
+    MinecraftVersion::Java1_16,
+    MinecraftVersion::Java1_17,
+];
+
+/// Version used to generate one region of the world, see `SeedInfo::mixed_version_fragments`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FragmentVersion {
+    Known(MinecraftVersion),
+    /// The version is not known, for example because the server regenerated old chunks using a
+    /// newer version (retrogen). The region matches a seed if it matches using any version of
+    /// `ANY_VERSION_CANDIDATES`.
+    Any,
+}
+
+impl FragmentVersion {
+    /// Versions that can have generated this region
+    pub fn versions(&self) -> Vec<MinecraftVersion> {
+        match self {
+            FragmentVersion::Known(v) => vec![*v],
+            FragmentVersion::Any => ANY_VERSION_CANDIDATES.to_vec(),
+        }
+    }
+}
+
+impl FromStr for FragmentVersion {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == ANY_VERSION {
+            Ok(FragmentVersion::Any)
+        } else {
+            s.parse().map(FragmentVersion::Known)
+        }
+    }
+}
+
 fn trim_at_dot(n: u32, x: &str) -> &str {
     let mut count = 0;
     let idx = x.find(|c| {
@@ -120,6 +259,13 @@ pub struct Options {
     pub error_margin_slime_chunks: u8,
     #[serde(default, skip_serializing_if = "is_default")]
     pub error_margin_slime_chunks_negative: u8,
+    // If set, the slime chunks are scored using this model instead of the error margins
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub slime_evidence_model: Option<SlimeEvidenceModel>,
+    // Weak slime evidence, such as chunks where no slimes spawned while AFK. Only used with the
+    // slime evidence model
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub slime_observations: Vec<SlimeObservation>,
     #[serde(flatten)]
     pub other: HashMap<String, serde_json::Value>,
 }
@@ -201,6 +347,16 @@ pub struct SeedInfo {
 }
 
 impl SeedInfo {
+    /// All the slime evidence of this seed info: the positive and negative slime chunks, and the
+    /// extra observations from the options
+    pub fn slime_observations(&self) -> Vec<SlimeObservation> {
+        let mut v: Vec<SlimeObservation> = self.positive.slime_chunks.iter().map(|c| SlimeObservation::Slime(*c)).collect();
+        v.extend(self.negative.slime_chunks.iter().map(|c| SlimeObservation::NotSlime(*c)));
+        v.extend_from_slice(&self.options.slime_observations);
+
+        v
+    }
+
     pub fn read<P: AsRef<Path>>(filename: P) -> Result<SeedInfo, ReadError> {
         use std::fs::File;
         let file = File::open(filename)?;
@@ -212,6 +368,134 @@ impl SeedInfo {
     pub fn version(&self) -> Result<MinecraftVersion, String> {
         self.version.parse()
     }
+
+    /// This seed info and all the nested `and` seed infos, together with the version used to
+    /// generate each one. Nested seed infos with an empty version use the version of the parent.
+    pub fn version_fragments(&self) -> Result<Vec<(MinecraftVersion, &SeedInfo)>, String> {
+        let mut fragments = vec![];
+        self.push_version_fragments(self.version()?, &mut fragments)?;
+
+        Ok(fragments)
+    }
+
+    /// Same as `version_fragments`, but the seed infos can also use the version "any", for regions
+    /// generated by an unknown version. See `FragmentVersion::Any`.
+    pub fn mixed_version_fragments(&self) -> Result<Vec<(FragmentVersion, &SeedInfo)>, String> {
+        let mut fragments = vec![];
+        self.push_mixed_version_fragments(self.version.parse()?, &mut fragments)?;
+
+        Ok(fragments)
+    }
+
+    fn push_mixed_version_fragments<'a>(&'a self, version: FragmentVersion, fragments: &mut Vec<(FragmentVersion, &'a SeedInfo)>) -> Result<(), String> {
+        fragments.push((version, self));
+        for s in &self.and {
+            let v = if s.version.is_empty() { version } else { s.version.parse()? };
+            s.push_mixed_version_fragments(v, fragments)?;
+        }
+
+        Ok(())
+    }
+
+    fn push_version_fragments<'a>(&'a self, version: MinecraftVersion, fragments: &mut Vec<(MinecraftVersion, &'a SeedInfo)>) -> Result<(), String> {
+        fragments.push((version, self));
+        for s in &self.and {
+            let v = if s.version.is_empty() { version } else { s.version()? };
+            s.push_version_fragments(v, fragments)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compact representation of this seed info, the finder settings and the candidates found,
+    /// which can be used as part of a URL. Use `from_share_string` to parse it.
+    pub fn to_share_string(&self, config: &SearchConfig, candidates: &[i64]) -> String {
+        SharedSearch {
+            seed_info: self.clone(),
+            config: config.clone(),
+            candidates: candidates.to_vec(),
+        }.to_share_string()
+    }
+
+    /// Parse a string created by `to_share_string`
+    pub fn from_share_string(x: &str) -> Result<SharedSearch, ReadError> {
+        SharedSearch::from_share_string(x)
+    }
+}
+
+// Share strings start with this prefix, to allow changing the format in the future
+const SHARE_STRING_PREFIX: &str = "v1.";
+
+/// Everything needed to reproduce a search: the input, the finder settings and the candidates
+/// found
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedSearch {
+    pub seed_info: SeedInfo,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub config: SearchConfig,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub candidates: Vec<i64>,
+}
+
+impl SharedSearch {
+    /// Serialize as deflate-compressed JSON, encoded using URL-safe base64
+    pub fn to_share_string(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap();
+        let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::best());
+        // Writing to a Vec cannot fail
+        encoder.write_all(&json).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        format!("{}{}", SHARE_STRING_PREFIX, base64_url_encode(&compressed))
+    }
+
+    pub fn from_share_string(x: &str) -> Result<SharedSearch, ReadError> {
+        let compressed = x
+            .strip_prefix(SHARE_STRING_PREFIX)
+            .and_then(base64_url_decode)
+            .ok_or(ReadError::InvalidShareString)?;
+        let mut json = vec![];
+        flate2::read::DeflateDecoder::new(&compressed[..]).read_to_end(&mut json)?;
+        let shared_search = serde_json::from_slice(&json)?;
+
+        Ok(shared_search)
+    }
+}
+
+const BASE64_URL_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Base64 with the URL-safe alphabet and without padding
+fn base64_url_encode(x: &[u8]) -> String {
+    let mut s = String::with_capacity((x.len() * 4).div_ceil(3));
+    for chunk in x.chunks(3) {
+        let mut buf = [0; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = (u32::from(buf[0]) << 16) | (u32::from(buf[1]) << 8) | u32::from(buf[2]);
+        for i in 0..chunk.len() + 1 {
+            s.push(BASE64_URL_ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize] as char);
+        }
+    }
+
+    s
+}
+
+fn base64_url_decode(s: &str) -> Option<Vec<u8>> {
+    let mut x = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        // A chunk of 1 character cannot encode any byte
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = BASE64_URL_ALPHABET.iter().position(|a| a == c)? as u32;
+            n |= v << (18 - 6 * i);
+        }
+        x.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+
+    Some(x)
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
@@ -255,14 +539,19 @@ pub struct SeedInfoV0_1 {
 
 impl From<SeedInfoV0_1> for SeedInfo {
     fn from(s: SeedInfoV0_1) -> SeedInfo {
+        let (biomes, biomes_quarter_scale) = match s.version.parse() {
+            Ok(version) => (biomes_for_version(s.biomes, version), biomes_for_version(s.biomes_quarter_scale, version)),
+            Err(_) => (s.biomes, s.biomes_quarter_scale),
+        };
+
         SeedInfo {
             version: s.version,
             world_seed: s.world_seed,
             world_seed_hash: s.world_seed_hash,
             description: s.description,
             options: s.options,
-            biomes: s.biomes,
-            biomes_quarter_scale: s.biomes_quarter_scale,
+            biomes,
+            biomes_quarter_scale,
             end_pillars: s.end_pillars,
             treasure_maps: s.treasure_maps,
             positive: s.positive,
@@ -335,27 +624,61 @@ impl From<SeedInfo> for SeedInfoCompat {
     }
 }
 
+// Biomes can be identified by their numeric id or by their name
+fn parse_biome_key<E: serde::de::Error>(k: &str) -> Result<BiomeId, E> {
+    k.parse().ok()
+        .or_else(|| biome_id_from_name(k).map(BiomeId))
+        .ok_or_else(|| E::custom(format!("unknown biome {:?}", k)))
+}
+
+// Merge the points of biomes that end up with the same id
+fn insert_biome_points<P>(biomes: &mut HashMap<BiomeId, Vec<P>>, k: BiomeId, v: Vec<P>) {
+    biomes.entry(k).or_default().extend(v);
+}
+
 fn deserialize_biomes<'de, D>(d: D) -> Result<HashMap<BiomeId, Vec<Point>>, D::Error> where D: Deserializer<'de> {
     let biomes = HashMap::<String, Vec<Point>>::deserialize(d)?;
-    Ok(biomes.into_iter().map(|(k, v)| (k.parse().unwrap(), v)).collect())
+    let mut r = HashMap::new();
+    for (k, v) in biomes {
+        insert_biome_points(&mut r, parse_biome_key(&k)?, v);
+    }
+    Ok(r)
 }
 
 fn deserialize_biomes4<'de, D>(d: D) -> Result<HashMap<BiomeId, Vec<Point4>>, D::Error> where D: Deserializer<'de> {
     let biomes = HashMap::<String, Vec<Point4>>::deserialize(d)?;
-    Ok(biomes.into_iter().map(|(k, v)| (k.parse().unwrap(), v)).collect())
+    let mut r = HashMap::new();
+    for (k, v) in biomes {
+        insert_biome_points(&mut r, parse_biome_key(&k)?, v);
+    }
+    Ok(r)
+}
+
+/// Convert the ids of renamed biomes to the ids used by this version, so that evidence copied
+/// from a different version uses the same ids as the biome generator.
+pub fn biomes_for_version<P>(biomes: HashMap<BiomeId, Vec<P>>, version: MinecraftVersion) -> HashMap<BiomeId, Vec<P>> {
+    let mut r = HashMap::new();
+    for (k, v) in biomes {
+        insert_biome_points(&mut r, BiomeId(biome_id_for_version(k.0, version)), v);
+    }
+    r
 }
 
-pub fn biomes_to_map<I>(biomes: I) -> Map
+/// One map for each cluster of observations, so observations that are far away from each other
+/// do not create a huge map. See `cluster_observations`.
+pub fn biomes_to_maps<I>(biomes: I) -> Vec<Map>
 where
     I: IntoIterator<Item = (BiomeId, Vec<Point>)>
 {
     let h: Vec<_> = biomes.into_iter().flat_map(|(k, v)| v.into_iter().map(move |x| (x, k))).collect();
-    let area = Area::from_coords(h.iter().map(|x| x.0));
-    let mut m = Map::new(area);
-    for (Point {x, z}, biome_id) in h {
-        m.a[((x - area.x) as usize, (z - area.z) as usize)] = biome_id.0;
-    }
-    m
+    cluster_observations(&h, |x| x.0).into_iter().map(|cluster| {
+        let area = Area::from_coords(cluster.iter().map(|x| x.0));
+        let mut m = Map::new(area);
+        for (Point {x, z}, biome_id) in cluster {
+            m.a[((x - area.x) as usize, (z - area.z) as usize)] = biome_id.0;
+        }
+        m
+    }).collect()
 }
 
 pub fn biomes_from_map(map: &Map) -> HashMap<BiomeId, Vec<Point>> {
@@ -376,6 +699,7 @@ pub fn biomes_from_map(map: &Map) -> HashMap<BiomeId, Vec<Point>> {
 pub enum ReadError {
     Io(std::io::Error),
     Serde(serde_json::Error),
+    InvalidShareString,
 }
 
 impl From<std::io::Error> for ReadError {
@@ -497,8 +821,8 @@ fn is_default<T: Default + PartialEq>(t: &T) -> bool {
 // https://github.com/serde-rs/json/issues/329#issuecomment-305608405
 mod opt_string {
     use std::fmt::Display;
-    use std::str::FromStr;
 
+    use crate::java_rng::parse_seed;
     use serde::{de, Serializer, Deserialize, Deserializer};
 
     pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
@@ -513,10 +837,9 @@ mod opt_string {
         }
     }
 
-    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
-        where T: FromStr,
-              T::Err: Display,
-              D: Deserializer<'de>
+    // Only used for seeds, so also accept unsigned and hexadecimal seeds
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+        where D: Deserializer<'de>
     {
         let s = String::deserialize(deserializer)?;
 
@@ -524,15 +847,103 @@ mod opt_string {
             // Deserialize empty string as None
             Ok(None)
         } else {
-            s.parse().map_err(de::Error::custom).map(Some)
+            parse_seed(&s).map_err(de::Error::custom).map(Some)
         }
     }
 }
 
+/// Serialize a seed as a string, because javascript numbers cannot represent all the 64-bit
+/// seeds. Use with `#[serde(with = "crate::seed_info::seed_string")]`
+pub mod seed_string {
+    use crate::java_rng::parse_seed;
+    use serde::{de, Serializer, Deserialize, Deserializer};
+
+    pub fn serialize<S>(value: &i64, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<i64, D::Error>
+        where D: Deserializer<'de>
+    {
+        let s = String::deserialize(deserializer)?;
+
+        parse_seed(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize a list of seeds as strings, because javascript numbers cannot represent all the
+/// 64-bit seeds. Use with `#[serde(with = "crate::seed_info::vec_seed_string")]`
+pub mod vec_seed_string {
+    use crate::java_rng::parse_seed;
+    use serde::{de, Serializer, Deserialize, Deserializer};
+    use serde::ser::SerializeSeq;
+
+    pub fn serialize<S>(value: &[i64], serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut seq = serializer.serialize_seq(Some(value.len()))?;
+        for seed in value {
+            seq.serialize_element(&seed.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<i64>, D::Error>
+        where D: Deserializer<'de>
+    {
+        let v = Vec::<String>::deserialize(deserializer)?;
+
+        v.iter().map(|s| parse_seed(s).map_err(de::Error::custom)).collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn biome_id_display() {
+        assert_eq!(BiomeId(7).to_string(), "River");
+        assert_eq!(BiomeId(-1).to_string(), "-1");
+        assert_eq!(BiomeId::from(7), BiomeId(7));
+        assert_eq!(i32::from(BiomeId(7)), 7);
+        assert_eq!(BiomeId(7).name(), biome_name(7));
+    }
+
+    #[test]
+    fn share_string_roundtrip() {
+        let mut seed_info = SeedInfo {
+            version: "1.7".to_string(),
+            description: "Shared search".to_string(),
+            ..Default::default()
+        };
+        seed_info.biomes.insert(BiomeId(7), (0..100).map(|x| Point { x, z: x / 3 }).collect());
+        seed_info.positive.slime_chunks.push(Chunk::new(3, -5));
+        let config = SearchConfig { structure_weight: 4, ..Default::default() };
+        let candidates = vec![-1234567890123, 42];
+
+        let x = seed_info.to_share_string(&config, &candidates);
+        assert!(x.bytes().all(|c| c.is_ascii_alphanumeric() || b".-_".contains(&c)));
+        let shared = SeedInfo::from_share_string(&x).unwrap();
+        assert_eq!(shared, SharedSearch { seed_info, config, candidates });
+
+        assert!(SeedInfo::from_share_string("v1.!!!!").is_err());
+        assert!(SeedInfo::from_share_string(&x[1..]).is_err());
+    }
+
+    #[test]
+    fn base64_url() {
+        for x in [&b""[..], b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar", &[0xFB, 0xFF][..]] {
+            assert_eq!(base64_url_decode(&base64_url_encode(x)).as_deref(), Some(x));
+        }
+        assert_eq!(base64_url_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_url_encode(b"fo"), "Zm8");
+        assert_eq!(base64_url_encode(&[0xFB, 0xFF]), "-_8");
+        assert_eq!(base64_url_decode("Z"), None);
+    }
+
     #[test]
     fn serialize_default() {
         let seed_info = SeedInfo {
@@ -596,6 +1007,67 @@ mod tests {
         assert_eq!(seed_info.biomes[&BiomeId(7)], vec![Point { x: 0, z: 0 }, Point { x: 2, z: 2 }]);
     }
 
+    #[test]
+    fn version_fragments() {
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.12",
+            "biomes": {
+                "7": [[0, 0]]
+            },
+            "and": [
+                {
+                    "seedInfo": "0.1",
+                    "version": "1.14",
+                    "biomes": {
+                        "7": [[1000, 0]]
+                    }
+                },
+                {
+                    "seedInfo": "0.1",
+                    "version": "",
+                    "description": "Same version as the parent"
+                }
+            ]
+        }"#;
+
+        let seed_info: SeedInfo = serde_json::from_str(json).unwrap();
+        let fragments = seed_info.version_fragments().unwrap();
+        let versions: Vec<_> = fragments.iter().map(|(v, _)| *v).collect();
+        assert_eq!(versions, vec![MinecraftVersion::Java1_11, MinecraftVersion::Java1_14, MinecraftVersion::Java1_11]);
+        assert_eq!(fragments[1].1.biomes[&BiomeId(7)], vec![Point { x: 1000, z: 0 }]);
+    }
+
+    #[test]
+    fn mixed_version_fragments() {
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.12",
+            "and": [
+                {
+                    "seedInfo": "0.1",
+                    "version": "any",
+                    "and": [
+                        {
+                            "seedInfo": "0.1",
+                            "version": ""
+                        }
+                    ]
+                }
+            ]
+        }"#;
+
+        let seed_info: SeedInfo = serde_json::from_str(json).unwrap();
+        assert!(seed_info.version_fragments().is_err());
+        let fragments = seed_info.mixed_version_fragments().unwrap();
+        let versions: Vec<_> = fragments.iter().map(|(v, _)| *v).collect();
+        assert_eq!(versions, vec![FragmentVersion::Known(MinecraftVersion::Java1_11), FragmentVersion::Any, FragmentVersion::Any]);
+        for v in FragmentVersion::Any.versions() {
+            assert_eq!(v.river_layers(), Some(RiverLayers::Java1_7));
+        }
+        assert!("1.0".parse::<FragmentVersion>().is_err());
+    }
+
     #[test]
     fn world_seed_string() {
         let json = r#"{
@@ -608,6 +1080,20 @@ mod tests {
         assert_eq!(seed_info.world_seed, Some(1234));
     }
 
+    #[test]
+    fn world_seed_hex_string() {
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.7",
+            "worldSeed": "0xFFFFFFFFFFFFFFFF",
+            "worldSeedHash": "18446744073709551614"
+        }"#;
+
+        let seed_info: SeedInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(seed_info.world_seed, Some(-1));
+        assert_eq!(seed_info.world_seed_hash, Some(-2));
+    }
+
     #[test]
     fn world_seed_empty_string() {
         let json = r#"{
@@ -635,6 +1121,45 @@ mod tests {
         assert_eq!(trim_at_dot(2, "..."), ".");
     }
 
+    #[test]
+    fn deserialize_biome_names() {
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.17",
+            "biomes": {
+                "1": [[0, 0]],
+                "minecraft:plains": [[1, 0]],
+                "Windswept Hills": [[2, 0]]
+            }
+        }"#;
+        let s: SeedInfo = serde_json::from_str(json).unwrap();
+        let mut plains = s.biomes[&BiomeId(1)].clone();
+        plains.sort();
+        assert_eq!(plains, vec![Point { x: 0, z: 0 }, Point { x: 1, z: 0 }]);
+        // Renamed in 1.18, so it uses the old id in 1.17
+        assert_eq!(s.biomes[&BiomeId(3)], vec![Point { x: 2, z: 0 }]);
+
+        // And old ids use the new id in 1.18
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.18",
+            "biomesQuarterScale": {
+                "3": [[0, 0]]
+            }
+        }"#;
+        let s: SeedInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(s.biomes_quarter_scale[&BiomeId(191)], vec![Point4 { x: 0, z: 0 }]);
+
+        let json = r#"{
+            "seedInfo": "0.1",
+            "version": "1.17",
+            "biomes": {
+                "not a biome": [[0, 0]]
+            }
+        }"#;
+        assert!(serde_json::from_str::<SeedInfo>(json).is_err());
+    }
+
     #[test]
     fn parse_1_16_version() {
         assert_eq!(MinecraftVersion::from_str("1.16"), Ok(MinecraftVersion::Java1_16));
@@ -642,4 +1167,16 @@ mod tests {
         assert_eq!(MinecraftVersion::from_str("1.16.2"), Ok(MinecraftVersion::Java1_16));
         assert_eq!(MinecraftVersion::from_str("1.16.3"), Ok(MinecraftVersion::Java1_16));
     }
+
+    #[test]
+    fn version_quirks() {
+        assert!(!MinecraftVersion::Java1_3.quirks().map_hills_zoom_world_seed_not_set);
+        assert!(MinecraftVersion::Java1_7.quirks().map_hills_zoom_world_seed_not_set);
+        assert!(MinecraftVersion::Java1_11.quirks().map_hills_zoom_world_seed_not_set);
+        assert!(!MinecraftVersion::Java1_13.quirks().map_hills_zoom_world_seed_not_set);
+        assert!(MinecraftVersion::Java1_9.quirks().mutated_birch_forest_mc_98995);
+        assert!(!MinecraftVersion::Java1_11.quirks().mutated_birch_forest_mc_98995);
+        assert!(!MinecraftVersion::Java1_16_1.quirks().mesa_plateau_own_category);
+        assert!(MinecraftVersion::Java1_16.quirks().mesa_plateau_own_category);
+    }
 }
@@ -1,16 +1,123 @@
 use crate::biome_layers::{Area, Map};
+use crate::cancel::CancelToken;
+use crate::cancel::Partial;
 use crate::chunk::Chunk;
+use crate::chunk::Point;
 use crate::java_rng::JavaRng;
 use log::info;
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
 use std::cmp::min;
 use std::num::Wrapping;
 
+/// Something seen in game that tells if a chunk is a slime chunk
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SlimeObservation {
+    /// The chunk is known to be a slime chunk, for example because a slime spawned in it
+    Slime(Chunk),
+    /// The chunk is known not to be a slime chunk
+    NotSlime(Chunk),
+    /// No slimes spawned in this chunk while AFK nearby for some hours. Slimes also need space
+    /// and darkness to spawn, so this is weaker evidence than `NotSlime`
+    #[serde(rename_all = "camelCase")]
+    NoSpawn { chunk: Chunk, afk_hours: f64 },
+}
+
+impl SlimeObservation {
+    pub fn chunk(&self) -> Chunk {
+        match self {
+            SlimeObservation::Slime(c) | SlimeObservation::NotSlime(c) => *c,
+            SlimeObservation::NoSpawn { chunk, .. } => *chunk,
+        }
+    }
+}
+
+/// How much to trust each kind of `SlimeObservation`. Instead of requiring the slime chunks of a
+/// seed to match the observations exactly, each observation that does not match adds a penalty,
+/// in bits, and the seeds whose total penalty is too high are rejected.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct SlimeEvidenceModel {
+    /// Probability that a `Slime` or `NotSlime` observation is wrong, for example because the
+    /// slime walked in from a different chunk
+    pub confirmed_error_rate: f64,
+    /// Probability that no slimes spawn in a slime chunk during one hour of AFK
+    pub no_spawn_rate_per_hour: f64,
+    /// `NoSpawn` observations shorter than this are ignored, slimes may just not have had time to
+    /// spawn
+    pub min_afk_hours: f64,
+    /// Maximum total penalty of an accepted seed
+    pub max_penalty_bits: f64,
+}
+
+impl Default for SlimeEvidenceModel {
+    fn default() -> Self {
+        Self {
+            confirmed_error_rate: 0.01,
+            no_spawn_rate_per_hour: 0.1,
+            min_afk_hours: 0.5,
+            max_penalty_bits: 8.0,
+        }
+    }
+}
+
+impl SlimeEvidenceModel {
+    /// Penalty of an observation that does not match the seed. Observations that match the seed
+    /// have no penalty.
+    pub fn penalty_bits(&self, observation: &SlimeObservation) -> f64 {
+        match *observation {
+            SlimeObservation::Slime(_) | SlimeObservation::NotSlime(_) => {
+                -self.confirmed_error_rate.log2()
+            }
+            SlimeObservation::NoSpawn { afk_hours, .. } => {
+                if afk_hours < self.min_afk_hours || afk_hours <= 0.0 {
+                    0.0
+                } else {
+                    -afk_hours * self.no_spawn_rate_per_hour.log2()
+                }
+            }
+        }
+    }
+}
+
+/// Sum of the penalties of the observations that do not match the slime chunks of this seed
+pub fn slime_penalty_bits(
+    seed: u64,
+    observations: &[SlimeObservation],
+    model: &SlimeEvidenceModel,
+) -> f64 {
+    observations
+        .iter()
+        .filter(|o| {
+            let is_slime = is_slime_chunk(seed, &o.chunk());
+            match o {
+                SlimeObservation::Slime(_) => !is_slime,
+                SlimeObservation::NotSlime(_) | SlimeObservation::NoSpawn { .. } => is_slime,
+            }
+        })
+        .map(|o| model.penalty_bits(o))
+        .sum()
+}
+
+// Used by SlimeChunks::new_probabilistic
+struct SlimePenalties {
+    // Penalty of each slime chunk that does not match
+    slime: f64,
+    // Penalty of each entry of no_slime_data
+    no_slime: Vec<f64>,
+    max: f64,
+}
+
 pub struct SlimeChunks {
     slime_data: Vec<u64>,
     no_slime_data: Vec<u64>,
     max_errors: usize,
     max_no_errors: usize,
     low_18_candidates: Vec<u32>,
+    // If set, the errors are weighted using these penalties instead of counted
+    penalties: Option<SlimePenalties>,
 }
 
 impl SlimeChunks {
@@ -41,23 +148,90 @@ impl SlimeChunks {
             max_errors,
             max_no_errors,
             low_18_candidates,
+            penalties: None,
         }
     }
 
+    /// Same as `new`, but the seeds are accepted if the total penalty of the observations that do
+    /// not match is at most `model.max_penalty_bits`, see `slime_penalty_bits`.
+    pub fn new_probabilistic(
+        observations: &[SlimeObservation],
+        model: &SlimeEvidenceModel,
+    ) -> SlimeChunks {
+        let slime_chunks: Vec<Chunk> = observations
+            .iter()
+            .filter(|o| matches!(o, SlimeObservation::Slime(_)))
+            .map(|o| o.chunk())
+            .collect();
+        let slime_penalty = model.penalty_bits(&SlimeObservation::Slime(Chunk::new(0, 0)));
+        // Only the slime chunks can be used to find the low 18 bits, so allow as many errors as
+        // the maximum penalty allows
+        let max_errors = if slime_penalty > 0.0 {
+            (model.max_penalty_bits / slime_penalty).floor().max(0.0) as usize
+        } else {
+            slime_chunks.len()
+        };
+        let mut sc = SlimeChunks::new(&slime_chunks, max_errors, &[], 0);
+
+        // Check the strongest evidence first, to reject the seeds faster. Observations without
+        // penalty are pruned.
+        let mut no_slime: Vec<(f64, u64)> = observations
+            .iter()
+            .filter(|o| !matches!(o, SlimeObservation::Slime(_)))
+            .map(|o| (model.penalty_bits(o), calculate_slime_data(&o.chunk())))
+            .filter(|(penalty, _)| *penalty > 0.0)
+            .collect();
+        no_slime.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        sc.no_slime_data = no_slime.iter().map(|(_, x)| *x).collect();
+        sc.max_no_errors = no_slime.len();
+        sc.penalties = Some(SlimePenalties {
+            slime: slime_penalty,
+            no_slime: no_slime.into_iter().map(|(penalty, _)| penalty).collect(),
+            max: model.max_penalty_bits,
+        });
+
+        sc
+    }
+
     pub fn find_seed(&self) -> Vec<u64> {
         self.find_seed_range(0, 1 << (48 - 18))
     }
 
+    /// Same as `find_seed`, but stops early if `cancel` is cancelled
+    pub fn find_seed_cancellable(&self, cancel: &CancelToken) -> Partial<u64> {
+        self.find_seed_range_cancellable(0, 1 << (48 - 18), cancel)
+    }
+
     // Use this to implement multithreading later
     // Range units are multiples of 2^18: lo=0, count=1 will search a 2^18 seed
     // range (runtime depends on number of candidates).
     // The maximum value for `lo = 0` is 2^30, or `count = 1 << 30`.
     pub fn find_seed_range(&self, lo: u32, count: u32) -> Vec<u64> {
+        self.find_seed_range_cancellable(lo, count, &CancelToken::new())
+            .results
+    }
+
+    /// Same as `find_seed_range`, but stops early if `cancel` is cancelled, returning the seeds
+    /// found so far.
+    pub fn find_seed_range_cancellable(
+        &self,
+        lo: u32,
+        count: u32,
+        cancel: &CancelToken,
+    ) -> Partial<u64> {
+        // Number of seeds checked between two checks of the cancel token
+        const CANCEL_CHECK_INTERVAL: u32 = 1 << 16;
         let hi = min(lo + count, 1 << 30);
         let mut v = vec![];
 
         for &l in &self.low_18_candidates {
             for seed in lo..hi {
+                if (seed - lo) % CANCEL_CHECK_INTERVAL == 0 && cancel.is_cancelled() {
+                    return Partial {
+                        results: v,
+                        truncated: true,
+                    };
+                }
                 let seed = ((seed as u64) << 18) | (l as u64);
 
                 if self.try_seed_skip_18(seed) {
@@ -67,11 +241,18 @@ impl SlimeChunks {
             }
         }
 
-        v
+        Partial {
+            results: v,
+            truncated: false,
+        }
     }
 
     // true if the seeds meets the requirements, skip the low18 check
     pub fn try_seed_skip_18(&self, seed: u64) -> bool {
+        if let Some(penalties) = &self.penalties {
+            return self.try_seed_penalties(seed, penalties);
+        }
+
         let mut errors = 0;
         for &x in &self.slime_data {
             let mut r = rng_with_slime_data(seed, x);
@@ -99,6 +280,29 @@ impl SlimeChunks {
         true
     }
 
+    fn try_seed_penalties(&self, seed: u64, penalties: &SlimePenalties) -> bool {
+        let mut penalty = 0.0;
+        for &x in &self.slime_data {
+            if !is_slime_data(seed, x) {
+                penalty += penalties.slime;
+                if penalty > penalties.max {
+                    return false;
+                }
+            }
+        }
+
+        for (&x, &p) in self.no_slime_data.iter().zip(&penalties.no_slime) {
+            if is_slime_data(seed, x) {
+                penalty += p;
+                if penalty > penalties.max {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     // true if the seeds meets the requirements
     pub fn try_seed(&self, seed: u64) -> bool {
         // Check the low 18 bits first
@@ -254,6 +458,100 @@ pub fn gen_map_from_seed(area: Area, seed: u64) -> Map {
     m
 }
 
+/// For each chunk of the area, the number of seeds that have a slime chunk there
+pub fn slime_probability_map(seeds: &[u64], area: Area) -> Array2<u32> {
+    let mut counts = Array2::zeros((area.w as usize, area.h as usize));
+    for &seed in seeds {
+        let map = gen_map_from_seed(area, seed);
+        ndarray::Zip::from(&mut counts)
+            .and(&map.a)
+            .for_each(|c, &is_slime| *c += is_slime as u32);
+    }
+
+    counts
+}
+
+/// How to draw the output of `slime_probability_map`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlimeMapMode {
+    /// Black if no seed has a slime chunk there, white if all the seeds have a slime chunk
+    /// there, and shades of green in between
+    Probability,
+    /// Highlight the chunks where the seeds disagree the most: checking if those chunks are
+    /// slime chunks will discard the most candidates
+    Disagreement,
+}
+
+/// Color used for all the chunks when there are no seeds
+const SLIME_UNKNOWN_COLOR: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+
+/// Color of a chunk that is a slime chunk in `count` out of `total` seeds
+pub fn slime_count_to_color(count: u32, total: u32, mode: SlimeMapMode) -> [u8; 4] {
+    assert!(count <= total);
+
+    if total == 0 {
+        return SLIME_UNKNOWN_COLOR;
+    }
+
+    match mode {
+        SlimeMapMode::Probability => {
+            if count == 0 {
+                [0x00, 0x00, 0x00, 0xFF]
+            } else if count == total {
+                [0xFF, 0xFF, 0xFF, 0xFF]
+            } else {
+                // Linear gradient from (0.0, 0.2, 0.0) to (0.0, 1.0, 0.0)
+                let t = count as f32 / total as f32;
+                let green = 0.2 + 0.8 * t;
+                [0x00, (green * 255.0) as u8, 0x00, 0xFF]
+            }
+        }
+        SlimeMapMode::Disagreement => {
+            // 0.0 if all the seeds agree, 1.0 if half of the seeds have a slime chunk there
+            let minority = count.min(total - count);
+            let d = (2 * minority) as f32 / total as f32;
+            [(d * 255.0) as u8, 0x00, 0x00, 0xFF]
+        }
+    }
+}
+
+/// Draw the output of `slime_probability_map` as an RGBA image, with x pointing right and z
+/// pointing down. `total` is the number of seeds used to generate the map.
+pub fn draw_slime_map(counts: &Array2<u32>, total: u32, mode: SlimeMapMode) -> Vec<u8> {
+    let (w, h) = counts.dim();
+    let mut v = vec![0; w * h * 4];
+    for ((x, z), &count) in counts.indexed_iter() {
+        let i = z * w + x;
+        v[i * 4..i * 4 + 4].copy_from_slice(&slime_count_to_color(count, total, mode));
+    }
+
+    v
+}
+
+/// The `n` chunks where the seeds disagree the most, most informative first. These are the best
+/// chunks to check next to discard candidates.
+pub fn most_informative_slime_chunks(
+    counts: &Array2<u32>,
+    total: u32,
+    area: Area,
+    n: usize,
+) -> Vec<Chunk> {
+    let mut chunks: Vec<(u32, Chunk)> = counts
+        .indexed_iter()
+        .map(|((x, z), &count)| {
+            let minority = count.min(total - count);
+            let chunk = Chunk::new(area.x as i32 + x as i32, area.z as i32 + z as i32);
+            (minority, chunk)
+        })
+        .filter(|(minority, _)| *minority > 0)
+        .collect();
+    // Stable sort, so chunks with the same score keep the x, z order
+    chunks.sort_by_key(|(minority, _)| std::cmp::Reverse(*minority));
+    chunks.truncate(n);
+
+    chunks.into_iter().map(|(_, chunk)| chunk).collect()
+}
+
 /// Generate a list of slime chunks and not slime chunks using the given seed
 pub fn generate_slime_chunks_and_not(
     seed: i64,
@@ -283,10 +581,229 @@ pub fn generate_slime_chunks_and_not(
     (vec![], vec![])
 }
 
+/// Slimes can only spawn in chunks that are less than 128 blocks away from the player
+const AFK_SPAWN_RADIUS: i64 = 128;
+/// And mobs cannot spawn closer than 24 blocks to the player
+const AFK_MIN_SPAWN_DISTANCE: i64 = 24;
+
+/// Slime chunks around one AFK spot, as returned by `best_slime_perimeter`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlimePerimeter {
+    /// Block coordinates of the AFK spot, always the center of a chunk
+    pub afk_point: Point,
+    /// Slime chunks whose center is inside the spawning sphere
+    pub slime_chunks: Vec<Chunk>,
+    /// Total number of chunks whose center is inside the spawning sphere
+    pub total_chunks: usize,
+}
+
+impl SlimePerimeter {
+    /// Fraction of the chunks inside the spawning sphere that are slime chunks
+    pub fn density(&self) -> f64 {
+        if self.total_chunks == 0 {
+            0.0
+        } else {
+            self.slime_chunks.len() as f64 / self.total_chunks as f64
+        }
+    }
+}
+
+fn chunk_center(c: Chunk) -> Point {
+    Point {
+        x: ((c.x as i64) << 4) + 8,
+        z: ((c.z as i64) << 4) + 8,
+    }
+}
+
+fn distance_squared(a: Point, b: Point) -> i64 {
+    let dx = a.x - b.x;
+    let dz = a.z - b.z;
+
+    dx * dx + dz * dz
+}
+
+// slime_map must contain all the chunks in a 128 block radius around afk_point
+fn slime_perimeter_at(slime_map: &Map, afk_point: Point) -> SlimePerimeter {
+    let afk_chunk = Chunk::from_point(afk_point);
+    let r = (AFK_SPAWN_RADIUS >> 4) as i32 + 1;
+    let mut slime_chunks = vec![];
+    let mut total_chunks = 0;
+
+    for x in afk_chunk.x - r..=afk_chunk.x + r {
+        for z in afk_chunk.z - r..=afk_chunk.z + r {
+            let c = Chunk::new(x, z);
+            let d2 = distance_squared(chunk_center(c), afk_point);
+            if d2 > AFK_SPAWN_RADIUS * AFK_SPAWN_RADIUS
+                || d2 < AFK_MIN_SPAWN_DISTANCE * AFK_MIN_SPAWN_DISTANCE
+            {
+                continue;
+            }
+            total_chunks += 1;
+            if slime_map.get(x as i64, z as i64) != 0 {
+                slime_chunks.push(c);
+            }
+        }
+    }
+
+    SlimePerimeter {
+        afk_point,
+        slime_chunks,
+        total_chunks,
+    }
+}
+
+/// Find the AFK spot with the most slime chunks around it.
+///
+/// Candidate AFK spots are the centers of the chunks less than `radius` blocks away from
+/// `center`. The chunk containing `center` is always a candidate. A chunk is considered to be
+/// inside the spawning sphere of an AFK spot if its center is between 24 and 128 blocks away
+/// horizontally. In case of tie, the spot closest to `center` wins.
+pub fn best_slime_perimeter(seed: u64, center: Point, radius: u64) -> SlimePerimeter {
+    let center_chunk = Chunk::from_point(center);
+    let radius_chunks = (radius >> 4) as i64 + 1;
+    let margin = radius_chunks + (AFK_SPAWN_RADIUS >> 4) + 1;
+    let area = Area {
+        x: center_chunk.x as i64 - margin,
+        z: center_chunk.z as i64 - margin,
+        w: (2 * margin + 1) as u64,
+        h: (2 * margin + 1) as u64,
+    };
+    let slime_map = gen_map_from_seed(area, seed);
+    let radius = radius as i64;
+
+    let mut best = slime_perimeter_at(&slime_map, chunk_center(center_chunk));
+    let mut best_distance = distance_squared(best.afk_point, center);
+    for dx in -radius_chunks..=radius_chunks {
+        for dz in -radius_chunks..=radius_chunks {
+            let afk_chunk = Chunk::new(center_chunk.x + dx as i32, center_chunk.z + dz as i32);
+            let afk_point = chunk_center(afk_chunk);
+            let d2 = distance_squared(afk_point, center);
+            if d2 > radius * radius {
+                continue;
+            }
+            let p = slime_perimeter_at(&slime_map, afk_point);
+            if p.slime_chunks.len() > best.slime_chunks.len()
+                || (p.slime_chunks.len() == best.slime_chunks.len() && d2 < best_distance)
+            {
+                best = p;
+                best_distance = d2;
+            }
+        }
+    }
+
+    best
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn find_seed_cancelled() {
+        let chunks = [Chunk::new(0, 0), Chunk::new(5, -3)];
+        let sc = SlimeChunks::new(&chunks, 0, &[], 0);
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let found = sc.find_seed_cancellable(&cancel);
+        assert!(found.truncated);
+        assert!(found.results.is_empty());
+
+        let found = sc.find_seed_range_cancellable(0, 4, &CancelToken::new());
+        assert!(!found.truncated);
+        assert_eq!(found.results, sc.find_seed_range(0, 4));
+    }
+
+    #[test]
+    fn probabilistic_slime_evidence() {
+        let seed = 0xbade12;
+        let (yes, no) = generate_slime_chunks_and_not(seed, 12, 12);
+        let model = SlimeEvidenceModel::default();
+        let mut observations: Vec<SlimeObservation> = yes
+            .iter()
+            .map(|c| SlimeObservation::Slime(*c))
+            .chain(no.iter().map(|c| SlimeObservation::NotSlime(*c)))
+            .collect();
+        assert_eq!(slime_penalty_bits(seed as u64, &observations, &model), 0.0);
+
+        // One wrong observation is allowed, two are not
+        observations.push(SlimeObservation::NotSlime(yes[0]));
+        let sc = SlimeChunks::new_probabilistic(&observations, &model);
+        assert!(sc.try_seed(seed as u64));
+        observations.push(SlimeObservation::Slime(no[0]));
+        let sc = SlimeChunks::new_probabilistic(&observations, &model);
+        assert!(!sc.try_seed(seed as u64));
+        observations.truncate(observations.len() - 2);
+
+        // No spawns for a few minutes are ignored, but no spawns for hours are strong evidence
+        let short = SlimeObservation::NoSpawn {
+            chunk: yes[1],
+            afk_hours: 0.25,
+        };
+        assert_eq!(model.penalty_bits(&short), 0.0);
+        let long = SlimeObservation::NoSpawn {
+            chunk: yes[1],
+            afk_hours: 3.0,
+        };
+        assert!(model.penalty_bits(&long) > model.max_penalty_bits);
+        observations.push(short);
+        assert!(SlimeChunks::new_probabilistic(&observations, &model).try_seed(seed as u64));
+        observations.push(long);
+        let sc = SlimeChunks::new_probabilistic(&observations, &model);
+        assert!(!sc.try_seed(seed as u64));
+
+        // try_seed agrees with slime_penalty_bits
+        for s in (0..1000).map(|i| (seed as u64) ^ (i << 18)) {
+            let penalty = slime_penalty_bits(s, &observations, &model);
+            assert_eq!(sc.try_seed(s), penalty <= model.max_penalty_bits, "{}", s);
+        }
+    }
+
+    #[test]
+    fn slime_probability_and_disagreement() {
+        let area = Area {
+            x: -5,
+            z: 3,
+            w: 16,
+            h: 8,
+        };
+        let seeds = [1234, 5678, 42];
+        let counts = slime_probability_map(&seeds, area);
+        for ((x, z), &count) in counts.indexed_iter() {
+            let c = Chunk::new(area.x as i32 + x as i32, area.z as i32 + z as i32);
+            let expected = seeds.iter().filter(|s| is_slime_chunk(**s, &c)).count();
+            assert_eq!(count as usize, expected);
+        }
+
+        let img = draw_slime_map(&counts, 3, SlimeMapMode::Probability);
+        assert_eq!(img.len(), 16 * 8 * 4);
+        let best = most_informative_slime_chunks(&counts, 3, area, 5);
+        for c in &best {
+            let count = counts[(
+                (c.x - area.x as i32) as usize,
+                (c.z - area.z as i32) as usize,
+            )];
+            assert!(count == 1 || count == 2);
+        }
+
+        // No known seeds
+        let counts = slime_probability_map(&[], area);
+        assert_eq!(
+            draw_slime_map(&counts, 0, SlimeMapMode::Disagreement)[0..4],
+            SLIME_UNKNOWN_COLOR
+        );
+        assert_eq!(most_informative_slime_chunks(&counts, 0, area, 5), vec![]);
+    }
+
+    #[test]
+    fn slime_colors() {
+        use SlimeMapMode::*;
+        assert_eq!(slime_count_to_color(0, 4, Probability), [0, 0, 0, 0xFF]);
+        assert_eq!(slime_count_to_color(4, 4, Probability), [0xFF; 4]);
+        assert_eq!(slime_count_to_color(0, 4, Disagreement), [0, 0, 0, 0xFF]);
+        assert_eq!(slime_count_to_color(4, 4, Disagreement), [0, 0, 0, 0xFF]);
+        assert_eq!(slime_count_to_color(2, 4, Disagreement), [0xFF, 0, 0, 0xFF]);
+    }
+
     #[test]
     fn slime_data() {
         // This was compared against java so it should be ok
@@ -343,6 +860,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn best_slime_perimeter_is_best() {
+        let seed = 0xbade12;
+        let center = Point { x: 100, z: -50 };
+        let best = best_slime_perimeter(seed, center, 64);
+
+        assert!(best.slime_chunks.len() <= best.total_chunks);
+        assert!(best.density() > 0.0 && best.density() <= 1.0);
+        for c in &best.slime_chunks {
+            assert!(is_slime_chunk(seed, c));
+        }
+
+        // The AFK spot at the center cannot be better than the best one
+        let area = Area { x: -20, z: -20, w: 40, h: 40 };
+        let slime_map = gen_map_from_seed(area, seed);
+        let at_center = slime_perimeter_at(&slime_map, chunk_center(Chunk::from_point(center)));
+        assert!(at_center.slime_chunks.len() <= best.slime_chunks.len());
+        assert_eq!(at_center.total_chunks, best.total_chunks);
+    }
+
     // Wtf, the z is the only variable that affects
     // the high bits [64, 32] of the slimedata
     // ^ Incorrect, if the result of (a+b+d) is negative, instead of
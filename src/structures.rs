@@ -1,6 +1,12 @@
+use crate::biome_info::biome_id;
+use crate::biome_layers::generate_up_to_layer;
+use crate::biome_layers::Area;
 use crate::chunk::Chunk;
+use crate::java_math::floor_div_i32;
 use crate::java_rng::mask;
 use crate::java_rng::JavaRng;
+use crate::seed_info::MinecraftVersion;
+use crate::seed_info::SeedStructures;
 
 // ceil(2^24 / 100)
 const ONE_PERCENT_OF_2_24: i32 = 0x28f5d;
@@ -138,10 +144,435 @@ impl TreasureChunks {
     }
 }
 
+/// Structures that are placed at one random chunk of each region of `spacing` x `spacing` chunks.
+/// The position only depends on the lower 48 bits of the world seed, so these structures can be
+/// used to filter 48-bit candidates before the biomes are checked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RegionStructure {
+    OceanRuin,
+    Shipwreck,
+}
+
+/// Placement parameters of a `RegionStructure`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegionConfig {
+    pub salt: i64,
+    /// Size of the region in chunks
+    pub spacing: i32,
+    /// The structure is never placed in the last `separation` chunks of the region
+    pub separation: i32,
+}
+
+impl RegionStructure {
+    /// Placement of this structure in this version, or `None` if the structure does not exist
+    pub fn config(self, version: MinecraftVersion) -> Option<RegionConfig> {
+        if version < MinecraftVersion::Java1_13 {
+            return None;
+        }
+        // The spacing was changed in 1.16.2
+        let new_spacing = version >= MinecraftVersion::Java1_16;
+        Some(match (self, new_spacing) {
+            (RegionStructure::OceanRuin, false) => RegionConfig {
+                salt: 14357621,
+                spacing: 16,
+                separation: 8,
+            },
+            (RegionStructure::OceanRuin, true) => RegionConfig {
+                salt: 14357621,
+                spacing: 20,
+                separation: 8,
+            },
+            (RegionStructure::Shipwreck, false) => RegionConfig {
+                salt: 165745295,
+                spacing: 16,
+                separation: 8,
+            },
+            (RegionStructure::Shipwreck, true) => RegionConfig {
+                salt: 165745295,
+                spacing: 24,
+                separation: 4,
+            },
+        })
+    }
+
+    pub fn biome_structure(self) -> BiomeStructure {
+        match self {
+            RegionStructure::OceanRuin => BiomeStructure::OceanRuin,
+            RegionStructure::Shipwreck => BiomeStructure::Shipwreck,
+        }
+    }
+}
+
+/// Chunk where the structure is placed in this region. Only the lower 48 bits of the world seed
+/// are used.
+pub fn region_structure_chunk(
+    world_seed: i64,
+    config: &RegionConfig,
+    region_x: i32,
+    region_z: i32,
+) -> Chunk {
+    // setLargeFeatureSeedWithSalt
+    let s = i64::from(region_x)
+        .wrapping_mul(341873128712)
+        .wrapping_add(i64::from(region_z).wrapping_mul(132897987541))
+        .wrapping_add(world_seed)
+        .wrapping_add(config.salt);
+    let mut r = JavaRng::with_seed(s as u64);
+    let range = config.spacing - config.separation;
+    let x = region_x * config.spacing + r.next_int_n(range);
+    let z = region_z * config.spacing + r.next_int_n(range);
+
+    Chunk::new(x, z)
+}
+
+/// Returns true if the structure is placed in this chunk. This does not check the biome, see
+/// `structure_biome_is_valid`.
+pub fn is_region_structure_chunk(world_seed: i64, config: &RegionConfig, chunk: &Chunk) -> bool {
+    let region_x = floor_div_i32(chunk.x, config.spacing);
+    let region_z = floor_div_i32(chunk.z, config.spacing);
+
+    region_structure_chunk(world_seed, config, region_x, region_z) == *chunk
+}
+
+/// Ocean ruins and shipwrecks of a `SeedStructures`
+pub fn region_structures(structures: &SeedStructures) -> Vec<(RegionStructure, Chunk)> {
+    let ocean_ruins = structures
+        .ocean_ruins
+        .iter()
+        .map(|c| (RegionStructure::OceanRuin, *c));
+    let shipwrecks = structures
+        .shipwrecks
+        .iter()
+        .map(|c| (RegionStructure::Shipwreck, *c));
+
+    ocean_ruins.chain(shipwrecks).collect()
+}
+
+/// Keep the 48-bit seeds that place all the structures in the observed chunks. The chunks must
+/// be the start chunk of the structure: big ocean ruins also generate smaller ruins around them,
+/// and those cannot be used here.
+///
+/// Returns an empty list if one of the structures does not exist in this version.
+pub fn filter_seeds_48_using_region_structures(
+    candidates: &[u64],
+    structures: &[(RegionStructure, Chunk)],
+    version: MinecraftVersion,
+) -> Vec<u64> {
+    let configs: Option<Vec<(RegionConfig, Chunk)>> = structures
+        .iter()
+        .map(|(s, c)| s.config(version).map(|config| (config, *c)))
+        .collect();
+    let configs = match configs {
+        Some(x) => x,
+        None => return vec![],
+    };
+
+    candidates
+        .iter()
+        .copied()
+        .filter(|seed| {
+            configs
+                .iter()
+                .all(|(config, c)| is_region_structure_chunk(*seed as i64, config, c))
+        })
+        .collect()
+}
+
+/// Structures that can only generate in some biomes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BiomeStructure {
+    Village,
+    DesertTemple,
+    JungleTemple,
+    WitchHut,
+    Igloo,
+    OceanMonument,
+    WoodlandMansion,
+    BuriedTreasure,
+    OceanRuin,
+    Shipwreck,
+}
+
+impl BiomeStructure {
+    /// Biomes where this structure can generate in this version. For ocean monuments these are
+    /// the biomes allowed near the center, see `structure_biome_is_valid`.
+    pub fn allowed_biomes(self, version: MinecraftVersion) -> Vec<i32> {
+        use biome_id::*;
+        match self {
+            BiomeStructure::Village => {
+                let mut v = vec![plains, desert, savanna];
+                if version >= MinecraftVersion::Java1_9 {
+                    v.push(taiga);
+                }
+                if version >= MinecraftVersion::Java1_14 {
+                    v.push(icePlains);
+                }
+                v
+            }
+            BiomeStructure::DesertTemple => vec![desert, desertHills],
+            BiomeStructure::JungleTemple => {
+                let mut v = vec![jungle, jungleHills];
+                if version >= MinecraftVersion::Java1_14 {
+                    v.extend([bambooJungle, bambooJungleHills]);
+                }
+                v
+            }
+            BiomeStructure::WitchHut => vec![swampland],
+            BiomeStructure::Igloo => {
+                if version >= MinecraftVersion::Java1_9 {
+                    vec![icePlains, coldTaiga]
+                } else {
+                    vec![]
+                }
+            }
+            BiomeStructure::OceanMonument => {
+                let mut v = vec![deepOcean];
+                if version >= MinecraftVersion::Java1_13 {
+                    v.extend([
+                        warmDeepOcean,
+                        lukewarmDeepOcean,
+                        coldDeepOcean,
+                        frozenDeepOcean,
+                    ]);
+                }
+                v
+            }
+            BiomeStructure::WoodlandMansion => vec![roofedForest, darkForestHills],
+            BiomeStructure::BuriedTreasure => vec![beach, coldBeach],
+            BiomeStructure::OceanRuin => ocean_biomes(),
+            BiomeStructure::Shipwreck => {
+                let mut v = ocean_biomes();
+                v.extend([beach, coldBeach]);
+                v
+            }
+        }
+    }
+}
+
+// All the ocean biomes, ocean ruins and shipwrecks only exist since 1.13
+fn ocean_biomes() -> Vec<i32> {
+    use biome_id::*;
+    let mut v = vec![ocean, deepOcean, frozenOcean];
+    v.extend(warmOcean..=frozenDeepOcean);
+
+    v
+}
+
+// Biomes allowed in a radius of 29 blocks around an ocean monument
+fn monument_surrounding_biomes(version: MinecraftVersion) -> Vec<i32> {
+    use biome_id::*;
+    let mut v = vec![ocean, deepOcean, river, frozenOcean, frozenRiver];
+    if version >= MinecraftVersion::Java1_13 {
+        v.extend(warmOcean..=frozenDeepOcean);
+    }
+
+    v
+}
+
+/// Returns true if the biomes around this chunk allow this structure to generate there. This
+/// only checks the biomes, the structure must also be selected by its own random number
+/// generator.
+///
+/// The biome checks of 1.18 are not implemented, so this always returns true in 1.18.
+pub fn structure_biome_is_valid(
+    world_seed: i64,
+    version: MinecraftVersion,
+    structure: BiomeStructure,
+    chunk: &Chunk,
+) -> bool {
+    if version >= MinecraftVersion::Java1_18 {
+        return true;
+    }
+    let x = i64::from(chunk.x) * 16 + 8;
+    let z = i64::from(chunk.z) * 16 + 8;
+    let allowed = structure.allowed_biomes(version);
+    match structure {
+        BiomeStructure::Village => biomes_viable(world_seed, version, x, z, 0, &allowed),
+        BiomeStructure::OceanMonument => {
+            biomes_viable(world_seed, version, x, z, 16, &allowed)
+                && biomes_viable(
+                    world_seed,
+                    version,
+                    x,
+                    z,
+                    29,
+                    &monument_surrounding_biomes(version),
+                )
+        }
+        BiomeStructure::WoodlandMansion => biomes_viable(world_seed, version, x, z, 32, &allowed),
+        // Buried treasures check the biome at the block (9, 9) of the chunk
+        BiomeStructure::BuriedTreasure => {
+            allowed.contains(&biome_at(world_seed, version, x + 1, z + 1))
+        }
+        // Starting from 1.16.2, the biome is checked at 1:4 scale, at the center of the chunk
+        BiomeStructure::OceanRuin | BiomeStructure::Shipwreck
+            if version >= MinecraftVersion::Java1_16 =>
+        {
+            allowed.contains(&biome_at_quarter_scale(
+                world_seed,
+                version,
+                i64::from(chunk.x) * 4 + 2,
+                i64::from(chunk.z) * 4 + 2,
+            ))
+        }
+        BiomeStructure::OceanRuin | BiomeStructure::Shipwreck => {
+            allowed.contains(&biome_at(world_seed, version, x + 1, z + 1))
+        }
+        BiomeStructure::DesertTemple
+        | BiomeStructure::JungleTemple
+        | BiomeStructure::WitchHut
+        | BiomeStructure::Igloo => allowed.contains(&biome_at(world_seed, version, x, z)),
+    }
+}
+
+/// Keep the seeds where all the structures are in a valid biome
+pub fn filter_seeds_using_structure_biomes(
+    candidates: &[i64],
+    structures: &[(BiomeStructure, Chunk)],
+    version: MinecraftVersion,
+) -> Vec<i64> {
+    candidates
+        .iter()
+        .copied()
+        .filter(|world_seed| {
+            structures
+                .iter()
+                .all(|(s, c)| structure_biome_is_valid(*world_seed, version, *s, c))
+        })
+        .collect()
+}
+
+// Biome at this block, after the voronoi zoom
+fn biome_at(world_seed: i64, version: MinecraftVersion, x: i64, z: i64) -> i32 {
+    let area = Area { x, z, w: 1, h: 1 };
+
+    generate_up_to_layer(version, area, world_seed, version.num_layers(), 0).a[(0, 0)]
+}
+
+// Biome at this 1:4 tile, before the voronoi zoom
+fn biome_at_quarter_scale(world_seed: i64, version: MinecraftVersion, x: i64, z: i64) -> i32 {
+    let area = Area { x, z, w: 1, h: 1 };
+
+    generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0).a[(0, 0)]
+}
+
+// Same as areBiomesViable: all the 1:4 tiles in a square of this radius around the block (x, z)
+// must have one of the allowed biomes
+fn biomes_viable(
+    world_seed: i64,
+    version: MinecraftVersion,
+    x: i64,
+    z: i64,
+    radius: i64,
+    allowed: &[i32],
+) -> bool {
+    let (x0, z0) = ((x - radius) >> 2, (z - radius) >> 2);
+    let (x1, z1) = ((x + radius) >> 2, (z + radius) >> 2);
+    let area = Area {
+        x: x0,
+        z: z0,
+        w: (x1 - x0 + 1) as u64,
+        h: (z1 - z0 + 1) as u64,
+    };
+    let m = generate_up_to_layer(version, area, world_seed, version.num_layers() - 1, 0);
+
+    m.a.iter().all(|b| allowed.contains(b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn temple_biomes() {
+        // This seed has deserts and swamps near the spawn
+        let world_seed = 22;
+        let version = MinecraftVersion::Java1_7;
+        let mut valid = 0;
+        for x in -20..20 {
+            for z in -20..20 {
+                let c = Chunk::new(x, z);
+                let b = biome_at(
+                    world_seed,
+                    version,
+                    i64::from(x) * 16 + 8,
+                    i64::from(z) * 16 + 8,
+                );
+                let is_desert = b == biome_id::desert || b == biome_id::desertHills;
+                assert_eq!(
+                    structure_biome_is_valid(world_seed, version, BiomeStructure::DesertTemple, &c),
+                    is_desert
+                );
+                if is_desert {
+                    valid += 1;
+                    assert!(!structure_biome_is_valid(
+                        world_seed,
+                        version,
+                        BiomeStructure::WitchHut,
+                        &c
+                    ));
+                }
+            }
+        }
+        assert!(valid > 0);
+
+        // No igloos before 1.9
+        let c = Chunk::new(0, 0);
+        assert!(!structure_biome_is_valid(
+            world_seed,
+            version,
+            BiomeStructure::Igloo,
+            &c
+        ));
+    }
+
+    #[test]
+    fn region_structure_positions() {
+        let v = MinecraftVersion::Java1_16;
+        let ruin = RegionStructure::OceanRuin.config(v).unwrap();
+        let shipwreck = RegionStructure::Shipwreck.config(v).unwrap();
+        let shipwreck_115 = RegionStructure::Shipwreck
+            .config(MinecraftVersion::Java1_15)
+            .unwrap();
+        // Values from java.util.Random
+        assert_eq!(region_structure_chunk(1234, &ruin, 0, 0), Chunk::new(11, 2));
+        assert_eq!(
+            region_structure_chunk(1234, &ruin, -3, 5),
+            Chunk::new(-59, 106)
+        );
+        assert_eq!(
+            region_structure_chunk(-5, &shipwreck, 2, -1),
+            Chunk::new(65, -5)
+        );
+        assert_eq!(
+            region_structure_chunk(-5, &shipwreck_115, 2, -1),
+            Chunk::new(39, -14)
+        );
+        assert!(is_region_structure_chunk(1234, &ruin, &Chunk::new(-59, 106)));
+        assert!(!is_region_structure_chunk(1234, &ruin, &Chunk::new(-58, 106)));
+        assert_eq!(RegionStructure::OceanRuin.config(MinecraftVersion::Java1_11), None);
+    }
+
+    #[test]
+    fn region_structure_filter_48() {
+        let version = MinecraftVersion::Java1_16;
+        let structures = [
+            (RegionStructure::OceanRuin, Chunk::new(11, 2)),
+            (RegionStructure::OceanRuin, Chunk::new(-59, 106)),
+        ];
+        let candidates = [1233, 1234, 1235];
+        assert_eq!(
+            filter_seeds_48_using_region_structures(&candidates, &structures, version),
+            vec![1234]
+        );
+        assert!(filter_seeds_48_using_region_structures(
+            &candidates,
+            &structures,
+            MinecraftVersion::Java1_7
+        )
+        .is_empty());
+    }
+
     #[test]
     fn constant_one_percent_of_2_24() {
         assert_eq!(ONE_PERCENT_OF_2_24, (0.01 * (1 << 24) as f32).ceil() as i32);
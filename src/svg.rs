@@ -0,0 +1,225 @@
+//! Export biome maps as SVG images
+//!
+//! Contiguous tiles with the same value are merged into one path, so the size of the output
+//! depends on the length of the borders between biomes instead of the area of the map. This
+//! makes it possible to export large areas, and the result can be zoomed without losing quality.
+
+use crate::biome_info::biome_name;
+use crate::biome_layers::biome_to_color;
+use crate::biome_layers::Map;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Closed polygon, as a list of vertices in tile coordinates. The last vertex is connected to
+/// the first one.
+pub type Ring = Vec<(i64, i64)>;
+
+/// Outline of all the tiles with the same value, as a list of rings. The interior of the tiles
+/// is the area enclosed by an odd number of rings, so holes are also represented as rings.
+pub fn value_outlines(map: &Map) -> BTreeMap<i32, Vec<Ring>> {
+    // Directed border edges, going clockwise around each tile when x points right and z points
+    // down. Each vertex of a border has the same number of incoming and outgoing edges, so the
+    // edges can always be chained into closed rings.
+    let mut edges: BTreeMap<i32, BTreeMap<(i64, i64), Vec<(i64, i64)>>> = BTreeMap::new();
+    let (w, h) = map.a.dim();
+    let value_at = |x: i64, z: i64| {
+        if x < 0 || z < 0 || x >= w as i64 || z >= h as i64 {
+            None
+        } else {
+            Some(map.a[(x as usize, z as usize)])
+        }
+    };
+    for ((x, z), v) in map.a.indexed_iter() {
+        let (x, z) = (x as i64, z as i64);
+        let e = edges.entry(*v).or_default();
+        let v = Some(*v);
+        let (x0, z0, x1, z1) = (map.x + x, map.z + z, map.x + x + 1, map.z + z + 1);
+        if value_at(x, z - 1) != v {
+            e.entry((x0, z0)).or_default().push((x1, z0));
+        }
+        if value_at(x + 1, z) != v {
+            e.entry((x1, z0)).or_default().push((x1, z1));
+        }
+        if value_at(x, z + 1) != v {
+            e.entry((x1, z1)).or_default().push((x0, z1));
+        }
+        if value_at(x - 1, z) != v {
+            e.entry((x0, z1)).or_default().push((x0, z0));
+        }
+    }
+
+    edges
+        .into_iter()
+        .map(|(v, mut edges)| (v, chain_edges(&mut edges)))
+        .collect()
+}
+
+fn chain_edges(edges: &mut BTreeMap<(i64, i64), Vec<(i64, i64)>>) -> Vec<Ring> {
+    let mut rings = vec![];
+    while let Some(start) = edges.keys().next().copied() {
+        let mut ring = vec![start];
+        let mut current = start;
+        loop {
+            let next = {
+                let out = edges.get_mut(&current).unwrap();
+                let next = out.pop().unwrap();
+                if out.is_empty() {
+                    edges.remove(&current);
+                }
+                next
+            };
+            if next == start {
+                break;
+            }
+            ring.push(next);
+            current = next;
+        }
+        rings.push(remove_collinear(ring));
+    }
+
+    rings
+}
+
+// Remove the vertices in the middle of a straight line
+fn remove_collinear(ring: Ring) -> Ring {
+    let n = ring.len();
+    let collinear = |i: usize| {
+        let (ax, az) = ring[(i + n - 1) % n];
+        let (bx, bz) = ring[i];
+        let (cx, cz) = ring[(i + 1) % n];
+        (bx - ax) * (cz - bz) == (bz - az) * (cx - bx)
+    };
+
+    (0..n).filter(|i| !collinear(*i)).map(|i| ring[i]).collect()
+}
+
+/// Draw a map as SVG, using the biome colors and adding the biome names as titles. `scale` is the
+/// number of blocks per tile: 1 for the last layer, 4 for the layers before the voronoi zoom,
+/// etc.
+pub fn draw_map_svg(map: &Map, scale: u32) -> String {
+    draw_map_svg_impl(map, scale, biome_to_color, |v| {
+        biome_name(v).map(str::to_string)
+    })
+}
+
+/// Same as `draw_map_svg`, but using a custom color for each value. Useful for the layers that
+/// do not contain biome ids.
+pub fn draw_map_svg_with<F>(map: &Map, scale: u32, f: F) -> String
+where
+    F: Fn(i32) -> [u8; 4],
+{
+    draw_map_svg_impl(map, scale, f, |_| None)
+}
+
+fn draw_map_svg_impl<F, T>(map: &Map, scale: u32, color: F, title: T) -> String
+where
+    F: Fn(i32) -> [u8; 4],
+    T: Fn(i32) -> Option<String>,
+{
+    let scale = i64::from(scale);
+    let area = map.area();
+    let mut s = String::new();
+    // Writing to a String cannot fail
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}" shape-rendering="crispEdges">"#,
+        area.x * scale,
+        area.z * scale,
+        area.w as i64 * scale,
+        area.h as i64 * scale,
+        area.w as i64 * scale,
+        area.h as i64 * scale,
+    )
+    .unwrap();
+    for (v, rings) in value_outlines(map) {
+        let [r, g, b, a] = color(v);
+        if a == 0 {
+            continue;
+        }
+        write!(s, r##"<path fill="#{:02x}{:02x}{:02x}""##, r, g, b).unwrap();
+        if a != 0xFF {
+            write!(s, r#" fill-opacity="{:.3}""#, f64::from(a) / 255.0).unwrap();
+        }
+        s.push_str(r#" fill-rule="evenodd" d=""#);
+        for ring in rings {
+            for (i, (x, z)) in ring.into_iter().enumerate() {
+                let cmd = if i == 0 { 'M' } else { 'L' };
+                write!(s, "{}{} {}", cmd, x * scale, z * scale).unwrap();
+            }
+            s.push('Z');
+        }
+        s.push('"');
+        match title(v) {
+            Some(title) => writeln!(s, "><title>{}</title></path>", title).unwrap(),
+            None => s.push_str("/>\n"),
+        }
+    }
+    s.push_str("</svg>\n");
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome_info::biome_id;
+    use crate::biome_layers::Area;
+
+    #[test]
+    fn merge_tiles() {
+        let area = Area {
+            x: -2,
+            z: 3,
+            w: 4,
+            h: 2,
+        };
+        // Left half plains, right half ocean
+        let map = Map::from_area_fn(area, |(x, _z)| {
+            if x < 2 {
+                biome_id::plains
+            } else {
+                biome_id::ocean
+            }
+        });
+        let outlines = value_outlines(&map);
+        assert_eq!(outlines.len(), 2);
+        let mut plains = outlines[&biome_id::plains][0].clone();
+        plains.sort();
+        assert_eq!(plains, vec![(-2, 3), (-2, 5), (0, 3), (0, 5)]);
+        assert_eq!(outlines[&biome_id::ocean].len(), 1);
+        assert_eq!(outlines[&biome_id::ocean][0].len(), 4);
+    }
+
+    #[test]
+    fn hole_and_checkers() {
+        let area = Area {
+            x: 0,
+            z: 0,
+            w: 3,
+            h: 3,
+        };
+        // A forest tile surrounded by plains
+        let map = Map::from_area_fn(area, |(x, z)| {
+            if (x, z) == (1, 1) {
+                biome_id::forest
+            } else {
+                biome_id::plains
+            }
+        });
+        let outlines = value_outlines(&map);
+        assert_eq!(outlines[&biome_id::plains].len(), 2);
+        assert_eq!(outlines[&biome_id::forest].len(), 1);
+
+        // Tiles that only touch at a corner
+        let map = Map::from_area_fn(area, |(x, z)| ((x + z) % 2) as i32);
+        let outlines = value_outlines(&map);
+        let num_edges: usize = outlines[&0].iter().map(|r| r.len()).sum();
+        // 5 separate tiles with 4 corners each
+        assert_eq!(num_edges, 5 * 4);
+
+        let svg = draw_map_svg(&map, 4);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"viewBox="0 0 12 12""#));
+        assert!(svg.contains("<title>Ocean</title>"));
+    }
+}
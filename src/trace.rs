@@ -0,0 +1,184 @@
+//! Record and replay the candidates checked by the river seed finder
+//!
+//! With the `record` feature enabled, `river_seed_finder_range` logs the candidates of each
+//! stage of the search into a thread-local trace. The trace can be saved in a compact binary
+//! format and later checked against the current code using
+//! `biome_layers::replay_river_seed_finder_trace`, to find the stage where a known seed was lost.
+//!
+//! The 26-bit stage checks 2^26 seeds, so only the accepted candidates of that stage are
+//! recorded. The other stages record both accepted and rejected candidates.
+
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"SSFT";
+const FORMAT_VERSION: u8 = 0;
+// stage + world_seed + accepted + score
+const EVENT_SIZE: usize = 1 + 8 + 1 + 4;
+
+/// Stage of the river seed finder pipeline
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Lower 26 bits, using quarter-scale rivers
+    Bits26,
+    /// Lower 34 bits, using the voronoi zoom
+    Bits34,
+    /// Full 64-bit seeds, using rivers and extra biomes
+    Bits64,
+}
+
+impl Stage {
+    fn to_u8(self) -> u8 {
+        match self {
+            Stage::Bits26 => 26,
+            Stage::Bits34 => 34,
+            Stage::Bits64 => 64,
+        }
+    }
+
+    fn from_u8(x: u8) -> Option<Self> {
+        Some(match x {
+            26 => Stage::Bits26,
+            34 => Stage::Bits34,
+            64 => Stage::Bits64,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub stage: Stage,
+    pub world_seed: i64,
+    pub accepted: bool,
+    /// Number of matching rivers in the 26 and 34-bit stages, weighted score as a percentage in
+    /// the 64-bit stage, or 0 if the candidate was rejected without a score
+    pub score: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[FORMAT_VERSION])?;
+        w.write_all(&(self.events.len() as u64).to_le_bytes())?;
+        for e in &self.events {
+            let mut buf = [0; EVENT_SIZE];
+            buf[0] = e.stage.to_u8();
+            buf[1..9].copy_from_slice(&e.world_seed.to_le_bytes());
+            buf[9] = u8::from(e.accepted);
+            buf[10..14].copy_from_slice(&e.score.to_le_bytes());
+            w.write_all(&buf)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Trace> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut header = [0; 4 + 1 + 8];
+        r.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid("not a seed finder trace"));
+        }
+        if header[4] != FORMAT_VERSION {
+            return Err(invalid("unsupported trace version"));
+        }
+        let num_events = u64::from_le_bytes(header[5..13].try_into().unwrap());
+
+        let mut events = vec![];
+        for _ in 0..num_events {
+            let mut buf = [0; EVENT_SIZE];
+            r.read_exact(&mut buf)?;
+            let stage = Stage::from_u8(buf[0]).ok_or_else(|| invalid("invalid stage"))?;
+            let accepted = match buf[9] {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid("invalid event")),
+            };
+            events.push(TraceEvent {
+                stage,
+                world_seed: i64::from_le_bytes(buf[1..9].try_into().unwrap()),
+                accepted,
+                score: u32::from_le_bytes(buf[10..14].try_into().unwrap()),
+            });
+        }
+
+        Ok(Trace { events })
+    }
+}
+
+#[cfg(feature = "record")]
+thread_local! {
+    static RECORDER: std::cell::RefCell<Option<Trace>> = std::cell::RefCell::new(None);
+}
+
+/// Start recording the candidates checked by this thread. Any previous recording is discarded.
+#[cfg(feature = "record")]
+pub fn start_recording() {
+    RECORDER.with(|r| *r.borrow_mut() = Some(Trace::default()));
+}
+
+/// Stop recording and return the recorded trace. Returns an empty trace if
+/// `start_recording` was not called.
+#[cfg(feature = "record")]
+pub fn stop_recording() -> Trace {
+    RECORDER.with(|r| r.borrow_mut().take().unwrap_or_default())
+}
+
+/// Add an event to the trace of this thread. Does nothing if the `record` feature is disabled
+/// or if this thread is not recording.
+#[inline]
+pub(crate) fn record(stage: Stage, world_seed: i64, accepted: bool, score: u32) {
+    #[cfg(feature = "record")]
+    RECORDER.with(|r| {
+        if let Some(trace) = r.borrow_mut().as_mut() {
+            trace.events.push(TraceEvent {
+                stage,
+                world_seed,
+                accepted,
+                score,
+            });
+        }
+    });
+    #[cfg(not(feature = "record"))]
+    let _ = (stage, world_seed, accepted, score);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read_trace() {
+        let trace = Trace {
+            events: vec![
+                TraceEvent {
+                    stage: Stage::Bits26,
+                    world_seed: 0x03A1F4CC,
+                    accepted: true,
+                    score: 120,
+                },
+                TraceEvent {
+                    stage: Stage::Bits64,
+                    world_seed: -1234567890123,
+                    accepted: false,
+                    score: 0,
+                },
+            ],
+        };
+        let mut buf = vec![];
+        trace.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 4 + 1 + 8 + 2 * EVENT_SIZE);
+        assert_eq!(Trace::read_from(&buf[..]).unwrap(), trace);
+
+        // Truncated trace
+        assert!(Trace::read_from(&buf[..buf.len() - 1]).is_err());
+        // Wrong magic
+        buf[0] = b'X';
+        assert!(Trace::read_from(&buf[..]).is_err());
+    }
+}
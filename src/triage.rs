@@ -0,0 +1,226 @@
+//! Split a list of candidate seeds by visiting a few coordinates in game
+//!
+//! When the seed finders return many candidates, most of them generate the same biomes near the
+//! evidence, but different biomes far away. Each candidate gets a fingerprint, the biomes at a
+//! few far apart points, and candidates with the same fingerprint are grouped together. For the
+//! largest group, the point that best splits the group is suggested: the user can check the
+//! biome at that point and discard the candidates that do not match.
+
+use crate::biome_layers::generate_up_to_layer;
+use crate::biome_layers::Area;
+use crate::chunk::Point;
+use crate::seed_info::BiomeId;
+use crate::seed_info::MinecraftVersion;
+use std::collections::HashMap;
+
+/// Points used to compute the fingerprint of a candidate
+pub const FINGERPRINT_POINTS: [Point; 8] = [
+    Point { x: 1024, z: 0 },
+    Point { x: -1024, z: 0 },
+    Point { x: 0, z: 1024 },
+    Point { x: 0, z: -1024 },
+    Point { x: 2048, z: 2048 },
+    Point { x: -2048, z: 2048 },
+    Point { x: 2048, z: -2048 },
+    Point { x: -2048, z: -2048 },
+];
+
+// Distance between the points that can be suggested to split a group
+const SPLIT_GRID_STEP: i64 = 256;
+// The suggested point is at most this far from the spawn, in each axis
+const SPLIT_GRID_RADIUS: i64 = 1024;
+// Only used since 1.18, where the biomes depend on the y coordinate: y = 64
+const SEA_LEVEL_Y_OFFSET: u32 = 32;
+
+/// Candidates with the same biomes at `FINGERPRINT_POINTS`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CandidateGroup {
+    pub fingerprint: Vec<BiomeId>,
+    pub seeds: Vec<i64>,
+}
+
+/// Coordinate to visit in order to split a group of candidates
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitPoint {
+    pub point: Point,
+    /// Candidates that generate each biome at `point`
+    pub seeds_by_biome: Vec<(BiomeId, Vec<i64>)>,
+}
+
+impl SplitPoint {
+    /// Size of the largest group after visiting this point, in the worst case
+    pub fn worst_case(&self) -> usize {
+        self.seeds_by_biome
+            .iter()
+            .map(|(_, seeds)| seeds.len())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Result of `triage_candidates`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Triage {
+    /// Groups of candidates, largest first
+    pub groups: Vec<CandidateGroup>,
+    /// Best point to split the largest group, or `None` if the largest group has only one
+    /// candidate or no point of the grid can split it
+    pub split: Option<SplitPoint>,
+}
+
+fn biome_at(world_seed: i64, version: MinecraftVersion, p: Point) -> BiomeId {
+    let area = Area {
+        x: p.x,
+        z: p.z,
+        w: 1,
+        h: 1,
+    };
+    let map = generate_up_to_layer(
+        version,
+        area,
+        world_seed,
+        version.num_layers(),
+        SEA_LEVEL_Y_OFFSET,
+    );
+
+    BiomeId(map.a[(0, 0)])
+}
+
+/// Biomes of a candidate at `FINGERPRINT_POINTS`
+pub fn fingerprint(world_seed: i64, version: MinecraftVersion) -> Vec<BiomeId> {
+    FINGERPRINT_POINTS
+        .iter()
+        .map(|p| biome_at(world_seed, version, *p))
+        .collect()
+}
+
+/// Group the candidates by fingerprint
+pub fn group_candidates(seeds: &[i64], version: MinecraftVersion) -> Vec<CandidateGroup> {
+    let mut groups: HashMap<Vec<BiomeId>, Vec<i64>> = HashMap::new();
+    for &seed in seeds {
+        groups
+            .entry(fingerprint(seed, version))
+            .or_default()
+            .push(seed);
+    }
+    let mut groups: Vec<CandidateGroup> = groups
+        .into_iter()
+        .map(|(fingerprint, seeds)| CandidateGroup { fingerprint, seeds })
+        .collect();
+    // Sort to make the output deterministic
+    groups.sort_by(|a, b| {
+        b.seeds
+            .len()
+            .cmp(&a.seeds.len())
+            .then_with(|| a.seeds.cmp(&b.seeds))
+    });
+
+    groups
+}
+
+/// Find the point that best splits a group of candidates: the point that minimizes the size of
+/// the largest group in the worst case. Points closer to the spawn are preferred, because they
+/// are faster to reach. Returns `None` if no point of the grid can split the group.
+pub fn best_split_point(seeds: &[i64], version: MinecraftVersion) -> Option<SplitPoint> {
+    if seeds.len() < 2 {
+        return None;
+    }
+
+    let mut points = vec![];
+    for x in -SPLIT_GRID_RADIUS / SPLIT_GRID_STEP..=SPLIT_GRID_RADIUS / SPLIT_GRID_STEP {
+        for z in -SPLIT_GRID_RADIUS / SPLIT_GRID_STEP..=SPLIT_GRID_RADIUS / SPLIT_GRID_STEP {
+            points.push(Point {
+                x: x * SPLIT_GRID_STEP,
+                z: z * SPLIT_GRID_STEP,
+            });
+        }
+    }
+    points.sort_by_key(|p| (p.x * p.x + p.z * p.z, *p));
+
+    let mut best: Option<SplitPoint> = None;
+    for point in points {
+        let mut seeds_by_biome: HashMap<BiomeId, Vec<i64>> = HashMap::new();
+        for &seed in seeds {
+            seeds_by_biome
+                .entry(biome_at(seed, version, point))
+                .or_default()
+                .push(seed);
+        }
+        if seeds_by_biome.len() < 2 {
+            continue;
+        }
+        let mut seeds_by_biome: Vec<(BiomeId, Vec<i64>)> = seeds_by_biome.into_iter().collect();
+        seeds_by_biome.sort_by_key(|(biome, seeds)| (std::cmp::Reverse(seeds.len()), *biome));
+        let split = SplitPoint {
+            point,
+            seeds_by_biome,
+        };
+        if best
+            .as_ref()
+            .map_or(true, |b| split.worst_case() < b.worst_case())
+        {
+            let perfect = split.worst_case() == 1;
+            best = Some(split);
+            if perfect {
+                break;
+            }
+        }
+    }
+
+    best
+}
+
+/// Group the candidates by fingerprint, and find the best point to split the largest group
+pub fn triage_candidates(seeds: &[i64], version: MinecraftVersion) -> Triage {
+    let groups = group_candidates(seeds, version);
+    let split = groups
+        .first()
+        .and_then(|g| best_split_point(&g.seeds, version));
+
+    Triage { groups, split }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triage_splits_largest_group() {
+        let version = MinecraftVersion::Java1_7;
+        // Seeds with the same lower 48 bits generate the same structures but different biomes
+        let seeds: Vec<i64> = (0..4).map(|i| 1234 + (i << 48)).collect();
+        let triage = triage_candidates(&seeds, version);
+        let total: usize = triage.groups.iter().map(|g| g.seeds.len()).sum();
+        assert_eq!(total, seeds.len());
+        for w in triage.groups.windows(2) {
+            assert!(w[0].seeds.len() >= w[1].seeds.len());
+        }
+        for g in &triage.groups {
+            for &seed in &g.seeds {
+                assert_eq!(fingerprint(seed, version), g.fingerprint);
+            }
+        }
+        if let Some(split) = triage.split {
+            let largest = &triage.groups[0];
+            assert!(split.seeds_by_biome.len() >= 2);
+            assert!(split.worst_case() < largest.seeds.len());
+            for (biome, seeds) in &split.seeds_by_biome {
+                for &seed in seeds {
+                    assert_eq!(biome_at(seed, version, split.point), *biome);
+                }
+            }
+        } else {
+            assert_eq!(triage.groups[0].seeds.len(), 1);
+        }
+    }
+
+    #[test]
+    fn same_seed_cannot_be_split() {
+        let version = MinecraftVersion::Java1_7;
+        assert_eq!(best_split_point(&[1234, 1234], version), None);
+        assert_eq!(best_split_point(&[1234], version), None);
+        let triage = triage_candidates(&[1234, 1234], version);
+        assert_eq!(triage.groups.len(), 1);
+        assert_eq!(triage.split, None);
+    }
+}
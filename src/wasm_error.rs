@@ -0,0 +1,77 @@
+//! Errors returned to JavaScript by the web and node bindings
+//!
+//! Both binding crates validate their input with the same helpers, and convert a `WasmError` to
+//! their own JavaScript value, which is the serialized `{ kind, message }` object.
+
+use crate::java_rng::parse_seed;
+use serde::Serialize;
+
+/// Maximum width and height of a fragment, in tiles
+pub const MAX_FRAGMENT_SIZE: usize = 1024;
+/// Maximum number of seeds used to draw a slime map
+pub const MAX_SLIME_MAP_SEEDS: usize = 1 << 20;
+
+/// Kind of error returned by the exports that validate their input
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WasmErrorKind {
+    InvalidSeed,
+    InvalidVersion,
+    UnsupportedVersion,
+    FragmentTooLarge,
+    TooManySeeds,
+    /// The options are not valid JSON, or are missing a field
+    InvalidOptions,
+    /// The saved state of a resumable search cannot be parsed
+    InvalidState,
+    /// The input text cannot be parsed, for example a debug screen
+    InvalidInput,
+}
+
+/// Error thrown to JavaScript as `{ kind, message }`, so the UI can show a friendly message
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WasmError {
+    pub kind: WasmErrorKind,
+    pub message: String,
+}
+
+impl WasmError {
+    pub fn new(kind: WasmErrorKind, message: String) -> Self {
+        log::error!("{}", message);
+        Self { kind, message }
+    }
+}
+
+pub fn parse_seed_or_error(seed: &str) -> Result<i64, WasmError> {
+    parse_seed(seed).map_err(|e| {
+        WasmError::new(
+            WasmErrorKind::InvalidSeed,
+            format!("{:?} is not a valid seed: {}", seed, e),
+        )
+    })
+}
+
+pub fn check_fragment_size(frag_size: usize) -> Result<(), WasmError> {
+    if frag_size == 0 || frag_size > MAX_FRAGMENT_SIZE {
+        return Err(WasmError::new(
+            WasmErrorKind::FragmentTooLarge,
+            format!(
+                "Fragment size must be between 1 and {}, got {}",
+                MAX_FRAGMENT_SIZE, frag_size
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parse the JSON options of an export
+pub fn parse_options<'a, T: serde::Deserialize<'a>>(o: &'a str) -> Result<T, WasmError> {
+    serde_json::from_str(o).map_err(|e| {
+        WasmError::new(
+            WasmErrorKind::InvalidOptions,
+            format!("Failed to parse options: {}", e),
+        )
+    })
+}
@@ -305,38 +305,161 @@ function undo_selection() {
     }
 }
 
-function runWorkers(numWorkers, seedInfo) {
-    foundSeeds = [];
+// Progress of the last search is saved here, to resume it after reloading the page
+const RIVERS_SEARCH_KEY = "riversSearch";
+
+function loadRiversSearch(seedInfoString) {
+    try {
+        let search = JSON.parse(localStorage.getItem(RIVERS_SEARCH_KEY));
+        if (
+            search &&
+            search.seedInfo === seedInfoString &&
+            search.pending.every(isValidRangeSearchState)
+        ) {
+            return search;
+        }
+    } catch (e) {
+        // Invalid JSON
+    }
+    return null;
+}
+
+// Check that a saved RangeSearchState can be passed to river_seed_finder_step
+function isValidRangeSearchState(state) {
+    let s = JSON.parse(state);
+    return (
+        Number.isInteger(s.rangeLo) &&
+        Number.isInteger(s.rangeHi) &&
+        Number.isInteger(s.next) &&
+        Array.isArray(s.found)
+    );
+}
+
+function newRiversSearch(seedInfoString) {
+    return {
+        seedInfo: seedInfoString,
+        nextX: 0,
+        checked: 0,
+        pending: [],
+        foundSeeds: [],
+    };
+}
+
+function saveRiversSearch(search) {
+    try {
+        localStorage.setItem(RIVERS_SEARCH_KEY, JSON.stringify(search));
+    } catch (e) {
+        console.error(e);
+    }
+}
+
+// Each worker searches one range at a time, in small steps using the
+// river_seed_finder_step command. The state of the unfinished ranges is saved
+// after each step, so the search can be resumed later.
+function runWorkers(numWorkers, seedInfo, search) {
+    foundSeeds = search.foundSeeds;
     let outta = document.getElementById("output_textarea");
     outta.value = "Calculating...";
-    let startedX = 0;
-    let x = 0;
+    let options = JSON.stringify({ seedInfo: seedInfo });
     let limit = 1 << 24;
     let range = 1 << 17;
-    //let range = (limit / numWorkers);
+    // States of the ranges that were not finished when the search was saved
+    let resumed = search.pending;
+    // Current state of each worker
+    let running = {};
+    let activeWorkers = 0;
+    let myWorkers = [];
+    let restarted = false;
+    let save = function() {
+        search.pending = [...resumed, ...Object.values(running)];
+        search.foundSeeds = foundSeeds;
+        saveRiversSearch(search);
+    };
+    // Serialized RangeSearchState of the next range
+    let nextTask = function() {
+        if (resumed.length > 0) {
+            return resumed.shift();
+        }
+        if (search.nextX < limit) {
+            let state = JSON.stringify({
+                rangeLo: search.nextX,
+                rangeHi: search.nextX + range,
+                next: search.nextX,
+                found: [],
+            });
+            search.nextX += range;
+            return state;
+        }
+        return null;
+    };
+    let postTask = function(myWorker, workerId) {
+        let state = nextTask();
+        if (state === null) {
+            delete running[workerId];
+            activeWorkers -= 1;
+            return false;
+        }
+        // The state is saved before the first step, so the range is not
+        // skipped if the search is resumed
+        running[workerId] = state;
+        myWorker.postMessage({
+            command: "river_seed_finder_step",
+            args: [options, state],
+        });
+        return true;
+    };
+    showProgressBar(Math.floor(search.checked / range), limit / range);
     for (let workerId = 0; workerId < numWorkers; workerId++) {
         let myWorker = new Worker("rivers_worker.js");
         workers.push(myWorker);
-        showProgressBar(x / range, limit / range);
-        myWorker.postMessage(
-            JSON.stringify({
-                seedInfo: seedInfo,
-                range: [startedX, startedX + range],
-            })
-        );
-        startedX += range;
-        console.log("Message posted to worker");
+        myWorkers.push(myWorker);
+        activeWorkers += 1;
         myWorker.onmessage = function(e) {
-            console.log("Got message from worker " + workerId);
-            x += range;
-            showProgressBar(x / range, limit / range);
-            foundSeeds = [...foundSeeds, ...e.data];
+            if (restarted) {
+                return;
+            }
+            if (e.data.error) {
+                console.error(e.data.error);
+                if (e.data.error.kind === "invalidState") {
+                    // The saved state does not parse, start a fresh search
+                    restarted = true;
+                    myWorkers.forEach(function(w) {
+                        w.terminate();
+                    });
+                    workers = workers.filter(function(w) {
+                        return !myWorkers.includes(w);
+                    });
+                    runWorkers(
+                        numWorkers,
+                        seedInfo,
+                        newRiversSearch(search.seedInfo)
+                    );
+                }
+                return;
+            }
+            let state = JSON.parse(e.data);
+            let prevNext = JSON.parse(running[workerId]).next;
+            search.checked += state.next - prevNext;
+            showProgressBar(Math.floor(search.checked / range), limit / range);
+            if (state.next < state.rangeHi) {
+                // Continue with the next step of the same range
+                running[workerId] = e.data;
+                save();
+                myWorker.postMessage({
+                    command: "river_seed_finder_step",
+                    args: [options, e.data],
+                });
+                return;
+            }
+            delete running[workerId];
+            foundSeeds = [...foundSeeds, ...state.found];
             outta.value = stringify(
                 { foundSeeds: foundSeeds },
                 { maxLength: 20 }
             );
             // Draw sample image to canvas
             if (l42AreaC && workerId == 0) {
+                let candidateSeed = state.rangeLo;
                 Rust.slime_seed_finder_web.then(function(
                     slime_seed_finder_web
                 ) {
@@ -348,7 +471,7 @@ function runWorkers(numWorkers, seedInfo) {
                     let r = slime_seed_finder_web.generate_rivers_candidate(
                         JSON.stringify({
                             version: minecraft_version,
-                            seed: "" + startedX,
+                            seed: "" + candidateSeed,
                             area: l42AreaC,
                         })
                     );
@@ -359,17 +482,9 @@ function runWorkers(numWorkers, seedInfo) {
                     );
                 });
             }
-            if (startedX < limit) {
-                myWorker.postMessage(
-                    JSON.stringify({
-                        seedInfo: seedInfo,
-                        range: [startedX, startedX + range],
-                    })
-                );
-                startedX += range;
-            } else if (x < limit) {
-                // Waiting for other workers to finish
-            } else {
+            let posted = postTask(myWorker, workerId);
+            save();
+            if (!posted && activeWorkers == 0) {
                 searchFinished = true;
                 workers = [];
                 alert(
@@ -380,14 +495,30 @@ function runWorkers(numWorkers, seedInfo) {
                 );
             }
         };
+        postTask(myWorker, workerId);
+    }
+    save();
+    if (activeWorkers == 0) {
+        outta.value = stringify({ foundSeeds: foundSeeds }, { maxLength: 20 });
+        alert("This search is already done");
     }
 }
 function runGui() {
     let seltextarea = document.getElementById("selection_output");
     let seedInfo = JSON.parse(seltextarea.value);
+    let seedInfoString = JSON.stringify(seedInfo);
+    let search = loadRiversSearch(seedInfoString);
+    if (
+        search === null ||
+        !confirm(
+            "Resume the previous search of this seedInfo? Cancel to start a new search."
+        )
+    ) {
+        search = newRiversSearch(seedInfoString);
+    }
     if (window.Worker) {
         let maxWorkers = navigator.hardwareConcurrency || 4;
-        runWorkers(maxWorkers, seedInfo);
+        runWorkers(maxWorkers, seedInfo, search);
     } else {
         alert("Version without webworkers not implemented");
     }
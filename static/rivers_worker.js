@@ -11,6 +11,22 @@ onmessage = function(e) {
                 );
                 console.log("Posting message back to main script");
                 postMessage(workerResult);
+            } else if (e.data.command === "river_seed_finder_step") {
+                // args: [options, state], the state is null to start a new search
+                let [options, state] = e.data.args;
+                // Invalid options or state throw a WasmError: { kind, message }
+                try {
+                    if (state === null) {
+                        state = slime_seed_finder_web.river_seed_finder_start(options);
+                    }
+                    let workerResult = slime_seed_finder_web.river_seed_finder_step(
+                        options,
+                        state
+                    );
+                    postMessage(workerResult);
+                } catch (err) {
+                    postMessage({ error: err });
+                }
             } else {
                 let workerResult = slime_seed_finder_web.river_seed_finder(
                     e.data
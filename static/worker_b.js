@@ -14,16 +14,22 @@ function doCalculation(slime_seed_finder_web, data, cb) {
     let FRAG_SIZE = data.FRAG_SIZE;
     let lastLayer = data.lastLayer;
     let y_offset = data.y_offset;
-    let rvec = slime_seed_finder_web.generate_fragment_up_to_layer(
-        version,
-        fx,
-        fy,
-        seed,
-        FRAG_SIZE,
-        lastLayer,
-        y_offset
-    );
-    let result = { rvec: rvec };
+    let result = null;
+    // Invalid arguments throw a WasmError: { kind, message }
+    try {
+        let rvec = slime_seed_finder_web.generate_fragment_up_to_layer(
+            version,
+            fx,
+            fy,
+            seed,
+            FRAG_SIZE,
+            lastLayer,
+            y_offset
+        );
+        result = { rvec: rvec };
+    } catch (e) {
+        err = e;
+    }
     cb(err, result);
 }
 
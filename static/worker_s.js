@@ -9,13 +9,19 @@ function doCalculation(slime_seed_finder_web, data, cb) {
     let fy = data.fy;
     let seed = data.seed;
     let FRAG_SIZE = data.FRAG_SIZE;
-    let rvec = slime_seed_finder_web.generate_fragment_slime_map(
-        fx,
-        fy,
-        seed,
-        FRAG_SIZE
-    );
-    let result = { rvec: rvec };
+    let result = null;
+    // Invalid arguments throw a WasmError: { kind, message }
+    try {
+        let rvec = slime_seed_finder_web.generate_fragment_slime_map(
+            fx,
+            fy,
+            seed,
+            FRAG_SIZE
+        );
+        result = { rvec: rvec };
+    } catch (e) {
+        err = e;
+    }
     cb(err, result);
 }
 